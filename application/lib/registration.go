@@ -9,10 +9,12 @@ import (
 	"log"
 	"net"
 	"os"
+	"strconv"
 	"strings"
 	"sync"
 	"time"
 
+	"github.com/go-redis/redis"
 	"github.com/golang/protobuf/proto"
 	pb "github.com/refraction-networking/gotapdance/protobuf"
 )
@@ -21,6 +23,15 @@ import (
 // send validated registrations over in order to notify all detector cores.
 const DETECTOR_REG_CHANNEL string = "dark_decoy_map"
 
+// DETECTOR_ACTIVE_SESSIONS is the redis sorted set holding every registration
+// sent to the detectors that hasn't expired yet, scored by its expiry (unix
+// ms). Detector cores read it when they (re)connect to catch up on what they
+// missed on DETECTOR_REG_CHANNEL.
+const DETECTOR_ACTIVE_SESSIONS string = "dark_decoy_sessions"
+
+// DEFAULT_PHANTOM_PORT is the port clients connect to their phantom on.
+const DEFAULT_PHANTOM_PORT uint16 = 443
+
 // AES_GCM_TAG_SIZE the size of the aesgcm tag used when generating the client to
 // station message.
 const AES_GCM_TAG_SIZE = 16
@@ -157,6 +168,7 @@ func (regManager *RegistrationManager) NewRegistration(c2s *pb.ClientToStation,
 
 	reg := DecoyRegistration{
 		DarkDecoy:          phantomAddr,
+		PhantomPort:        DEFAULT_PHANTOM_PORT,
 		Keys:               conjureKeys,
 		Covert:             c2s.GetCovertAddress(),
 		Mask:               c2s.GetMaskedDecoyServerName(),
@@ -197,6 +209,7 @@ func (regManager *RegistrationManager) NewRegistrationC2SWrapper(c2sw *pb.C2SWra
 	regSrc := c2sw.GetRegistrationSource()
 	reg := DecoyRegistration{
 		DarkDecoy:          phantomAddr,
+		PhantomPort:        DEFAULT_PHANTOM_PORT,
 		registrationAddr:   net.IP(c2sw.GetRegistrationAddress()),
 		Keys:               &conjureKeys,
 		Covert:             c2s.GetCovertAddress(),
@@ -257,6 +270,7 @@ func (regManager *RegistrationManager) RemoveOldRegistrations() {
 // DecoyRegistration is a struct for tracking individual sessions that are expecting or tracking connections.
 type DecoyRegistration struct {
 	DarkDecoy          net.IP
+	PhantomPort        uint16
 	registrationAddr   net.IP
 	Keys               *ConjureSharedKeys
 	Covert, Mask       string
@@ -394,7 +408,8 @@ func (reg *DecoyRegistration) PreScanned() bool {
 // 					false - host is not life
 //			error	reason decision was made
 func (reg *DecoyRegistration) PhantomIsLive() (bool, error) {
-	return phantomIsLive(net.JoinHostPort(reg.DarkDecoy.String(), "443"))
+	port := strconv.Itoa(int(reg.phantomPort()))
+	return phantomIsLive(net.JoinHostPort(reg.DarkDecoy.String(), port))
 }
 
 func phantomIsLive(address string) (bool, error) {
@@ -705,26 +720,60 @@ func (r *RegisteredDecoys) removeOldRegistrations(logger *log.Logger) {
 // **NOTE**: If you mess with this function make sure the
 // session tracking tests on the detector side do what you expect
 // them to do. (conjure/src/session.rs)
-func registerForDetector(reg *DecoyRegistration) {
-	client := getRedisClient()
-	if client == nil {
-		fmt.Printf("couldn't connect to redis")
-		return
+// phantomPort is the port the client connects to the phantom on, for
+// registrations that didn't set one.
+func (reg *DecoyRegistration) phantomPort() uint16 {
+	if reg.PhantomPort == 0 {
+		return DEFAULT_PHANTOM_PORT
 	}
+	return reg.PhantomPort
+}
 
-	duration := uint64(3 * time.Minute.Nanoseconds())
+// detectorMessage is the StationToDetector the detector matches the client's
+// connections to its phantom against. The pb package (gotapdance's copy of
+// signalling.proto) doesn't have dst_port yet, so it's appended to the
+// marshalled message as field 4, as proto/signalling.proto defines it.
+func detectorMessage(reg *DecoyRegistration, timeoutNs uint64) ([]byte, error) {
 	src := reg.registrationAddr.String()
 	phantom := reg.DarkDecoy.String()
 	msg := &pb.StationToDetector{
 		PhantomIp: &phantom,
 		ClientIp:  &src,
-		TimeoutNs: &duration,
+		TimeoutNs: &timeoutNs,
 	}
 
 	s2d, err := proto.Marshal(msg)
+	if err != nil {
+		return nil, err
+	}
+
+	const dstPortField = 4<<3 | proto.WireVarint
+	s2d = append(s2d, proto.EncodeVarint(dstPortField)...)
+	return append(s2d, proto.EncodeVarint(uint64(reg.phantomPort()))...), nil
+}
+
+func registerForDetector(reg *DecoyRegistration) {
+	client := getRedisClient()
+	if client == nil {
+		fmt.Printf("couldn't connect to redis")
+		return
+	}
+
+	duration := uint64(3 * time.Minute.Nanoseconds())
+	s2d, err := detectorMessage(reg, duration)
 	if err != nil {
 		// throw(fit)
 		return
 	}
-	client.Publish(DETECTOR_REG_CHANNEL, string(s2d))
+
+	// Add to the active set before publishing, so a detector that subscribes
+	// in between still finds it there.
+	now := time.Now()
+	expiry := now.Add(time.Duration(duration)).UnixNano() / int64(time.Millisecond)
+	pipe := client.TxPipeline()
+	pipe.ZAdd(DETECTOR_ACTIVE_SESSIONS, redis.Z{Score: float64(expiry), Member: string(s2d)})
+	pipe.ZRemRangeByScore(DETECTOR_ACTIVE_SESSIONS, "-inf",
+		fmt.Sprintf("(%d", now.UnixNano()/int64(time.Millisecond)))
+	pipe.Publish(DETECTOR_REG_CHANNEL, string(s2d))
+	pipe.Exec()
 }
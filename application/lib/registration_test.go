@@ -4,7 +4,9 @@ import (
 	"bytes"
 	"encoding/hex"
 	"fmt"
+	"io/ioutil"
 	"net"
+	"strings"
 	"sync"
 	"testing"
 	"time"
@@ -205,6 +207,34 @@ func TestRegisterForDetectorOnce(t *testing.T) {
 	}
 }
 
+// test/station_to_detector.hex is what the detector's tests (src/sessions.rs)
+// turn into the session key a connection to 10.10.0.1:8443 has to match.
+func TestDetectorMessage(t *testing.T) {
+	reg := &DecoyRegistration{
+		DarkDecoy:        net.ParseIP("10.10.0.1"),
+		PhantomPort:      8443,
+		registrationAddr: net.ParseIP("192.168.0.1"),
+	}
+	s2d, err := detectorMessage(reg, uint64(3*time.Minute.Nanoseconds()))
+	require.Nil(t, err)
+
+	expected, err := ioutil.ReadFile("./test/station_to_detector.hex")
+	require.Nil(t, err)
+	require.Equal(t, strings.TrimSpace(string(expected)), hex.EncodeToString(s2d))
+
+	// Still parses without dst_port in pb
+	parsed := pb.StationToDetector{}
+	require.Nil(t, proto.Unmarshal(s2d, &parsed))
+	require.Equal(t, "10.10.0.1", parsed.GetPhantomIp())
+	require.Equal(t, "192.168.0.1", parsed.GetClientIp())
+
+	// Unset, it's DEFAULT_PHANTOM_PORT (443)
+	reg.PhantomPort = 0
+	s2d, err = detectorMessage(reg, 0)
+	require.Nil(t, err)
+	require.Equal(t, []byte{0x20, 0xbb, 0x03}, s2d[len(s2d)-3:])
+}
+
 func TestRegisterForDetectorArray(t *testing.T) {
 	var addrs = []string{}
 	var clientAddr = "192.0.2.1"
@@ -0,0 +1,235 @@
+// Flow and session expiry: the timer wheel against what the trackers did
+// before it (a VecDeque of SYN events next to a HashSet of flows, and a
+// HashMap of session timeouts scanned with retain on every cleanup). Sessions
+// go through a SessionTracker and its SessionTable, as in the ingest process
+// (cleanup) and on the cores (refresh).
+//
+//     cargo bench --bench expiry
+
+#[macro_use]
+extern crate criterion;
+extern crate rust_dark_decoy;
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+
+use rust_dark_decoy::clock;
+use rust_dark_decoy::flow_tracker::{Flow, Transport};
+use rust_dark_decoy::session_table::SessionTable;
+use rust_dark_decoy::sessions::{SessionDetails, SessionKey, SessionTracker};
+use rust_dark_decoy::timer_wheel::{TimerWheel, DEFAULT_TICK_NS};
+
+const MS: u64 = 1000 * 1000;
+const SEC: u64 = 1000 * MS;
+const TIMEOUT_TRACKED_NS: u64 = 30 * SEC;
+const CLEANUP_NS: u64 = 100 * MS;
+
+fn flow(i: u64) -> Flow
+{
+    Flow::from_parts(IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | (i as u32 & 0xff_ffff))),
+                     IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), (i >> 24) as u16 | 1024, 443)
+}
+
+// 40 seconds of SYNs at a steady rate, a quarter of them retransmissions of
+// an earlier SYN. Generated as they're used, as both trackers hold millions
+// of flows at the higher rates anyway.
+const SYN_SECS: u64 = 40;
+
+fn syns(per_sec: u64) -> impl Iterator<Item = (u64, Flow)>
+{
+    let mut x: u64 = 1;
+    (0..per_sec * SYN_SECS).map(move |i| {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let f = match (x >> 60) & 3 {
+            0 if i > 0 => flow((x >> 20) % i),
+            _ => flow(i),
+        };
+        (i * SEC / per_sec, f)
+    })
+}
+
+struct QueueTracker
+{
+    tracked: HashSet<Flow>,
+    drops: VecDeque<(u64, Flow)>,
+}
+
+impl QueueTracker
+{
+    fn syn(&mut self, now: u64, f: Flow)
+    {
+        self.drops.push_back((now + TIMEOUT_TRACKED_NS, f));
+        self.tracked.insert(f);
+    }
+
+    fn cleanup(&mut self, now: u64) -> usize
+    {
+        let before = self.tracked.len();
+        while let Some(&(t, f)) = self.drops.front() {
+            if t > now {
+                break;
+            }
+            self.drops.pop_front();
+            self.tracked.remove(&f);
+        }
+        before - self.tracked.len()
+    }
+}
+
+fn bench_syn_tracking(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("syn_tracking");
+    group.sample_size(10);
+    for &per_sec in &[10_000u64, 100_000] {
+        group.throughput(Throughput::Elements(per_sec * SYN_SECS));
+
+        group.bench_with_input(BenchmarkId::new("queue", per_sec), &per_sec, |b, &per_sec| {
+            b.iter(|| {
+                let mut t = QueueTracker { tracked: HashSet::new(), drops: VecDeque::new() };
+                let mut next_cleanup = CLEANUP_NS;
+                let mut dropped = 0;
+                for (now, f) in syns(per_sec) {
+                    if now >= next_cleanup {
+                        dropped += t.cleanup(now);
+                        next_cleanup += CLEANUP_NS;
+                    }
+                    t.syn(now, f);
+                }
+                dropped
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("wheel", per_sec), &per_sec, |b, &per_sec| {
+            b.iter(|| {
+                let mut w = TimerWheel::new(DEFAULT_TICK_NS, 0);
+                let mut next_cleanup = CLEANUP_NS;
+                let mut dropped = 0;
+                for (now, f) in syns(per_sec) {
+                    if now >= next_cleanup {
+                        dropped += w.expire(now, |_| {});
+                        next_cleanup += CLEANUP_NS;
+                    }
+                    w.insert(f, now + TIMEOUT_TRACKED_NS);
+                }
+                dropped
+            })
+        });
+    }
+    group.finish();
+}
+
+fn client(i: u64) -> Ipv4Addr
+{
+    Ipv4Addr::from(0x0a00_0000 | i as u32)
+}
+
+// How the keys used to be made
+fn string_key(i: u64) -> String
+{
+    format!("{}-192.0.2.1:443", client(i))
+}
+
+fn session_key(i: u64) -> SessionKey
+{
+    SessionKey::new(IpAddr::V4(client(i)), IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 443, Transport::Tcp)
+}
+
+fn session_table(n: u64) -> Arc<SessionTable>
+{
+    // Well clear of the 7/8 it stops at
+    Arc::new(SessionTable::new(2 * n as usize))
+}
+
+// One cleanup with n sessions, timeouts spread over five minutes, so a
+// cleanup expires about one in 3000.
+fn bench_session_cleanup(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("session_cleanup");
+    for &n in &[10_000u64, 100_000, 1_000_000] {
+        let deadline = |i: u64| SEC + (i * 7919) % (300 * SEC);
+        let now = SEC + CLEANUP_NS;
+        group.throughput(Throughput::Elements(n));
+
+        group.bench_function(BenchmarkId::new("retain", n), |b| {
+            b.iter_batched(|| (0..n).map(|i| (string_key(i), deadline(i))).collect::<HashMap<_, _>>(),
+                           |mut map| {
+                               map.retain(|_, v| *v > now);
+                               map
+                           },
+                           BatchSize::LargeInput)
+        });
+
+        // The sessions are added at SEC on the capture clock, so each run
+        // starts far enough past the last one's that all of these are new.
+        let start = Cell::new(0);
+        group.bench_function(BenchmarkId::new("tracker", n), |b| {
+            b.iter_batched(|| {
+                               start.set(start.get() + 1000 * SEC);
+                               clock::follow_capture(start.get() + SEC);
+                               let mut st = SessionTracker::with_table(session_table(n), true);
+                               for i in 0..n {
+                                   let client = client(i).to_string();
+                                   st.add_session(SessionDetails::new(&client, "192.0.2.1", 443,
+                                                                      deadline(i) - SEC).unwrap());
+                               }
+                               st
+                           },
+                           |mut st| {
+                               clock::follow_capture(start.get() + now);
+                               st.drop_stale_sessions();
+                               st
+                           },
+                           BatchSize::PerIteration)
+        });
+    }
+    group.finish();
+}
+
+// Refreshing a session on every forwarded packet (update_session)
+fn bench_session_refresh(c: &mut Criterion)
+{
+    let n = 100_000u64;
+    let keys: Vec<String> = (0..n).map(string_key).collect();
+    let mut group = c.benchmark_group("session_refresh");
+    group.throughput(Throughput::Elements(n));
+
+    group.bench_function("map", |b| {
+        let mut map: HashMap<String, u64> = keys.iter().map(|k| (k.clone(), SEC)).collect();
+        let mut now = SEC;
+        b.iter(|| {
+            now += MS;
+            for k in &keys {
+                if let Some(v) = map.get_mut(k) {
+                    if *v < now + 300 * SEC {
+                        *v = now + 300 * SEC;
+                    }
+                }
+            }
+        })
+    });
+
+    // As the cores do, without the lock, leaving the tracker's wheel to
+    // catch up when the old deadline comes due
+    group.bench_function("table", |b| {
+        let table = session_table(n);
+        let keys: Vec<SessionKey> = (0..n).map(session_key).collect();
+        for k in &keys {
+            table.insert(*k, SEC).unwrap();
+        }
+        let mut now = SEC;
+        b.iter(|| {
+            now += MS;
+            for k in &keys {
+                table.extend(k, now + 300 * SEC);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_syn_tracking, bench_session_cleanup, bench_session_refresh);
+criterion_main!(benches);
@@ -0,0 +1,79 @@
+// Looking up a packet's session: the typed, FxHash'd SessionKey against the
+// strings SessionTracker used to build with format!() for every packet.
+//
+//     cargo bench --bench session_keys
+
+#[macro_use]
+extern crate criterion;
+extern crate rust_dark_decoy;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+
+use rust_dark_decoy::flow_tracker::{FlowNoSrcPort, Transport};
+use rust_dark_decoy::sessions::SessionKey;
+use rust_dark_decoy::util::FxBuildHasher;
+
+const SESSIONS: u32 = 100_000;
+// Half the lookups are for flows with a session
+const LOOKUPS: u32 = 2 * SESSIONS;
+
+// How the keys used to be made
+fn string_key(flow: &FlowNoSrcPort) -> String
+{
+    let suffix = match flow.transport {
+        Transport::Tcp => "",
+        Transport::Udp => "/udp",
+    };
+    match flow.dst_ip.is_ipv6() {
+        true => format!("{}{}", SocketAddr::new(flow.dst_ip, flow.dst_port), suffix),
+        false => format!("{}-{}:{}{}", flow.src_ip, flow.dst_ip, flow.dst_port, suffix),
+    }
+}
+
+fn flows(v6: bool) -> Vec<FlowNoSrcPort>
+{
+    (0..LOOKUPS).map(|i| {
+        let (client, phantom) = match v6 {
+            true => (IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, i as u16)),
+                     IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 2, 0, 0, 0, (i >> 16) as u16, i as u16))),
+            false => (IpAddr::V4(Ipv4Addr::from(0x0a00_0000 | i)),
+                      IpAddr::V4(Ipv4Addr::from(0xc000_0200 | (i & 0xff)))),
+        };
+        FlowNoSrcPort::from_parts(client, phantom, 443, Transport::Tcp)
+    }).collect()
+}
+
+fn bench_lookup(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("session_lookup");
+    group.throughput(Throughput::Elements(LOOKUPS as u64));
+    for &v6 in &[false, true] {
+        let flows = flows(v6);
+        let family = if v6 { "v6" } else { "v4" };
+
+        let strings: HashMap<String, u64> =
+            flows.iter().step_by(2).map(|f| (string_key(f), 0)).collect();
+        group.bench_with_input(BenchmarkId::new("string", family), &flows, |b, flows| {
+            b.iter(|| flows.iter().filter(|f| strings.contains_key(&string_key(f))).count())
+        });
+
+        let sip: HashMap<SessionKey, u64> =
+            flows.iter().step_by(2).map(|f| (SessionKey::from_flow(f), 0)).collect();
+        group.bench_with_input(BenchmarkId::new("typed_sip", family), &flows, |b, flows| {
+            b.iter(|| flows.iter().filter(|f| sip.contains_key(&SessionKey::from_flow(f))).count())
+        });
+
+        let fx: HashMap<SessionKey, u64, FxBuildHasher> =
+            flows.iter().step_by(2).map(|f| (SessionKey::from_flow(f), 0)).collect();
+        group.bench_with_input(BenchmarkId::new("typed_fx", family), &flows, |b, flows| {
+            b.iter(|| flows.iter().filter(|f| fx.contains_key(&SessionKey::from_flow(f))).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
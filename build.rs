@@ -1,8 +0,0 @@
-extern crate cc;
-
-fn main() {
-    cc::Build::new()
-                .file("libtapdance/tapdance.c")
-                .include("src")
-                .compile("libtapdance.a");
-}
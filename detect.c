@@ -54,6 +54,11 @@ size_t write_reporter(uint8_t* buf, size_t len);
 #define NO_ZC_BUFFER_LEN 9000
 #define MAX_NUM_FORKED_PROCS 256
 pid_t g_forked_pids[MAX_NUM_FORKED_PROCS];
+pid_t g_session_ingest_pid = 0;
+// When the session ingest was last started, and when to start it again if
+// it's died (0 if it hasn't)
+time_t g_session_ingest_started = 0;
+time_t g_session_ingest_restart = 0;
 #ifdef TAPDANCE_USE_PF_RING_ZERO_COPY
 pfring_zc_queue* g_ring = 0;
 pfring_zc_buffer_pool* g_pool = 0;
@@ -67,6 +72,7 @@ void* g_rust_cli_conf_proto_ptr = 0;
 void* g_rust_failed_map = 0;
 int g_update_cli_conf_when_convenient = 0;
 int g_update_overloaded_decoys_when_convenient = 0;
+int g_reload_config_when_convenient = 0;
 
 #define TIMESPEC_DIFF(a, b) ((a.tv_sec - b.tv_sec)*1000000000LL + \
                              ((int64_t)a.tv_nsec - (int64_t)b.tv_nsec))
@@ -174,6 +180,11 @@ void the_program(uint8_t core_id, unsigned int log_interval,
                 g_update_overloaded_decoys_when_convenient = 0;
                 //rust_update_overloaded_decoys(rust_ptr);
             }
+            if(unlikely(g_reload_config_when_convenient))
+            {
+                g_reload_config_when_convenient = 0;
+                rust_reload_config(rust_ptr);
+            }
         }
         if(unlikely(ns_since_status_report > log_interval_ns))
         {
@@ -208,6 +219,20 @@ static void notify_overloaded_decoys_file_update(int sig, siginfo_t* si,
     g_update_overloaded_decoys_when_convenient = 1;
 }
 
+static void notify_config_reload(int sig, siginfo_t* si, void* junk)
+{
+    g_reload_config_when_convenient = 1;
+}
+
+// SIGHUP to the parent reloads the prefix list and station config in every
+// child.
+void sighup_parent(int sig)
+{
+    int i;
+    for(i=0; i<g_num_worker_procs; i++)
+        kill(g_forked_pids[i], SIGHUP);
+}
+
 void sigproc_child(int sig)
 {
     static char called = 0;
@@ -239,8 +264,12 @@ void sigproc_parent(int sig)
     int i, junk;
     for(i=0; i<g_num_worker_procs; i++)
         kill(g_forked_pids[i], SIGTERM);
+    if(g_session_ingest_pid > 0)
+        kill(g_session_ingest_pid, SIGTERM);
     for(i=0; i<g_num_worker_procs; i++)
         waitpid(g_forked_pids[i], &junk, 0);
+    if(g_session_ingest_pid > 0)
+        waitpid(g_session_ingest_pid, &junk, 0);
     fprintf(stderr, "PF_RING Tapdance done shutting down!\n");
     exit(0);
 }
@@ -343,6 +372,64 @@ pid_t start_tapdance_process(int core_affinity, unsigned int cluster_id,
     return the_pid;
 }
 
+// Start the process that fills the phantom session table the cores share
+// (detector_session_table) from redis, and expires sessions out of it. It
+// exits 0 if the config has each core get its own sessions instead.
+pid_t start_session_ingest()
+{
+    pid_t the_pid = fork();
+    if(the_pid == 0)
+    {
+        // A restarted one is forked after main sets these up, and they'd
+        // have it stop or reload the workers
+        signal(SIGINT, SIG_DFL);
+        signal(SIGTERM, SIG_DFL);
+        signal(SIGHUP, SIG_DFL);
+        signal(SIGPIPE, ignore_sigpipe);
+        exit(rust_session_ingest());
+    }
+    printf("Session ingest: PID %d\n", the_pid);
+    g_session_ingest_started = time(NULL);
+    return the_pid;
+}
+
+// If the session ingest dies, no core gets new sessions or loses old ones, so
+// it's started again (by main's wait loop): straight away at first, then
+// waiting twice as long each time (up to a minute) while it keeps dying
+// within a minute of starting.
+#define SESSION_INGEST_MAX_BACKOFF_S 60
+
+void schedule_session_ingest_restart()
+{
+    static unsigned int backoff = 0;
+
+    if(time(NULL) - g_session_ingest_started < SESSION_INGEST_MAX_BACKOFF_S)
+        backoff = backoff ? backoff * 2 : 1;
+    else
+        backoff = 0;
+    if(backoff > SESSION_INGEST_MAX_BACKOFF_S)
+        backoff = SESSION_INGEST_MAX_BACKOFF_S;
+    printf("Restarting the session ingest in %us\n", backoff);
+    g_session_ingest_restart = time(NULL) + backoff;
+}
+
+void print_wait_status(int wait_status)
+{
+    if (WIFEXITED(wait_status))
+        printf("exited, status=%d\n", WEXITSTATUS(wait_status));
+    else if (WIFSIGNALED(wait_status))
+        if (WCOREDUMP(wait_status))
+            printf("killed by signal %d -- Coredump created\n", WTERMSIG(wait_status));
+        else
+            printf("killed by signal %d\n", WTERMSIG(wait_status));
+    else if (WIFSTOPPED(wait_status))
+        printf("stopped by signal %d\n", WSTOPSIG(wait_status));
+    else if (WIFCONTINUED(wait_status))
+        printf("continued\n");
+    else
+        printf("...not sure what happened!\n");
+}
+
 struct cmd_options
 {
     // Number of cores to spread across.
@@ -592,7 +679,14 @@ int main(int argc, char* argv[])
     sa2.sa_sigaction = notify_overloaded_decoys_file_update;
     sigaction(SIGUSR2, &sa2, NULL);
 
+    struct sigaction sa3;
+    sa3.sa_flags = SA_SIGINFO; // use sa_sigaction, not sa_handler
+    sigemptyset(&sa3.sa_mask);
+    sa3.sa_sigaction = notify_config_reload;
+    sigaction(SIGHUP, &sa3, NULL);
+
     handle_zmq_proxy(options.zmq_address, options.zmq_worker_address);
+    g_session_ingest_pid = start_session_ingest();
 
     int i;
     int core_num = options.core_affinity_offset;
@@ -608,32 +702,54 @@ int main(int argc, char* argv[])
     }
     signal(SIGINT, sigproc_parent);
     signal(SIGTERM, sigproc_parent);
+    signal(SIGHUP, sighup_parent);
 
-    int wait_status = 0, wait_ret = 0, wait_errno = 0;
-    for(i=0; i<g_num_worker_procs; i++)
+    // Wait for the workers, restarting the session ingest whenever it dies
+    // in the meantime
+    int wait_status = 0, wait_errno = 0, workers_left = g_num_worker_procs;
+    pid_t wait_ret;
+    while(workers_left > 0)
     {
-        do
+        if (g_session_ingest_restart && time(NULL) >= g_session_ingest_restart)
         {
-            wait_ret = waitpid(g_forked_pids[i], &wait_status, 0);
-            wait_errno = errno;
-            if (wait_ret == -1 && wait_errno != EINTR)
-                perror("waitpid");
-        } while (wait_ret == -1 && wait_errno == EINTR);
+            g_session_ingest_restart = 0;
+            g_session_ingest_pid = start_session_ingest();
+        }
+        // Don't block while a restart is due, but don't spin either
+        wait_ret = waitpid(-1, &wait_status, g_session_ingest_restart ? WNOHANG : 0);
+        wait_errno = errno;
+        if (wait_ret == 0)
+        {
+            sleep(1);
+            continue;
+        }
+        if (wait_ret == -1)
+        {
+            if (wait_errno == EINTR)
+                continue;
+            perror("waitpid");
+            break;
+        }
+
+        if (wait_ret == g_session_ingest_pid)
+        {
+            printf("...session ingest ");
+            print_wait_status(wait_status);
+            g_session_ingest_pid = 0;
+            if (!WIFEXITED(wait_status) || WEXITSTATUS(wait_status) != 0)
+                schedule_session_ingest_restart();
+            continue;
+        }
+        for(i=0; i<g_num_worker_procs; i++)
+            if (g_forked_pids[i] == wait_ret)
+                break;
+        // The ZMQ proxy
+        if (i == g_num_worker_procs)
+            continue;
 
         printf("...child proc %d ", i);
-        if (WIFEXITED(wait_status))
-            printf("exited, status=%d\n", WEXITSTATUS(wait_status));
-        else if (WIFSIGNALED(wait_status))
-            if (WCOREDUMP(wait_status))
-                printf("killed by signal %d -- Coredump created\n", WTERMSIG(wait_status));
-            else
-                printf("killed by signal %d\n", WTERMSIG(wait_status));
-        else if (WIFSTOPPED(wait_status))
-            printf("stopped by signal %d\n", WSTOPSIG(wait_status));
-        else if (WIFCONTINUED(wait_status))
-            printf("continued\n");
-        else
-            printf("...not sure what happened!\n");
+        print_wait_status(wait_status);
+        workers_left--;
     }
     sigproc_parent(SIGTERM);
     return 0;
@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+#[macro_use] extern crate arrayref;
+extern crate rust_dark_decoy;
+
+use rust_dark_decoy::curve25519;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 64 {
+        return;
+    }
+    curve25519::shared_secret_from_representative(array_ref![data, 0, 32],
+                                                  array_ref![data, 32, 32]);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate rust_dark_decoy;
+
+use rust_dark_decoy::elligator;
+
+// Fixed station key; the interesting input is the TLS record.
+const STATION_PRIVKEY: [u8; 32] = [
+    224, 192, 103, 26, 96, 135, 130, 174, 250, 208, 30, 113, 46, 128, 127, 111,
+    215, 199, 5, 141, 38, 124, 34, 127, 102, 142, 245, 81, 49, 70, 119, 119];
+
+fuzz_target!(|data: &[u8]| {
+    let _ = elligator::extract_payloads(&STATION_PRIVKEY, data);
+});
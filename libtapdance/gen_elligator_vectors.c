@@ -0,0 +1,64 @@
+// Prints test vectors for the native Rust tag decoder (src/curve25519.rs):
+//   <station privkey> <representative> <decoded point> <shared secret>
+// one line per vector, all hex. Uses a fixed-seed PRNG so output is stable.
+//
+// Usage: gen_elligator_vectors [count]
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "elligator2.h"
+
+int curve25519_donna(unsigned char *, const unsigned char *, const unsigned char *);
+
+static uint64_t prng_state = 0x9e3779b97f4a7c15ULL;
+
+static unsigned char prng_byte()
+{
+    prng_state ^= prng_state << 13;
+    prng_state ^= prng_state >> 7;
+    prng_state ^= prng_state << 17;
+    return prng_state >> 24;
+}
+
+static void print_hex(const unsigned char *buf)
+{
+    int i;
+    for (i = 0; i < 32; i++)
+        printf("%02x", buf[i]);
+}
+
+int main(int argc, char **argv)
+{
+    int count = (argc > 1) ? atoi(argv[1]) : 8;
+    int i, j;
+
+    for (i = 0; i < count; i++) {
+        unsigned char privkey[32], repr[32], point[32], secret[32];
+        for (j = 0; j < 32; j++) {
+            privkey[j] = prng_byte();
+            repr[j] = prng_byte();
+        }
+        // Clients randomize the top two bits; the station clears them.
+        repr[31] &= 0x3f;
+
+        // Edge cases: r = 0 and r = 1
+        if (i == 0)
+            memset(repr, 0, sizeof(repr));
+        if (i == 1) {
+            memset(repr, 0, sizeof(repr));
+            repr[0] = 1;
+        }
+
+        decode(point, repr);
+        curve25519_donna(secret, privkey, point);
+
+        print_hex(privkey); printf(" ");
+        print_hex(repr); printf(" ");
+        print_hex(point); printf(" ");
+        print_hex(secret); printf("\n");
+    }
+    return 0;
+}
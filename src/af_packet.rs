@@ -0,0 +1,458 @@
+//
+// AF_PACKET (TPACKET_V3) capture
+//
+// An alternative to PF_RING for feeding the detector: the kernel fills a ring
+// of blocks mmap'd into our address space, and hands us a block at a time
+// once it's full or a short timeout passes. With a fanout group, every
+// detector process opens its own ring on the same interface and the kernel
+// hashes flows across them, so a connection's packets always reach the same
+// core (like zbalance_ipc's clusters).
+//
+// See detector-afpacket.rs for the capture loop built on this.
+//
+
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{fence, Ordering};
+
+use libc;
+
+// From linux/if_packet.h and linux/if_ether.h
+const SOL_PACKET: libc::c_int = 263;
+const PACKET_RX_RING: libc::c_int = 5;
+const PACKET_STATISTICS: libc::c_int = 6;
+const PACKET_VERSION: libc::c_int = 10;
+const PACKET_FANOUT: libc::c_int = 18;
+const TPACKET_V3: libc::c_int = 2;
+const PACKET_FANOUT_HASH: u32 = 0;
+const PACKET_FANOUT_FLAG_DEFRAG: u32 = 0x8000;
+const PACKET_OUTGOING: u8 = 4;
+const ETH_P_ALL: u16 = 0x0003;
+
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1;
+
+// struct tpacket_block_desc / tpacket_hdr_v1 offsets
+const BLOCK_STATUS: usize = 8;
+const BLOCK_NUM_PKTS: usize = 12;
+const BLOCK_FIRST_PKT: usize = 16;
+
+// struct tpacket3_hdr offsets, and where the sockaddr_ll after it starts
+const PKT_NEXT_OFFSET: usize = 0;
+const PKT_SNAPLEN: usize = 12;
+const PKT_MAC: usize = 24;
+const PKT_HDR_LEN: usize = 48;
+const SLL_PKTTYPE: usize = 10;
+const SLL_LEN: usize = 20;
+
+#[repr(C)]
+struct TpacketReq3
+{
+    tp_block_size: u32,
+    tp_block_nr: u32,
+    tp_frame_size: u32,
+    tp_frame_nr: u32,
+    tp_retire_blk_tov: u32,
+    tp_sizeof_priv: u32,
+    tp_feature_req_word: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct TpacketStatsV3
+{
+    tp_packets: u32,
+    tp_drops: u32,
+    tp_freeze_q_cnt: u32,
+}
+
+pub struct RingConfig
+{
+    // Block size must be a multiple of the page size; the ring is
+    // block_size * block_nr bytes.
+    pub block_size: u32,
+    pub block_nr: u32,
+    // Upper bound on a captured frame (including headers)
+    pub frame_size: u32,
+    // How long the kernel holds on to a block that isn't full yet. On a quiet
+    // link this is how late phantom packets get forwarded.
+    pub block_timeout_ms: u32,
+}
+
+impl Default for RingConfig
+{
+    fn default() -> RingConfig
+    {
+        RingConfig {
+            block_size: 1 << 22,
+            block_nr: 64,
+            frame_size: 1 << 11,
+            block_timeout_ms: 2,
+        }
+    }
+}
+
+// Cumulative since the ring was opened
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct RingStats
+{
+    pub packets: u64,
+    pub drops: u64,
+}
+
+pub struct AfPacketRing
+{
+    fd: libc::c_int,
+    map: *mut u8,
+    block_size: usize,
+    block_nr: usize,
+    cur_block: usize,
+    stats: RingStats,
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32
+{
+    let mut b = [0u8; 4];
+    b.copy_from_slice(&buf[off..off + 4]);
+    u32::from_ne_bytes(b)
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16
+{
+    let mut b = [0u8; 2];
+    b.copy_from_slice(&buf[off..off + 2]);
+    u16::from_ne_bytes(b)
+}
+
+// Calls f with every (incoming) frame in a block the kernel has handed us.
+// Returns how many frames were passed to f. Anything inconsistent ends the
+// walk rather than reading outside the block.
+pub fn walk_block<F: FnMut(&[u8])>(block: &[u8], f: &mut F) -> usize
+{
+    if block.len() < BLOCK_FIRST_PKT + 4 {
+        return 0;
+    }
+    let num_pkts = read_u32(block, BLOCK_NUM_PKTS);
+    let mut off = read_u32(block, BLOCK_FIRST_PKT) as usize;
+    let mut seen = 0;
+    for _ in 0..num_pkts {
+        if off + PKT_HDR_LEN + SLL_LEN > block.len() {
+            break;
+        }
+        let snaplen = read_u32(block, off + PKT_SNAPLEN) as usize;
+        let mac = read_u16(block, off + PKT_MAC) as usize;
+        let pkttype = block[off + PKT_HDR_LEN + SLL_PKTTYPE];
+        let start = off + mac;
+        if start + snaplen > block.len() {
+            break;
+        }
+        if pkttype != PACKET_OUTGOING {
+            f(&block[start..start + snaplen]);
+            seen += 1;
+        }
+        let next = read_u32(block, off + PKT_NEXT_OFFSET) as usize;
+        if next == 0 {
+            break;
+        }
+        off += next;
+    }
+    seen
+}
+
+fn setsockopt<T>(fd: libc::c_int, opt: libc::c_int, val: &T) -> io::Result<()>
+{
+    let rc = unsafe {
+        libc::setsockopt(fd, SOL_PACKET, opt, val as *const T as *const libc::c_void,
+                         mem::size_of::<T>() as libc::socklen_t)
+    };
+    match rc {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+impl AfPacketRing
+{
+    // Opens a TPACKET_V3 ring on iface. Processes that pass the same
+    // fanout_group share the interface's traffic, split by flow.
+    pub fn open(iface: &str, fanout_group: Option<u16>, conf: &RingConfig)
+        -> io::Result<AfPacketRing>
+    {
+        let name = CString::new(iface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "bad interface name"))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe {
+            libc::socket(libc::AF_PACKET, libc::SOCK_RAW, ETH_P_ALL.to_be() as libc::c_int)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // From here on the ring owns fd (and later the mapping), so errors
+        // clean up through Drop.
+        let mut ring = AfPacketRing {
+            fd,
+            map: ptr::null_mut(),
+            block_size: conf.block_size as usize,
+            block_nr: conf.block_nr as usize,
+            cur_block: 0,
+            stats: RingStats::default(),
+        };
+
+        setsockopt(fd, PACKET_VERSION, &TPACKET_V3)?;
+        let req = TpacketReq3 {
+            tp_block_size: conf.block_size,
+            tp_block_nr: conf.block_nr,
+            tp_frame_size: conf.frame_size,
+            tp_frame_nr: (conf.block_size / conf.frame_size) * conf.block_nr,
+            tp_retire_blk_tov: conf.block_timeout_ms,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        setsockopt(fd, PACKET_RX_RING, &req)?;
+
+        let map = unsafe {
+            libc::mmap(ptr::null_mut(), ring.block_size * ring.block_nr,
+                       libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0)
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        ring.map = map as *mut u8;
+
+        let mut sll: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        sll.sll_family = libc::AF_PACKET as u16;
+        sll.sll_protocol = ETH_P_ALL.to_be();
+        sll.sll_ifindex = ifindex as i32;
+        let rc = unsafe {
+            libc::bind(fd, &sll as *const libc::sockaddr_ll as *const libc::sockaddr,
+                       mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t)
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Some(group) = fanout_group {
+            let arg: u32 = group as u32
+                | ((PACKET_FANOUT_HASH | PACKET_FANOUT_FLAG_DEFRAG) << 16);
+            setsockopt(fd, PACKET_FANOUT, &arg)?;
+        }
+        Ok(ring)
+    }
+
+    fn block(&self, i: usize) -> *mut u8
+    {
+        unsafe { self.map.add(i * self.block_size) }
+    }
+
+    fn block_ready(&self, i: usize) -> bool
+    {
+        let status = unsafe {
+            ptr::read_volatile(self.block(i).add(BLOCK_STATUS) as *const u32)
+        };
+        fence(Ordering::Acquire);
+        status & TP_STATUS_USER != 0
+    }
+
+    // Passes every frame that has arrived to f, waiting up to timeout_ms
+    // for some if there are none yet. Returns how many frames f saw.
+    pub fn poll<F: FnMut(&[u8])>(&mut self, timeout_ms: i32, mut f: F) -> io::Result<usize>
+    {
+        if !self.block_ready(self.cur_block) {
+            let mut pfd = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN | libc::POLLERR,
+                revents: 0,
+            };
+            let rc = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+            if rc < 0 {
+                let e = io::Error::last_os_error();
+                return match e.kind() {
+                    io::ErrorKind::Interrupted => Ok(0),
+                    _ => Err(e),
+                };
+            }
+        }
+
+        let mut seen = 0;
+        // At most one lap, so a flood can't keep us from the periodic work
+        for _ in 0..self.block_nr {
+            if !self.block_ready(self.cur_block) {
+                break;
+            }
+            let block = self.block(self.cur_block);
+            {
+                let buf = unsafe { slice::from_raw_parts(block as *const u8, self.block_size) };
+                seen += walk_block(buf, &mut f);
+            }
+            fence(Ordering::Release);
+            unsafe {
+                ptr::write_volatile(block.add(BLOCK_STATUS) as *mut u32, TP_STATUS_KERNEL);
+            }
+            self.cur_block = (self.cur_block + 1) % self.block_nr;
+        }
+        Ok(seen)
+    }
+
+    // The kernel's counters reset every time they're read, so we keep the
+    // running totals.
+    pub fn stats(&mut self) -> io::Result<RingStats>
+    {
+        let mut st = TpacketStatsV3::default();
+        let mut len = mem::size_of::<TpacketStatsV3>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(self.fd, SOL_PACKET, PACKET_STATISTICS,
+                             &mut st as *mut TpacketStatsV3 as *mut libc::c_void, &mut len)
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.stats.packets += st.tp_packets as u64;
+        self.stats.drops += st.tp_drops as u64;
+        Ok(self.stats)
+    }
+}
+
+impl Drop for AfPacketRing
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            if !self.map.is_null() {
+                libc::munmap(self.map as *mut libc::c_void, self.block_size * self.block_nr);
+            }
+            libc::close(self.fd);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use af_packet::*;
+    use std::process::{Command, Stdio};
+
+    // Lays frames out in a block the way the kernel does (each frame's
+    // tpacket3_hdr followed by its sockaddr_ll, then the frame at tp_mac).
+    fn make_block(frames: &[(&[u8], u8)]) -> Vec<u8>
+    {
+        let mut block = vec![0u8; 4096];
+        let first = 48;
+        block[12..16].copy_from_slice(&(frames.len() as u32).to_ne_bytes());
+        block[16..20].copy_from_slice(&(first as u32).to_ne_bytes());
+        let mut off = first;
+        for (i, &(frame, pkttype)) in frames.iter().enumerate() {
+            let mac = 48 + 20 + 2; // header, sockaddr_ll, padding
+            let len = (mac + frame.len() + 15) & !15;
+            let next = if i + 1 == frames.len() { 0 } else { len as u32 };
+            block[off..off + 4].copy_from_slice(&next.to_ne_bytes());
+            block[off + 12..off + 16].copy_from_slice(&(frame.len() as u32).to_ne_bytes());
+            block[off + 24..off + 26].copy_from_slice(&(mac as u16).to_ne_bytes());
+            block[off + 48 + 10] = pkttype;
+            block[off + mac..off + mac + frame.len()].copy_from_slice(frame);
+            off += len;
+        }
+        block
+    }
+
+    #[test]
+    fn test_walk_block()
+    {
+        let block = make_block(&[(b"first frame", 0), (b"sent by us", 4), (b"third", 0)]);
+        let mut frames = Vec::new();
+        let n = walk_block(&block, &mut |f: &[u8]| frames.push(f.to_vec()));
+        assert_eq!(n, 2);
+        assert_eq!(frames, vec![b"first frame".to_vec(), b"third".to_vec()]);
+
+        // A block claiming more than it holds stops at the end
+        let mut bad = make_block(&[(b"only one", 0)]);
+        bad[12..16].copy_from_slice(&5u32.to_ne_bytes());
+        bad[0x30..0x34].copy_from_slice(&4000u32.to_ne_bytes());
+        let n = walk_block(&bad, &mut |_: &[u8]| {});
+        assert_eq!(n, 1);
+
+        assert_eq!(walk_block(&[0u8; 8], &mut |_: &[u8]| {}), 0);
+    }
+
+    fn ip(args: &[&str]) -> bool
+    {
+        Command::new("ip").args(args).stderr(Stdio::null()).status()
+            .map(|s| s.success()).unwrap_or(false)
+    }
+
+    fn send_frame(iface: &str, frame: &[u8])
+    {
+        let name = CString::new(iface).unwrap();
+        unsafe {
+            let fd = libc::socket(libc::AF_PACKET, libc::SOCK_RAW, 0);
+            assert!(fd >= 0);
+            let mut sll: libc::sockaddr_ll = mem::zeroed();
+            sll.sll_family = libc::AF_PACKET as u16;
+            sll.sll_ifindex = libc::if_nametoindex(name.as_ptr()) as i32;
+            sll.sll_halen = 6;
+            let rc = libc::sendto(fd, frame.as_ptr() as *const libc::c_void, frame.len(), 0,
+                                  &sll as *const libc::sockaddr_ll as *const libc::sockaddr,
+                                  mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t);
+            assert_eq!(rc, frame.len() as isize);
+            libc::close(fd);
+        }
+    }
+
+    // Needs root (CAP_NET_ADMIN and CAP_NET_RAW):
+    //   sudo cargo test -- --ignored af_packet
+    #[test]
+    #[ignore]
+    fn test_veth()
+    {
+        let (a, b) = ("cjtest0", "cjtest1");
+        let _ = ip(&["link", "del", a]);
+        assert!(ip(&["link", "add", a, "type", "veth", "peer", "name", b]));
+        assert!(ip(&["link", "set", a, "up"]));
+        assert!(ip(&["link", "set", b, "up"]));
+
+        let conf = RingConfig { block_size: 1 << 16, block_nr: 4, ..Default::default() };
+        let mut rings = vec![AfPacketRing::open(b, Some(0x4a17), &conf).unwrap(),
+                             AfPacketRing::open(b, Some(0x4a17), &conf).unwrap()];
+
+        let mut got = Vec::new();
+        let mut poll_all = |rings: &mut Vec<AfPacketRing>, timeout| {
+            for ring in rings.iter_mut() {
+                ring.poll(timeout, |f| {
+                    if f.len() > 14 && f[12..14] == [0x88, 0xb5] {
+                        got.push(f.to_vec());
+                    }
+                }).unwrap();
+            }
+        };
+
+        // Ethernet frames with an unused ethertype so the stack ignores them.
+        // The rings are small, so keep draining them as we go, like the
+        // detector would.
+        let mut sent = Vec::new();
+        for i in 0..64u8 {
+            let mut frame = vec![0xffu8; 6];
+            frame.extend_from_slice(&[0x02, 0, 0, 0, 0, i]);
+            frame.extend_from_slice(&[0x88, 0xb5]);
+            frame.extend_from_slice(format!("conjure test frame {}", i).as_bytes());
+            send_frame(a, &frame);
+            sent.push(frame);
+            poll_all(&mut rings, 0);
+        }
+        for _ in 0..10 {
+            poll_all(&mut rings, 20);
+        }
+        let _ = ip(&["link", "del", a]);
+
+        got.sort();
+        sent.sort();
+        assert_eq!(got, sent);
+        let total: u64 = rings.iter_mut().map(|r| r.stats().unwrap().packets).sum();
+        assert!(total >= sent.len() as u64);
+    }
+}
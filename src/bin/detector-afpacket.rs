@@ -0,0 +1,348 @@
+// The detector without PF_RING: reads packets from AF_PACKET (TPACKET_V3)
+// rings and drives PerCoreGlobal on the same cadence as detect.c's
+// the_program (process every packet, clean up every 100ms, report every
+// log interval, reload on SIGHUP).
+//
+// Usage:
+//   detector-afpacket -i <iface> -K <keyfile> [-n <procs>] [-c <fanout group>]
+//                     [-o <core offset>] [-s <skip core>] [-z <lcore offset>]
+//                     [-l <log interval secs>] [-a <zmq address>]
+//                     [-w <zmq worker address>]
+//
+// Options mean the same as for dark-decoy, except -c: instead of a PF_RING
+// cluster it names the AF_PACKET fanout group the worker processes join, so
+// the kernel splits the interface's traffic between them by flow. Two
+// detectors on the same interface need different groups (or both see
+// everything). It defaults to one derived from our pid.
+//
+// Like dark-decoy, this forks a ZMQ proxy, the session ingest (restarted if
+// it dies) and one worker per core, and forwards SIGHUP/SIGINT/SIGTERM to the
+// workers. Needs CAP_NET_RAW (plus whatever the station itself needs for tun
+// devices).
+
+extern crate libc;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate rust_dark_decoy;
+extern crate zmq;
+
+use std::env;
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_dark_decoy::PerCoreGlobal;
+use rust_dark_decoy::af_packet::{AfPacketRing, RingConfig};
+use rust_dark_decoy::key_ring;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_millis(100);
+const POLL_TIMEOUT_MS: i32 = 10;
+// The session ingest is restarted after twice as long each time (up to this)
+// while it keeps dying within this long of starting
+const INGEST_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// The same flags mean "reload"/"stop" in workers and "tell the workers to"
+// in the parent, so one set of handlers serves both (as in detect.c).
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_sig: libc::c_int)
+{
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_stop(_sig: libc::c_int)
+{
+    STOP.store(true, Ordering::SeqCst);
+}
+
+fn set_handler(sig: libc::c_int, handler: extern "C" fn(libc::c_int))
+{
+    unsafe { libc::signal(sig, handler as libc::sighandler_t); }
+}
+
+struct Options
+{
+    iface: String,
+    key: [u8; 32],
+    procs: usize,
+    fanout_group: u16,
+    core_offset: usize,
+    skip_core: Option<usize>,
+    lcore_offset: i32,
+    log_interval: Duration,
+    zmq_address: String,
+    zmq_worker_address: String,
+}
+
+fn usage() -> !
+{
+    eprintln!("usage: detector-afpacket -i <iface> -K <keyfile> [-n <procs>] [-c <fanout group>] [-o <core offset>] [-s <skip core>] [-z <lcore offset>] [-l <log interval secs>] [-a <zmq address>] [-w <zmq worker address>]");
+    process::exit(2);
+}
+
+fn fail(msg: String) -> !
+{
+    eprintln!("detector-afpacket: {}", msg);
+    process::exit(1);
+}
+
+fn parse_args() -> Options
+{
+    let args: Vec<String> = env::args().collect();
+    let mut iface = None;
+    let mut key_path = None;
+    let mut opts = Options {
+        iface: String::new(),
+        key: [0; 32],
+        procs: 1,
+        fanout_group: (process::id() & 0xffff) as u16,
+        core_offset: 0,
+        skip_core: None,
+        lcore_offset: 0,
+        log_interval: Duration::from_secs(1),
+        zmq_address: "ipc://@detector".to_string(),
+        zmq_worker_address: "ipc://@detector-workers".to_string(),
+    };
+
+    let mut i = 1;
+    while i < args.len() {
+        let opt = args[i].as_str();
+        if opt == "-h" || opt == "--help" || i + 1 >= args.len() {
+            usage();
+        }
+        let val = args[i + 1].as_str();
+        match opt {
+            "-i" => iface = Some(val.to_string()),
+            "-K" => key_path = Some(val.to_string()),
+            "-n" => opts.procs = val.parse().unwrap_or_else(|_| usage()),
+            "-c" => opts.fanout_group = val.parse().unwrap_or_else(|_| usage()),
+            "-o" => opts.core_offset = val.parse().unwrap_or_else(|_| usage()),
+            "-s" => opts.skip_core = Some(val.parse().unwrap_or_else(|_| usage())),
+            "-z" => opts.lcore_offset = val.parse().unwrap_or_else(|_| usage()),
+            "-l" => opts.log_interval = Duration::from_secs(val.parse().unwrap_or_else(|_| usage())),
+            "-a" => opts.zmq_address = val.to_string(),
+            "-w" => opts.zmq_worker_address = val.to_string(),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    opts.iface = iface.unwrap_or_else(|| usage());
+    let key_path = key_path.unwrap_or_else(|| usage());
+    opts.key = key_ring::read_key_file(Path::new(&key_path)).unwrap_or_else(fail);
+    if opts.procs == 0 {
+        usage();
+    }
+    opts
+}
+
+fn set_affinity(core: usize)
+{
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            eprintln!("detector-afpacket: can't pin to core {}: {}", core,
+                      std::io::Error::last_os_error());
+        }
+    }
+}
+
+// Same as dark-decoy's handle_zmq_proxy: workers publish registrations to
+// zmq_worker_address, and this passes them on to whoever subscribes to
+// zmq_address.
+fn run_zmq_proxy(zmq_address: &str, zmq_worker_address: &str) -> Result<(), zmq::Error>
+{
+    let ctx = zmq::Context::new();
+    let mut publisher = ctx.socket(zmq::PUB)?;
+    println!("binding zmq socket to {}", zmq_address);
+    publisher.bind(zmq_address)?;
+
+    let mut workers = ctx.socket(zmq::SUB)?;
+    workers.set_subscribe(b"")?;
+    println!("binding zmq worker socket to {}", zmq_worker_address);
+    workers.bind(zmq_worker_address)?;
+
+    zmq::proxy(&mut workers, &mut publisher)
+}
+
+fn run_worker(opts: &Options, lcore: i32)
+{
+    let mut ring = AfPacketRing::open(&opts.iface, Some(opts.fanout_group), &RingConfig::default())
+        .unwrap_or_else(|e| fail(format!("can't open AF_PACKET ring on {}: {}", opts.iface, e)));
+    let mut global = PerCoreGlobal::init(lcore, opts.key, &opts.zmq_worker_address);
+    println!(">>>> starting core {}", lcore);
+
+    let mut drops_prev = 0;
+    let mut last_cleanup = Instant::now();
+    let mut last_report = Instant::now();
+    while !STOP.load(Ordering::SeqCst) {
+        if let Err(e) = ring.poll(POLL_TIMEOUT_MS, |frame| global.process_packet(frame)) {
+            fail(format!("reading from {}: {}", opts.iface, e));
+        }
+        global.event_loop_tick();
+
+        let now = Instant::now();
+        if now.duration_since(last_cleanup) > CLEANUP_INTERVAL {
+            last_cleanup = now;
+            global.periodic_cleanup();
+            if RELOAD.swap(false, Ordering::SeqCst) {
+                global.reload_config();
+            }
+        }
+        if now.duration_since(last_report) > opts.log_interval {
+            last_report = now;
+            global.periodic_report();
+            // Always report to gobbler (prometheus philosophy)
+            match ring.stats() {
+                Ok(stats) => {
+                    report!("drop {} {}", stats.drops - drops_prev, stats.drops);
+                    drops_prev = stats.drops;
+                },
+                Err(e) => eprintln!("detector-afpacket: can't read ring stats: {}", e),
+            }
+        }
+    }
+    println!("core {} exiting", lcore);
+}
+
+fn fork() -> libc::pid_t
+{
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        fail(format!("fork failed: {}", std::io::Error::last_os_error()));
+    }
+    pid
+}
+
+// Same as dark-decoy's start_session_ingest: fills the session table the
+// workers share from redis, and expires sessions out of it. Exits 0 if the
+// config has each worker get its own sessions instead.
+fn start_session_ingest() -> libc::pid_t
+{
+    let pid = fork();
+    if pid == 0 {
+        // Our handlers would have it stop or reload the workers
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_DFL);
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+        }
+        process::exit(rust_dark_decoy::rust_session_ingest());
+    }
+    println!("Session ingest: PID {}", pid);
+    pid
+}
+
+fn print_status(what: &str, pid: libc::pid_t, status: libc::c_int)
+{
+    if libc::WIFEXITED(status) {
+        println!("...{} {} exited, status={}", what, pid, libc::WEXITSTATUS(status));
+    } else if libc::WIFSIGNALED(status) {
+        println!("...{} {} killed by signal {}", what, pid, libc::WTERMSIG(status));
+    }
+}
+
+fn main()
+{
+    let opts = parse_args();
+
+    set_handler(libc::SIGHUP, on_sighup);
+    set_handler(libc::SIGINT, on_stop);
+    set_handler(libc::SIGTERM, on_stop);
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_IGN); }
+
+    let proxy = fork();
+    if proxy == 0 {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+        }
+        if let Err(e) = run_zmq_proxy(&opts.zmq_address, &opts.zmq_worker_address) {
+            fail(format!("zmq proxy: {}", e));
+        }
+        process::exit(0);
+    }
+
+    let mut ingest = Some(start_session_ingest());
+    let mut ingest_started = Instant::now();
+    let mut ingest_backoff = Duration::from_secs(0);
+    let mut ingest_restart = None;
+
+    let mut children = Vec::new();
+    let mut core = opts.core_offset;
+    for i in 0..opts.procs {
+        if Some(core) == opts.skip_core {
+            core += 1;
+        }
+        let lcore = i as i32 + opts.lcore_offset;
+        let pid = fork();
+        if pid == 0 {
+            set_affinity(core);
+            run_worker(&opts, lcore);
+            process::exit(0);
+        }
+        println!("Core {}: PID {}, lcore {}", lcore, pid, core);
+        children.push(pid);
+        core += 1;
+    }
+
+    let mut stopping = false;
+    while !children.is_empty() {
+        if RELOAD.swap(false, Ordering::SeqCst) {
+            for &pid in children.iter() {
+                unsafe { libc::kill(pid, libc::SIGHUP); }
+            }
+        }
+        if STOP.load(Ordering::SeqCst) && !stopping {
+            stopping = true;
+            for &pid in children.iter().chain(ingest.iter()) {
+                unsafe { libc::kill(pid, libc::SIGTERM); }
+            }
+            ingest_restart = None;
+        }
+        if ingest_restart.is_some_and(|at| Instant::now() >= at) {
+            ingest_restart = None;
+            ingest = Some(start_session_ingest());
+            ingest_started = Instant::now();
+        }
+
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+        if pid > 0 && ingest == Some(pid) {
+            ingest = None;
+            print_status("session ingest", pid, status);
+            let clean = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+            if !stopping && !clean {
+                // Without it no worker gets new sessions or loses old ones
+                ingest_backoff = if ingest_started.elapsed() < INGEST_MAX_BACKOFF {
+                    (ingest_backoff * 2).max(Duration::from_secs(1)).min(INGEST_MAX_BACKOFF)
+                } else {
+                    Duration::from_secs(0)
+                };
+                println!("Restarting the session ingest in {}s", ingest_backoff.as_secs());
+                ingest_restart = Some(Instant::now() + ingest_backoff);
+            }
+        } else if pid > 0 && children.contains(&pid) {
+            children.retain(|&c| c != pid);
+            print_status("child proc", pid, status);
+            // Losing a core silently would leave part of the traffic unwatched
+            if !stopping {
+                eprintln!("detector-afpacket: worker {} died, shutting down", pid);
+                STOP.store(true, Ordering::SeqCst);
+            }
+        } else if pid <= 0 {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+    unsafe { libc::kill(proxy, libc::SIGTERM); }
+    if let Some(pid) = ingest {
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0); }
+    }
+}
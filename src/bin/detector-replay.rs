@@ -0,0 +1,300 @@
+// Offline replay of captured traffic through the detector core.
+//
+// Feeds every frame of a .pcap/.pcapng file through the same per-packet path
+// that PF_RING drives in production, without a tun device, ZMQ peer, redis or
+// station environment variables. Prints every registration found, every
+// packet that would have been forwarded to a phantom, and the final counters.
+//
+// Usage:
+//   detector-replay -k <privkey> [-s <sessions>] [-c <station config>]
+//                   [-p <prefix list>] [-g <gre offset>] [-v] <capture>
+//
+// The private key file uses the same format as the station (privkey or
+// privkey || pubkey; only the first 32 bytes are used), or -k can name a
+// station key directory (keys.toml + key files) to try several keys. The
+// sessions file pre-registers phantoms so their traffic is forwarded, one per
+// line:
+//   <client ip|-> <phantom ip> [timeout seconds [phantom port [tcp|udp]]]
+// With -p, phantom traffic is only forwarded for phantoms in the prefix list
+// (same format as /var/lib/dark-decoy.prefixes), as on the station.
+//
+// Time goes by the capture's timestamps, not the clock on the wall: flows,
+// sessions and fragments time out, the replay window moves and the tag check
+// limits refill as they did when it was captured, and the 100ms cleanup runs
+// every 100ms of capture.
+
+extern crate hex;
+extern crate log;
+extern crate pnet;
+extern crate rust_dark_decoy;
+extern crate toml;
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process;
+
+use pnet::packet::Packet;
+
+use rust_dark_decoy::{clock, logging, PerCoreGlobal, StationConfig};
+use rust_dark_decoy::flow_tracker::{Flow, FlowTracker, Transport};
+use rust_dark_decoy::forward_sink::{ForwardSink, PacketMeta};
+use rust_dark_decoy::key_ring;
+use rust_dark_decoy::key_ring::KeyRing;
+use rust_dark_decoy::pcap::{PcapReader, to_ethernet_frame};
+use rust_dark_decoy::prefix_list::PrefixList;
+use rust_dark_decoy::registration_sink::RegistrationSink;
+use rust_dark_decoy::sessions::SessionDetails;
+use rust_dark_decoy::signalling::C2SWrapper;
+use rust_dark_decoy::util::IpPacket;
+
+const DEFAULT_SESSION_TIMEOUT_S: u64 = 3600;
+const CLEANUP_INTERVAL_NS: u64 = 100 * 1000 * 1000;
+
+struct PrintForwarder;
+
+impl ForwardSink for PrintForwarder
+{
+    fn forward(&mut self, ip_pkt: &IpPacket, _meta: &PacketMeta) -> io::Result<()>
+    {
+        let (src, dst, len) = match ip_pkt {
+            IpPacket::V4(p) => (IpAddr::V4(p.get_source()), IpAddr::V4(p.get_destination()),
+                                p.packet().len()),
+            IpPacket::V6(p) => (IpAddr::V6(p.get_source()), IpAddr::V6(p.get_destination()),
+                                p.packet().len()),
+        };
+        println!("forward {} -> {} ({} bytes)", src, dst, len);
+        Ok(())
+    }
+}
+
+struct PrintRegistrar;
+
+fn addr_from_bytes(b: &[u8]) -> String
+{
+    if b.len() == 4 {
+        let mut a = [0u8; 4];
+        a.copy_from_slice(b);
+        IpAddr::from(a).to_string()
+    } else if b.len() == 16 {
+        let mut a = [0u8; 16];
+        a.copy_from_slice(b);
+        IpAddr::from(a).to_string()
+    } else {
+        hex::encode(b)
+    }
+}
+
+impl RegistrationSink for PrintRegistrar
+{
+    fn send(&mut self, reg: &C2SWrapper) -> Result<(), Box<dyn Error>>
+    {
+        println!("registration {} -> {} key generation {} shared_secret {} payload {{ {:?} }}",
+                 addr_from_bytes(reg.get_registration_address()),
+                 addr_from_bytes(reg.get_decoy_address()),
+                 reg.get_key_generation(),
+                 hex::encode(reg.get_shared_secret()),
+                 reg.get_registration_payload());
+        Ok(())
+    }
+}
+
+fn usage() -> !
+{
+    eprintln!("usage: detector-replay -k <privkey> [-s <sessions>] [-c <station config>] [-p <prefix list>] [-g <gre offset>] [-v] <capture>");
+    process::exit(2);
+}
+
+fn fail(msg: String) -> !
+{
+    eprintln!("detector-replay: {}", msg);
+    process::exit(1);
+}
+
+fn read_keys(path: &str) -> KeyRing
+{
+    let path = Path::new(path);
+    let res = match path.is_dir() {
+        true => KeyRing::load_dir(path, clock::system_time()),
+        false => key_ring::read_key_file(path).map(KeyRing::single),
+    };
+    res.unwrap_or_else(fail)
+}
+
+fn read_sessions(path: &str) -> Vec<SessionDetails>
+{
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(format!("can't read sessions {}: {}", path, e)));
+    let mut sessions = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 {
+            fail(format!("{}:{}: expected <client ip|-> <phantom ip> [timeout [port [tcp|udp]]]", path, i + 1));
+        }
+        let client = if parts[0] == "-" { "" } else { parts[0] };
+        let timeout_s = match parts.get(2) {
+            Some(t) => t.parse::<u64>()
+                .unwrap_or_else(|_| fail(format!("{}:{}: bad timeout {}", path, i + 1, t))),
+            None => DEFAULT_SESSION_TIMEOUT_S,
+        };
+        let port = match parts.get(3) {
+            Some(p) => p.parse::<u16>()
+                .unwrap_or_else(|_| fail(format!("{}:{}: bad port {}", path, i + 1, p))),
+            None => 0,
+        };
+        let transport = match parts.get(4) {
+            Some(&"tcp") | None => Transport::Tcp,
+            Some(&"udp") => Transport::Udp,
+            Some(t) => fail(format!("{}:{}: bad transport {}", path, i + 1, t)),
+        };
+        match SessionDetails::new(client, parts[1], port, timeout_s * 1000 * 1000 * 1000) {
+            Ok(mut sd) => {
+                sd.transport = transport;
+                sessions.push(sd)
+            },
+            Err(e) => fail(format!("{}:{}: {}", path, i + 1, e)),
+        }
+    }
+    sessions
+}
+
+fn main()
+{
+    let args: Vec<String> = env::args().collect();
+    let mut key_path = None;
+    let mut sessions_path = None;
+    let mut conf_path = None;
+    let mut prefixes_path = None;
+    let mut gre_offset = 0;
+    let mut verbose = false;
+    let mut capture_path = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        let opt = args[i].as_str();
+        let needs_arg = opt == "-k" || opt == "-s" || opt == "-c" || opt == "-p" || opt == "-g";
+        if needs_arg && i + 1 >= args.len() {
+            usage();
+        }
+        match opt {
+            "-k" => key_path = Some(args[i + 1].clone()),
+            "-s" => sessions_path = Some(args[i + 1].clone()),
+            "-c" => conf_path = Some(args[i + 1].clone()),
+            "-p" => prefixes_path = Some(args[i + 1].clone()),
+            "-g" => gre_offset = args[i + 1].parse::<usize>().unwrap_or_else(|_| usage()),
+            "-v" => verbose = true,
+            "-h" | "--help" => usage(),
+            _ if capture_path.is_none() && !opt.starts_with('-') => capture_path = Some(opt.to_string()),
+            _ => usage(),
+        }
+        i += if needs_arg { 2 } else { 1 };
+    }
+
+    let key_path = key_path.unwrap_or_else(|| usage());
+    let capture_path = capture_path.unwrap_or_else(|| usage());
+
+    logging::init(if verbose { log::LogLevel::Debug } else { log::LogLevel::Warn }, 0);
+    // Replays run on an operator's machine; there is nobody to hide clients from.
+    Flow::set_log_client(true);
+
+    let conf = match conf_path {
+        Some(p) => {
+            let contents = fs::read_to_string(&p)
+                .unwrap_or_else(|e| fail(format!("can't read station config {}: {}", p, e)));
+            toml::from_str(&contents)
+                .unwrap_or_else(|e| fail(format!("can't parse station config {}: {}", p, e)))
+        },
+        None => StationConfig::default(),
+    };
+
+    let mut flow_tracker = FlowTracker::new_without_ingest();
+    if let Some(p) = sessions_path {
+        for sd in read_sessions(&p) {
+            flow_tracker.phantom_flows.add_session(sd);
+        }
+    }
+
+    let mut global = PerCoreGlobal::with_sinks(read_keys(&key_path), 0, conf, flow_tracker,
+                                               Box::new(PrintForwarder),
+                                               Box::new(PrintRegistrar));
+    global.gre_offset = gre_offset;
+    if let Some(p) = prefixes_path {
+        global.ip_tree = PrefixList::from_file(&p).unwrap_or_else(fail);
+    }
+
+    let f = File::open(&capture_path)
+        .unwrap_or_else(|e| fail(format!("can't open {}: {}", capture_path, e)));
+    let mut reader = PcapReader::new(BufReader::new(f))
+        .unwrap_or_else(|e| fail(format!("{}: {}", capture_path, e)));
+
+    let mut frames: u64 = 0;
+    let mut skipped: u64 = 0;
+    let mut next_cleanup = None;
+    loop {
+        let pkt = match reader.next_packet() {
+            Ok(Some(pkt)) => pkt,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("detector-replay: stopping early, {}: {}", capture_path, e);
+                break;
+            }
+        };
+        frames += 1;
+        clock::follow_capture(pkt.ts_ns);
+        let now = clock::now_ns();
+        match next_cleanup {
+            Some(at) if now >= at => {
+                global.periodic_cleanup();
+                next_cleanup = Some(now + CLEANUP_INTERVAL_NS);
+            },
+            Some(_) => {},
+            None => next_cleanup = Some(now + CLEANUP_INTERVAL_NS),
+        }
+        match to_ethernet_frame(pkt.linktype, &pkt.data) {
+            Some(frame) => global.process_packet(&frame),
+            None => skipped += 1,
+        }
+    }
+
+    let stats = &global.stats;
+    println!("frames {} (unsupported link type {})", frames, skipped);
+    println!("stats {} pkts ({} v4, {} v6) {} bytes, {} tcp, {} udp, {} tls ({} bytes), {} decoy port syns, {} tags checked",
+             stats.packets_this_period,
+             stats.ipv4_packets_this_period,
+             stats.ipv6_packets_this_period,
+             stats.bytes_this_period,
+             stats.tcp_packets_this_period,
+             stats.udp_packets_this_period,
+             stats.tls_packets_this_period,
+             stats.tls_bytes_this_period,
+             stats.port_443_syns_this_period,
+             stats.elligator_this_period);
+    println!("reassembled {} ({} dropped, {} unfinished)",
+             stats.tags_reassembled_this_period,
+             stats.reassembly_drops_this_period,
+             global.flow_tracker.count_reassembling_flows());
+    println!("registrations sent {} replayed tags dropped {} rate limited {} (source) {} (core)",
+             stats.registrations_sent_this_period,
+             stats.replayed_tags_this_period,
+             stats.source_limited_this_period,
+             stats.global_limited_this_period);
+    println!("tracked flows {} phantom sessions {} udp phantom flows {} ({} datagrams over the limit)",
+             global.flow_tracker.count_tracked_flows(),
+             global.flow_tracker.count_phantom_flows(),
+             global.flow_tracker.count_udp_phantom_flows(),
+             stats.udp_flows_over_limit_this_period);
+    if !global.ip_tree.is_empty() {
+        println!("phantom packets in prefix list {} outside {}",
+                 stats.in_tree_this_period,
+                 stats.not_in_tree_this_period);
+    }
+}
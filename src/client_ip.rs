@@ -0,0 +1,298 @@
+//
+// How client addresses appear in logs
+//
+// Logging client addresses in full is off by default. In between that and
+// "_" there are modes that still let an operator tie events together without
+// keeping the address itself:
+//
+//  - prefix: the client's /24 (IPv4) or /48 (IPv6), lengths configurable
+//  - pseudonym: a keyed hash of the address (HKDF-SHA256, i.e. HMAC based),
+//    "c-" and 12 hex digits. The UTC day goes into the hash, so pseudonyms
+//    change daily and can't be followed further than that. The key is read
+//    from a file so every core (and station) gives the same pseudonyms.
+//  - asn: origin AS and country from a local ip2asn database
+//    (https://iptoasn.com, ip2asn-combined.tsv), e.g. "AS64496/US"
+//
+// The mode is process wide, set at startup, and applies wherever a client
+// address is displayed: Flow, FlowNoSrcPort and SessionDetails, and anything
+// else that goes through write_client().
+//
+
+extern crate hkdf;
+extern crate sha2;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+
+use clock;
+use prefix_list::{mask_v4, mask_v6};
+
+pub const DEFAULT_PREFIX_V4: u8 = 24;
+pub const DEFAULT_PREFIX_V6: u8 = 48;
+const PSEUDONYM_INFO: &[u8] = b"conjure client ip pseudonym";
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+pub enum ClientIpMode
+{
+    Hidden,
+    Full,
+    Prefix { v4: u8, v6: u8 },
+    Pseudonym { key: Vec<u8> },
+    Asn(AsnDb),
+}
+
+static MODE: RwLock<ClientIpMode> = RwLock::new(ClientIpMode::Hidden);
+
+pub fn set_mode(mode: ClientIpMode)
+{
+    match MODE.write() {
+        Ok(mut m) => *m = mode,
+        Err(poisoned) => *poisoned.into_inner() = mode,
+    }
+}
+
+// Writes ip (and port, if the mode shows addresses in full and there is one)
+// the way the current mode says to.
+pub fn write_client(f: &mut fmt::Formatter, ip: IpAddr, port: Option<u16>) -> fmt::Result
+{
+    match MODE.read() {
+        Ok(m) => m.write(f, ip, port),
+        Err(poisoned) => poisoned.into_inner().write(f, ip, port),
+    }
+}
+
+// For Display impls: format!("{}", Client(ip, None))
+pub struct Client(pub IpAddr, pub Option<u16>);
+
+impl fmt::Display for Client
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write_client(f, self.0, self.1)
+    }
+}
+
+impl ClientIpMode
+{
+    fn write(&self, f: &mut fmt::Formatter, ip: IpAddr, port: Option<u16>) -> fmt::Result
+    {
+        match *self {
+            ClientIpMode::Hidden => write!(f, "_"),
+            ClientIpMode::Full => match port {
+                Some(port) => write!(f, "{}", SocketAddr::new(ip, port)),
+                None => write!(f, "{}", ip),
+            },
+            ClientIpMode::Prefix { v4, v6 } => match ip {
+                IpAddr::V4(a) => write!(f, "{}/{}", IpAddr::from(mask_v4(u32::from(a), v4).to_be_bytes()), v4),
+                IpAddr::V6(a) => write!(f, "{}/{}", IpAddr::from(mask_v6(u128::from(a), v6).to_be_bytes()), v6),
+            },
+            ClientIpMode::Pseudonym { ref key } => {
+                write!(f, "c-{}", pseudonym(key, clock::unix_secs() / DAY_SECS, ip))
+            },
+            ClientIpMode::Asn(ref db) => match db.lookup(ip) {
+                Some((asn, country)) => write!(f, "AS{}/{}", asn, country),
+                None => write!(f, "AS?"),
+            },
+        }
+    }
+}
+
+// 12 hex digits naming ip on the given day
+fn pseudonym(key: &[u8], day: u64, ip: IpAddr) -> String
+{
+    let kdf = hkdf::Hkdf::<sha2::Sha256>::extract(Some(&day.to_be_bytes()), key);
+    let mut info = PSEUDONYM_INFO.to_vec();
+    match ip {
+        IpAddr::V4(a) => info.extend_from_slice(&a.octets()),
+        IpAddr::V6(a) => info.extend_from_slice(&a.octets()),
+    }
+    let mut out = [0u8; 6];
+    // Can only fail for outputs longer than 255 hashes
+    let _ = kdf.expand(&info, &mut out);
+    out.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Reads the pseudonym key: at least 16 bytes of (any) file content.
+pub fn read_key(path: &str) -> io::Result<Vec<u8>>
+{
+    let key = ::std::fs::read(path)?;
+    if key.len() < 16 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{} is too short for a key", path)));
+    }
+    Ok(key)
+}
+
+struct AsnRange
+{
+    // IPv4 as IPv4-mapped IPv6
+    start: u128,
+    end: u128,
+    asn: u32,
+    country: [u8; 2],
+}
+
+// Address ranges to origin AS and country, sorted by start
+pub struct AsnDb
+{
+    ranges: Vec<AsnRange>,
+}
+
+fn db_key(ip: IpAddr) -> u128
+{
+    match ip {
+        IpAddr::V4(a) => u128::from(a.to_ipv6_mapped()),
+        IpAddr::V6(a) => u128::from(a),
+    }
+}
+
+impl AsnDb
+{
+    // ip2asn's TSV format: range start, range end, AS number, country code,
+    // AS description. Ranges with AS 0 are "not routed" and left out.
+    pub fn load(path: &str) -> io::Result<AsnDb>
+    {
+        AsnDb::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<AsnDb>
+    {
+        let bad = |n: usize| io::Error::new(io::ErrorKind::InvalidData,
+                                            format!("bad ip2asn line {}", n + 1));
+        let mut ranges = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut cols = line.split('\t');
+            let (start, end, asn, country) = match (cols.next(), cols.next(), cols.next(), cols.next()) {
+                (Some(s), Some(e), Some(a), Some(c)) => (s, e, a, c),
+                _ if line.trim().is_empty() => continue,
+                _ => return Err(bad(n)),
+            };
+            let start = start.parse::<IpAddr>().map_err(|_| bad(n))?;
+            let end = end.parse::<IpAddr>().map_err(|_| bad(n))?;
+            let asn = asn.parse::<u32>().map_err(|_| bad(n))?;
+            if asn == 0 {
+                continue;
+            }
+            let mut cc = [b'?'; 2];
+            if country.len() == 2 && country.is_ascii() {
+                cc.copy_from_slice(country.as_bytes());
+            }
+            ranges.push(AsnRange { start: db_key(start), end: db_key(end), asn, country: cc });
+        }
+        ranges.sort_by_key(|r| r.start);
+        Ok(AsnDb { ranges })
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.ranges.is_empty()
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<(u32, &str)>
+    {
+        let key = db_key(ip);
+        let i = match self.ranges.binary_search_by_key(&key, |r| r.start) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let r = &self.ranges[i];
+        match key <= r.end {
+            true => Some((r.asn, ::std::str::from_utf8(&r.country).unwrap_or("??"))),
+            false => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use client_ip::*;
+    use clock;
+    use std::fmt;
+    use std::net::IpAddr;
+
+    struct Shown<'a>(&'a ClientIpMode, &'a str, Option<u16>);
+
+    impl<'a> fmt::Display for Shown<'a>
+    {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+        {
+            self.0.write(f, self.1.parse().unwrap(), self.2)
+        }
+    }
+
+    fn show(mode: &ClientIpMode, ip: &str, port: Option<u16>) -> String
+    {
+        Shown(mode, ip, port).to_string()
+    }
+
+    #[test]
+    fn test_modes()
+    {
+        assert_eq!(show(&ClientIpMode::Hidden, "192.0.2.7", Some(5555)), "_");
+        assert_eq!(show(&ClientIpMode::Full, "192.0.2.7", Some(5555)), "192.0.2.7:5555");
+        assert_eq!(show(&ClientIpMode::Full, "2001:db8::7", Some(5555)), "[2001:db8::7]:5555");
+        assert_eq!(show(&ClientIpMode::Full, "2001:db8::7", None), "2001:db8::7");
+
+        let prefix = ClientIpMode::Prefix { v4: DEFAULT_PREFIX_V4, v6: DEFAULT_PREFIX_V6 };
+        assert_eq!(show(&prefix, "192.0.2.7", Some(5555)), "192.0.2.0/24");
+        assert_eq!(show(&prefix, "2001:db8:1:2::7", None), "2001:db8:1::/48");
+
+        let pseudo = ClientIpMode::Pseudonym { key: vec![7; 32] };
+        let a = show(&pseudo, "192.0.2.7", Some(5555));
+        assert!(a.starts_with("c-") && a.len() == 14);
+        assert_eq!(show(&pseudo, "192.0.2.7", None), a);
+        assert!(show(&pseudo, "192.0.2.8", None) != a);
+        let other_key = ClientIpMode::Pseudonym { key: vec![8; 32] };
+        assert!(show(&other_key, "192.0.2.7", None) != a);
+
+        let ip: IpAddr = "192.0.2.7".parse().unwrap();
+        assert!(pseudonym(&[7; 32], 100, ip) != pseudonym(&[7; 32], 101, ip));
+    }
+
+    #[test]
+    fn test_pseudonym_day()
+    {
+        // The day comes from the detector's clock, so a replay names clients
+        // as the station did on the day of the capture.
+        clock::follow_capture(100 * DAY_SECS * 1000 * 1000 * 1000 + 5);
+        let pseudo = ClientIpMode::Pseudonym { key: vec![7; 32] };
+        let ip: IpAddr = "192.0.2.7".parse().unwrap();
+        assert_eq!(show(&pseudo, "192.0.2.7", None), format!("c-{}", pseudonym(&[7; 32], 100, ip)));
+    }
+
+    #[test]
+    fn test_asn_db()
+    {
+        let tsv = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+                   1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+                   192.0.2.0\t192.0.2.127\t64496\tDE\tEXAMPLE\n\
+                   \n\
+                   2001:db8::\t2001:db8:ffff:ffff:ffff:ffff:ffff:ffff\t64497\tNL\tEXAMPLE6\n";
+        let db = AsnDb::from_reader(tsv.as_bytes()).unwrap();
+        assert_eq!(db.len(), 3);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(db.lookup(ip("1.0.0.9")), Some((13335, "US")));
+        assert_eq!(db.lookup(ip("1.0.2.1")), None);
+        assert_eq!(db.lookup(ip("192.0.2.127")), Some((64496, "DE")));
+        assert_eq!(db.lookup(ip("192.0.2.128")), None);
+        assert_eq!(db.lookup(ip("2001:db8:5::1")), Some((64497, "NL")));
+        assert_eq!(db.lookup(ip("0.0.0.1")), None);
+
+        let mode = ClientIpMode::Asn(db);
+        assert_eq!(show(&mode, "192.0.2.1", Some(1)), "AS64496/DE");
+        assert_eq!(show(&mode, "10.0.0.1", None), "AS?");
+
+        assert!(AsnDb::from_reader("1.0.0.0\tx\t1\tUS\n".as_bytes()).is_err());
+    }
+}
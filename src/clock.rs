@@ -0,0 +1,93 @@
+//
+// The time the detector goes by
+//
+// Everything that times out (tracked flows, reassembly, fragments, phantom
+// sessions, the replay window, rate limits, key expiry) asks now_ns() or
+// system_time() rather than the OS. On the station they're just the monotonic
+// and wall clocks. detector-replay makes them follow the capture's timestamps
+// instead, so a replay times out what the station would have, however fast it
+// runs.
+//
+
+use std::cell::Cell;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use time::precise_time_ns;
+
+#[derive(Clone, Copy)]
+struct Capture
+{
+    // Monotonic time when we saw the first timestamp
+    start_ns: u64,
+    // Capture timestamps (Unix ns)
+    first_ns: u64,
+    latest_ns: u64,
+}
+
+thread_local! {
+    static CAPTURE: Cell<Option<Capture>> = const { Cell::new(None) };
+}
+
+// Monotonic ns, as precise_time_ns()
+pub fn now_ns() -> u64
+{
+    match CAPTURE.with(|c| c.get()) {
+        Some(c) => c.start_ns + (c.latest_ns - c.first_ns),
+        None => precise_time_ns(),
+    }
+}
+
+pub fn system_time() -> SystemTime
+{
+    match CAPTURE.with(|c| c.get()) {
+        Some(c) => UNIX_EPOCH + Duration::from_nanos(c.latest_ns),
+        None => SystemTime::now(),
+    }
+}
+
+pub fn unix_secs() -> u64
+{
+    system_time().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// From now on this thread's clock reads ts_ns, a capture timestamp (Unix ns).
+// The first one maps to the current monotonic time, and time never goes
+// backwards for packets captured out of order.
+pub fn follow_capture(ts_ns: u64)
+{
+    CAPTURE.with(|c| {
+        let next = match c.get() {
+            Some(cap) => Capture { latest_ns: cap.latest_ns.max(ts_ns), ..cap },
+            None => Capture { start_ns: precise_time_ns(), first_ns: ts_ns, latest_ns: ts_ns },
+        };
+        c.set(Some(next));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+    use clock::*;
+
+    #[test]
+    fn test_follow_capture()
+    {
+        thread::spawn(|| {
+            let ts = 1_600_000_000 * 1_000_000_000u64;
+            follow_capture(ts);
+            let start = now_ns();
+            assert_eq!(unix_secs(), 1_600_000_000);
+
+            follow_capture(ts + 2_500_000_000);
+            assert_eq!(now_ns(), start + 2_500_000_000);
+            // Out of order
+            follow_capture(ts + 1_000_000_000);
+            assert_eq!(now_ns(), start + 2_500_000_000);
+            assert_eq!(system_time(), UNIX_EPOCH + Duration::from_nanos(ts + 2_500_000_000));
+        }).join().unwrap();
+
+        // Other threads still go by the OS
+        assert!(unix_secs() > 1_700_000_000);
+    }
+}
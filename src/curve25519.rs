@@ -0,0 +1,398 @@
+//
+// Curve25519 for tag decoding
+//
+// Native replacement for the parts of libtapdance the detector uses when
+// checking a tag: the Elligator2 representative-to-point map (decode() in
+// libtapdance/elligator2.c) and X25519 (curve25519-donna). Field elements are
+// five 51-bit limbs; products are computed in u128.
+//
+// decode() must match the C implementation bit for bit, including its quirks:
+// the representative is taken as a full 256-bit little-endian integer (the
+// caller clears the two random high bits), and the output is always the
+// x-coordinate with the sign bit cleared. The tests below use vectors
+// produced by the C code (libtapdance/gen_elligator_vectors.c).
+//
+
+const MASK51: u64 = (1 << 51) - 1;
+
+// A = 486662, the Montgomery curve coefficient.
+const A: u64 = 486662;
+// (A - 2) / 4, used by the ladder.
+const A24: u64 = 121665;
+
+// p - 2 and (p - 1) / 2, little endian. Used for inversion and the Legendre
+// symbol. Both exponents are public, so the variable-time pow is fine.
+const P_MINUS_2: [u8; 32] = [
+    0xeb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+const P_MINUS_1_HALF: [u8; 32] = [
+    0xf6, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f];
+
+#[derive(Copy, Clone)]
+struct Fe([u64; 5]);
+
+impl Fe
+{
+    fn zero() -> Fe { Fe([0, 0, 0, 0, 0]) }
+    fn one() -> Fe { Fe([1, 0, 0, 0, 0]) }
+    fn small(v: u64) -> Fe { Fe([v, 0, 0, 0, 0]) }
+
+    // Interprets all 256 bits; bit 255 is folded back in as 2^255 = 19.
+    fn from_bytes(b: &[u8; 32]) -> Fe
+    {
+        let load = |i: usize| -> u64 {
+            let mut v = 0u64;
+            for j in 0..8 {
+                v |= (b[i + j] as u64) << (8 * j);
+            }
+            v
+        };
+        let mut h = Fe([
+            load(0) & MASK51,
+            (load(6) >> 3) & MASK51,
+            (load(12) >> 6) & MASK51,
+            (load(19) >> 1) & MASK51,
+            (load(24) >> 12) & MASK51,
+        ]);
+        h.0[0] += 19 * ((b[31] >> 7) as u64);
+        h.carry()
+    }
+
+    // Canonical (fully reduced) little-endian encoding.
+    fn to_bytes(&self) -> [u8; 32]
+    {
+        let mut h = self.carry().0;
+
+        // h < 2^255 + small, so h >= p iff h + 19 >= 2^255.
+        let mut q = (h[0] + 19) >> 51;
+        q = (h[1] + q) >> 51;
+        q = (h[2] + q) >> 51;
+        q = (h[3] + q) >> 51;
+        q = (h[4] + q) >> 51;
+
+        h[0] += 19 * q;
+        h[1] += h[0] >> 51; h[0] &= MASK51;
+        h[2] += h[1] >> 51; h[1] &= MASK51;
+        h[3] += h[2] >> 51; h[2] &= MASK51;
+        h[4] += h[3] >> 51; h[3] &= MASK51;
+        h[4] &= MASK51;
+
+        let mut out = [0u8; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut pos = 0;
+        for limb in h.iter() {
+            acc |= (*limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 && pos < 32 {
+                out[pos] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                pos += 1;
+            }
+        }
+        if pos < 32 {
+            out[pos] = acc as u8;
+        }
+        out
+    }
+
+    // Propagates carries so every limb is below 2^51 (plus a small amount in
+    // limb 0).
+    fn carry(&self) -> Fe
+    {
+        let mut h = self.0;
+        let mut c;
+        c = h[0] >> 51; h[0] &= MASK51; h[1] += c;
+        c = h[1] >> 51; h[1] &= MASK51; h[2] += c;
+        c = h[2] >> 51; h[2] &= MASK51; h[3] += c;
+        c = h[3] >> 51; h[3] &= MASK51; h[4] += c;
+        c = h[4] >> 51; h[4] &= MASK51; h[0] += 19 * c;
+        Fe(h)
+    }
+
+    fn add(&self, o: &Fe) -> Fe
+    {
+        let mut h = [0u64; 5];
+        for i in 0..5 {
+            h[i] = self.0[i] + o.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    fn sub(&self, o: &Fe) -> Fe
+    {
+        // Add 4p first so limbs never go negative.
+        let four_p = [0x1fffffffffffb4u64, 0x1ffffffffffffc, 0x1ffffffffffffc,
+                      0x1ffffffffffffc, 0x1ffffffffffffc];
+        let mut h = [0u64; 5];
+        for i in 0..5 {
+            h[i] = self.0[i] + four_p[i] - o.0[i];
+        }
+        Fe(h).carry()
+    }
+
+    fn neg(&self) -> Fe
+    {
+        Fe::zero().sub(self)
+    }
+
+    fn mul(&self, o: &Fe) -> Fe
+    {
+        let a = &self.0;
+        let b = &o.0;
+        let m = |x: u64, y: u64| -> u128 { (x as u128) * (y as u128) };
+
+        let b1_19 = b[1] * 19;
+        let b2_19 = b[2] * 19;
+        let b3_19 = b[3] * 19;
+        let b4_19 = b[4] * 19;
+
+        let r0 = m(a[0], b[0]) + m(a[1], b4_19) + m(a[2], b3_19) + m(a[3], b2_19) + m(a[4], b1_19);
+        let r1 = m(a[0], b[1]) + m(a[1], b[0]) + m(a[2], b4_19) + m(a[3], b3_19) + m(a[4], b2_19);
+        let r2 = m(a[0], b[2]) + m(a[1], b[1]) + m(a[2], b[0]) + m(a[3], b4_19) + m(a[4], b3_19);
+        let r3 = m(a[0], b[3]) + m(a[1], b[2]) + m(a[2], b[1]) + m(a[3], b[0]) + m(a[4], b4_19);
+        let r4 = m(a[0], b[4]) + m(a[1], b[3]) + m(a[2], b[2]) + m(a[3], b[1]) + m(a[4], b[0]);
+
+        let mask = MASK51 as u128;
+        let r1 = r1 + (r0 >> 51);
+        let r2 = r2 + (r1 >> 51);
+        let r3 = r3 + (r2 >> 51);
+        let r4 = r4 + (r3 >> 51);
+        let c = (r4 >> 51) as u64;
+
+        Fe([
+            (r0 & mask) as u64 + 19 * c,
+            (r1 & mask) as u64,
+            (r2 & mask) as u64,
+            (r3 & mask) as u64,
+            (r4 & mask) as u64,
+        ]).carry()
+    }
+
+    fn square(&self) -> Fe
+    {
+        self.mul(self)
+    }
+
+    fn mul_small(&self, v: u64) -> Fe
+    {
+        self.mul(&Fe::small(v))
+    }
+
+    fn pow(&self, exp: &[u8; 32]) -> Fe
+    {
+        let mut r = Fe::one();
+        for i in (0..256).rev() {
+            r = r.square();
+            if (exp[i / 8] >> (i % 8)) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    fn invert(&self) -> Fe
+    {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn is_one(&self) -> bool
+    {
+        self.to_bytes() == Fe::one().to_bytes()
+    }
+}
+
+// Constant-time conditional swap of a and b when swap == 1.
+fn cswap(swap: u64, a: &mut Fe, b: &mut Fe)
+{
+    let mask = 0u64.wrapping_sub(swap);
+    for i in 0..5 {
+        let t = mask & (a.0[i] ^ b.0[i]);
+        a.0[i] ^= t;
+        b.0[i] ^= t;
+    }
+}
+
+// X25519 scalar multiplication (RFC 7748), with the same scalar clamping and
+// u-coordinate handling as curve25519-donna.
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32]
+{
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let mut u = *u;
+    u[31] &= 0x7f;
+    let x1 = Fe::from_bytes(&u);
+
+    let mut x2 = Fe::one();
+    let mut z2 = Fe::zero();
+    let mut x3 = x1;
+    let mut z3 = Fe::one();
+    let mut swap = 0u64;
+
+    for t in (0..255).rev() {
+        let k_t = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= k_t;
+        cswap(swap, &mut x2, &mut x3);
+        cswap(swap, &mut z2, &mut z3);
+        swap = k_t;
+
+        let a = x2.add(&z2);
+        let aa = a.square();
+        let b = x2.sub(&z2);
+        let bb = b.square();
+        let e = aa.sub(&bb);
+        let c = x3.add(&z3);
+        let d = x3.sub(&z3);
+        let da = d.mul(&a);
+        let cb = c.mul(&b);
+        x3 = da.add(&cb).square();
+        z3 = x1.mul(&da.sub(&cb).square());
+        x2 = aa.mul(&bb);
+        z2 = e.mul(&aa.add(&e.mul_small(A24)));
+    }
+    cswap(swap, &mut x2, &mut x3);
+    cswap(swap, &mut z2, &mut z3);
+
+    x2.mul(&z2.invert()).to_bytes()
+}
+
+// Elligator2 representative -> curve25519 x-coordinate, with non-square
+// u = 2:
+//   v = -A / (1 + 2r^2)
+//   x = v        if v^3 + Av^2 + v is a non-zero square
+//   x = -v - A   otherwise
+pub fn elligator2_decode(representative: &[u8; 32]) -> [u8; 32]
+{
+    let r = Fe::from_bytes(representative);
+
+    let denom = r.square().mul_small(2).add(&Fe::one());
+    let v = denom.invert().mul_small(A).neg();
+
+    let v2 = v.square();
+    let epsilon = v2.mul(&v).add(&v2.mul_small(A)).add(&v);
+
+    let x = if epsilon.pow(&P_MINUS_1_HALF).is_one() {
+        v
+    } else {
+        v.neg().sub(&Fe::small(A))
+    };
+
+    let mut out = x.to_bytes();
+    out[31] &= 0x7f;
+    out
+}
+
+// Shared secret between the station and the client whose Elligator2-encoded
+// public key is `representative`. Equivalent to
+// libtapdance's get_shared_secret_from_tag().
+pub fn shared_secret_from_representative(station_privkey: &[u8; 32],
+                                         representative: &[u8; 32]) -> [u8; 32]
+{
+    let client_public = elligator2_decode(representative);
+    x25519(station_privkey, &client_public)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use curve25519::*;
+    use hex;
+
+    fn arr(s: &str) -> [u8; 32]
+    {
+        let v = hex::decode(s).unwrap();
+        let mut a = [0u8; 32];
+        a.copy_from_slice(&v);
+        a
+    }
+
+    #[test]
+    fn test_x25519_rfc7748()
+    {
+        // RFC 7748 section 5.2
+        let k = arr("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = arr("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(hex::encode(x25519(&k, &u)),
+                   "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552");
+
+        // RFC 7748 section 6.1, Alice's public key
+        let alice = arr("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let mut base = [0u8; 32];
+        base[0] = 9;
+        assert_eq!(hex::encode(x25519(&alice, &base)),
+                   "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a");
+    }
+
+    // (station privkey, representative, decoded point, shared secret), as
+    // produced by libtapdance/gen_elligator_vectors.c.
+    const C_VECTORS: [(&str, &str, &str, &str); 8] = [
+        ("0be5a1d6b0b8ad3f7d6b6d9cedcd696c78d0732656942fc4411e382afd106da0",
+         "0000000000000000000000000000000000000000000000000000000000000000",
+         "0000000000000000000000000000000000000000000000000000000000000000",
+         "0000000000000000000000000000000000000000000000000000000000000000"),
+        ("6379fafb9effad18d9352df486a7b7cebb37f6eb7bc3763953de5e214206e6be",
+         "0100000000000000000000000000000000000000000000000000000000000000",
+         "9cdb525555555555555555555555555555555555555555555555555555555555",
+         "d96c39102abdeac06a41aee7f58f023f75bf04925bd878ea46c3f81cd0030d60"),
+        ("feb8b7fd23eeb0dde7842de5337553e741153510aa76daeda967963badba6783",
+         "07429148bd9336383277833555ac7b5f9bc518ebb3fa0f9bdb78bf09e8413919",
+         "0c4c5c8760156776e0a6c71b5e677afb34e2b95764bf623caac3538956f20037",
+         "b8b0d32096e6352a76188e7ebe4e4b6a59ef84b0fe9a6eb944ca61d3f1e0344d"),
+        ("91337dbbf2b0c0d7823701350d6409a6812a4355269ea77df1918a66d073061c",
+         "e1ca9e12d3439697d4ffe9132f04961c208ddd57a6b70bf589b19ee7fe46c31a",
+         "c379e6799a9bcc13329d5455cb0027be84b8432c0677a66389f17c5f30b4e906",
+         "9938e851a94f43bc9f26258079f634c4791ebe3e89724d2dc3d930b8468f4a39"),
+        ("d80f16407ff8f3b6ee7baacd35d4501c8c275fd849cae79377a909199b794b7e",
+         "ce9ea009470b61660be5a9bcff520c5ca36efce8d23a3b70269b23e477c8d300",
+         "cc50cde576d0840611aeb206f6286d7a7b33d4602d605305ce9030b7ff606c05",
+         "df6bbe724788a3451c81cfe96e9fab7f536d3bd82040da29e2f70024b01efb0a"),
+        ("bb624c74cf7c52523e6646078d759b3d88c39c3a4337d159c56a048533659560",
+         "137957ab130d2d21bf384e8cce0642afadbe3cbda029b9a95041eeb9bf337d14",
+         "3a75c23ae2814c77a82976cfd0a61d117a27f3e1a0429c20f6f0e96a287f786b",
+         "8dbc0f9710558a882035825f096fc50ef4b1fd684d4ff5d4e964189c5001aa37"),
+        ("93cb9c98eaa1ee3658a80e1be93ceb3dcba803b9cf5bc8db27c18507f8d356a6",
+         "0a60a80adff2c8657b5573e6bd579574d957580a0615de8564dc19eaca533215",
+         "cc5c7306069ebcedfb6f5ebade21f29266318e5202816398422b1102989cf603",
+         "83300e71b0e19002b658adf71655ece9d4ebc972e3fc6bba651b10e5457a8115"),
+        ("cbdae93ca9fe8e9b0e40ed037f6df535da5ca6f9e21371b1d7725d12ea06e7cc",
+         "9c48d9e3050802d80c204a663fd3a3bcc7b9dbad3e8e7b1ce210dc7369a1b326",
+         "bdda7139ab06ca360814a191c33845ed4a8910987cc3e68d41a0e67d307ae60f",
+         "4646394d0eae75d6a4c515b4e7ba6ba8df95951e5f258ea475ff171e76eb9a34"),
+    ];
+
+    #[test]
+    fn test_elligator2_matches_libtapdance()
+    {
+        for v in C_VECTORS.iter() {
+            let key = arr(v.0);
+            let repr = arr(v.1);
+            assert_eq!(hex::encode(elligator2_decode(&repr)), v.2);
+            assert_eq!(hex::encode(shared_secret_from_representative(&key, &repr)), v.3);
+        }
+    }
+
+    #[test]
+    fn test_field_encoding_is_canonical()
+    {
+        // p itself and p + 1 reduce to 0 and 1.
+        let p = arr("edffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f");
+        assert_eq!(Fe::from_bytes(&p).to_bytes(), [0u8; 32]);
+        let mut p1 = p;
+        p1[0] += 1;
+        assert!(Fe::from_bytes(&p1).is_one());
+
+        // Bit 255 counts as 2^255 = 19 mod p.
+        let mut top = [0u8; 32];
+        top[31] = 0x80;
+        assert_eq!(Fe::from_bytes(&top).to_bytes(), Fe::small(19).to_bytes());
+    }
+}
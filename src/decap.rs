@@ -0,0 +1,440 @@
+//
+// Tunnel decapsulation
+//
+// Depending on how a station is fed, the client traffic we care about can be
+// wrapped in a few layers: stacked 802.1Q/802.1ad tags, MPLS label stacks, a
+// GRE tunnel from a router (possibly carrying a mirrored Ethernet frame), an
+// ERSPAN session (type II or III) or VXLAN. decapsulate() peels all of that
+// off, starting from an Ethernet frame, until it reaches an IP packet that
+// isn't itself a tunnel we know.
+//
+// PARSE_GRE_OFFSET still works for anything else: the detector skips that
+// many bytes before getting here.
+//
+
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv6::Ipv6Packet;
+
+use util::{IpPacket, UpperLayer};
+
+// Most headers (Ethernet, IP, GRE, ...) we go through before giving up
+pub const MAX_LAYERS: usize = 16;
+pub const MAX_VLAN_TAGS: usize = 4;
+pub const MAX_MPLS_LABELS: usize = 8;
+pub const VXLAN_PORT: u16 = 4789;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+// Pre-802.1ad QinQ, still used by some switches
+const ETHERTYPE_QINQ_OLD: u16 = 0x9100;
+const ETHERTYPE_MPLS: u16 = 0x8847;
+const ETHERTYPE_MPLS_MCAST: u16 = 0x8848;
+const ETHERTYPE_TEB: u16 = 0x6558;
+const ETHERTYPE_ERSPAN_2: u16 = 0x88be;
+const ETHERTYPE_ERSPAN_3: u16 = 0x22eb;
+
+const GRE_CHECKSUM: u16 = 0x8000;
+const GRE_ROUTING: u16 = 0x4000;
+const GRE_KEY: u16 = 0x2000;
+const GRE_SEQ: u16 = 0x1000;
+const GRE_VERSION: u16 = 0x0007;
+
+const VXLAN_VALID_VNI: u8 = 0x08;
+
+// Packets (or frames) we found each kind of encapsulation on, since startup.
+// A frame with stacked tags counts once, as qinq. failed counts frames we
+// couldn't get through: truncated tags, labels or tunnel headers, a tunnel
+// with an unknown payload, or too many layers.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct DecapCounters
+{
+    pub vlan: u64,
+    pub qinq: u64,
+    pub mpls: u64,
+    pub gre: u64,
+    pub erspan: u64,
+    pub vxlan: u64,
+    pub failed: u64,
+}
+
+// Where the innermost Ethernet header and the IP packet start in the frame
+// given to decapsulate(). Without an inner Ethernet frame (e.g. IP over GRE)
+// eth_offset stays on the outer one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decapsulated
+{
+    pub eth_offset: usize,
+    pub ip_offset: usize,
+    pub ipv6: bool,
+}
+
+impl Decapsulated
+{
+    pub fn ip<'p>(&self, frame: &'p [u8]) -> Option<IpPacket<'p>>
+    {
+        let data = &frame[self.ip_offset..];
+        match self.ipv6 {
+            false => Ipv4Packet::new(data).map(IpPacket::V4),
+            true => Ipv6Packet::new(data).map(IpPacket::V6),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Next
+{
+    Ethernet,
+    Ip(bool),
+    Mpls,
+    Gre,
+    Erspan2,
+    Erspan3,
+    Vxlan,
+}
+
+fn be16(data: &[u8]) -> u16
+{
+    (data[0] as u16) << 8 | data[1] as u16
+}
+
+fn from_ethertype(ethertype: u16) -> Option<Next>
+{
+    match ethertype {
+        ETHERTYPE_IPV4 => Some(Next::Ip(false)),
+        ETHERTYPE_IPV6 => Some(Next::Ip(true)),
+        ETHERTYPE_MPLS | ETHERTYPE_MPLS_MCAST => Some(Next::Mpls),
+        _ => None,
+    }
+}
+
+// Ethernet header plus any VLAN tags. Returns its length and what follows.
+fn ethernet(data: &[u8], counters: &mut DecapCounters) -> Option<(usize, Option<Next>)>
+{
+    if data.len() < 14 {
+        return None;
+    }
+    let mut ethertype = be16(&data[12..]);
+    let mut len = 14;
+    let mut tags = 0;
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ
+        || ethertype == ETHERTYPE_QINQ_OLD
+    {
+        if tags == MAX_VLAN_TAGS || data.len() < len + 4 {
+            return None;
+        }
+        ethertype = be16(&data[len + 2..]);
+        len += 4;
+        tags += 1;
+    }
+    match tags {
+        0 => {},
+        1 => counters.vlan += 1,
+        _ => counters.qinq += 1,
+    }
+    Some((len, from_ethertype(ethertype)))
+}
+
+// An MPLS label stack. What's under it isn't labelled, so guess from the
+// first nibble: IPv4, IPv6, or a pseudowire control word followed by an
+// Ethernet frame.
+fn mpls(data: &[u8]) -> Option<(usize, Next)>
+{
+    let mut len = 0;
+    loop {
+        if len == MAX_MPLS_LABELS * 4 || data.len() < len + 4 {
+            return None;
+        }
+        let bottom = data[len + 2] & 0x1 != 0;
+        len += 4;
+        if bottom {
+            break;
+        }
+    }
+    match data.get(len).map(|b| b >> 4) {
+        Some(4) => Some((len, Next::Ip(false))),
+        Some(6) => Some((len, Next::Ip(true))),
+        Some(0) => Some((len + 4, Next::Ethernet)),
+        _ => None,
+    }
+}
+
+fn gre(data: &[u8]) -> Option<(usize, Next)>
+{
+    if data.len() < 4 {
+        return None;
+    }
+    let flags = be16(data);
+    // Version 1 is PPTP's, and source routing has long been deprecated
+    if flags & (GRE_VERSION | GRE_ROUTING) != 0 {
+        return None;
+    }
+    let mut len = 4;
+    for opt in &[GRE_CHECKSUM, GRE_KEY, GRE_SEQ] {
+        if flags & opt != 0 {
+            len += 4;
+        }
+    }
+    if data.len() < len {
+        return None;
+    }
+    let next = match be16(&data[2..]) {
+        ETHERTYPE_TEB => Next::Ethernet,
+        // Type I has no ERSPAN header (and no sequence number)
+        ETHERTYPE_ERSPAN_2 if flags & GRE_SEQ == 0 => Next::Ethernet,
+        ETHERTYPE_ERSPAN_2 => Next::Erspan2,
+        ETHERTYPE_ERSPAN_3 => Next::Erspan3,
+        ethertype => from_ethertype(ethertype)?,
+    };
+    Some((len, next))
+}
+
+fn erspan2(data: &[u8]) -> Option<(usize, Next)>
+{
+    match data.first().map(|b| b >> 4) {
+        Some(1) if data.len() >= 8 => Some((8, Next::Ethernet)),
+        _ => None,
+    }
+}
+
+// Type III can carry IP packets as well as Ethernet frames (the frame type),
+// and may have an 8 byte platform specific subheader (the O flag).
+fn erspan3(data: &[u8]) -> Option<(usize, Next)>
+{
+    if data.len() < 12 || data[0] >> 4 != 2 {
+        return None;
+    }
+    let len = if data[11] & 0x1 != 0 { 20 } else { 12 };
+    match (data[10] >> 2) & 0x1f {
+        0 => Some((len, Next::Ethernet)),
+        2 => match data.get(len).map(|b| b >> 4) {
+            Some(4) => Some((len, Next::Ip(false))),
+            Some(6) => Some((len, Next::Ip(true))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn vxlan(data: &[u8]) -> Option<(usize, Next)>
+{
+    match data.first() {
+        Some(flags) if flags & VXLAN_VALID_VNI != 0 && data.len() >= 8 =>
+            Some((8, Next::Ethernet)),
+        _ => None,
+    }
+}
+
+// If the IP packet at the start of data carries a tunnel, where the tunnel
+// header starts (from the start of data) and what it is. Fragments are
+// never decapsulated.
+fn ip_tunnel(data: &[u8], ipv6: bool) -> Option<(usize, Next)>
+{
+    let ip = match ipv6 {
+        false => IpPacket::V4(Ipv4Packet::new(data)?),
+        true => IpPacket::V6(Ipv6Packet::new(data)?),
+    };
+    let (next, payload) = match ip.upper_layer() {
+        UpperLayer::Payload(IpNextHeaderProtocols::Gre, payload) => (Next::Gre, payload),
+        UpperLayer::Payload(IpNextHeaderProtocols::Udp, payload)
+            if payload.len() >= 8 && be16(&payload[2..]) == VXLAN_PORT => (Next::Vxlan, payload),
+        _ => return None,
+    };
+    let offset = payload.as_ptr() as usize - data.as_ptr() as usize;
+    match next {
+        Next::Vxlan => Some((offset + 8, next)),
+        _ => Some((offset, next)),
+    }
+}
+
+// Finds the IP packet in frame (which starts with an Ethernet header). None if
+// there isn't one, e.g. ARP, or a tunnel we couldn't get through (which is
+// counted).
+pub fn decapsulate(frame: &[u8], counters: &mut DecapCounters) -> Option<Decapsulated>
+{
+    let mut pos = 0;
+    let mut eth_offset = 0;
+    let mut next = Next::Ethernet;
+    let mut tunneled = false;
+    if frame.len() < 14 {
+        return None;
+    }
+
+    for _ in 0..MAX_LAYERS {
+        let data = &frame[pos..];
+        let step = match next {
+            Next::Ethernet => {
+                eth_offset = pos;
+                match ethernet(data, counters) {
+                    Some((len, Some(next))) => Some((len, next)),
+                    // Not IP (or MPLS), so not something we look at, unless it
+                    // came out of a tunnel
+                    Some((_, None)) if !tunneled => return None,
+                    _ => None,
+                }
+            },
+            Next::Ip(ipv6) => match ip_tunnel(data, ipv6) {
+                Some(step) => {
+                    tunneled = true;
+                    Some(step)
+                },
+                None => return Some(Decapsulated { eth_offset, ip_offset: pos, ipv6 }),
+            },
+            Next::Mpls => {
+                counters.mpls += 1;
+                mpls(data)
+            },
+            Next::Gre => {
+                counters.gre += 1;
+                gre(data)
+            },
+            Next::Erspan2 => {
+                counters.erspan += 1;
+                erspan2(data)
+            },
+            Next::Erspan3 => {
+                counters.erspan += 1;
+                erspan3(data)
+            },
+            Next::Vxlan => {
+                counters.vxlan += 1;
+                vxlan(data)
+            },
+        };
+        match step {
+            Some((len, n)) if len <= data.len() => {
+                pos += len;
+                next = n;
+            },
+            _ => {
+                counters.failed += 1;
+                return None;
+            },
+        }
+    }
+    counters.failed += 1;
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use decap::*;
+
+    const MACS: [u8; 12] = [0x02, 0, 0, 0, 0, 0xdd, 0x02, 0, 0, 0, 0, 0x55];
+
+    // IPv4 header (no checksum) around payload
+    fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8>
+    {
+        let len = 20 + payload.len();
+        let mut p = vec![0x45, 0, (len >> 8) as u8, len as u8, 0, 1, 0, 0, 64, protocol, 0, 0,
+                         10, 0, 0, 1, 10, 0, 0, 2];
+        p.extend_from_slice(payload);
+        p
+    }
+
+    fn eth(tags: &[[u8; 4]], ethertype: u16, payload: &[u8]) -> Vec<u8>
+    {
+        let mut f = MACS.to_vec();
+        for tag in tags {
+            f.extend_from_slice(tag);
+        }
+        f.push((ethertype >> 8) as u8);
+        f.push(ethertype as u8);
+        f.extend_from_slice(payload);
+        f
+    }
+
+    fn syn() -> Vec<u8>
+    {
+        ipv4(6, &[0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0,
+                  0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0])
+    }
+
+    fn cat(parts: &[&[u8]]) -> Vec<u8>
+    {
+        parts.iter().flat_map(|p| p.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn test_plain()
+    {
+        let mut c = DecapCounters::default();
+        let inner = syn();
+
+        let d = decapsulate(&eth(&[], 0x0800, &inner), &mut c).unwrap();
+        assert_eq!(d, Decapsulated { eth_offset: 0, ip_offset: 14, ipv6: false });
+
+        // QinQ
+        let f = eth(&[[0x88, 0xa8, 0, 10], [0x81, 0x00, 0, 20]], 0x0800, &inner);
+        let d = decapsulate(&f, &mut c).unwrap();
+        assert_eq!(d.ip_offset, 22);
+        assert_eq!(d.ip(&f).unwrap().tcp().unwrap().get_destination(), 443);
+        let f = eth(&[[0x81, 0x00, 0, 10]], 0x86dd, &[0x60; 40]);
+        assert_eq!(decapsulate(&f, &mut c).unwrap().ip_offset, 18);
+
+        // ARP isn't a failure; a truncated tag is
+        assert!(decapsulate(&eth(&[], 0x0806, &[0; 28]), &mut c).is_none());
+        assert!(decapsulate(&eth(&[], 0x8100, &[0, 10]), &mut c).is_none());
+        assert_eq!(c, DecapCounters { vlan: 1, qinq: 1, failed: 1, ..Default::default() });
+
+        // Two labels, then IPv4
+        let f = eth(&[], 0x8847, &cat(&[&[0, 1, 0, 64, 0, 2, 1, 64], &inner]));
+        assert_eq!(decapsulate(&f, &mut c).unwrap().ip_offset, 22);
+        assert_eq!(c.mpls, 1);
+    }
+
+    #[test]
+    fn test_tunnels()
+    {
+        let mut c = DecapCounters::default();
+        let inner = syn();
+        let inner_eth = eth(&[[0x81, 0x00, 0, 30]], 0x0800, &inner);
+
+        // GRE with a key and sequence number, carrying IP
+        let gre = ipv4(47, &cat(&[&[0x30, 0, 0x08, 0x00, 0, 0, 0, 5, 0, 0, 0, 9], &inner]));
+        let f = eth(&[], 0x0800, &gre);
+        let d = decapsulate(&f, &mut c).unwrap();
+        assert_eq!(d, Decapsulated { eth_offset: 0, ip_offset: 14 + 20 + 12, ipv6: false });
+        assert_eq!(&f[d.ip_offset..], &inner[..]);
+
+        // ERSPAN type II (GRE with a sequence number) of a tagged frame
+        let erspan = ipv4(47, &cat(&[&[0x10, 0, 0x88, 0xbe, 0, 0, 0, 1],
+                                     &[0x10, 0, 0, 1, 0, 0, 0, 0], &inner_eth]));
+        let f = eth(&[], 0x0800, &erspan);
+        let d = decapsulate(&f, &mut c).unwrap();
+        assert_eq!(d.eth_offset, 14 + 20 + 16);
+        assert_eq!(&f[d.eth_offset..], &inner_eth[..]);
+
+        // ERSPAN type III with the subheader
+        let erspan = ipv4(47, &cat(&[&[0x10, 0, 0x22, 0xeb, 0, 0, 0, 1],
+                                     &[0x20, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1], &[0; 8],
+                                     &inner_eth]));
+        let f = eth(&[], 0x0800, &erspan);
+        assert_eq!(decapsulate(&f, &mut c).unwrap().eth_offset, 14 + 20 + 28);
+
+        // VXLAN
+        let vxlan = ipv4(17, &cat(&[&[0xc0, 0x00, 0x12, 0xb5, 0, 0, 0, 0],
+                                    &[0x08, 0, 0, 0, 0, 0, 42, 0], &inner_eth]));
+        let f = eth(&[], 0x0800, &vxlan);
+        let d = decapsulate(&f, &mut c).unwrap();
+        assert_eq!(d.ip_offset, 14 + 20 + 16 + 18);
+        assert_eq!(&f[d.ip_offset..], &inner[..]);
+
+        assert_eq!(c, DecapCounters { vlan: 3, gre: 3, erspan: 2, vxlan: 1,
+                                      ..Default::default() });
+
+        // Unknown GRE payload, a truncated tunnel, and tunnels all the way down
+        let f = eth(&[], 0x0800, &ipv4(47, &[0, 0, 0x12, 0x34]));
+        assert!(decapsulate(&f, &mut c).is_none());
+        let f = eth(&[], 0x0800, &ipv4(47, &[0x20, 0, 0x08, 0x00, 0, 0]));
+        assert!(decapsulate(&f, &mut c).is_none());
+        let mut f = syn();
+        for _ in 0..MAX_LAYERS {
+            f = ipv4(47, &cat(&[&[0, 0, 0x08, 0x00], &f]));
+        }
+        assert!(decapsulate(&eth(&[], 0x0800, &f), &mut c).is_none());
+        assert_eq!(c.failed, 3);
+    }
+}
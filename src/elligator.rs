@@ -1,6 +1,6 @@
 use std::panic;
 
-use c_api;
+use curve25519;
 
 use std::error::Error;
 use util::{HKDFKeys, FSP};
@@ -81,8 +81,8 @@ pub fn extract_payloads(secret_key: &[u8], tls_record: &[u8]) -> Result<([u8; 32
             // client should randomize first (and second) bit, here we set it back to 0
             stego_repr_and_fsp[31] &= 0x3f;
 
-            let mut shared_secret: [u8; 32] = [0; 32];
-            c_api::c_get_shared_secret_from_tag(secret_key, &mut stego_repr_and_fsp, &mut shared_secret);
+            let shared_secret = curve25519::shared_secret_from_representative(
+                array_ref![secret_key, 0, 32], array_ref![stego_repr_and_fsp, 0, 32]);
 
             let keys = match HKDFKeys::new(shared_secret.as_ref()) {
                 Ok(keys) => keys,
@@ -1,6 +1,6 @@
-use std::collections::{HashSet, VecDeque};
-use time::precise_time_ns;
+use clock::now_ns;
 
+use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
 use pnet::packet::tcp::TcpPacket;
 use pnet::packet::udp::UdpPacket;
@@ -8,7 +8,10 @@ use pnet::packet::udp::UdpPacket;
 use util::IpPacket;
 use std::fmt;
 
-use sessions::SessionTracker;
+use client_ip::{self, write_client, ClientIpMode};
+use sessions::{ConnCounts, ConnEvent, ConnUpdate, IngestConfig, SessionKey, SessionTracker};
+use tcp_reassembly::{Reassembly, TlsRecordReassembler};
+use timer_wheel::{TimerWheel, DEFAULT_TICK_NS};
 
 // All members are stored in host-order, even src_ip and dst_ip.
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
@@ -20,22 +23,12 @@ pub struct Flow
     pub dst_port: u16,
 }
 
-// flow log client should only ever be set at initialization so this should
-// never result in a race condition. Also all threads read the same
-// environment variable so they will all set it the same.  
-pub static mut FLOW_CLIENT_LOG: bool = false;
 impl fmt::Display for Flow {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let socket_src = SocketAddr::new(self.src_ip, self.src_port);
         let socket_dst = SocketAddr::new(self.dst_ip, self.dst_port);
-
-        unsafe {
-            match FLOW_CLIENT_LOG {
-                true => write!(f, "{} -> {}",socket_src, socket_dst),
-                false => write!(f, "_ -> {}", socket_dst),
-            }
-        }
+        write_client(f, self.src_ip, Some(self.src_port))?;
+        write!(f, " -> {}", socket_dst)
     }
 }
 
@@ -96,13 +89,23 @@ impl Flow
         return (src_bytes, dst_bytes)
     }
 
+    // Full addresses or none; see client_ip for the other modes.
     pub fn set_log_client(log: bool) {
-        unsafe {
-            FLOW_CLIENT_LOG = log;
-        }
+        client_ip::set_mode(match log {
+            true => ClientIpMode::Full,
+            false => ClientIpMode::Hidden,
+        });
     }
 }
 
+// Transport protocol of a flow to a phantom.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum Transport
+{
+    Tcp,
+    Udp,
+}
+
 // All members are stored in host-order, even src_ip and dst_ip.
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub struct FlowNoSrcPort
@@ -110,20 +113,15 @@ pub struct FlowNoSrcPort
     pub src_ip: IpAddr,
     pub dst_ip: IpAddr,
     pub dst_port: u16,
+    pub transport: Transport,
 }
 
 
 impl fmt::Display for FlowNoSrcPort {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let socket_src = SocketAddr::new(self.src_ip, 0);
         let socket_dst = SocketAddr::new(self.dst_ip, self.dst_port);
-
-        unsafe {
-            match FLOW_CLIENT_LOG {
-                true => write!(f, "{} -> {}",socket_src, socket_dst),
-                false => write!(f, "_ -> {}", socket_dst),
-            }
-        }
+        write_client(f, self.src_ip, Some(0))?;
+        write!(f, " -> {}", socket_dst)
     }
 }
 
@@ -135,19 +133,39 @@ impl FlowNoSrcPort {
                 src_ip: IpAddr::V4(pkt.get_source()),
                 dst_ip: IpAddr::V4(pkt.get_destination()),
                 dst_port: tcp_pkt.get_destination(),
+                transport: Transport::Tcp,
             },
             IpPacket::V6(pkt) => FlowNoSrcPort {
                 src_ip: IpAddr::V6(pkt.get_source()),
                 dst_ip: IpAddr::V6(pkt.get_destination()),
                 dst_port: tcp_pkt.get_destination(),
+                transport: Transport::Tcp,
+            },
+        }
+    }
+
+    pub fn new_udp(ip_pkt: &IpPacket, udp_pkt: &UdpPacket) -> FlowNoSrcPort
+    {
+        match ip_pkt {
+            IpPacket::V4(pkt) => FlowNoSrcPort {
+                src_ip: IpAddr::V4(pkt.get_source()),
+                dst_ip: IpAddr::V4(pkt.get_destination()),
+                dst_port: udp_pkt.get_destination(),
+                transport: Transport::Udp,
+            },
+            IpPacket::V6(pkt) => FlowNoSrcPort {
+                src_ip: IpAddr::V6(pkt.get_source()),
+                dst_ip: IpAddr::V6(pkt.get_destination()),
+                dst_port: udp_pkt.get_destination(),
+                transport: Transport::Udp,
             },
         }
     }
-    pub fn from_parts(sip: IpAddr, dip: IpAddr,  dport: u16) -> FlowNoSrcPort
+    pub fn from_parts(sip: IpAddr, dip: IpAddr,  dport: u16, transport: Transport) -> FlowNoSrcPort
     {
-        FlowNoSrcPort { src_ip: sip, dst_ip: dip, dst_port: dport }
+        FlowNoSrcPort { src_ip: sip, dst_ip: dip, dst_port: dport, transport: transport }
     }
-    pub fn from_flow(f: &Flow) -> FlowNoSrcPort {FlowNoSrcPort{src_ip: f.src_ip, dst_ip: f.dst_ip, dst_port: f.dst_port}}
+    pub fn from_flow(f: &Flow, transport: Transport) -> FlowNoSrcPort {FlowNoSrcPort{src_ip: f.src_ip, dst_ip: f.dst_ip, dst_port: f.dst_port, transport: transport}}
 
     pub fn export_addrs(&self) -> (Vec<u8>, Vec<u8>) {
         let src_bytes = match self.src_ip {
@@ -164,31 +182,48 @@ impl FlowNoSrcPort {
     }
 }
 
-pub struct SchedEvent
-{
-    // Nanoseconds since an unspecified epoch (precise_time_ns()).
-    drop_time: u64,
-    flow: Flow,
-}
-
 pub struct FlowTracker
 {
     // Keys present in this map are potentially tagged flows.
     // Key not present in map => sure flow isn't of interest. Ignore all non-SYN packets.
-    // Key present, value InTLSHandshake => don't yet know if it's of interest yet
-    tracked_flows: HashSet<Flow>,
-
-    // stale_drops_tracked is used to periodically drop idle flows that are being tracked.
-    stale_drops_tracked: VecDeque<SchedEvent>,
+    // Key present => don't yet know if it's of interest yet. Flows are
+    // dropped TIMEOUT_TRACKED_NS after their last SYN.
+    tracked_flows: TimerWheel<Flow>,
 
     // Known dark decoy destination IPs that should be picked up.
     // Map values are timeouts, which are used to drop stale dark decoys
     pub phantom_flows: SessionTracker,
     // pub phantom_flows: Arc<RwLock<HashMap<IpAddr, u64>>>,
+
+    // Tracked flows whose first TLS app data record didn't fit in one segment.
+    reassembly: TlsRecordReassembler,
+
+    // UDP flows (e.g. QUIC) to registered phantoms that we are forwarding.
+    // There's no handshake or close to key off of, so they're dropped once
+    // they've gone TIMEOUT_UDP_NS without a datagram.
+    udp_phantom_flows: TimerWheel<Flow>,
+    // How many of those each session has
+    udp_session_flows: HashMap<SessionKey, usize>,
+}
+
+// What mark_udp_phantom_flow made of a datagram
+#[derive(Debug, PartialEq)]
+pub enum UdpFlowMark
+{
+    // First we've seen of the flow (or it had gone idle)
+    New,
+    Known,
+    // Its session already has MAX_UDP_FLOWS_PER_SESSION flows. It isn't
+    // followed, but its datagrams are still forwarded.
+    OverLimit,
 }
 
 // Amount of time that we timeout all flows
 const TIMEOUT_TRACKED_NS: u64 = 30 * 1000 * 1000 * 1000;
+// Idle time after which we forget a UDP phantom flow
+const TIMEOUT_UDP_NS: u64 = 60 * 1000 * 1000 * 1000;
+// UDP flows followed per session (like MAX_CONNS_PER_SESSION for TCP)
+const MAX_UDP_FLOWS_PER_SESSION: usize = 1024;
 //const FIN_TIMEOUT_NS: u64 = 2*1000*1000*1000;
 
 
@@ -196,31 +231,42 @@ const TIMEOUT_TRACKED_NS: u64 = 30 * 1000 * 1000 * 1000;
 
 impl FlowTracker
 {
-    pub fn new() -> FlowTracker
+    pub fn new(ingest: IngestConfig) -> FlowTracker
     {
 
-        let ret = FlowTracker
-            {
-                tracked_flows: HashSet::new(),
-                phantom_flows: SessionTracker::new(),
-                stale_drops_tracked: VecDeque::with_capacity(16384),
-            };
+        let ret = FlowTracker::new_without_ingest();
 
         // launch thread to ingest from redis
-        ret.phantom_flows.spawn_update_thread();
+        ret.phantom_flows.spawn_update_thread(ingest);
         ret
     }
+
+    // Same as new(), but no redis ingest thread is launched; sessions only get
+    // into phantom_flows if the caller adds them (e.g. offline replay).
+    pub fn new_without_ingest() -> FlowTracker
+    {
+        FlowTracker::with_sessions(SessionTracker::new())
+    }
+
+    // Sessions come from phantom_flows, e.g. one reading the session table
+    // shared by every core.
+    pub fn with_sessions(phantom_flows: SessionTracker) -> FlowTracker
+    {
+        let now = now_ns();
+        FlowTracker
+            {
+                tracked_flows: TimerWheel::new(DEFAULT_TICK_NS, now),
+                phantom_flows: phantom_flows,
+                reassembly: TlsRecordReassembler::new(),
+                udp_phantom_flows: TimerWheel::new(DEFAULT_TICK_NS, now),
+                udp_session_flows: HashMap::new(),
+            }
+    }
     pub fn begin_tracking_flow(&mut self, flow: &Flow)
     {
-        // Always push back, even if the entry was already there. Doesn't hurt
-        // to do a second check on overdueness, and this is simplest.
-        self.stale_drops_tracked.push_back(
-            SchedEvent {
-                drop_time: precise_time_ns() + TIMEOUT_TRACKED_NS,
-                flow: *flow,
-            });
-        // Begin tracking as a potential TD flow (if not already in the set).
-        self.tracked_flows.insert(*flow);
+        // Begin tracking as a potential TD flow, or give it more time if a
+        // retransmitted SYN shows it's still trying.
+        self.tracked_flows.insert(*flow, now_ns() + TIMEOUT_TRACKED_NS);
     }
 
 
@@ -231,50 +277,72 @@ impl FlowTracker
 
     pub fn is_tracked_flow(&self, flow: &Flow) -> bool
     {
-        self.tracked_flows.contains(&flow)
+        self.tracked_flows.contains_key(flow)
     }
 
     /// used to update (increase) the time that we  consider a session 
     /// valid for tracking purposes. Called when packets from a session are
     /// seen so that forwarding continues past the original registration timeout. 
-    pub fn update_phantom_flow(&mut self, flow: &FlowNoSrcPort)
+    pub fn update_phantom_flow(&mut self, flow: &FlowNoSrcPort, len: usize)
+    {
+        self.phantom_flows.update_session(flow, len)
+    }
+
+    // Follows the state of a TCP connection to a registered phantom; see
+    // SessionTracker::update_connection.
+    pub fn update_phantom_connection(&mut self, flow: &Flow, event: ConnEvent, len: usize)
+        -> Option<ConnUpdate>
+    {
+        self.phantom_flows.update_connection(flow, event, len)
+    }
+
+    // Notes a datagram on a UDP flow to a registered phantom.
+    pub fn mark_udp_phantom_flow(&mut self, flow: &Flow) -> UdpFlowMark
     {
-        self.phantom_flows.update_session(flow)
+        let idle_time = now_ns() + TIMEOUT_UDP_NS;
+        if self.udp_phantom_flows.contains_key(flow) {
+            self.udp_phantom_flows.insert(*flow, idle_time);
+            return UdpFlowMark::Known;
+        }
+        let count = self.udp_session_flows.entry(udp_session_key(flow)).or_insert(0);
+        if *count >= MAX_UDP_FLOWS_PER_SESSION {
+            return UdpFlowMark::OverLimit;
+        }
+        *count += 1;
+        self.udp_phantom_flows.insert(*flow, idle_time);
+        UdpFlowMark::New
     }
 
     pub fn stop_tracking_flow(&mut self, flow: &Flow)
     {
         self.tracked_flows.remove(flow);
+        self.reassembly.remove(flow);
+    }
+
+    pub fn is_reassembling(&self, flow: &Flow) -> bool
+    {
+        self.reassembly.is_reassembling(flow)
+    }
+
+    // Starts collecting the TLS record at the start of payload (the first app
+    // data segment of a tracked flow). Complete if it's all there already.
+    pub fn begin_reassembly(&mut self, flow: &Flow, seq: u32, payload: &[u8]) -> Reassembly
+    {
+        self.reassembly.start(flow, seq, payload, now_ns())
+    }
+
+    pub fn continue_reassembly(&mut self, flow: &Flow, seq: u32, payload: &[u8]) -> Reassembly
+    {
+        self.reassembly.add_segment(flow, seq, payload)
     }
 
     // drop_stale_tracked_flows returns the number of tracked flows that it drops.
     fn drop_stale_tracked_flows(&mut self) -> usize {
-        let right_now = precise_time_ns();
-        let num_tracked_flows_before = self.tracked_flows.len();
-        loop {
-            let flow = match self.stale_drops_tracked.front() {
-                Some(cur) => {
-                    if cur.drop_time <= right_now {
-                        Some(cur.flow)
-                    } else {
-                        None
-                    }
-                }
-                None => None,
-            };
-            match flow {
-                Some(flow) => {
-                    self.stale_drops_tracked.pop_front();
-                    self.tracked_flows.remove(&flow);
-                },
-                None => {
-                    // entries in stale_drops_tracked are supposed to be sorted by time, so
-                    // once we see a flow that doesn't need to be removed, then
-                    // there is no need to check further
-                    return num_tracked_flows_before - self.tracked_flows.len();
-                }
-            }
-        }
+        let right_now = now_ns();
+        let reassembly = &mut self.reassembly;
+        let dropped = self.tracked_flows.expire(right_now, |flow| reassembly.remove(&flow));
+        self.reassembly.drop_stale(right_now);
+        dropped
     }
 
     // drop_stale_phantom_flows returns the number of registered dark decoy
@@ -283,11 +351,31 @@ impl FlowTracker
         self.phantom_flows.drop_stale_sessions()
     }
 
+    // drop_stale_udp_flows returns the number of idle UDP phantom flows that
+    // it drops.
+    fn drop_stale_udp_flows(&mut self) -> usize {
+        let counts = &mut self.udp_session_flows;
+        self.udp_phantom_flows.expire(now_ns(), |flow| {
+            let key = udp_session_key(&flow);
+            let last = match counts.get_mut(&key) {
+                Some(count) => {
+                    *count -= 1;
+                    *count == 0
+                },
+                None => false,
+            };
+            if last {
+                counts.remove(&key);
+            }
+        })
+    }
+
     // This function returns the number of flows that it drops.
     #[allow(non_snake_case)]
     pub fn drop_all_stale_flows(&mut self) -> usize
     {
         self.drop_stale_tracked_flows() + self.drop_stale_phantom_flows()
+            + self.drop_stale_udp_flows()
     }
 
     pub fn count_tracked_flows(&self) -> usize
@@ -298,12 +386,32 @@ impl FlowTracker
     {
         self.phantom_flows.len()
     }
+    pub fn count_phantom_connections(&self) -> ConnCounts
+    {
+        self.phantom_flows.total_connection_counts()
+    }
+    pub fn count_udp_phantom_flows(&self) -> usize
+    {
+        self.udp_phantom_flows.len()
+    }
+    pub fn count_reassembling_flows(&self) -> usize
+    {
+        self.reassembly.len()
+    }
+}
+
+
+fn udp_session_key(flow: &Flow) -> SessionKey
+{
+    SessionKey::from_flow(&FlowNoSrcPort::from_flow(flow, Transport::Udp))
 }
 
 
 #[cfg(test)]
 mod tests {
-    use flow_tracker::{FlowNoSrcPort, Flow};
+    use clock;
+    use flow_tracker::{FlowNoSrcPort, Flow, FlowTracker, Transport, UdpFlowMark};
+    use flow_tracker::{MAX_UDP_FLOWS_PER_SESSION, TIMEOUT_UDP_NS};
     use std::fmt::Write;
 
     #[test]
@@ -340,6 +448,7 @@ mod tests {
             src_ip: "2601::abcd:ef00".parse().unwrap(),
             dst_ip: "26ff::1".parse().unwrap(),
             dst_port: 443,
+            transport: Transport::Tcp,
         };
 
         let mut output = String::new();
@@ -352,6 +461,7 @@ mod tests {
             src_ip: "10.22.0.1".parse().unwrap(),
             dst_ip: "128.138.97.6".parse().unwrap(),
             dst_port: 443,
+            transport: Transport::Tcp,
         };
 
         let mut output = String::new();
@@ -391,6 +501,7 @@ mod tests {
             src_ip: "2601::abcd:ef00".parse().unwrap(),
             dst_ip: "26ff::1".parse().unwrap(),
             dst_port: 443,
+            transport: Transport::Tcp,
         };
 
         let mut output = String::new();
@@ -403,6 +514,7 @@ mod tests {
             src_ip: "10.22.0.1".parse().unwrap(),
             dst_ip: "128.138.97.6".parse().unwrap(),
             dst_port: 443,
+            transport: Transport::Tcp,
         };
 
         let mut output = String::new();
@@ -437,4 +549,51 @@ mod tests {
         assert_eq!(vec![0x26, 0x01, 0,0,0,0,0,0,0,0,0,0,0xab, 0xcd, 0xef, 0x00], src);
         assert_eq!(vec![0x26, 0xff, 0,0,0,0,0,0,0,0,0,0,   0,    0,    0,    1], dst);
     }
+
+    #[test]
+    fn test_udp_phantom_flows() {
+        let mut ft = FlowTracker::new_without_ingest();
+        let flow = Flow {
+            src_ip: "10.22.0.1".parse().unwrap(),
+            dst_ip: "128.138.97.6".parse().unwrap(),
+            src_port: 5672,
+            dst_port: 443,
+        };
+
+        assert_eq!(ft.mark_udp_phantom_flow(&flow), UdpFlowMark::New);
+        assert_eq!(ft.mark_udp_phantom_flow(&flow), UdpFlowMark::Known);
+        assert_eq!(ft.count_udp_phantom_flows(), 1);
+
+        // Not idle yet
+        assert_eq!(ft.drop_all_stale_flows(), 0);
+        assert_eq!(ft.count_udp_phantom_flows(), 1);
+    }
+
+    #[test]
+    fn test_udp_flows_per_session() {
+        let start = 1000 * 1000 * 1000 * 1000;
+        clock::follow_capture(start);
+        let mut ft = FlowTracker::new_without_ingest();
+        let flow = |client: &str, src_port: u16| Flow {
+            src_ip: client.parse().unwrap(),
+            dst_ip: "128.138.97.6".parse().unwrap(),
+            src_port,
+            dst_port: 443,
+        };
+
+        for port in 0..MAX_UDP_FLOWS_PER_SESSION as u16 {
+            assert_eq!(ft.mark_udp_phantom_flow(&flow("10.22.0.1", port)), UdpFlowMark::New);
+        }
+        assert_eq!(ft.mark_udp_phantom_flow(&flow("10.22.0.1", 5000)), UdpFlowMark::OverLimit);
+        assert_eq!(ft.mark_udp_phantom_flow(&flow("10.22.0.1", 0)), UdpFlowMark::Known);
+        // Another client's session has its own
+        assert_eq!(ft.mark_udp_phantom_flow(&flow("10.22.0.2", 5000)), UdpFlowMark::New);
+        assert_eq!(ft.count_udp_phantom_flows(), MAX_UDP_FLOWS_PER_SESSION + 1);
+
+        // Room again once they've gone idle
+        clock::follow_capture(start + 2 * TIMEOUT_UDP_NS);
+        assert_eq!(ft.drop_all_stale_flows(), MAX_UDP_FLOWS_PER_SESSION + 1);
+        assert!(ft.udp_session_flows.is_empty());
+        assert_eq!(ft.mark_udp_phantom_flow(&flow("10.22.0.1", 5000)), UdpFlowMark::New);
+    }
 }
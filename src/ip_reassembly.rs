@@ -0,0 +1,502 @@
+//
+// IP fragment reassembly
+//
+// Fragmented packets (IPv4, or IPv6 with a Fragment header) are collected
+// here until they're whole, so that registrations and phantom traffic that
+// got fragmented on the way are seen like everything else. The result is the
+// complete IP packet, headers fixed up, as if it had never been fragmented.
+//
+// As in tcp_reassembly, everything is bounded: at most MAX_PACKETS packets
+// and MAX_BUFFERED_BYTES bytes are held per core, each source address may
+// have max_per_source packets in progress, a packet may come in at most
+// MAX_FRAGMENTS pieces and 65535 bytes, and a packet that isn't complete
+// within the timeout is forgotten. Fragments that overlap drop the whole
+// packet (RFC 5722); exact duplicates are ignored.
+//
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::IpAddr;
+
+use pnet::packet::Packet;
+use pnet::packet::ip::IpNextHeaderProtocol;
+
+use tcp_reassembly::Reassembly;
+use util::{self, FragmentInfo, IpPacket};
+
+pub const MAX_PACKETS: usize = 1024;
+pub const MAX_BUFFERED_BYTES: usize = 8 * 1024 * 1024;
+pub const MAX_FRAGMENTS: usize = 64;
+// Largest payload a fragmented packet can have (the IP length fields are 16
+// bits, and we don't do jumbograms)
+const MAX_PAYLOAD_LEN: usize = 65535;
+pub const DEFAULT_MAX_PER_SOURCE: usize = 16;
+pub const DEFAULT_TIMEOUT_NS: u64 = 30 * 1000 * 1000 * 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct FragKey
+{
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    id: u32,
+}
+
+// Totals since the reassembler was created
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct FragmentCounters
+{
+    pub reassembled: u64,
+    // Packets given up on (over a limit, overlapping or inconsistent
+    // fragments), or lone fragments refused for the same reasons
+    pub dropped: u64,
+    // Fragments refused because their source had too many packets pending
+    pub source_limited: u64,
+    // Packets still incomplete when their time ran out
+    pub timed_out: u64,
+}
+
+struct PartialPacket
+{
+    // Headers of the first fragment (for IPv6, up to but not including the
+    // Fragment header), once it's here
+    header: Option<Vec<u8>>,
+    // Payload length, once the last fragment is here
+    total: Option<usize>,
+    // Fragment data by offset; they never overlap
+    frags: BTreeMap<usize, Vec<u8>>,
+    received: usize,
+    expire_time: u64,
+}
+
+pub struct FragmentReassembler
+{
+    partial: HashMap<FragKey, PartialPacket>,
+    per_source: HashMap<IpAddr, usize>,
+    // Expiry events, sorted by time, as in TlsRecordReassembler
+    expiries: VecDeque<(u64, FragKey)>,
+    bytes_buffered: usize,
+    max_per_source: usize,
+    timeout_ns: u64,
+    counters: FragmentCounters,
+}
+
+fn ipv4_header_checksum(hdr: &[u8]) -> u16
+{
+    let mut sum: u32 = 0;
+    for (i, pair) in hdr.chunks(2).enumerate() {
+        if i == 5 {
+            continue; // the checksum itself
+        }
+        sum += (pair[0] as u32) << 8 | *pair.get(1).unwrap_or(&0) as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+// The whole packet from the first fragment's headers and the payload.
+fn build_packet(mut header: Vec<u8>, protocol: IpNextHeaderProtocol,
+                frags: &BTreeMap<usize, Vec<u8>>, total: usize) -> Option<Vec<u8>>
+{
+    match header[0] >> 4 {
+        4 => {
+            let len = header.len() + total;
+            if len > MAX_PAYLOAD_LEN {
+                return None;
+            }
+            header[2] = (len >> 8) as u8;
+            header[3] = len as u8;
+            // Keep DF (and the reserved bit), clear MF and the offset
+            header[6] &= 0xc0;
+            header[7] = 0;
+            let sum = ipv4_header_checksum(&header);
+            header[10] = (sum >> 8) as u8;
+            header[11] = sum as u8;
+        },
+        6 => {
+            let len = header.len() - 40 + total;
+            if len > MAX_PAYLOAD_LEN {
+                return None;
+            }
+            header[4] = (len >> 8) as u8;
+            header[5] = len as u8;
+            // Whatever pointed at the Fragment header now points at what it
+            // carried
+            let mut next_at = 6;
+            let mut pos = 40;
+            while pos < header.len() {
+                let hdr_len = util::ipv6_ext_header_len(IpNextHeaderProtocol(header[next_at]),
+                                                        &header[pos..])?;
+                next_at = pos;
+                pos += hdr_len;
+            }
+            header[next_at] = protocol.0;
+        },
+        _ => return None,
+    }
+    let mut pkt = header;
+    pkt.reserve(total);
+    for data in frags.values() {
+        pkt.extend_from_slice(data);
+    }
+    Some(pkt)
+}
+
+impl FragmentReassembler
+{
+    pub fn new(max_per_source: usize, timeout_ns: u64) -> FragmentReassembler
+    {
+        FragmentReassembler {
+            partial: HashMap::new(),
+            per_source: HashMap::new(),
+            expiries: VecDeque::new(),
+            bytes_buffered: 0,
+            max_per_source,
+            timeout_ns,
+            counters: FragmentCounters::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.partial.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.partial.is_empty()
+    }
+
+    pub fn bytes_buffered(&self) -> usize
+    {
+        self.bytes_buffered
+    }
+
+    pub fn counters(&self) -> FragmentCounters
+    {
+        self.counters
+    }
+
+    // Adds one fragment of ip: frag and data as IpPacket::upper_layer found
+    // them. Returns the whole packet once this completes it.
+    pub fn add(&mut self, ip: &IpPacket, frag: &FragmentInfo, data: &[u8], now: u64)
+        -> Reassembly
+    {
+        let (key, header) = match ip {
+            IpPacket::V4(p) => {
+                let ihl = (p.get_header_length() as usize * 4).min(p.packet().len());
+                (FragKey {
+                    src: IpAddr::V4(p.get_source()),
+                    dst: IpAddr::V4(p.get_destination()),
+                    protocol: frag.protocol.0,
+                    id: frag.id,
+                }, &p.packet()[..ihl])
+            },
+            IpPacket::V6(p) => {
+                // data is the end of the payload; before it come the
+                // unfragmentable headers and the Fragment header
+                let unfragmentable = p.payload().len().saturating_sub(data.len() + 8);
+                (FragKey {
+                    src: IpAddr::V6(p.get_source()),
+                    dst: IpAddr::V6(p.get_destination()),
+                    protocol: frag.protocol.0,
+                    id: frag.id,
+                }, &p.packet()[..40 + unfragmentable])
+            },
+        };
+
+        let end = frag.offset + data.len();
+        // Only the last fragment may be short of a multiple of 8 bytes
+        if end > MAX_PAYLOAD_LEN || (frag.more && (data.is_empty() || data.len() & 7 != 0)) {
+            return self.give_up(&key);
+        }
+
+        if !self.partial.contains_key(&key) {
+            if *self.per_source.get(&key.src).unwrap_or(&0) >= self.max_per_source {
+                self.counters.source_limited += 1;
+                return Reassembly::Dropped;
+            }
+            if self.partial.len() >= MAX_PACKETS {
+                self.counters.dropped += 1;
+                return Reassembly::Dropped;
+            }
+            let expire_time = now + self.timeout_ns;
+            self.partial.insert(key, PartialPacket {
+                header: None,
+                total: None,
+                frags: BTreeMap::new(),
+                received: 0,
+                expire_time,
+            });
+            *self.per_source.entry(key.src).or_insert(0) += 1;
+            self.expiries.push_back((expire_time, key));
+        }
+
+        {
+            let pkt = self.partial.get_mut(&key).unwrap();
+
+            let overlaps_prev = match pkt.frags.range(..=frag.offset).next_back() {
+                Some((&off, prev)) if off == frag.offset && prev.len() == data.len() => {
+                    // A duplicate (retransmission, or the network repeating itself)
+                    return Reassembly::Pending;
+                },
+                Some((&off, prev)) => off + prev.len() > frag.offset,
+                None => false,
+            };
+            let overlaps_next = match pkt.frags.range(frag.offset + 1..).next() {
+                Some((&off, _)) => off < end,
+                None => false,
+            };
+            let past_end = match pkt.total {
+                Some(total) => end > total || (!frag.more && end != total),
+                None => !frag.more && pkt.frags.iter().next_back()
+                    .map(|(off, d)| off + d.len() > end).unwrap_or(false),
+            };
+            if overlaps_prev || overlaps_next || past_end
+                || pkt.frags.len() >= MAX_FRAGMENTS
+                || self.bytes_buffered + data.len() > MAX_BUFFERED_BYTES {
+                return self.give_up(&key);
+            }
+
+            pkt.frags.insert(frag.offset, data.to_vec());
+            pkt.received += data.len();
+            self.bytes_buffered += data.len();
+            if frag.offset == 0 {
+                pkt.header = Some(header.to_vec());
+            }
+            if !frag.more {
+                pkt.total = Some(end);
+            }
+            if pkt.header.is_none() || pkt.total != Some(pkt.received) {
+                return Reassembly::Pending;
+            }
+        }
+
+        // No overlaps, and as many bytes as the packet is long: it's all here
+        let pkt = self.remove(&key).unwrap();
+        match build_packet(pkt.header.unwrap(), frag.protocol, &pkt.frags, pkt.received) {
+            Some(whole) => {
+                self.counters.reassembled += 1;
+                Reassembly::Complete(whole)
+            },
+            None => {
+                self.counters.dropped += 1;
+                Reassembly::Dropped
+            },
+        }
+    }
+
+    fn give_up(&mut self, key: &FragKey) -> Reassembly
+    {
+        self.remove(key);
+        self.counters.dropped += 1;
+        Reassembly::Dropped
+    }
+
+    fn remove(&mut self, key: &FragKey) -> Option<PartialPacket>
+    {
+        let pkt = self.partial.remove(key)?;
+        self.bytes_buffered -= pkt.received;
+        let last = match self.per_source.get_mut(&key.src) {
+            Some(n) => {
+                *n -= 1;
+                *n == 0
+            },
+            None => false,
+        };
+        if last {
+            self.per_source.remove(&key.src);
+        }
+        Some(pkt)
+    }
+
+    // Forgets packets that weren't completed in time. Returns how many were
+    // dropped.
+    pub fn drop_stale(&mut self, now: u64) -> usize
+    {
+        let mut dropped = 0;
+        loop {
+            let (expire_time, key) = match self.expiries.front() {
+                Some(&(t, k)) if t <= now => (t, k),
+                _ => break,
+            };
+            self.expiries.pop_front();
+            let live = match self.partial.get(&key) {
+                Some(pkt) => pkt.expire_time == expire_time,
+                None => false,
+            };
+            if live {
+                self.remove(&key);
+                dropped += 1;
+            }
+        }
+        self.counters.timed_out += dropped as u64;
+        dropped
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pnet::packet::ipv4::Ipv4Packet;
+    use pnet::packet::ipv6::Ipv6Packet;
+    use ip_reassembly::*;
+    use tcp_reassembly::Reassembly;
+    use util::{IpPacket, UpperLayer};
+
+    // UDP to 443 with a payload of len bytes counting up from 0
+    fn udp(len: usize) -> Vec<u8>
+    {
+        let mut u = vec![0x9c, 0x40, 0x01, 0xbb, ((len + 8) >> 8) as u8, (len + 8) as u8, 0, 0];
+        for i in 0..len {
+            u.push(i as u8);
+        }
+        u
+    }
+
+    fn ipv4(src: u8, id: u16, payload: &[u8]) -> Vec<u8>
+    {
+        let len = 20 + payload.len();
+        let mut p = vec![0x45, 0, (len >> 8) as u8, len as u8, (id >> 8) as u8, id as u8,
+                         0, 0, 64, 17, 0, 0, 192, 0, 2, src, 198, 51, 100, 1];
+        let sum = ipv4_header_checksum(&p);
+        p[10] = (sum >> 8) as u8;
+        p[11] = sum as u8;
+        p.extend_from_slice(payload);
+        p
+    }
+
+    // Fragments of an IPv4 packet at the given payload offsets
+    fn fragment_v4(pkt: &[u8], cuts: &[usize]) -> Vec<Vec<u8>>
+    {
+        let payload = &pkt[20..];
+        let mut out = Vec::new();
+        for (i, &start) in cuts.iter().enumerate() {
+            let end = cuts.get(i + 1).cloned().unwrap_or(payload.len());
+            let mut f = pkt[..20].to_vec();
+            let len = 20 + end - start;
+            f[2] = (len >> 8) as u8;
+            f[3] = len as u8;
+            let more = if end < payload.len() { 0x20 } else { 0 };
+            f[6] = more | ((start / 8) >> 8) as u8;
+            f[7] = (start / 8) as u8;
+            f.extend_from_slice(&payload[start..end]);
+            out.push(f);
+        }
+        out
+    }
+
+    fn add(r: &mut FragmentReassembler, pkt: &[u8], now: u64) -> Reassembly
+    {
+        let ip = match pkt[0] >> 4 {
+            4 => IpPacket::V4(Ipv4Packet::new(pkt).unwrap()),
+            _ => IpPacket::V6(Ipv6Packet::new(pkt).unwrap()),
+        };
+        match ip.upper_layer() {
+            UpperLayer::Fragment(frag, data) => r.add(&ip, &frag, data, now),
+            other => panic!("not a fragment: {:?}", other),
+        }
+    }
+
+    fn complete(r: Reassembly) -> Vec<u8>
+    {
+        match r {
+            Reassembly::Complete(v) => v,
+            Reassembly::Pending => panic!("expected Complete, got Pending"),
+            Reassembly::Dropped => panic!("expected Complete, got Dropped"),
+        }
+    }
+
+    #[test]
+    fn test_ipv4()
+    {
+        let mut r = FragmentReassembler::new(DEFAULT_MAX_PER_SOURCE, DEFAULT_TIMEOUT_NS);
+        let pkt = ipv4(1, 0x1234, &udp(3000));
+        let frags = fragment_v4(&pkt, &[0, 1480, 2960]);
+
+        // Out of order, with a duplicate
+        assert!(matches!(add(&mut r, &frags[2], 0), Reassembly::Pending));
+        assert!(matches!(add(&mut r, &frags[0], 0), Reassembly::Pending));
+        assert!(matches!(add(&mut r, &frags[0], 0), Reassembly::Pending));
+        assert_eq!(r.len(), 1);
+        assert_eq!(r.bytes_buffered(), 1480 + 48);
+        assert_eq!(complete(add(&mut r, &frags[1], 0)), pkt);
+        assert!(r.is_empty());
+        assert_eq!(r.bytes_buffered(), 0);
+
+        // An overlap (a different second fragment) drops the whole packet
+        let other = fragment_v4(&pkt, &[0, 1472]);
+        assert!(matches!(add(&mut r, &frags[0], 0), Reassembly::Pending));
+        assert!(matches!(add(&mut r, &other[1], 0), Reassembly::Dropped));
+        assert!(r.is_empty());
+
+        // So does a middle fragment that isn't a multiple of 8 bytes
+        let odd = fragment_v4(&pkt, &[0, 1481]);
+        assert!(matches!(add(&mut r, &odd[0], 0), Reassembly::Dropped));
+
+        let c = r.counters();
+        assert_eq!((c.reassembled, c.dropped), (1, 2));
+    }
+
+    #[test]
+    fn test_ipv6()
+    {
+        let mut r = FragmentReassembler::new(DEFAULT_MAX_PER_SOURCE, DEFAULT_TIMEOUT_NS);
+
+        // IPv6 + Hop-by-Hop options + UDP, and the same with a Fragment
+        // header after the Hop-by-Hop options
+        let hdr = |next: u8, len: usize| {
+            let mut h = vec![0x60, 0, 0, 0, (len >> 8) as u8, len as u8, 0, 64];
+            h.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+            h.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+            h.extend_from_slice(&[next, 0, 1, 4, 0, 0, 0, 0]);
+            h
+        };
+        let body = udp(2000);
+        let mut pkt = hdr(17, 8 + body.len());
+        pkt.extend_from_slice(&body);
+
+        let mut first = hdr(44, 8 + 8 + 1232);
+        first.extend_from_slice(&[17, 0, 0, 1, 0, 0, 0, 9]);
+        first.extend_from_slice(&body[..1232]);
+        let mut last = hdr(44, 8 + 8 + body.len() - 1232);
+        last.extend_from_slice(&[17, 0, 0x04, 0xd0, 0, 0, 0, 9]);
+        last.extend_from_slice(&body[1232..]);
+
+        assert!(matches!(add(&mut r, &last, 0), Reassembly::Pending));
+        let whole = complete(add(&mut r, &first, 0));
+        assert_eq!(whole, pkt);
+        let ip = IpPacket::V6(Ipv6Packet::new(&whole).unwrap());
+        assert_eq!(ip.udp().unwrap().get_destination(), 443);
+    }
+
+    #[test]
+    fn test_limits()
+    {
+        let mut r = FragmentReassembler::new(2, 1000);
+        let first = |src: u8, id: u16| fragment_v4(&ipv4(src, id, &udp(100)), &[0, 56])[0].clone();
+
+        assert!(matches!(add(&mut r, &first(1, 1), 0), Reassembly::Pending));
+        assert!(matches!(add(&mut r, &first(1, 2), 500), Reassembly::Pending));
+        // Source 1 has as many as it's allowed
+        assert!(matches!(add(&mut r, &first(1, 3), 500), Reassembly::Dropped));
+        assert!(matches!(add(&mut r, &first(2, 3), 500), Reassembly::Pending));
+        assert_eq!(r.counters().source_limited, 1);
+
+        // The first one times out, making room
+        assert_eq!(r.drop_stale(1000), 1);
+        assert_eq!(r.len(), 2);
+        assert!(matches!(add(&mut r, &first(1, 3), 1000), Reassembly::Pending));
+        assert_eq!(r.drop_stale(2000), 3);
+        assert!(r.is_empty());
+        assert_eq!(r.bytes_buffered(), 0);
+        assert_eq!(r.counters().timed_out, 4);
+
+        // Fragments claiming to go past 64k
+        let mut big = first(3, 4);
+        big[6] = 0x3f;
+        big[7] = 0xff;
+        assert!(matches!(add(&mut r, &big, 0), Reassembly::Dropped));
+    }
+}
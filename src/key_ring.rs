@@ -0,0 +1,395 @@
+//
+// Station key ring
+//
+// Clients learn the station's Conjure public key from ClientConf, so a key
+// can't simply be swapped out: clients with the old config would all fail to
+// register at once. Instead the detector holds a ring of keys, the current one
+// plus any previous generations that haven't expired yet, and tries each one
+// (current first) when checking a tag.
+//
+// The ring is normally loaded from a key directory containing a keys.toml
+// manifest, e.g.
+//
+//   [[key]]
+//   generation = 4
+//   file = "privkey.4"
+//
+//   [[key]]
+//   generation = 3
+//   file = "privkey.3"
+//   expires = 1767225600    # unix seconds
+//
+// Key files have the same format as the station privkey (privkey or
+// privkey || pubkey; only the first 32 bytes are used) and relative paths are
+// relative to the directory. The manifest and key files are polled for
+// changes, so rotating keys is a matter of dropping in a new key file and
+// updating the manifest; the workers pick it up without a restart.
+//
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_derive::Deserialize;
+use toml;
+
+pub const MANIFEST_NAME: &str = "keys.toml";
+
+#[derive(Clone)]
+pub struct StationKey
+{
+    pub generation: u32,
+    pub priv_key: [u8; 32],
+    // None => never expires
+    pub expires: Option<SystemTime>,
+}
+
+impl StationKey
+{
+    pub fn is_expired(&self, now: SystemTime) -> bool
+    {
+        match self.expires {
+            Some(t) => t <= now,
+            None => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyEntry
+{
+    generation: u32,
+    file: String,
+    #[serde(default)]
+    expires: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct KeyManifest
+{
+    #[serde(default)]
+    key: Vec<KeyEntry>,
+}
+
+type Source = (PathBuf, Option<SystemTime>, u64);
+
+pub struct KeyRing
+{
+    // Sorted by generation, newest (current) first.
+    keys: Vec<StationKey>,
+
+    // Where the ring was loaded from, if anywhere, and the (path, mtime, len)
+    // of every file that went into it so we can tell when it changes.
+    dir: Option<PathBuf>,
+    sources: Vec<Source>,
+
+    // After a failed reload we don't want to retry (and complain) on every
+    // call until something changes, but a key file that shows up after the
+    // manifest naming it has to be noticed eventually.
+    retry_at: Option<SystemTime>,
+
+    // reload_if_due doesn't look at the files again before this
+    check_at: Option<SystemTime>,
+}
+
+const RETRY_SECS: u64 = 10;
+const CHECK_SECS: u64 = 10;
+
+fn stat(path: &Path) -> (Option<SystemTime>, u64)
+{
+    match fs::metadata(path) {
+        Ok(m) => (m.modified().ok(), m.len()),
+        Err(_) => (None, 0),
+    }
+}
+
+pub fn read_key_file(path: &Path) -> Result<[u8; 32], String>
+{
+    let bytes = fs::read(path)
+        .map_err(|e| format!("can't read key {}: {}", path.display(), e))?;
+    if bytes.len() < 32 {
+        return Err(format!("key file {} is shorter than 32 bytes", path.display()));
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes[..32]);
+    Ok(key)
+}
+
+impl KeyRing
+{
+    // A ring with just the one key (generation 0) that never expires, for
+    // stations that aren't set up with a key directory.
+    pub fn single(priv_key: [u8; 32]) -> KeyRing
+    {
+        KeyRing {
+            keys: vec![StationKey { generation: 0, priv_key, expires: None }],
+            dir: None,
+            sources: Vec::new(),
+            retry_at: None,
+            check_at: None,
+        }
+    }
+
+    // Keys already expired at now are left out.
+    pub fn load_dir(dir: &Path, now: SystemTime) -> Result<KeyRing, String>
+    {
+        let (keys, sources) = KeyRing::read_dir(dir, now)?;
+        Ok(KeyRing { keys, dir: Some(dir.to_path_buf()), sources, retry_at: None, check_at: None })
+    }
+
+    fn read_dir(dir: &Path, now: SystemTime)
+        -> Result<(Vec<StationKey>, Vec<Source>), String>
+    {
+        let manifest_path = dir.join(MANIFEST_NAME);
+        let contents = fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("can't read {}: {}", manifest_path.display(), e))?;
+        let manifest: KeyManifest = toml::from_str(&contents)
+            .map_err(|e| format!("can't parse {}: {}", manifest_path.display(), e))?;
+
+        let (mtime, len) = stat(&manifest_path);
+        let mut sources = vec![(manifest_path.clone(), mtime, len)];
+        let mut keys: Vec<StationKey> = Vec::new();
+        for entry in manifest.key.iter() {
+            if keys.iter().any(|k| k.generation == entry.generation) {
+                return Err(format!("{}: generation {} listed twice",
+                                   manifest_path.display(), entry.generation));
+            }
+            let path = dir.join(&entry.file);
+            let key = StationKey {
+                generation: entry.generation,
+                priv_key: read_key_file(&path)?,
+                expires: entry.expires.map(|s| UNIX_EPOCH + Duration::from_secs(s)),
+            };
+            let (mtime, len) = stat(&path);
+            sources.push((path, mtime, len));
+            if !key.is_expired(now) {
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Err(format!("{}: no unexpired keys", manifest_path.display()));
+        }
+        keys.sort_by_key(|k| Reverse(k.generation));
+        Ok((keys, sources))
+    }
+
+    // Keys to try, current first.
+    pub fn keys(&self) -> &[StationKey]
+    {
+        &self.keys
+    }
+
+    pub fn current(&self) -> &StationKey
+    {
+        &self.keys[0]
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.keys.is_empty()
+    }
+
+    // Forgets expired keys, though never the last one standing: a station
+    // with an expired key is better than a station with none. Returns how
+    // many were dropped.
+    pub fn drop_expired(&mut self, now: SystemTime) -> usize
+    {
+        let before = self.keys.len();
+        let current = self.keys[0].clone();
+        self.keys.retain(|k| !k.is_expired(now));
+        if self.keys.is_empty() {
+            self.keys.push(current);
+        }
+        before - self.keys.len()
+    }
+
+    // reload_if_changed, at most once every CHECK_SECS (stat'ing every key
+    // file on every cleanup adds up across the cores).
+    pub fn reload_if_due(&mut self, now: SystemTime) -> Result<bool, String>
+    {
+        if self.check_at.is_some_and(|t| now < t) {
+            return Ok(false);
+        }
+        self.check_at = Some(now + Duration::from_secs(CHECK_SECS));
+        self.reload_if_changed(now)
+    }
+
+    // Reloads the ring if it came from a directory and anything in it has
+    // changed since it was last read. On error the ring is left as it was.
+    // Returns Ok(true) if the ring was reloaded.
+    pub fn reload_if_changed(&mut self, now: SystemTime) -> Result<bool, String>
+    {
+        let dir = match self.dir {
+            Some(ref d) => d.clone(),
+            None => return Ok(false),
+        };
+        let changed = self.sources.iter().any(|&(ref path, mtime, len)| {
+            stat(path) != (mtime, len)
+        });
+        let retry = match self.retry_at {
+            Some(t) => t <= now,
+            None => false,
+        };
+        if !changed && !retry {
+            return Ok(false);
+        }
+        match KeyRing::read_dir(&dir, now) {
+            Ok((keys, sources)) => {
+                self.keys = keys;
+                self.sources = sources;
+                self.retry_at = None;
+                Ok(true)
+            },
+            Err(e) => {
+                for src in self.sources.iter_mut() {
+                    let (mtime, len) = stat(&src.0);
+                    src.1 = mtime;
+                    src.2 = len;
+                }
+                self.retry_at = Some(now + Duration::from_secs(RETRY_SECS));
+                Err(e)
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use key_ring::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn test_dir(name: &str) -> PathBuf
+    {
+        let dir = env::temp_dir().join(format!("key_ring_test_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn now_secs() -> u64
+    {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn test_single()
+    {
+        let mut ring = KeyRing::single([7u8; 32]);
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.current().generation, 0);
+        assert!(!ring.reload_if_changed(SystemTime::now()).unwrap());
+        assert_eq!(ring.drop_expired(SystemTime::now()), 0);
+    }
+
+    #[test]
+    fn test_load_dir()
+    {
+        let dir = test_dir("load");
+        fs::write(dir.join("privkey.1"), &[1u8; 64][..]).unwrap();
+        fs::write(dir.join("privkey.2"), &[2u8; 32][..]).unwrap();
+        fs::write(dir.join("privkey.3"), &[3u8; 32][..]).unwrap();
+        fs::write(dir.join(MANIFEST_NAME), format!("
+[[key]]
+generation = 1
+file = \"privkey.1\"
+expires = 1000
+
+[[key]]
+generation = 3
+file = \"privkey.3\"
+
+[[key]]
+generation = 2
+file = \"privkey.2\"
+expires = {}
+", now_secs() + 3600)).unwrap();
+
+        let later = SystemTime::now() + Duration::from_secs(7200);
+        let ring = KeyRing::load_dir(&dir, later).unwrap();
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.current().generation, 3);
+
+        let mut ring = KeyRing::load_dir(&dir, SystemTime::now()).unwrap();
+        // Generation 1 is long expired; the rest are newest first.
+        let gens: Vec<u32> = ring.keys().iter().map(|k| k.generation).collect();
+        assert_eq!(gens, vec![3, 2]);
+        assert_eq!(ring.current().priv_key, [3u8; 32]);
+        assert_eq!(ring.keys()[1].priv_key, [2u8; 32]);
+
+        assert_eq!(ring.drop_expired(later), 1);
+        assert_eq!(ring.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload()
+    {
+        let dir = test_dir("reload");
+        fs::write(dir.join("a"), &[1u8; 32][..]).unwrap();
+        fs::write(dir.join("b"), &[2u8; 32][..]).unwrap();
+        fs::write(dir.join(MANIFEST_NAME), "[[key]]\ngeneration = 1\nfile = \"a\"\n").unwrap();
+
+        let mut ring = KeyRing::load_dir(&dir, SystemTime::now()).unwrap();
+        assert_eq!(ring.len(), 1);
+        let now = SystemTime::now();
+        assert!(!ring.reload_if_changed(now).unwrap());
+
+        // Rotate in generation 2
+        fs::write(dir.join(MANIFEST_NAME),
+                  "[[key]]\ngeneration = 1\nfile = \"a\"\n\n[[key]]\ngeneration = 2\nfile = \"b\"\n").unwrap();
+        assert!(ring.reload_if_changed(now).unwrap());
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.current().generation, 2);
+
+        // A broken manifest leaves the ring alone
+        fs::write(dir.join(MANIFEST_NAME), "[[key]]\ngeneration = 3\nfile = \"missing\"\n").unwrap();
+        assert!(ring.reload_if_changed(now).is_err());
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.current().generation, 2);
+        // ...and isn't retried until RETRY_SECS later
+        assert!(!ring.reload_if_changed(now).unwrap());
+        assert!(ring.reload_if_changed(now + Duration::from_secs(RETRY_SECS)).is_err());
+
+        // So does one with nothing usable in it
+        fs::write(dir.join(MANIFEST_NAME), "").unwrap();
+        assert!(ring.reload_if_changed(now).is_err());
+        assert_eq!(ring.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_if_due()
+    {
+        let dir = test_dir("due");
+        fs::write(dir.join("a"), &[1u8; 32][..]).unwrap();
+        fs::write(dir.join("b"), &[2u8; 32][..]).unwrap();
+        fs::write(dir.join(MANIFEST_NAME), "[[key]]\ngeneration = 1\nfile = \"a\"\n").unwrap();
+
+        let now = SystemTime::now();
+        let mut ring = KeyRing::load_dir(&dir, now).unwrap();
+        assert!(!ring.reload_if_due(now).unwrap());
+
+        fs::write(dir.join(MANIFEST_NAME),
+                  "[[key]]\ngeneration = 1\nfile = \"a\"\n\n[[key]]\ngeneration = 2\nfile = \"b\"\n").unwrap();
+        // Not looked at again until CHECK_SECS later
+        assert!(!ring.reload_if_due(now + Duration::from_secs(1)).unwrap());
+        assert_eq!(ring.len(), 1);
+        assert!(ring.reload_if_due(now + Duration::from_secs(CHECK_SECS)).unwrap());
+        assert_eq!(ring.current().generation, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
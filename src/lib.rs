@@ -11,7 +11,6 @@ extern crate errno;
 extern crate hex;
 extern crate aes_gcm;
 
-extern crate radix; // https://github.com/refraction-networking/radix
 extern crate tuntap; // https://github.com/ewust/tuntap.rs
 extern crate zmq;
 extern crate protobuf;
@@ -23,39 +22,73 @@ extern crate serde_derive;
 use std::mem::transmute;
 use time::precise_time_ns;
 
-use radix::PrefixTree;
-use std::io::BufReader;
-use std::io::BufRead;
-use std::fs::File;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::io;
+use std::panic;
+use std::path::Path;
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::Duration;
 use serde_derive::Deserialize;
 
 use std::ffi::CStr;
 use std::os::raw::c_char;
 
-use tuntap::{IFF_TUN,TunTap};
-
 // Must go before all other modules so that the report! macro will be visible.
 #[macro_use]
 pub mod logging;
 
+pub mod af_packet;
 pub mod c_api;
+pub mod client_ip;
+pub mod clock;
+pub mod curve25519;
+pub mod decap;
 pub mod elligator;
 pub mod flow_tracker;
+pub mod forward_sink;
+pub mod ip_reassembly;
+pub mod key_ring;
+pub mod metrics;
+pub mod pcap;
+pub mod prefix_list;
 pub mod process_packet;
+pub mod rate_limit;
+pub mod registration_sink;
+pub mod replay_filter;
 pub mod util;
 pub mod signalling;
 pub mod sessions;
+pub mod session_table;
+pub mod shm;
+pub mod tcp_reassembly;
+pub mod timer_wheel;
 
 
-use flow_tracker::{Flow,FlowTracker};
+use client_ip::{AsnDb, ClientIpMode};
+use decap::DecapCounters;
+use flow_tracker::FlowTracker;
+use forward_sink::{ForwardCounters, ForwardSink, PacketMeta, RawSocketSink, TapSink, TunSink};
+use ip_reassembly::{FragmentCounters, FragmentReassembler};
+use key_ring::KeyRing;
+use logging::{Event, LogConfig};
+use metrics::{MetricsSnapshot, SharedMetrics};
+use prefix_list::PrefixList;
+use rate_limit::{Limit, TagCheckLimiter};
+use registration_sink::{QueuedSink, RedisStreamSink, RegistrationSink, RegistrationTransport,
+                        SinkCounters, UnixDatagramSink, ZmqPubSink, ZmqPushSink};
+use replay_filter::ReplayFilter;
+use session_table::{Occupancy, SessionTable};
+use sessions::{ConnCounts, IngestConfig, SessionTracker};
 
 
 // Global program state for one instance of a TapDance station process.
 pub struct PerCoreGlobal
 {
-    priv_key: [u8; 32],
+    // Station private keys, current first (see key_ring.rs)
+    pub keys: KeyRing,
 
     lcore: i32,
     pub flow_tracker: FlowTracker,
@@ -65,23 +98,45 @@ pub struct PerCoreGlobal
     // Just some scratch space for mio.
     //events_buf: Events,
 
-    pub tun: TunTap,
+    // Where packets for registered phantoms are sent (tun{lcore} normally)
+    forwarder: Box<dyn ForwardSink>,
+    // Link-layer details of the frame being processed, for the forwarder
+    pkt_meta: PacketMeta,
 
     pub stats: PerCoreStats,
 
-    // List of IP prefixes we'll respond to as dark decoys
-    pub ip_tree:   PrefixTree,
-    // ZMQ socket for sending information to the dark decoy application
-    zmq_sock:      zmq::Socket,
+    // List of IP prefixes we'll respond to as dark decoys. Phantom traffic
+    // to anything outside it isn't forwarded (unless it's empty, i.e. no
+    // list was loaded).
+    pub ip_tree:   PrefixList,
+    // Where new registrations are sent (ZMQ socket to the dark decoy
+    // application normally)
+    registrar:     Box<dyn RegistrationSink>,
+    // Where PhantomEvents go, if the application wants them
+    phantom_events: Option<QueuedSink<Box<dyn RegistrationTransport>>>,
 
     // Filter list of addresses to ignore traffic from. This primarily functions to prevent liveness
     // testing from other stations in a conjure cluster from clogging up the logs with connection
     // notifications. 
     filter_list: Vec<String>,
 
+    // TCP destination ports we look for registration tags on.
+    decoy_ports: Vec<u16>,
+
+    // Shared secrets of recent registrations, so copies of a tagged record
+    // aren't passed on again. None if replay protection is turned off.
+    replay_filter: Option<ReplayFilter>,
+
+    // Limits on how many tags we check, per source prefix and in total
+    tag_limiter: TagCheckLimiter,
+
+    // Fragmented packets being put back together. None if fragments are
+    // just counted and dropped.
+    fragments: Option<FragmentReassembler>,
+
     // If we're reading from a GRE tap, we can provide an optional offset that we read
     // into the packet (skipping the GRE header).
-    gre_offset: usize,
+    pub gre_offset: usize,
 }
 
 // Tracking of some pretty straightforward quantities
@@ -92,12 +147,36 @@ pub struct PerCoreStats
     pub ipv4_packets_this_period: u64,
     pub ipv6_packets_this_period: u64,
     pub tcp_packets_this_period: u64,
+    pub udp_packets_this_period: u64,
     pub tls_packets_this_period: u64,
     pub bytes_this_period: u64,
     //pub reconns_this_period: u64,
     pub tls_bytes_this_period: u64,
     pub port_443_syns_this_period: u64,
     //pub cli2cov_raw_etherbytes_this_period: u64,
+    // First records that needed more than one segment, and ones we gave up on
+    pub tags_reassembled_this_period: u64,
+    pub reassembly_drops_this_period: u64,
+    pub registrations_sent_this_period: u64,
+    pub registration_send_failures_this_period: u64,
+    pub phantom_events_this_period: u64,
+    pub forward_failures_this_period: u64,
+    // Datagrams on UDP phantom flows past a session's limit
+    pub udp_flows_over_limit_this_period: u64,
+    // Tags that decrypted fine but had been seen before
+    pub replayed_tags_this_period: u64,
+    // Tag checks skipped for going over the per-source or per-core limit
+    pub source_limited_this_period: u64,
+    pub global_limited_this_period: u64,
+    // IP fragments, and IPv6 packets with too many (or truncated) extension
+    // headers
+    pub ip_fragments_this_period: u64,
+    pub bad_ip_headers_this_period: u64,
+    // The fragment reassembler's own counters, as of the last cleanup
+    pub fragment_counters: FragmentCounters,
+    // Encapsulations found on the way to the IP packet (cumulative, like the
+    // sink counters below, but kept up to date by the packet path itself)
+    pub decap: DecapCounters,
 
     // CPU time counters (cumulative)
     tot_usr_us: i64,
@@ -107,17 +186,509 @@ pub struct PerCoreStats
     // not a duration).
     last_measure_time: u64,
 
+    // Phantom packets whose phantom was (not) in the prefix list
     pub not_in_tree_this_period: u64,
     pub in_tree_this_period: u64,
+
+    // The registration and phantom event sinks' own counters, as of the
+    // last cleanup
+    pub sink_counters: SinkCounters,
+    pub phantom_event_counters: SinkCounters,
+    // The phantom session table's, shared by every core unless each has its
+    // own
+    pub session_table: Occupancy,
+    // Same for the forwarding sink
+    pub forward_sink: &'static str,
+    pub forward_counters: ForwardCounters,
+
+    // Everything from periods that have already been reported, and what the
+    // metrics endpoint serves (those totals plus the current period).
+    totals: MetricsSnapshot,
+    pub metrics: SharedMetrics,
 }
 
 // Currently used to parse the Toml config. If this needs to play a larger role 
 // in the future this can be added to the PerCoreGlobal.
-#[derive(Deserialize)]
-struct StationConfig {
-    detector_filter_list: Vec<String>,
+#[derive(Deserialize, Default)]
+pub struct StationConfig {
+    pub detector_filter_list: Vec<String>,
+
+    // If set, each detector core serves Prometheus metrics on this address
+    // with the core number added to the port (e.g. "127.0.0.1:9100").
+    #[serde(default)]
+    pub detector_metrics_addr: Option<String>,
+
+    // TCP ports the detector checks for registration tags on. Defaults to 443
+    // only. (Phantom sessions are matched on whatever port they registered.)
+    #[serde(default)]
+    pub detector_decoy_ports: Vec<u16>,
+
+    // Directory with a keys.toml manifest of station private keys to accept
+    // tags for. If unset, only the key detect.c was started with is used.
+    #[serde(default)]
+    pub detector_key_dir: Option<String>,
+
+    // How long (seconds) registration shared secrets are remembered to drop
+    // replayed tags. Defaults to an hour; 0 turns replay protection off.
+    #[serde(default)]
+    pub detector_replay_window: Option<u64>,
+
+    // File the replay filter is kept in, shared by all detector cores.
+    // Defaults to /dev/shm/conjure-replay-filter.
+    #[serde(default)]
+    pub detector_replay_filter_path: Option<String>,
+
+    // Token bucket limits on tag checks (each costs an X25519 operation):
+    // a rate per second and burst size for each core in total, and for each
+    // source prefix (/24 and /48 unless set otherwise). A rate of 0 (the
+    // default) means no limit.
+    #[serde(default)]
+    pub detector_tag_check_rate: f64,
+    #[serde(default)]
+    pub detector_tag_check_burst: f64,
+    #[serde(default)]
+    pub detector_source_tag_check_rate: f64,
+    #[serde(default)]
+    pub detector_source_tag_check_burst: f64,
+    #[serde(default)]
+    pub detector_source_prefix_v4: Option<u8>,
+    #[serde(default)]
+    pub detector_source_prefix_v6: Option<u8>,
+
+    // Where registrations go: "zmq-pub" (the default, through detect.c's
+    // proxy), "zmq-push", "redis" (XADD to a stream) or "unix" (datagrams).
+    // detector_registration_addr is the ZMQ endpoint, Redis URL or socket
+    // path (zmq-pub defaults to the proxy, redis to redis://127.0.0.1/).
+    #[serde(default)]
+    pub detector_registration_sink: Option<String>,
+    #[serde(default)]
+    pub detector_registration_addr: Option<String>,
+    // Redis stream name (default conjure_registrations), trimmed to about
+    // 10000 entries
+    #[serde(default)]
+    pub detector_registration_stream: Option<String>,
+    // ZMQ PUSH high-water mark (default 1000), also for detector_event_sink
+    #[serde(default)]
+    pub detector_registration_hwm: Option<i32>,
+    // Registrations each core holds on to while the sink is failing
+    // (default 1000; 0 for none). Not used for zmq-pub, which can't tell.
+    #[serde(default)]
+    pub detector_registration_queue: Option<usize>,
+
+    // Redis the phantom sessions come from (default redis://127.0.0.1/), the
+    // sorted set the application keeps active sessions in (default
+    // dark_decoy_sessions), read whenever the detector (re)connects, and the
+    // one the detector keeps revokes and extensions in so that doesn't bring
+    // back what they undid (default dark_decoy_session_ops)
+    #[serde(default)]
+    pub detector_session_redis: Option<String>,
+    #[serde(default)]
+    pub detector_session_set: Option<String>,
+    #[serde(default)]
+    pub detector_session_ops: Option<String>,
+    // The session table every core reads (default /dev/shm/conjure-sessions),
+    // filled by the session ingest process detect.c starts, and its slots
+    // (default 262144; it takes sessions up to 7/8 full). "" for each core to
+    // keep its own sessions from its own Redis subscription instead.
+    #[serde(default)]
+    pub detector_session_table: Option<String>,
+    #[serde(default)]
+    pub detector_session_table_slots: Option<usize>,
+
+    // Where PhantomEvents go (a phantom's first SYN, closed connections,
+    // sessions that expired unused): "zmq-push", "redis" or "unix", as for
+    // registrations. Unset, none are sent. The Redis stream defaults to
+    // conjure_phantom_events, with the message in field "event".
+    #[serde(default)]
+    pub detector_event_sink: Option<String>,
+    #[serde(default)]
+    pub detector_event_addr: Option<String>,
+    #[serde(default)]
+    pub detector_event_stream: Option<String>,
+    // Events each core holds on to while the sink is failing (default 1000)
+    #[serde(default)]
+    pub detector_event_queue: Option<usize>,
+
+    // Where packets for phantoms go: "tun" (the default, tun<core>),
+    // "tun-writev" or "tap-writev" (a tun/tap device written in batches of up
+    // to detector_forward_batch packets, default 32) or "raw" (Ethernet frames
+    // sent out of an interface through an AF_PACKET socket).
+    // detector_forward_iface names the device or interface; "{}" in it is
+    // replaced by the core number (default tun{} / tap{}; required for raw).
+    #[serde(default)]
+    pub detector_forward_sink: Option<String>,
+    #[serde(default)]
+    pub detector_forward_iface: Option<String>,
+    #[serde(default)]
+    pub detector_forward_batch: Option<usize>,
+    // For tap-writev and raw: keep the 802.1Q tag packets arrived with, and
+    // the destination MAC to use instead of the tap device's own address (or,
+    // for raw, the original destination)
+    #[serde(default)]
+    pub detector_forward_vlan: bool,
+    #[serde(default)]
+    pub detector_forward_mac: Option<String>,
+
+    // What to do with IP fragments: "reassemble" (the default) or "drop".
+    // Each source address can have detector_fragment_source_limit (default
+    // 16) packets being reassembled at a time, for up to
+    // detector_fragment_timeout seconds (default 30).
+    #[serde(default)]
+    pub detector_fragments: Option<String>,
+    #[serde(default)]
+    pub detector_fragment_source_limit: Option<usize>,
+    #[serde(default)]
+    pub detector_fragment_timeout: Option<u64>,
+
+    // Log level ("error", "warn", "info", "debug" (the default) or "trace")
+    // and format ("text" or "json"). Structured events (registrations,
+    // phantom connections, ...) are limited per event type to
+    // detector_log_event_rate a second (default 20, 0 for no limit) with
+    // bursts of detector_log_event_burst (default 200), and
+    // detector_log_sample can log only one in N events of a type, e.g.
+    // { phantom_syn = 100 }. All of these are reread on SIGHUP.
+    #[serde(default)]
+    pub detector_log_level: Option<String>,
+    #[serde(default)]
+    pub detector_log_format: Option<String>,
+    #[serde(default)]
+    pub detector_log_event_rate: Option<f64>,
+    #[serde(default)]
+    pub detector_log_event_burst: Option<f64>,
+    #[serde(default)]
+    pub detector_log_sample: HashMap<String, u32>,
+
+    // How client addresses appear in logs: "none" ("_"), "full", "prefix"
+    // (detector_client_ip_prefix_v4/_v6, default /24 and /48), "pseudonym"
+    // (keyed hash that changes daily, key read from detector_client_ip_key)
+    // or "asn" (origin AS and country from the ip2asn TSV at
+    // detector_client_ip_db). If unset, LOG_CLIENT_IP=true means "full".
+    // Read once at startup.
+    #[serde(default)]
+    pub detector_client_ip_log: Option<String>,
+    #[serde(default)]
+    pub detector_client_ip_prefix_v4: Option<u8>,
+    #[serde(default)]
+    pub detector_client_ip_prefix_v6: Option<u8>,
+    #[serde(default)]
+    pub detector_client_ip_key: Option<String>,
+    #[serde(default)]
+    pub detector_client_ip_db: Option<String>,
+}
+
+const DEFAULT_DECOY_PORT: u16 = 443;
+
+fn decoy_ports(configured: Vec<u16>) -> Vec<u16>
+{
+    match configured.is_empty() {
+        true => vec![DEFAULT_DECOY_PORT],
+        false => configured,
+    }
+}
+
+const DEFAULT_SOURCE_PREFIX_V4: u8 = 24;
+const DEFAULT_SOURCE_PREFIX_V6: u8 = 48;
+
+fn tag_limiter(conf: &StationConfig) -> TagCheckLimiter
+{
+    let limit = |rate: f64, burst: f64| match rate > 0.0 {
+        true => Some(Limit { per_sec: rate, burst }),
+        false => None,
+    };
+    TagCheckLimiter::new(
+        limit(conf.detector_tag_check_rate, conf.detector_tag_check_burst),
+        limit(conf.detector_source_tag_check_rate, conf.detector_source_tag_check_burst),
+        conf.detector_source_prefix_v4.unwrap_or(DEFAULT_SOURCE_PREFIX_V4),
+        conf.detector_source_prefix_v6.unwrap_or(DEFAULT_SOURCE_PREFIX_V6),
+        clock::now_ns())
+}
+
+fn log_config(conf: &StationConfig) -> LogConfig
+{
+    let mut log_conf = LogConfig::default();
+    if let Some(ref level) = conf.detector_log_level {
+        match level.parse::<log::LogLevelFilter>() {
+            Ok(level) => log_conf.level = level,
+            Err(_) => error!("unknown detector_log_level {}, logging at {}", level, log_conf.level),
+        }
+    }
+    match conf.detector_log_format.as_deref() {
+        Some("json") => log_conf.json = true,
+        Some("text") | None => {},
+        Some(other) => error!("unknown detector_log_format {}, logging text", other),
+    }
+    let rate = conf.detector_log_event_rate.unwrap_or(logging::DEFAULT_EVENT_RATE);
+    log_conf.event_limit = match rate > 0.0 {
+        true => Some(Limit {
+            per_sec: rate,
+            burst: conf.detector_log_event_burst.unwrap_or(logging::DEFAULT_EVENT_BURST).max(1.0),
+        }),
+        false => None,
+    };
+    log_conf.sample = conf.detector_log_sample.clone();
+    log_conf
+}
+
+// Anything that can't be set up (missing key or database) hides clients
+// rather than falling back to logging them.
+fn client_ip_mode(conf: &StationConfig) -> ClientIpMode
+{
+    let mode = match conf.detector_client_ip_log {
+        Some(ref mode) => mode.clone(),
+        None => match env::var("LOG_CLIENT_IP").as_ref().map(|s| s.as_str()) {
+            Ok("true") => "full".to_string(),
+            _ => "none".to_string(),
+        },
+    };
+    match mode.as_str() {
+        "none" => ClientIpMode::Hidden,
+        "full" => ClientIpMode::Full,
+        "prefix" => ClientIpMode::Prefix {
+            v4: conf.detector_client_ip_prefix_v4.unwrap_or(client_ip::DEFAULT_PREFIX_V4).min(32),
+            v6: conf.detector_client_ip_prefix_v6.unwrap_or(client_ip::DEFAULT_PREFIX_V6).min(128),
+        },
+        "pseudonym" => {
+            let path = conf.detector_client_ip_key.as_deref().unwrap_or("");
+            match client_ip::read_key(path) {
+                Ok(key) => ClientIpMode::Pseudonym { key },
+                Err(e) => {
+                    error!("can't read client IP pseudonym key {}: {}, hiding clients", path, e);
+                    ClientIpMode::Hidden
+                },
+            }
+        },
+        "asn" => {
+            let path = conf.detector_client_ip_db.as_deref().unwrap_or("");
+            match AsnDb::load(path) {
+                Ok(db) => {
+                    info!("loaded {} AS ranges from {}", db.len(), path);
+                    ClientIpMode::Asn(db)
+                },
+                Err(e) => {
+                    error!("can't load client IP database {}: {}, hiding clients", path, e);
+                    ClientIpMode::Hidden
+                },
+            }
+        },
+        other => {
+            error!("unknown detector_client_ip_log {}, hiding clients", other);
+            ClientIpMode::Hidden
+        },
+    }
+}
+
+// The mode is process wide, so the first core to start sets it up.
+static CLIENT_IP_INIT: Once = Once::new();
+
+fn fragment_reassembler(conf: &StationConfig) -> Option<FragmentReassembler>
+{
+    match conf.detector_fragments.as_deref() {
+        Some("drop") => None,
+        Some("reassemble") | None => Some(FragmentReassembler::new(
+            conf.detector_fragment_source_limit.unwrap_or(ip_reassembly::DEFAULT_MAX_PER_SOURCE),
+            conf.detector_fragment_timeout.map(|s| s * 1000 * 1000 * 1000)
+                .unwrap_or(ip_reassembly::DEFAULT_TIMEOUT_NS))),
+        Some(other) => {
+            error!("unknown detector_fragments {}, reassembling", other);
+            Some(FragmentReassembler::new(ip_reassembly::DEFAULT_MAX_PER_SOURCE,
+                                          ip_reassembly::DEFAULT_TIMEOUT_NS))
+        },
+    }
+}
+
+const DEFAULT_REGISTRATION_HWM: i32 = 1000;
+const DEFAULT_REGISTRATION_QUEUE: usize = 1000;
+const DEFAULT_REGISTRATION_REDIS: &str = "redis://127.0.0.1/";
+const DEFAULT_REGISTRATION_STREAM: &str = "conjure_registrations";
+const REGISTRATION_STREAM_MAXLEN: usize = 10000;
+const DEFAULT_EVENT_STREAM: &str = "conjure_phantom_events";
+
+// Builds a zmq-push, redis or unix transport for a QueuedSink sending what
+// (e.g. "registration"), or None if kind is something else. addr_key is the
+// config key addr came from.
+fn sink_transport(what: &str, kind: &str, addr: Option<&str>, addr_key: &str,
+                  conf: &StationConfig, stream: &str, field: &str)
+    -> Result<Option<Box<dyn RegistrationTransport>>, String>
+{
+    let need_addr = || addr.ok_or_else(|| format!("{} sink {} needs {}", what, kind, addr_key));
+
+    let transport: Box<dyn RegistrationTransport> = match kind {
+        "zmq-push" => {
+            let hwm = conf.detector_registration_hwm.unwrap_or(DEFAULT_REGISTRATION_HWM);
+            Box::new(ZmqPushSink::new(need_addr()?, hwm).map_err(|e| e.to_string())?)
+        },
+        "redis" => {
+            Box::new(RedisStreamSink::new(addr.unwrap_or(DEFAULT_REGISTRATION_REDIS), stream,
+                                          field, REGISTRATION_STREAM_MAXLEN)
+                     .map_err(|e| e.to_string())?)
+        },
+        "unix" => Box::new(UnixDatagramSink::new(need_addr()?).map_err(|e| e.to_string())?),
+        _ => return Ok(None),
+    };
+    Ok(Some(transport))
 }
 
+fn registration_sink(conf: &StationConfig, workers_socket_addr: &str)
+    -> Result<Box<dyn RegistrationSink>, String>
+{
+    let kind = conf.detector_registration_sink.as_deref().unwrap_or("zmq-pub");
+    let addr = conf.detector_registration_addr.as_deref();
+    let queue = conf.detector_registration_queue.unwrap_or(DEFAULT_REGISTRATION_QUEUE);
+    if kind == "zmq-pub" {
+        return Ok(Box::new(ZmqPubSink::new(addr.unwrap_or(workers_socket_addr))));
+    }
+
+    let stream = conf.detector_registration_stream.as_deref()
+        .unwrap_or(DEFAULT_REGISTRATION_STREAM);
+    match sink_transport("registration", kind, addr, "detector_registration_addr", conf,
+                         stream, "reg")? {
+        Some(transport) => Ok(Box::new(QueuedSink::new(transport, queue))),
+        None => Err(format!("unknown registration sink {}", kind)),
+    }
+}
+
+fn ingest_config(conf: &StationConfig) -> IngestConfig
+{
+    let mut ingest = IngestConfig::default();
+    if let Some(ref url) = conf.detector_session_redis {
+        ingest.url = url.clone();
+    }
+    if let Some(ref set) = conf.detector_session_set {
+        ingest.active = set.clone();
+    }
+    if let Some(ref set) = conf.detector_session_ops {
+        ingest.ops = set.clone();
+    }
+    ingest
+}
+
+// None if detector_session_table is ""
+fn shared_session_table(conf: &StationConfig) -> Option<io::Result<SessionTable>>
+{
+    let path = conf.detector_session_table.as_deref().unwrap_or(DEFAULT_SESSION_TABLE_PATH);
+    if path.is_empty() {
+        return None;
+    }
+    let slots = conf.detector_session_table_slots.unwrap_or(session_table::DEFAULT_SLOTS);
+    Some(SessionTable::open_shared(path, slots))
+}
+
+// None unless detector_event_sink is set
+fn phantom_event_sink(conf: &StationConfig)
+    -> Result<Option<QueuedSink<Box<dyn RegistrationTransport>>>, String>
+{
+    let kind = match conf.detector_event_sink {
+        Some(ref kind) => kind.as_str(),
+        None => return Ok(None),
+    };
+    let queue = conf.detector_event_queue.unwrap_or(DEFAULT_REGISTRATION_QUEUE);
+    let stream = conf.detector_event_stream.as_deref().unwrap_or(DEFAULT_EVENT_STREAM);
+    match sink_transport("phantom event", kind, conf.detector_event_addr.as_deref(),
+                         "detector_event_addr", conf, stream, "event")? {
+        Some(transport) => Ok(Some(QueuedSink::named(transport, queue, "phantom event"))),
+        None => Err(format!("unknown phantom event sink {}", kind)),
+    }
+}
+
+const DEFAULT_FORWARD_BATCH: usize = 32;
+
+fn forward_sink(conf: &StationConfig, lcore: i32) -> Result<Box<dyn ForwardSink>, String>
+{
+    let kind = conf.detector_forward_sink.as_deref().unwrap_or("tun");
+    let iface = |default: &str| conf.detector_forward_iface.as_deref().unwrap_or(default)
+        .replace("{}", &lcore.to_string());
+    let batch = conf.detector_forward_batch.unwrap_or(DEFAULT_FORWARD_BATCH);
+    let dst_mac = match conf.detector_forward_mac {
+        Some(ref mac) => Some(forward_sink::parse_mac(mac)
+                              .ok_or_else(|| format!("bad detector_forward_mac {}", mac))?),
+        None => None,
+    };
+
+    match kind {
+        "tun" => Ok(Box::new(TunSink::new(lcore))),
+        "tun-writev" | "tap-writev" => {
+            let tap = kind == "tap-writev";
+            let name = iface(if tap { "tap{}" } else { "tun{}" });
+            TapSink::open(&name, tap, conf.detector_forward_vlan, dst_mac, batch)
+                .map(|s| Box::new(s) as Box<dyn ForwardSink>)
+                .map_err(|e| format!("can't open {} forwarding sink on {}: {}", kind, name, e))
+        },
+        "raw" => {
+            if conf.detector_forward_iface.is_none() {
+                return Err("forwarding sink raw needs detector_forward_iface".to_string());
+            }
+            let name = iface("");
+            RawSocketSink::open(&name, conf.detector_forward_vlan, dst_mac)
+                .map(|s| Box::new(s) as Box<dyn ForwardSink>)
+                .map_err(|e| format!("can't open raw forwarding sink on {}: {}", name, e))
+        },
+        _ => Err(format!("unknown forwarding sink {}", kind)),
+    }
+}
+
+fn read_station_config() -> Result<StationConfig, String>
+{
+    let conf_path = env::var(STATION_CONF_PATH)
+        .map_err(|e| format!("{} not set: {}", STATION_CONF_PATH, e))?;
+    let contents = fs::read_to_string(&conf_path)
+        .map_err(|e| format!("Something went wrong reading the station config file {}: {}",
+                             conf_path, e))?;
+    toml::from_str(&contents)
+        .map_err(|e| format!("Failed to parse toml station config {}: {}", conf_path, e))
+}
+
+// What a config reload changed
+#[derive(Default, Debug)]
+pub struct ConfigDiff
+{
+    pub prefixes: usize,
+    pub prefixes_added: Vec<String>,
+    pub prefixes_removed: Vec<String>,
+    pub filter_added: Vec<String>,
+    pub filter_removed: Vec<String>,
+    // Set if the decoy ports changed
+    pub decoy_ports: Option<Vec<u16>>,
+    // Whether the tag check limits (or the prefixes sources are grouped by)
+    // changed, which starts every bucket over full
+    pub tag_limits: bool,
+}
+
+impl ConfigDiff
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.prefixes_added.is_empty() && self.prefixes_removed.is_empty()
+            && self.filter_added.is_empty() && self.filter_removed.is_empty()
+            && self.decoy_ports.is_none() && !self.tag_limits
+    }
+
+    fn log(&self)
+    {
+        if self.is_empty() {
+            info!("Config reloaded, nothing changed");
+            return;
+        }
+        info!("Config reloaded: {} prefixes (+{} -{}), filter list +{:?} -{:?}",
+              self.prefixes, self.prefixes_added.len(), self.prefixes_removed.len(),
+              self.filter_added, self.filter_removed);
+        for p in self.prefixes_added.iter() {
+            debug!("Prefix added: {}", p);
+        }
+        for p in self.prefixes_removed.iter() {
+            debug!("Prefix removed: {}", p);
+        }
+        if let Some(ref ports) = self.decoy_ports {
+            info!("Decoy ports now {:?}", ports);
+        }
+        if self.tag_limits {
+            info!("Tag check limits changed");
+        }
+    }
+}
+
+const DEFAULT_REPLAY_FILTER_PATH: &'static str = "/dev/shm/conjure-replay-filter";
+const DEFAULT_SESSION_TABLE_PATH: &'static str = "/dev/shm/conjure-sessions";
+
 const IP_LIST_PATH: &'static str = "/var/lib/dark-decoy.prefixes";
 const STATION_CONF_PATH: &'static str = "CJ_STATION_CONFIG";
 
@@ -125,32 +696,39 @@ impl PerCoreGlobal
 {
     fn new(priv_key: [u8; 32], the_lcore: i32, workers_socket_addr: &str) -> PerCoreGlobal
     {
+        // Parse toml station config to get filter list
+        let value = read_station_config().unwrap_or_else(|e| panic!("{}", e));
+        logging::configure(log_config(&value));
 
-        let tun = TunTap::new(IFF_TUN, &format!("tun{}", the_lcore)).unwrap();
-        tun.set_up().unwrap();
+        let forwarder = forward_sink(&value, the_lcore).unwrap_or_else(|e| {
+            error!("{}, forwarding to tun{}", e, the_lcore);
+            Box::new(TunSink::new(the_lcore))
+        });
 
-        // Setup ZMQ
-        let zmq_ctx = zmq::Context::new();
-        let zmq_sock = zmq_ctx.socket(zmq::PUB).unwrap();
-        zmq_sock.connect(workers_socket_addr).expect("failed connecting to ZMQ");
+        let registrar = registration_sink(&value, workers_socket_addr).unwrap_or_else(|e| {
+            error!("{}, sending registrations to {} over ZMQ PUB", e, workers_socket_addr);
+            Box::new(ZmqPubSink::new(workers_socket_addr))
+        });
 
-        // Parse toml station config to get filter list
-        let conf_path = env::var(STATION_CONF_PATH).unwrap();
-        let contents = fs::read_to_string(conf_path)
-            .expect("Something went wrong reading the station config file");
-        let value: StationConfig = toml::from_str(&contents)
-            .expect("Failed to parse toml station config");
-
-        // Also all threads read the same environment variable so they will all
-        // set it the same, race condition for setting client ip logging doesn't
-        // affect the outcome. LOG_CLIENT_IP set in conjure.conf
-        let client_ip_logging_str = env::var("LOG_CLIENT_IP").unwrap();
-        match client_ip_logging_str.as_ref() {
-            "true" => Flow::set_log_client(true),
-            "false" => Flow::set_log_client(false),
-            &_ => Flow::set_log_client(false), // default disable 
+        // Sessions come from the table the ingest process fills, unless
+        // this core has to get them itself.
+        let flow_tracker = match shared_session_table(&value) {
+            Some(Ok(table)) => FlowTracker::with_sessions(SessionTracker::with_table(Arc::new(table), false)),
+            Some(Err(e)) => {
+                error!("failed to open the session table ({}), getting sessions from redis on \
+                        this core", e);
+                FlowTracker::new(ingest_config(&value))
+            },
+            None => FlowTracker::new(ingest_config(&value)),
         };
 
+        let phantom_events = phantom_event_sink(&value).unwrap_or_else(|e| {
+            error!("{}, not sending phantom events", e);
+            None
+        });
+
+        CLIENT_IP_INIT.call_once(|| client_ip::set_mode(client_ip_mode(&value)));
+
         let gre_offset = match env::var("PARSE_GRE_OFFSET") {
             Ok(val) => val.parse::<usize>().unwrap(),
             Err(env::VarError::NotPresent) => 0,
@@ -159,39 +737,254 @@ impl PerCoreGlobal
 
         debug!("gre_offset: {}", gre_offset);
 
+        let metrics_addr = value.detector_metrics_addr.clone();
+        let replay_window = value.detector_replay_window
+            .unwrap_or(replay_filter::DEFAULT_WINDOW_SECS);
+        let replay_path = value.detector_replay_filter_path.clone()
+            .unwrap_or(DEFAULT_REPLAY_FILTER_PATH.to_string());
+
+        let keys = match value.detector_key_dir {
+            Some(ref dir) => match KeyRing::load_dir(Path::new(dir), clock::system_time()) {
+                Ok(ring) => {
+                    debug!("loaded {} station keys from {}, current generation {}",
+                           ring.len(), dir, ring.current().generation);
+                    ring
+                },
+                Err(e) => {
+                    error!("failed to load station keys ({}), using the default key", e);
+                    KeyRing::single(priv_key)
+                },
+            },
+            None => KeyRing::single(priv_key),
+        };
+
+        let mut global = PerCoreGlobal::with_sinks(keys, the_lcore, value,
+                                                   flow_tracker,
+                                                   forwarder,
+                                                   registrar);
+        global.gre_offset = gre_offset;
+        if phantom_events.is_some() {
+            global.flow_tracker.phantom_flows.set_reporting(true);
+            global.phantom_events = phantom_events;
+        }
+
+        // with_sinks gave us a filter private to this core; swap in the one
+        // all the cores share.
+        if replay_window > 0 {
+            match ReplayFilter::open_shared(&replay_path, replay_window) {
+                Ok(f) => global.replay_filter = Some(f),
+                Err(e) => error!("failed to open replay filter {} ({}), replays are only \
+                                  caught on this core", replay_path, e),
+            }
+        }
+
+        if let Some(base) = metrics_addr {
+            match metrics::core_addr(&base, the_lcore) {
+                Ok(addr) => match metrics::spawn_server(addr, the_lcore, global.stats.metrics.clone()) {
+                    Ok(_) => debug!("serving metrics on {}", addr),
+                    Err(e) => error!("failed to serve metrics on {}: {}", addr, e),
+                },
+                Err(e) => error!("{}", e),
+            }
+        }
+        global
+    }
+
+    // Builds the per-core state around caller-provided sinks and flow tracker
+    // without touching the environment, tun devices or ZMQ. This is what
+    // offline tools (e.g. pcap replay) use to drive the detector.
+    pub fn with_sinks(keys: KeyRing, the_lcore: i32, conf: StationConfig,
+                      flow_tracker: FlowTracker,
+                      forwarder: Box<dyn ForwardSink>,
+                      registrar: Box<dyn RegistrationSink>) -> PerCoreGlobal
+    {
+        let tag_limiter = tag_limiter(&conf);
+        let fragments = fragment_reassembler(&conf);
         PerCoreGlobal {
-            priv_key: priv_key,
+            keys: keys,
             lcore: the_lcore,
             // sessions: HashMap::new(),
-            flow_tracker: FlowTracker::new(),
-            tun: tun,
+            flow_tracker: flow_tracker,
+            forwarder: forwarder,
+            pkt_meta: PacketMeta::default(),
             stats: PerCoreStats::new(),
-            ip_tree: PrefixTree::new(),
-            zmq_sock: zmq_sock,
-            filter_list: value.detector_filter_list,
-            gre_offset: gre_offset,
+            ip_tree: PrefixList::new(),
+            registrar: registrar,
+            phantom_events: None,
+            filter_list: conf.detector_filter_list,
+            decoy_ports: decoy_ports(conf.detector_decoy_ports),
+            replay_filter: match conf.detector_replay_window {
+                Some(0) => None,
+                Some(w) => Some(ReplayFilter::new(w)),
+                None => Some(ReplayFilter::new(replay_filter::DEFAULT_WINDOW_SECS)),
+            },
+            tag_limiter: tag_limiter,
+            fragments: fragments,
+            gre_offset: 0,
         }
     }
 
-    fn read_ip_list(&mut self)
+    // Everything a detector process needs to do before its first packet
+    // (rust_detect_init for detect.c, or a Rust capture loop): logging, the
+    // reporter FIFO, the per-core state and the phantom prefix list.
+    pub fn init(lcore_id: i32, priv_key: [u8; 32], workers_socket_addr: &str) -> PerCoreGlobal
     {
-        let f = match File::open(IP_LIST_PATH) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Failed to read {}: {:?}", IP_LIST_PATH, e);
-                return;
-            }
+        logging::init(log::LogLevel::Debug, lcore_id);
+
+        let s = format!("/tmp/dark-decoy-reporter-{}.fifo", lcore_id);
+        c_api::c_open_reporter(s);
+        report!("reset");
+
+        let mut global = PerCoreGlobal::new(priv_key, lcore_id, workers_socket_addr);
+        global.read_ip_list();
+
+        debug!("Initialized rust core {}", global.lcore);
+        global
+    }
+
+    // The 100ms housekeeping; see rust_periodic_cleanup.
+    pub fn periodic_cleanup(&mut self)
+    {
+        self.flow_tracker.drop_all_stale_flows();
+        self.publish_phantom_events();
+        // Pick up key rotations, and stop accepting keys past their expiry
+        let now = clock::system_time();
+        let reloaded = self.keys.reload_if_due(now);
+        self.log_key_reload(reloaded);
+        self.keys.drop_expired(now);
+        self.tag_limiter.drop_idle(clock::now_ns());
+        if let Some(ref mut fragments) = self.fragments {
+            fragments.drop_stale(clock::now_ns());
+        }
+        self.registrar.flush();
+        if let Some(ref mut sink) = self.phantom_events {
+            sink.flush();
+        }
+        self.event_loop_tick();
+        self.update_counters();
+        self.stats.publish_metrics(self.flow_tracker.count_tracked_flows(),
+                                   self.flow_tracker.count_phantom_flows(),
+                                   self.flow_tracker.count_phantom_connections());
+    }
+
+    fn log_key_reload(&self, reloaded: Result<bool, String>)
+    {
+        match reloaded {
+            Ok(true) => debug!("reloaded station keys, current generation {}",
+                               self.keys.current().generation),
+            Ok(false) => {},
+            Err(e) => error!("failed to reload station keys: {}", e),
+        }
+    }
+
+    pub fn periodic_report(&mut self)
+    {
+        self.update_counters();
+        self.stats.periodic_status_report(
+            self.flow_tracker.count_tracked_flows(),
+            self.flow_tracker.count_phantom_flows(),
+            self.flow_tracker.count_phantom_connections());
+    }
+
+    // After every burst of captured packets; see rust_event_loop_tick.
+    pub fn event_loop_tick(&mut self)
+    {
+        // Failures are counted as drops by the sink
+        if let Err(e) = self.forwarder.flush() {
+            debug!("failed to forward batched phantom packets: {}", e);
+        }
+    }
+
+    // Sends the PhantomEvents collected since the last cleanup
+    fn publish_phantom_events(&mut self)
+    {
+        let sink = match self.phantom_events {
+            Some(ref mut sink) => sink,
+            None => return,
         };
-        #[allow(unused_mut)]
-        let mut file = BufReader::new(&f);
-        for line in file.lines() {
-            let res = self.ip_tree.add_cidr(&line.unwrap());
-            if res.is_err() {
-                error!("Bad IP list: {:?}", res);
-                return;
+        for event in self.flow_tracker.phantom_flows.take_events() {
+            // Failures are counted by the sink
+            if sink.send_message(&event).is_ok() {
+                self.stats.phantom_events_this_period += 1;
             }
         }
+    }
 
+    fn update_counters(&mut self)
+    {
+        self.stats.sink_counters = self.registrar.counters();
+        if let Some(ref sink) = self.phantom_events {
+            self.stats.phantom_event_counters = sink.counters();
+        }
+        self.stats.session_table = self.flow_tracker.phantom_flows.occupancy();
+        self.stats.forward_sink = self.forwarder.name();
+        self.stats.forward_counters = self.forwarder.counters();
+        if let Some(ref fragments) = self.fragments {
+            self.stats.fragment_counters = fragments.counters();
+        }
+    }
+
+    fn read_ip_list(&mut self)
+    {
+        match PrefixList::from_file(IP_LIST_PATH) {
+            Ok(list) => self.ip_tree = list,
+            Err(e) => error!("Bad IP list: {}", e),
+        }
+    }
+
+    // Rereads the prefix list and station config and swaps in whatever
+    // parsed. Either one failing leaves that part as it was. Station keys
+    // that changed are picked up straight away too.
+    pub fn reload_config(&mut self)
+    {
+        let prefixes = PrefixList::from_file(IP_LIST_PATH)
+            .map_err(|e| error!("Keeping current IP list: {}", e)).ok();
+        let conf = read_station_config()
+            .map_err(|e| error!("Keeping current station config: {}", e)).ok();
+        if let Some(ref conf) = conf {
+            logging::configure(log_config(conf));
+        }
+        let diff = self.apply_config(prefixes, conf);
+        diff.log();
+        let reloaded = self.keys.reload_if_changed(clock::system_time());
+        self.log_key_reload(reloaded);
+    }
+
+    // Swaps in a new prefix list and/or station config, returning what
+    // changed. Only the parts that are safe to change under a running
+    // detector are taken from the config (filter list, decoy ports and tag
+    // check limits). The limiter is only replaced if its limits changed, as
+    // a new one starts with full buckets.
+    pub fn apply_config(&mut self, prefixes: Option<PrefixList>,
+                        conf: Option<StationConfig>) -> ConfigDiff
+    {
+        let mut diff = ConfigDiff::default();
+        if let Some(list) = prefixes {
+            let (added, removed) = self.ip_tree.diff(&list);
+            diff.prefixes_added = added;
+            diff.prefixes_removed = removed;
+            self.ip_tree = list;
+        }
+        diff.prefixes = self.ip_tree.len();
+        if let Some(conf) = conf {
+            let limiter = tag_limiter(&conf);
+            if !limiter.same_limits(&self.tag_limiter) {
+                diff.tag_limits = true;
+                self.tag_limiter = limiter;
+            }
+
+            let (added, removed) = util::list_diff(&self.filter_list, &conf.detector_filter_list);
+            diff.filter_added = added;
+            diff.filter_removed = removed;
+            self.filter_list = conf.detector_filter_list;
+
+            let ports = decoy_ports(conf.detector_decoy_ports);
+            if ports != self.decoy_ports {
+                diff.decoy_ports = Some(ports.clone());
+            }
+            self.decoy_ports = ports;
+        }
+        diff
     }
 
 }
@@ -205,21 +998,124 @@ impl PerCoreStats
                        ipv4_packets_this_period: 0,
                        ipv6_packets_this_period: 0,
                        tcp_packets_this_period: 0,
+                       udp_packets_this_period: 0,
                        tls_packets_this_period: 0,
                        bytes_this_period: 0,
                        //reconns_this_period: 0,
                        tls_bytes_this_period: 0,
                        port_443_syns_this_period: 0,
                        //cli2cov_raw_etherbytes_this_period: 0,
+                       tags_reassembled_this_period: 0,
+                       reassembly_drops_this_period: 0,
+                       registrations_sent_this_period: 0,
+                       registration_send_failures_this_period: 0,
+                       phantom_events_this_period: 0,
+                       forward_failures_this_period: 0,
+                       udp_flows_over_limit_this_period: 0,
+                       replayed_tags_this_period: 0,
+                       source_limited_this_period: 0,
+                       global_limited_this_period: 0,
+                       ip_fragments_this_period: 0,
+                       bad_ip_headers_this_period: 0,
+                       fragment_counters: FragmentCounters::default(),
+                       decap: DecapCounters::default(),
 
                        tot_usr_us: 0,
                        tot_sys_us: 0,
                        last_measure_time: precise_time_ns(),
 
                         not_in_tree_this_period: 0,
-                        in_tree_this_period: 0 }
+                        in_tree_this_period: 0,
+                        sink_counters: SinkCounters::default(),
+                        phantom_event_counters: SinkCounters::default(),
+                        session_table: Occupancy::default(),
+                        forward_sink: "",
+                        forward_counters: ForwardCounters::default(),
+
+                        totals: MetricsSnapshot::default(),
+                        metrics: SharedMetrics::default() }
     }
-    fn periodic_status_report(&mut self, tracked: usize, dark_decoys: usize)
+
+    // Cumulative counters as of right now: reported periods plus this one.
+    pub fn cumulative(&self) -> MetricsSnapshot
+    {
+        let t = &self.totals;
+        MetricsSnapshot {
+            packets: t.packets + self.packets_this_period,
+            bytes: t.bytes + self.bytes_this_period,
+            ipv4_packets: t.ipv4_packets + self.ipv4_packets_this_period,
+            ipv6_packets: t.ipv6_packets + self.ipv6_packets_this_period,
+            tcp_packets: t.tcp_packets + self.tcp_packets_this_period,
+            udp_packets: t.udp_packets + self.udp_packets_this_period,
+            tls_packets: t.tls_packets + self.tls_packets_this_period,
+            tls_bytes: t.tls_bytes + self.tls_bytes_this_period,
+            port_443_syns: t.port_443_syns + self.port_443_syns_this_period,
+            tags_checked: t.tags_checked + self.elligator_this_period,
+            tags_reassembled: t.tags_reassembled + self.tags_reassembled_this_period,
+            reassembly_drops: t.reassembly_drops + self.reassembly_drops_this_period,
+            registrations_sent: t.registrations_sent + self.registrations_sent_this_period,
+            registration_send_failures: t.registration_send_failures
+                + self.registration_send_failures_this_period,
+            forward_failures: t.forward_failures + self.forward_failures_this_period,
+            udp_flows_over_limit: t.udp_flows_over_limit + self.udp_flows_over_limit_this_period,
+            outside_prefixes: t.outside_prefixes + self.not_in_tree_this_period,
+            replayed_tags: t.replayed_tags + self.replayed_tags_this_period,
+            source_limited_tags: t.source_limited_tags + self.source_limited_this_period,
+            global_limited_tags: t.global_limited_tags + self.global_limited_this_period,
+            ip_fragments: t.ip_fragments + self.ip_fragments_this_period,
+            bad_ip_headers: t.bad_ip_headers + self.bad_ip_headers_this_period,
+            fragments_reassembled: self.fragment_counters.reassembled,
+            fragment_drops: self.fragment_counters.dropped,
+            fragments_source_limited: self.fragment_counters.source_limited,
+            fragment_timeouts: self.fragment_counters.timed_out,
+            vlan_frames: self.decap.vlan,
+            qinq_frames: self.decap.qinq,
+            mpls_packets: self.decap.mpls,
+            gre_packets: self.decap.gre,
+            erspan_packets: self.decap.erspan,
+            vxlan_packets: self.decap.vxlan,
+            decap_failures: self.decap.failed,
+            registration_errors: self.sink_counters.errors,
+            registrations_dropped: self.sink_counters.dropped,
+            registrations_retried: self.sink_counters.retried,
+            registrations_queued: self.sink_counters.queued,
+            phantom_events: t.phantom_events + self.phantom_events_this_period,
+            phantom_events_dropped: self.phantom_event_counters.dropped,
+            phantom_sessions_rejected: self.session_table.rejected,
+            forward_sink: self.forward_sink,
+            forwarded_packets: self.forward_counters.forwarded,
+            forward_drops: self.forward_counters.dropped,
+            forward_latency_ns: self.forward_counters.latency_ns,
+
+            tracked_flows: t.tracked_flows,
+            phantom_flows: t.phantom_flows,
+            phantom_session_slots: self.session_table.slots as u64,
+            phantom_conns_syn_seen: t.phantom_conns_syn_seen,
+            phantom_conns_established: t.phantom_conns_established,
+            phantom_conns_half_closed: t.phantom_conns_half_closed,
+            phantom_conns_closed: t.phantom_conns_closed,
+            cpu_user_us: self.tot_usr_us,
+            cpu_sys_us: self.tot_sys_us,
+        }
+    }
+
+    // Updates what the metrics endpoint serves. Cheap enough to call from
+    // the periodic cleanup, never from the packet path.
+    fn publish_metrics(&mut self, tracked: usize, dark_decoys: usize, conns: ConnCounts)
+    {
+        self.totals.tracked_flows = tracked as u64;
+        self.totals.phantom_flows = dark_decoys as u64;
+        self.totals.phantom_conns_syn_seen = conns.syn_seen as u64;
+        self.totals.phantom_conns_established = conns.established as u64;
+        self.totals.phantom_conns_half_closed = conns.half_closed as u64;
+        self.totals.phantom_conns_closed = conns.closed as u64;
+        let snapshot = self.cumulative();
+        match self.metrics.lock() {
+            Ok(mut m) => *m = snapshot,
+            Err(poisoned) => *poisoned.into_inner() = snapshot,
+        }
+    }
+    fn periodic_status_report(&mut self, tracked: usize, dark_decoys: usize, conns: ConnCounts)
     {
         let cur_measure_time = precise_time_ns();
         let (user_secs, user_usecs, sys_secs, sys_usecs) =
@@ -250,27 +1146,45 @@ impl PerCoreStats
                 0,
                 0);
         */
-        report!("stats {} pkts ({} v4, {} v6) dark decoy flows {} tracked flows {} tags checked {}",
+        report!("stats {} pkts ({} v4, {} v6) dark decoy flows {} tracked flows {} tags checked {} reassembled {} ({} dropped)",
             self.packets_this_period,
             self.ipv4_packets_this_period,
             self.ipv6_packets_this_period,
             dark_decoys,
             tracked,
-            self.elligator_this_period);
+            self.elligator_this_period,
+            self.tags_reassembled_this_period,
+            self.reassembly_drops_this_period);
+
+        self.tot_usr_us = user_microsecs;
+        self.tot_sys_us = sys_microsecs;
+        self.publish_metrics(tracked, dark_decoys, conns);
+        self.totals = self.cumulative();
 
         self.elligator_this_period = 0;
         self.packets_this_period = 0;
         self.ipv4_packets_this_period = 0;
         self.ipv6_packets_this_period = 0;
         self.tcp_packets_this_period = 0;
+        self.udp_packets_this_period = 0;
         self.tls_packets_this_period = 0;
         self.bytes_this_period = 0;
         //self.reconns_this_period = 0;
         self.tls_bytes_this_period = 0;
         self.port_443_syns_this_period = 0;
+        self.tags_reassembled_this_period = 0;
+        self.reassembly_drops_this_period = 0;
+        self.registrations_sent_this_period = 0;
+        self.registration_send_failures_this_period = 0;
+        self.phantom_events_this_period = 0;
+        self.forward_failures_this_period = 0;
+        self.udp_flows_over_limit_this_period = 0;
+        self.replayed_tags_this_period = 0;
+        self.source_limited_this_period = 0;
+        self.global_limited_this_period = 0;
+        self.ip_fragments_this_period = 0;
+        self.bad_ip_headers_this_period = 0;
 
-        self.tot_usr_us = user_microsecs;
-        self.tot_sys_us = sys_microsecs;
         self.last_measure_time = cur_measure_time;
 
         self.not_in_tree_this_period = 0;
@@ -284,9 +1198,7 @@ pub extern "C" fn rust_periodic_report(ptr: *mut PerCoreGlobal)
 {
     #[allow(unused_mut)]
     let mut global = unsafe { &mut *ptr };
-    global.stats.periodic_status_report(
-        global.flow_tracker.count_tracked_flows(),
-        global.flow_tracker.count_phantom_flows());
+    global.periodic_report();
 }
 
 #[repr(C)]
@@ -300,32 +1212,35 @@ pub extern "C" fn rust_detect_init(lcore_id: i32, ckey: *const u8, workers_socke
 -> RustGlobalsStruct
 {
 
-    logging::init(log::LogLevel::Debug, lcore_id);
-
     let key = *array_ref![unsafe{std::slice::from_raw_parts(ckey, 32 as usize)},
                             0, 32];
 
-    let s = format!("/tmp/dark-decoy-reporter-{}.fifo", lcore_id);
-    c_api::c_open_reporter(s);
-    report!("reset");
-
     let addr: &CStr = unsafe { CStr::from_ptr(workers_socket_addr) };
 
-    let mut global = PerCoreGlobal::new(key, lcore_id, addr.to_str().unwrap());
-    global.read_ip_list();
-
-    debug!("Initialized rust core {}", global.lcore);
+    let global = PerCoreGlobal::init(lcore_id, key, addr.to_str().unwrap());
 
     RustGlobalsStruct { global: unsafe { transmute(Box::new(global)) } }
                         //fail_map: unsafe { transmute(Box::new(fail_map)) },
                         //cli_conf: unsafe { transmute(Box::new(cli_conf)) } }
 }
 
-// Called so we can tick the event loop forward. Must not block.
+// Called (between packets) after the detector gets a SIGHUP: rereads the
+// phantom prefix list and station config.
 #[no_mangle]
-pub extern "C" fn rust_event_loop_tick(_ptr: *mut PerCoreGlobal)
+pub extern "C" fn rust_reload_config(ptr: *mut PerCoreGlobal)
 {
+    #[allow(unused_mut)]
+    let mut global = unsafe { &mut *ptr };
+    global.reload_config();
+}
 
+// Called so we can tick the event loop forward. Must not block.
+#[no_mangle]
+pub extern "C" fn rust_event_loop_tick(ptr: *mut PerCoreGlobal)
+{
+    #[allow(unused_mut)]
+    let mut global = unsafe { &mut *ptr };
+    global.event_loop_tick();
 }
 
 // Drops TLS flows that took too long to send their first app data packet,
@@ -336,7 +1251,7 @@ pub extern "C" fn rust_periodic_cleanup(ptr: *mut PerCoreGlobal)
 {
     #[allow(unused_mut)]
     let mut global = unsafe { &mut *ptr };
-    global.flow_tracker.drop_all_stale_flows();
+    global.periodic_cleanup();
 
     /*
     // Any session that hangs around for 30 seconds with a None cli stream
@@ -352,4 +1267,77 @@ pub extern "C" fn rust_periodic_cleanup(ptr: *mut PerCoreGlobal)
     */
 }
 
+// How often the session ingest process expires sessions, and logs how full
+// the table is
+const SESSION_EXPIRY_INTERVAL_MS: u64 = 100;
+const SESSION_TABLE_REPORT_NS: u64 = 10 * 1000 * 1000 * 1000;
 
+// The session ingest process, forked by detect.c alongside the cores: fills
+// the shared session table from Redis, expires the sessions that time out
+// (sending ExpiredUnused events for those nobody used, if there's an event
+// sink) and logs the table's occupancy. Returns 0 if the config has no shared
+// table, or 1 if it couldn't carry on (detect.c starts it again).
+#[no_mangle]
+pub extern "C" fn rust_session_ingest() -> i32
+{
+    logging::init(log::LogLevel::Debug, -1);
+    match panic::catch_unwind(session_ingest) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            error!("session ingest stopping: {}", e);
+            1
+        },
+        // The panic has been printed
+        Err(_) => 1,
+    }
+}
+
+fn session_ingest() -> Result<(), String>
+{
+    let conf = read_station_config()?;
+    logging::configure(log_config(&conf));
+
+    let table = match shared_session_table(&conf) {
+        Some(Ok(table)) => Arc::new(table),
+        Some(Err(e)) => return Err(format!("failed to open the session table: {}", e)),
+        None => {
+            info!("no shared session table, each core gets its own sessions");
+            return Ok(());
+        },
+    };
+    // The last ingest process may have died writing to it
+    table.recover_writer();
+    let mut sessions = SessionTracker::with_table(table, true);
+    let updates = sessions.spawn_update_thread(ingest_config(&conf));
+
+    let mut events = phantom_event_sink(&conf).unwrap_or_else(|e| {
+        error!("{}, not sending phantom events", e);
+        None
+    });
+    sessions.set_reporting(events.is_some());
+
+    let mut last_report = precise_time_ns();
+    loop {
+        thread::sleep(Duration::from_millis(SESSION_EXPIRY_INTERVAL_MS));
+        if updates.is_finished() {
+            return Err("the redis ingest thread panicked".to_string());
+        }
+        sessions.drop_stale_sessions();
+        if let Some(ref mut sink) = events {
+            for event in sessions.take_events() {
+                // Failures are counted by the sink
+                let _ = sink.send_message(&event);
+            }
+            sink.flush();
+        }
+        if precise_time_ns() - last_report >= SESSION_TABLE_REPORT_NS {
+            last_report = precise_time_ns();
+            let occupancy = sessions.occupancy();
+            Event::new(log::LogLevel::Info, "session_table")
+                .num("sessions", occupancy.sessions as u64)
+                .num("slots", occupancy.slots as u64)
+                .num("rejected", occupancy.rejected)
+                .emit();
+        }
+    }
+}
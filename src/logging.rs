@@ -1,21 +1,140 @@
-
-
 extern crate log;
 extern crate time;
 
-use log::{LogRecord, LogLevel, LogMetadata};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+use log::{LogRecord, LogLevel, LogLevelFilter, LogMetadata};
+use time::precise_time_ns;
+
+use flow_tracker::Flow;
+use rate_limit::{Limit, TokenBucket};
+use signalling::RegistrationSource;
+
+// Per event type, unless the station config says otherwise
+pub const DEFAULT_EVENT_RATE: f64 = 20.0;
+pub const DEFAULT_EVENT_BURST: f64 = 200.0;
+
+// The level and format can be changed at any time (see configure), so the
+// log crate's own max level stays at Trace and the logger does the filtering.
+static LEVEL: AtomicUsize = AtomicUsize::new(LogLevelFilter::Debug as usize);
+static JSON: AtomicBool = AtomicBool::new(false);
+static CORE: AtomicIsize = AtomicIsize::new(0);
 
-pub struct SimpleLogger
+pub struct LogConfig
 {
-    log_level:  LogLevel,
-    lcore_id:   i32,
+    pub level: LogLevelFilter,
+    pub json: bool,
+    // How often events of any one type may be logged (None: always)
+    pub event_limit: Option<Limit>,
+    // Log only one in this many events of the given type
+    pub sample: HashMap<String, u32>,
+}
+
+impl Default for LogConfig
+{
+    fn default() -> LogConfig
+    {
+        LogConfig {
+            level: LogLevelFilter::Debug,
+            json: false,
+            event_limit: Some(Limit { per_sec: DEFAULT_EVENT_RATE, burst: DEFAULT_EVENT_BURST }),
+            sample: HashMap::new(),
+        }
+    }
+}
+
+pub struct SimpleLogger;
+
+fn enabled(level: LogLevel) -> bool
+{
+    level as usize <= LEVEL.load(Ordering::Relaxed)
+}
+
+fn json_str(out: &mut String, s: &str)
+{
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+enum Value
+{
+    Str(String),
+    Num(u64),
+}
+
+// One line: the usual "<time> (Core n) LEVEL: ..." in text mode, or a JSON
+// object with ts, core, level and event first.
+fn format_line(json: bool, level: LogLevel, kind: &str, fields: &[(&str, Value)]) -> String
+{
+    let t = time::now();
+    let core = CORE.load(Ordering::Relaxed);
+    let mut out = String::with_capacity(256);
+    if json {
+        // unwrap relies on the format strings being valid.
+        let ts = time::strftime("%Y-%m-%dT%H:%M:%S.%f%z", &t).unwrap();
+        let _ = write!(out, "{{\"ts\":\"{}\",\"core\":{},\"level\":\"{}\",\"event\":", ts, core,
+                       level);
+        json_str(&mut out, kind);
+        for &(name, ref val) in fields {
+            out.push(',');
+            json_str(&mut out, name);
+            out.push(':');
+            match *val {
+                Value::Str(ref s) => json_str(&mut out, s),
+                Value::Num(n) => {
+                    let _ = write!(out, "{}", n);
+                },
+            }
+        }
+        out.push('}');
+    } else {
+        let ts = time::strftime("%Y-%m-%d %H:%M:%S.%f %z", &t).unwrap();
+        let _ = write!(out, "{} (Core {}) {}: ", ts, core, level);
+        // Plain log messages look like they always did
+        match (kind, fields.first()) {
+            ("log", Some(&(_, Value::Str(ref msg)))) => out.push_str(msg),
+            _ => {
+                out.push_str(kind);
+                for &(name, ref val) in fields {
+                    let _ = match *val {
+                        Value::Str(ref s) => write!(out, " {}=\"{}\"", name, s),
+                        Value::Num(n) => write!(out, " {}={}", name, n),
+                    };
+                }
+            },
+        }
+    }
+    out
+}
+
+fn write_line(level: LogLevel, kind: &str, fields: &[(&str, Value)])
+{
+    println!("{}", format_line(JSON.load(Ordering::Relaxed), level, kind, fields));
 }
 
 impl log::Log for SimpleLogger
 {
     fn enabled(&self, metadata: &LogMetadata) -> bool
     {
-        metadata.level() <= self.log_level
+        enabled(metadata.level())
     }
 
     fn log(&self, record: &LogRecord)
@@ -26,10 +145,7 @@ impl log::Log for SimpleLogger
             if record.level() != LogLevel::Trace ||
             (!s.starts_with("event loop") && !s.starts_with("tick_to")
              && !s.starts_with("ticking")) {
-                let t = time::now();
-                // unwrap relies on "%b %d, %Y %T" being a valid format string.
-                let t_s = time::strftime("%Y-%m-%d %H:%M:%S.%f %z", &t).unwrap();
-                println!("{} (Core {}) {}: {}", t_s, self.lcore_id, record.level(), s);
+                write_line(record.level(), "log", &[("msg", Value::Str(s))]);
             }
         }
     }
@@ -37,12 +153,148 @@ impl log::Log for SimpleLogger
 
 pub fn init(log_level: LogLevel, core_id: i32)
 {
+    LEVEL.store(log_level.to_log_level_filter() as usize, Ordering::Relaxed);
+    CORE.store(core_id as isize, Ordering::Relaxed);
     log::set_logger(|max_log_level| {
-        max_log_level.set(log_level.to_log_level_filter());
-        Box::new(SimpleLogger{log_level: log_level, lcore_id: core_id})
+        max_log_level.set(LogLevelFilter::Trace);
+        Box::new(SimpleLogger)
     }).unwrap_or_else(|e|{error!("failed to init logging: {}", e);});
 }
 
+// Applies the level and format to the whole process, and the event limits to
+// the calling thread (each core's packet thread has its own).
+pub fn configure(conf: LogConfig)
+{
+    LEVEL.store(conf.level as usize, Ordering::Relaxed);
+    JSON.store(conf.json, Ordering::Relaxed);
+    EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        events.limit = conf.event_limit;
+        events.sample = conf.sample;
+        events.kinds.clear();
+    });
+}
+
+struct EventCount
+{
+    bucket: TokenBucket,
+    // Events of this type to pass over before the next one sampled
+    skip: u64,
+    // Not logged since the last one that was
+    suppressed: u64,
+}
+
+struct EventLimiter
+{
+    limit: Option<Limit>,
+    sample: HashMap<String, u32>,
+    kinds: HashMap<&'static str, EventCount>,
+}
+
+impl EventLimiter
+{
+    // Whether an event of this type gets logged. If so, how many were
+    // suppressed (sampled out or over the rate limit) since the last one.
+    fn admit(&mut self, kind: &'static str, now_ns: u64) -> Option<u64>
+    {
+        let every = self.sample.get(kind).map(|n| (*n).max(1)).unwrap_or(1) as u64;
+        let limit = self.limit;
+        let count = self.kinds.entry(kind).or_insert_with(|| EventCount {
+            bucket: TokenBucket::new(&limit.unwrap_or(Limit { per_sec: 0.0, burst: 0.0 }), now_ns),
+            skip: 0,
+            suppressed: 0,
+        });
+        let sampled = count.skip == 0;
+        count.skip = if sampled { every - 1 } else { count.skip - 1 };
+        let allowed = sampled && match limit {
+            Some(ref l) => count.bucket.take(l, now_ns),
+            None => true,
+        };
+        if !allowed {
+            count.suppressed += 1;
+            return None;
+        }
+        Some(mem::replace(&mut count.suppressed, 0))
+    }
+}
+
+thread_local! {
+    static EVENTS: RefCell<EventLimiter> = RefCell::new(EventLimiter {
+        limit: LogConfig::default().event_limit,
+        sample: HashMap::new(),
+        kinds: HashMap::new(),
+    });
+}
+
+// A structured log event: a type (e.g. "registration") and fields, written
+// as one line by emit(). Whether it gets logged is decided up front, so the
+// field setters cost nothing for events that are filtered out.
+//
+//     Event::new(LogLevel::Debug, "phantom_syn").flow(&flow).emit();
+pub struct Event
+{
+    level: LogLevel,
+    kind: &'static str,
+    // None if this one isn't getting logged
+    fields: Option<Vec<(&'static str, Value)>>,
+    suppressed: u64,
+}
+
+impl Event
+{
+    pub fn new(level: LogLevel, kind: &'static str) -> Event
+    {
+        let admitted = match enabled(level) {
+            true => EVENTS.with(|e| e.borrow_mut().admit(kind, precise_time_ns())),
+            false => None,
+        };
+        Event {
+            level,
+            kind,
+            fields: admitted.map(|_| Vec::new()),
+            suppressed: admitted.unwrap_or(0),
+        }
+    }
+
+    pub fn str(mut self, name: &'static str, val: &dyn fmt::Display) -> Event
+    {
+        if let Some(ref mut fields) = self.fields {
+            fields.push((name, Value::Str(val.to_string())));
+        }
+        self
+    }
+
+    pub fn num(mut self, name: &'static str, val: u64) -> Event
+    {
+        if let Some(ref mut fields) = self.fields {
+            fields.push((name, Value::Num(val)));
+        }
+        self
+    }
+
+    // The flow as Flow prints it, i.e. with the client as the client_ip
+    // mode says
+    pub fn flow(self, flow: &Flow) -> Event
+    {
+        self.str("flow", flow)
+    }
+
+    pub fn source(self, source: RegistrationSource) -> Event
+    {
+        self.str("source", &format_args!("{:?}", source))
+    }
+
+    pub fn emit(mut self)
+    {
+        if let Some(mut fields) = self.fields.take() {
+            if self.suppressed > 0 {
+                fields.push(("suppressed", Value::Num(self.suppressed)));
+            }
+            write_line(self.level, self.kind, &fields);
+        }
+    }
+}
+
 //HACKY_CFG_NO_TEST_BEGIN
 #[macro_export]
 macro_rules! report {
@@ -65,3 +317,54 @@ macro_rules! report {
 //HACKY_CFG_YES_TEST_END*/
 
 
+#[cfg(test)]
+mod tests {
+    use logging::*;
+    use rate_limit::Limit;
+
+    const SEC: u64 = 1000 * 1000 * 1000;
+
+    #[test]
+    fn test_event_limiter()
+    {
+        let mut sample = HashMap::new();
+        sample.insert("phantom_syn".to_string(), 3);
+        let mut l = EventLimiter {
+            limit: Some(Limit { per_sec: 1.0, burst: 2.0 }),
+            sample,
+            kinds: HashMap::new(),
+        };
+
+        assert_eq!(l.admit("registration", 0), Some(0));
+        assert_eq!(l.admit("registration", 0), Some(0));
+        assert_eq!(l.admit("registration", 0), None);
+        assert_eq!(l.admit("registration", 0), None);
+        // Other types have their own bucket
+        assert_eq!(l.admit("forward_failed", 0), Some(0));
+        assert_eq!(l.admit("registration", SEC), Some(2));
+
+        // One in three, then the bucket runs out too
+        let logged: Vec<_> = (0..9).map(|_| l.admit("phantom_syn", SEC)).collect();
+        assert_eq!(logged, vec![Some(0), None, None, Some(2), None, None, None, None, None]);
+        assert_eq!(l.admit("phantom_syn", 10 * SEC), Some(5));
+    }
+
+    #[test]
+    fn test_format()
+    {
+        let fields = [("flow", Value::Str("_ -> 192.0.2.1:443".to_string())),
+                      ("note", Value::Str("a \"quoted\"\nline".to_string())),
+                      ("key_generation", Value::Num(2))];
+        let line = format_line(true, LogLevel::Info, "registration", &fields);
+        assert!(line.starts_with("{\"ts\":\""));
+        assert!(line.ends_with(",\"level\":\"INFO\",\"event\":\"registration\",\
+                                \"flow\":\"_ -> 192.0.2.1:443\",\"note\":\"a \\\"quoted\\\"\\nline\",\
+                                \"key_generation\":2}"));
+
+        let line = format_line(false, LogLevel::Info, "registration", &fields[..1]);
+        assert!(line.ends_with(" INFO: registration flow=\"_ -> 192.0.2.1:443\""));
+        let line = format_line(false, LogLevel::Warn, "log",
+                               &[("msg", Value::Str("plain".to_string()))]);
+        assert!(line.ends_with(" WARN: plain"));
+    }
+}
@@ -0,0 +1,425 @@
+//
+// Prometheus exposition of the per-core detector counters
+//
+// Every detector process (one per core) serves its own counters, labelled
+// with the core number, on a local HTTP endpoint. The packet path never
+// touches this; PerCoreStats copies its running totals into the shared
+// snapshot from the periodic cleanup/report calls, and the server thread only
+// ever reads that snapshot.
+//
+
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Cumulative values since the detector started (the flow counts and CPU
+// times are current values).
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct MetricsSnapshot
+{
+    pub packets: u64,
+    pub bytes: u64,
+    pub ipv4_packets: u64,
+    pub ipv6_packets: u64,
+    pub tcp_packets: u64,
+    pub udp_packets: u64,
+    pub tls_packets: u64,
+    pub tls_bytes: u64,
+    pub port_443_syns: u64,
+    pub tags_checked: u64,
+    pub tags_reassembled: u64,
+    pub reassembly_drops: u64,
+    pub registrations_sent: u64,
+    pub registration_send_failures: u64,
+    pub forward_failures: u64,
+    pub udp_flows_over_limit: u64,
+    pub outside_prefixes: u64,
+    pub replayed_tags: u64,
+    pub source_limited_tags: u64,
+    pub global_limited_tags: u64,
+    pub ip_fragments: u64,
+    pub bad_ip_headers: u64,
+    pub fragments_reassembled: u64,
+    pub fragment_drops: u64,
+    pub fragments_source_limited: u64,
+    pub fragment_timeouts: u64,
+    pub vlan_frames: u64,
+    pub qinq_frames: u64,
+    pub mpls_packets: u64,
+    pub gre_packets: u64,
+    pub erspan_packets: u64,
+    pub vxlan_packets: u64,
+    pub decap_failures: u64,
+    pub registration_errors: u64,
+    pub registrations_dropped: u64,
+    pub registrations_retried: u64,
+    pub phantom_events: u64,
+    pub phantom_events_dropped: u64,
+    pub phantom_sessions_rejected: u64,
+    // Labelled with the forwarding sink's name
+    pub forward_sink: &'static str,
+    pub forwarded_packets: u64,
+    pub forward_drops: u64,
+    pub forward_latency_ns: u64,
+
+    pub registrations_queued: u64,
+    pub tracked_flows: u64,
+    pub phantom_flows: u64,
+    pub phantom_session_slots: u64,
+    pub phantom_conns_syn_seen: u64,
+    pub phantom_conns_established: u64,
+    pub phantom_conns_half_closed: u64,
+    pub phantom_conns_closed: u64,
+
+    pub cpu_user_us: i64,
+    pub cpu_sys_us: i64,
+}
+
+pub type SharedMetrics = Arc<Mutex<MetricsSnapshot>>;
+
+fn counter(out: &mut String, name: &str, help: &str, lcore: i32, val: u64)
+{
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{}{{core=\"{}\"}} {}", name, lcore, val);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, lcore: i32, val: u64)
+{
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{}{{core=\"{}\"}} {}", name, lcore, val);
+}
+
+// Renders the snapshot in the Prometheus text format (version 0.0.4).
+pub fn render(lcore: i32, m: &MetricsSnapshot) -> String
+{
+    let mut out = String::with_capacity(4096);
+    counter(&mut out, "conjure_detector_packets_total",
+            "Frames seen by the detector.", lcore, m.packets);
+    counter(&mut out, "conjure_detector_bytes_total",
+            "Bytes seen by the detector (excluding any GRE offset).", lcore, m.bytes);
+    counter(&mut out, "conjure_detector_ipv4_packets_total",
+            "IPv4 packets seen.", lcore, m.ipv4_packets);
+    counter(&mut out, "conjure_detector_ipv6_packets_total",
+            "IPv6 packets seen.", lcore, m.ipv6_packets);
+    counter(&mut out, "conjure_detector_tcp_packets_total",
+            "TCP packets seen.", lcore, m.tcp_packets);
+    counter(&mut out, "conjure_detector_udp_packets_total",
+            "UDP packets seen.", lcore, m.udp_packets);
+    counter(&mut out, "conjure_detector_tls_packets_total",
+            "TCP packets to a decoy port.", lcore, m.tls_packets);
+    counter(&mut out, "conjure_detector_tls_bytes_total",
+            "Bytes of TCP packets to a decoy port.", lcore, m.tls_bytes);
+    counter(&mut out, "conjure_detector_decoy_port_syns_total",
+            "SYNs to a decoy port (flows that start being tracked).", lcore, m.port_443_syns);
+    counter(&mut out, "conjure_detector_tags_checked_total",
+            "TLS records checked for a registration tag.", lcore, m.tags_checked);
+    counter(&mut out, "conjure_detector_tags_reassembled_total",
+            "Tagged-record candidates that spanned more than one segment.", lcore,
+            m.tags_reassembled);
+    counter(&mut out, "conjure_detector_reassembly_drops_total",
+            "First records the detector gave up reassembling.", lcore, m.reassembly_drops);
+    counter(&mut out, "conjure_detector_registrations_sent_total",
+            "Registrations handed to the registration sink.", lcore, m.registrations_sent);
+    counter(&mut out, "conjure_detector_registration_send_failures_total",
+            "Registrations the registration sink failed to send.", lcore,
+            m.registration_send_failures);
+    counter(&mut out, "conjure_detector_forward_failures_total",
+            "Phantom packets that could not be forwarded.", lcore, m.forward_failures);
+    counter(&mut out, "conjure_detector_udp_flows_over_limit_total",
+            "Datagrams on UDP phantom flows not followed because their session had too many.",
+            lcore, m.udp_flows_over_limit);
+    counter(&mut out, "conjure_detector_outside_prefixes_total",
+            "Phantom packets not forwarded because the phantom isn't in the prefix list.",
+            lcore, m.outside_prefixes);
+    counter(&mut out, "conjure_detector_replayed_tags_total",
+            "Registration tags dropped because their shared secret was seen recently.",
+            lcore, m.replayed_tags);
+    let name = "conjure_detector_rate_limited_tags_total";
+    let _ = writeln!(out, "# HELP {} Tag checks skipped for going over a rate limit.", name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{}{{core=\"{}\",limit=\"source\"}} {}", name, lcore,
+                     m.source_limited_tags);
+    let _ = writeln!(out, "{}{{core=\"{}\",limit=\"core\"}} {}", name, lcore,
+                     m.global_limited_tags);
+    counter(&mut out, "conjure_detector_ip_fragments_total",
+            "IP fragments seen.", lcore, m.ip_fragments);
+    counter(&mut out, "conjure_detector_fragments_reassembled_total",
+            "Fragmented packets put back together.", lcore, m.fragments_reassembled);
+    let name = "conjure_detector_fragment_drops_total";
+    let _ = writeln!(out, "# HELP {} Fragments or fragmented packets given up on \
+                                 (other: overlapping, malformed or over the per-core limits).",
+                     name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{}{{core=\"{}\",reason=\"other\"}} {}", name, lcore, m.fragment_drops);
+    let _ = writeln!(out, "{}{{core=\"{}\",reason=\"source_limit\"}} {}", name, lcore,
+                     m.fragments_source_limited);
+    let _ = writeln!(out, "{}{{core=\"{}\",reason=\"timeout\"}} {}", name, lcore,
+                     m.fragment_timeouts);
+    counter(&mut out, "conjure_detector_bad_ip_headers_total",
+            "IPv6 packets skipped for too many or truncated extension headers.", lcore,
+            m.bad_ip_headers);
+    let name = "conjure_detector_encapsulated_packets_total";
+    let _ = writeln!(out, "# HELP {} Frames that were tagged (vlan: once, qinq: more than once) \
+                                 or came through an MPLS label stack or a tunnel.", name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for &(encap, val) in &[("vlan", m.vlan_frames), ("qinq", m.qinq_frames),
+                           ("mpls", m.mpls_packets), ("gre", m.gre_packets),
+                           ("erspan", m.erspan_packets), ("vxlan", m.vxlan_packets)] {
+        let _ = writeln!(out, "{}{{core=\"{}\",encap=\"{}\"}} {}", name, lcore, encap, val);
+    }
+    counter(&mut out, "conjure_detector_decap_failures_total",
+            "Frames dropped for truncated or unknown encapsulation headers.", lcore,
+            m.decap_failures);
+    counter(&mut out, "conjure_detector_registration_errors_total",
+            "Failed attempts to hand a registration to the application (including retries).",
+            lcore, m.registration_errors);
+    counter(&mut out, "conjure_detector_registrations_dropped_total",
+            "Registrations given up on because the registration queue was full (or off).",
+            lcore, m.registrations_dropped);
+    counter(&mut out, "conjure_detector_registrations_retried_total",
+            "Registrations delivered from the registration queue.", lcore,
+            m.registrations_retried);
+    counter(&mut out, "conjure_detector_phantom_events_total",
+            "Phantom session events handed to the event sink.", lcore, m.phantom_events);
+    counter(&mut out, "conjure_detector_phantom_events_dropped_total",
+            "Phantom session events given up on because the event queue was full (or off).",
+            lcore, m.phantom_events_dropped);
+    counter(&mut out, "conjure_detector_phantom_sessions_rejected_total",
+            "Sessions the session table had no room for.", lcore, m.phantom_sessions_rejected);
+
+    let sink = |out: &mut String, name: &str, val: &dyn fmt::Display| {
+        let _ = writeln!(out, "{}{{core=\"{}\",sink=\"{}\"}} {}", name, lcore, m.forward_sink, val);
+    };
+    let name = "conjure_detector_forwarded_packets_total";
+    let _ = writeln!(out, "# HELP {} Phantom packets the forwarding sink handed to the kernel.", name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    sink(&mut out, name, &m.forwarded_packets);
+    let name = "conjure_detector_forward_drops_total";
+    let _ = writeln!(out, "# HELP {} Phantom packets the forwarding sink dropped.", name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    sink(&mut out, name, &m.forward_drops);
+    let name = "conjure_detector_forward_latency_seconds";
+    let _ = writeln!(out, "# HELP {} Time from forwarding a phantom packet to the kernel taking it.",
+                     name);
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    sink(&mut out, &format!("{}_sum", name),
+         &format_args!("{}.{:09}", m.forward_latency_ns / 1000000000,
+                       m.forward_latency_ns % 1000000000));
+    sink(&mut out, &format!("{}_count", name), &m.forwarded_packets);
+
+    gauge(&mut out, "conjure_detector_registrations_queued",
+          "Registrations waiting for the application to take them.", lcore,
+          m.registrations_queued);
+    gauge(&mut out, "conjure_detector_tracked_flows",
+          "Flows currently tracked waiting for their first TLS record.", lcore,
+          m.tracked_flows);
+    gauge(&mut out, "conjure_detector_phantom_flows",
+          "Registered phantom sessions currently known.", lcore, m.phantom_flows);
+    gauge(&mut out, "conjure_detector_phantom_session_slots",
+          "Slots in the phantom session table (shared by every core unless each has its own).",
+          lcore, m.phantom_session_slots);
+    let name = "conjure_detector_phantom_connections";
+    let _ = writeln!(out, "# HELP {} TCP connections to registered phantoms, by the state the \
+                                 client's packets put them in.", name);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for &(state, val) in &[("syn_seen", m.phantom_conns_syn_seen),
+                           ("established", m.phantom_conns_established),
+                           ("half_closed", m.phantom_conns_half_closed),
+                           ("closed", m.phantom_conns_closed)] {
+        let _ = writeln!(out, "{}{{core=\"{}\",state=\"{}\"}} {}", name, lcore, state, val);
+    }
+
+    let name = "conjure_detector_cpu_seconds_total";
+    let _ = writeln!(out, "# HELP {} CPU time used by the detector process.", name);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{}{{core=\"{}\",mode=\"user\"}} {}.{:06}", name, lcore,
+                     m.cpu_user_us / 1000000, m.cpu_user_us % 1000000);
+    let _ = writeln!(out, "{}{{core=\"{}\",mode=\"system\"}} {}.{:06}", name, lcore,
+                     m.cpu_sys_us / 1000000, m.cpu_sys_us % 1000000);
+    out
+}
+
+// Reads (and ignores everything but the request line of) one HTTP request,
+// then answers it.
+fn handle_conn(mut stream: TcpStream, lcore: i32, metrics: &SharedMetrics) -> io::Result<()>
+{
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut req = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        req.extend_from_slice(&buf[..n]);
+        if req.len() > 8192 {
+            break;
+        }
+    }
+
+    let line = req.split(|b| *b == b'\n').next().unwrap_or(&[]);
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let resp = if method == "GET" && (path == "/metrics" || path.starts_with("/metrics?")) {
+        let snapshot = match metrics.lock() {
+            Ok(m) => m.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        let body = render(lcore, &snapshot);
+        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(resp.as_bytes())
+}
+
+// Address core lcore listens on: base_addr's port plus the core number, so
+// every detector process on the box gets its own port.
+pub fn core_addr(base_addr: &str, lcore: i32) -> Result<SocketAddr, String>
+{
+    let mut addr: SocketAddr = base_addr.parse()
+        .map_err(|e| format!("bad metrics address {}: {}", base_addr, e))?;
+    let port = addr.port() as i32 + lcore;
+    if lcore < 0 || port > 65535 {
+        return Err(format!("no metrics port for core {} from {}", lcore, base_addr));
+    }
+    addr.set_port(port as u16);
+    Ok(addr)
+}
+
+// Binds addr and serves /metrics from a background thread.
+pub fn spawn_server(addr: SocketAddr, lcore: i32, metrics: SharedMetrics) -> io::Result<()>
+{
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    if let Err(e) = handle_conn(s, lcore, &metrics) {
+                        debug!("metrics request failed: {}", e);
+                    }
+                },
+                Err(e) => debug!("metrics accept failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use metrics::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_render()
+    {
+        let m = MetricsSnapshot {
+            packets: 12,
+            tags_checked: 3,
+            phantom_flows: 7,
+            phantom_session_slots: 1024,
+            phantom_conns_established: 3,
+            cpu_user_us: 2500001,
+            cpu_sys_us: 40,
+            source_limited_tags: 9,
+            registrations_dropped: 4,
+            registrations_queued: 17,
+            phantom_events: 5,
+            ip_fragments: 8,
+            fragment_timeouts: 2,
+            gre_packets: 11,
+            decap_failures: 1,
+            forward_sink: "tap-writev",
+            forwarded_packets: 6,
+            forward_latency_ns: 1500000,
+            udp_flows_over_limit: 13,
+            ..Default::default()
+        };
+
+        let out = render(2, &m);
+        assert!(out.contains("# TYPE conjure_detector_packets_total counter\n"));
+        assert!(out.contains("conjure_detector_packets_total{core=\"2\"} 12\n"));
+        assert!(out.contains("conjure_detector_tags_checked_total{core=\"2\"} 3\n"));
+        assert!(out.contains("# TYPE conjure_detector_phantom_flows gauge\n"));
+        assert!(out.contains("conjure_detector_phantom_flows{core=\"2\"} 7\n"));
+        assert!(out.contains("conjure_detector_phantom_session_slots{core=\"2\"} 1024\n"));
+        assert!(out.contains("conjure_detector_phantom_sessions_rejected_total{core=\"2\"} 0\n"));
+        assert!(out.contains("conjure_detector_phantom_connections{core=\"2\",state=\"established\"} 3\n"));
+        assert!(out.contains("conjure_detector_phantom_connections{core=\"2\",state=\"closed\"} 0\n"));
+        assert!(out.contains("conjure_detector_cpu_seconds_total{core=\"2\",mode=\"user\"} 2.500001\n"));
+        assert!(out.contains("conjure_detector_cpu_seconds_total{core=\"2\",mode=\"system\"} 0.000040\n"));
+        assert!(out.contains("conjure_detector_rate_limited_tags_total{core=\"2\",limit=\"source\"} 9\n"));
+        assert!(out.contains("conjure_detector_rate_limited_tags_total{core=\"2\",limit=\"core\"} 0\n"));
+        assert!(out.contains("conjure_detector_registrations_dropped_total{core=\"2\"} 4\n"));
+        assert!(out.contains("conjure_detector_ip_fragments_total{core=\"2\"} 8\n"));
+        assert!(out.contains("conjure_detector_fragment_drops_total{core=\"2\",reason=\"timeout\"} 2\n"));
+        assert!(out.contains("conjure_detector_encapsulated_packets_total{core=\"2\",encap=\"gre\"} 11\n"));
+        assert!(out.contains("conjure_detector_encapsulated_packets_total{core=\"2\",encap=\"vxlan\"} 0\n"));
+        assert!(out.contains("conjure_detector_decap_failures_total{core=\"2\"} 1\n"));
+        assert!(out.contains("conjure_detector_udp_flows_over_limit_total{core=\"2\"} 13\n"));
+        assert!(out.contains("# TYPE conjure_detector_registrations_queued gauge\n"));
+        assert!(out.contains("conjure_detector_registrations_queued{core=\"2\"} 17\n"));
+        assert!(out.contains("conjure_detector_phantom_events_total{core=\"2\"} 5\n"));
+        assert!(out.contains("conjure_detector_forwarded_packets_total{core=\"2\",sink=\"tap-writev\"} 6\n"));
+        assert!(out.contains("conjure_detector_forward_drops_total{core=\"2\",sink=\"tap-writev\"} 0\n"));
+        assert!(out.contains("# TYPE conjure_detector_forward_latency_seconds summary\n"));
+        assert!(out.contains("conjure_detector_forward_latency_seconds_sum{core=\"2\",sink=\"tap-writev\"} 0.001500000\n"));
+        assert!(out.contains("conjure_detector_forward_latency_seconds_count{core=\"2\",sink=\"tap-writev\"} 6\n"));
+    }
+
+    #[test]
+    fn test_core_addr()
+    {
+        assert_eq!(core_addr("127.0.0.1:9100", 3).unwrap(), "127.0.0.1:9103".parse().unwrap());
+        assert_eq!(core_addr("[::1]:9100", 0).unwrap(), "[::1]:9100".parse().unwrap());
+        assert!(core_addr("127.0.0.1:65535", 1).is_err());
+        assert!(core_addr("localhost", 1).is_err());
+    }
+
+    #[test]
+    fn test_serve()
+    {
+        let metrics: SharedMetrics = Default::default();
+        metrics.lock().unwrap().registrations_sent = 5;
+
+        // Port 0 so the test doesn't collide with anything; find out what we
+        // got by binding ourselves first.
+        let probe = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = probe.local_addr().unwrap();
+        drop(probe);
+        spawn_server(addr, 1, metrics.clone()).unwrap();
+
+        let get = |path: &str| -> String {
+            let mut s = TcpStream::connect(addr).unwrap();
+            write!(s, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut resp = String::new();
+            s.read_to_string(&mut resp).unwrap();
+            resp
+        };
+
+        let resp = get("/metrics");
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("conjure_detector_registrations_sent_total{core=\"1\"} 5\n"));
+
+        metrics.lock().unwrap().registrations_sent = 6;
+        assert!(get("/metrics").contains("conjure_detector_registrations_sent_total{core=\"1\"} 6\n"));
+
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,552 @@
+//
+// Minimal pcap / pcapng reader
+//
+// Used to feed captured traffic through the detector offline (see
+// src/bin/detector-replay.rs). Only what the detector needs is parsed: packet
+// bytes, capture timestamp and link type. Both classic pcap (either byte
+// order, micro- or nanosecond timestamps) and pcapng (Enhanced and Simple
+// Packet Blocks, any number of interfaces and sections) are supported.
+//
+
+use std::io;
+use std::io::Read;
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_IPV4: u32 = 228;
+pub const LINKTYPE_IPV6: u32 = 229;
+
+const PCAP_MAGIC_US: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const PCAPNG_IDB: u32 = 0x00000001;
+const PCAPNG_SPB: u32 = 0x00000003;
+const PCAPNG_EPB: u32 = 0x00000006;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+// Refuse to allocate for absurd record lengths in corrupt captures.
+const MAX_RECORD_LEN: usize = 256 * 1024 * 1024;
+
+pub struct PcapPacket
+{
+    // Capture timestamp in nanoseconds since the unix epoch.
+    pub ts_ns: u64,
+    pub linktype: u32,
+    // Length of the packet on the wire; data may be shorter if truncated.
+    pub orig_len: u32,
+    pub data: Vec<u8>,
+}
+
+struct Interface
+{
+    linktype: u32,
+    snaplen: u32,
+    // Units per second of the interface's timestamps.
+    ts_per_sec: u64,
+}
+
+enum Format
+{
+    Pcap { ns: bool, linktype: u32 },
+    PcapNg { interfaces: Vec<Interface> },
+}
+
+pub struct PcapReader<R: Read>
+{
+    inner: R,
+    big_endian: bool,
+    format: Format,
+}
+
+fn err(msg: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl<R: Read> PcapReader<R>
+{
+    // Reads the file header and determines the format from its magic number.
+    pub fn new(mut inner: R) -> io::Result<PcapReader<R>>
+    {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+
+        let le = u32::from_le_bytes(magic);
+        let be = u32::from_be_bytes(magic);
+
+        if le == PCAPNG_SHB {
+            let mut reader = PcapReader {
+                inner: inner,
+                big_endian: false,
+                format: Format::PcapNg { interfaces: Vec::new() },
+            };
+            let mut len_bytes = [0u8; 4];
+            reader.inner.read_exact(&mut len_bytes)?;
+            reader.read_section_header(len_bytes)?;
+            return Ok(reader);
+        }
+
+        let (big_endian, ns) = if le == PCAP_MAGIC_US {
+            (false, false)
+        } else if le == PCAP_MAGIC_NS {
+            (false, true)
+        } else if be == PCAP_MAGIC_US {
+            (true, false)
+        } else if be == PCAP_MAGIC_NS {
+            (true, true)
+        } else {
+            return Err(err("not a pcap or pcapng file"));
+        };
+
+        // version (2+2), thiszone, sigfigs, snaplen, network
+        let mut hdr = [0u8; 20];
+        inner.read_exact(&mut hdr)?;
+        let mut reader = PcapReader {
+            inner: inner,
+            big_endian: big_endian,
+            format: Format::Pcap { ns: ns, linktype: 0 },
+        };
+        let linktype = reader.u32_at(&hdr, 16);
+        reader.format = Format::Pcap { ns: ns, linktype: linktype };
+        Ok(reader)
+    }
+
+    // Returns the next packet, or None at a clean end of file.
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapPacket>>
+    {
+        match self.format {
+            Format::Pcap { .. } => self.next_pcap_packet(),
+            Format::PcapNg { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn u16_at(&self, buf: &[u8], off: usize) -> u16
+    {
+        let b = [buf[off], buf[off + 1]];
+        if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+    }
+
+    fn u32_at(&self, buf: &[u8], off: usize) -> u32
+    {
+        let b = [buf[off], buf[off + 1], buf[off + 2], buf[off + 3]];
+        if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    }
+
+    // Like read_exact, but distinguishes EOF before the first byte (Ok(false))
+    // from EOF in the middle of the buffer (error).
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> io::Result<bool>
+    {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) => {
+                    if filled == 0 {
+                        return Ok(false);
+                    }
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "truncated capture record"));
+                },
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_vec(&mut self, len: usize) -> io::Result<Vec<u8>>
+    {
+        if len > MAX_RECORD_LEN {
+            return Err(err("capture record too large"));
+        }
+        let mut v = vec![0u8; len];
+        self.inner.read_exact(&mut v)?;
+        Ok(v)
+    }
+
+    fn next_pcap_packet(&mut self) -> io::Result<Option<PcapPacket>>
+    {
+        let (ns, linktype) = match self.format {
+            Format::Pcap { ns, linktype } => (ns, linktype),
+            _ => unreachable!(),
+        };
+
+        let mut hdr = [0u8; 16];
+        if !self.read_or_eof(&mut hdr)? {
+            return Ok(None);
+        }
+        let ts_sec = self.u32_at(&hdr, 0) as u64;
+        let ts_frac = self.u32_at(&hdr, 4) as u64;
+        let incl_len = self.u32_at(&hdr, 8) as usize;
+        let orig_len = self.u32_at(&hdr, 12);
+
+        let data = self.read_vec(incl_len)?;
+        let ts_ns = ts_sec * 1_000_000_000 + if ns { ts_frac } else { ts_frac * 1000 };
+
+        Ok(Some(PcapPacket { ts_ns: ts_ns, linktype: linktype, orig_len: orig_len, data: data }))
+    }
+
+    // Called with the block type and length already consumed. Determines the
+    // section's byte order and forgets any interfaces from the previous section.
+    fn read_section_header(&mut self, len_bytes: [u8; 4]) -> io::Result<()>
+    {
+        let mut bom = [0u8; 4];
+        self.inner.read_exact(&mut bom)?;
+        if u32::from_le_bytes(bom) == PCAPNG_BYTE_ORDER_MAGIC {
+            self.big_endian = false;
+        } else if u32::from_be_bytes(bom) == PCAPNG_BYTE_ORDER_MAGIC {
+            self.big_endian = true;
+        } else {
+            return Err(err("bad pcapng byte order magic"));
+        }
+        let total_len = self.u32_at(&len_bytes, 0) as usize;
+        if total_len < 28 || total_len % 4 != 0 {
+            return Err(err("bad pcapng section header length"));
+        }
+        // Skip version, section length, options and trailing length.
+        self.read_vec(total_len - 12)?;
+        self.format = Format::PcapNg { interfaces: Vec::new() };
+        Ok(())
+    }
+
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<PcapPacket>>
+    {
+        loop {
+            let mut hdr = [0u8; 8];
+            if !self.read_or_eof(&mut hdr)? {
+                return Ok(None);
+            }
+
+            // The section header's length can only be interpreted once its
+            // byte order magic is known, so it has its own path.
+            if u32::from_le_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]) == PCAPNG_SHB {
+                self.read_section_header([hdr[4], hdr[5], hdr[6], hdr[7]])?;
+                continue;
+            }
+
+            let block_type = self.u32_at(&hdr, 0);
+            let total_len = self.u32_at(&hdr, 4) as usize;
+            if total_len < 12 || total_len % 4 != 0 {
+                return Err(err("bad pcapng block length"));
+            }
+            // Body plus the trailing copy of the block length.
+            let body = self.read_vec(total_len - 8)?;
+            let body = &body[..body.len() - 4];
+
+            match block_type {
+                PCAPNG_IDB => {
+                    let iface = self.parse_interface(body)?;
+                    if let Format::PcapNg { ref mut interfaces } = self.format {
+                        interfaces.push(iface);
+                    }
+                },
+                PCAPNG_EPB => {
+                    if body.len() < 20 {
+                        return Err(err("short enhanced packet block"));
+                    }
+                    let if_id = self.u32_at(body, 0) as usize;
+                    let ts = ((self.u32_at(body, 4) as u64) << 32) | self.u32_at(body, 8) as u64;
+                    let cap_len = self.u32_at(body, 12) as usize;
+                    let orig_len = self.u32_at(body, 16);
+                    if 20 + cap_len > body.len() {
+                        return Err(err("enhanced packet block overflows its length"));
+                    }
+                    let (linktype, ts_per_sec) = self.interface_info(if_id)?;
+                    return Ok(Some(PcapPacket {
+                        ts_ns: scale_ts(ts, ts_per_sec),
+                        linktype: linktype,
+                        orig_len: orig_len,
+                        data: body[20..20 + cap_len].to_vec(),
+                    }));
+                },
+                PCAPNG_SPB => {
+                    if body.len() < 4 {
+                        return Err(err("short simple packet block"));
+                    }
+                    let orig_len = self.u32_at(body, 0);
+                    let (linktype, _) = self.interface_info(0)?;
+                    let snaplen = match self.format {
+                        Format::PcapNg { ref interfaces } => interfaces[0].snaplen,
+                        _ => 0,
+                    };
+                    let mut cap_len = body.len() - 4;
+                    if (orig_len as usize) < cap_len {
+                        cap_len = orig_len as usize;
+                    }
+                    if snaplen != 0 && (snaplen as usize) < cap_len {
+                        cap_len = snaplen as usize;
+                    }
+                    return Ok(Some(PcapPacket {
+                        ts_ns: 0,
+                        linktype: linktype,
+                        orig_len: orig_len,
+                        data: body[4..4 + cap_len].to_vec(),
+                    }));
+                },
+                // Name resolution, statistics, custom blocks, ...
+                _ => continue,
+            }
+        }
+    }
+
+    fn interface_info(&self, if_id: usize) -> io::Result<(u32, u64)>
+    {
+        match self.format {
+            Format::PcapNg { ref interfaces } => match interfaces.get(if_id) {
+                Some(i) => Ok((i.linktype, i.ts_per_sec)),
+                None => Err(err("packet references unknown pcapng interface")),
+            },
+            _ => Err(err("not a pcapng capture")),
+        }
+    }
+
+    fn parse_interface(&self, body: &[u8]) -> io::Result<Interface>
+    {
+        if body.len() < 8 {
+            return Err(err("short interface description block"));
+        }
+        let mut iface = Interface {
+            linktype: self.u16_at(body, 0) as u32,
+            snaplen: self.u32_at(body, 4),
+            ts_per_sec: 1_000_000,
+        };
+
+        let mut off = 8;
+        while off + 4 <= body.len() {
+            let code = self.u16_at(body, off);
+            let len = self.u16_at(body, off + 2) as usize;
+            off += 4;
+            if code == 0 || off + len > body.len() {
+                break;
+            }
+            if code == PCAPNG_OPT_IF_TSRESOL && len >= 1 {
+                let res = body[off];
+                let exp = (res & 0x7f) as u32;
+                // MSB clear: negative power of 10, set: negative power of 2.
+                iface.ts_per_sec = if res & 0x80 == 0 {
+                    10u64.checked_pow(exp).unwrap_or(1_000_000)
+                } else {
+                    1u64.checked_shl(exp).unwrap_or(1_000_000)
+                };
+            }
+            off += (len + 3) & !3;
+        }
+        Ok(iface)
+    }
+}
+
+fn scale_ts(ts: u64, ts_per_sec: u64) -> u64
+{
+    if ts_per_sec == 0 {
+        return ts;
+    }
+    let secs = ts / ts_per_sec;
+    let frac = ts % ts_per_sec;
+    secs * 1_000_000_000 + ((frac as u128) * 1_000_000_000 / ts_per_sec as u128) as u64
+}
+
+// The detector consumes Ethernet frames. Captures taken on other link types
+// that carry IP are given a synthetic Ethernet header so they can be replayed
+// too. Returns None for link types we don't understand.
+pub fn to_ethernet_frame(linktype: u32, data: &[u8]) -> Option<Vec<u8>>
+{
+    fn with_ethertype(ethertype: [u8; 2], ip: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(14 + ip.len());
+        frame.extend_from_slice(&[0u8; 12]);
+        frame.extend_from_slice(&ethertype);
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    match linktype {
+        LINKTYPE_ETHERNET => Some(data.to_vec()),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => {
+            match data.first().map(|b| b >> 4) {
+                Some(4) => Some(with_ethertype([0x08, 0x00], data)),
+                Some(6) => Some(with_ethertype([0x86, 0xdd], data)),
+                _ => None,
+            }
+        },
+        LINKTYPE_LINUX_SLL => {
+            // 16 byte cooked header, protocol type in the last two bytes.
+            if data.len() < 16 {
+                return None;
+            }
+            Some(with_ethertype([data[14], data[15]], &data[16..]))
+        },
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pcap::*;
+
+    fn pcap_file(big_endian: bool, ns: bool, linktype: u32, pkts: &[(u32, u32, &[u8])]) -> Vec<u8>
+    {
+        let w32 = |v: &mut Vec<u8>, x: u32| {
+            if big_endian { v.extend_from_slice(&x.to_be_bytes()) } else { v.extend_from_slice(&x.to_le_bytes()) }
+        };
+        let w16 = |v: &mut Vec<u8>, x: u16| {
+            if big_endian { v.extend_from_slice(&x.to_be_bytes()) } else { v.extend_from_slice(&x.to_le_bytes()) }
+        };
+        let mut v = Vec::new();
+        w32(&mut v, if ns { 0xa1b23c4d } else { 0xa1b2c3d4 });
+        w16(&mut v, 2);
+        w16(&mut v, 4);
+        w32(&mut v, 0);
+        w32(&mut v, 0);
+        w32(&mut v, 65535);
+        w32(&mut v, linktype);
+        for &(sec, frac, data) in pkts {
+            w32(&mut v, sec);
+            w32(&mut v, frac);
+            w32(&mut v, data.len() as u32);
+            w32(&mut v, data.len() as u32);
+            v.extend_from_slice(data);
+        }
+        v
+    }
+
+    #[test]
+    fn test_pcap_both_byte_orders()
+    {
+        for &big_endian in &[false, true] {
+            let f = pcap_file(big_endian, false, LINKTYPE_ETHERNET,
+                              &[(1, 5, b"abc"), (2, 0, b"defgh")]);
+            let mut r = PcapReader::new(&f[..]).unwrap();
+
+            let p = r.next_packet().unwrap().unwrap();
+            assert_eq!(p.data, b"abc".to_vec());
+            assert_eq!(p.ts_ns, 1_000_005_000);
+            assert_eq!(p.linktype, LINKTYPE_ETHERNET);
+
+            let p = r.next_packet().unwrap().unwrap();
+            assert_eq!(p.data, b"defgh".to_vec());
+            assert_eq!(p.ts_ns, 2_000_000_000);
+
+            assert!(r.next_packet().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_pcap_nanosecond_and_truncated()
+    {
+        let mut f = pcap_file(false, true, LINKTYPE_RAW, &[(3, 7, b"\x45\x00")]);
+        let p = PcapReader::new(&f[..]).unwrap().next_packet().unwrap().unwrap();
+        assert_eq!(p.ts_ns, 3_000_000_007);
+        assert_eq!(p.linktype, LINKTYPE_RAW);
+
+        // Cut the last record short
+        f.pop();
+        let mut r = PcapReader::new(&f[..]).unwrap();
+        assert!(r.next_packet().is_err());
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8>
+    {
+        let mut padded = body.to_vec();
+        while padded.len() % 4 != 0 {
+            padded.push(0);
+        }
+        let total = (padded.len() + 12) as u32;
+        let mut v = Vec::new();
+        v.extend_from_slice(&block_type.to_le_bytes());
+        v.extend_from_slice(&total.to_le_bytes());
+        v.extend_from_slice(&padded);
+        v.extend_from_slice(&total.to_le_bytes());
+        v
+    }
+
+    #[test]
+    fn test_pcapng_epb_and_spb()
+    {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&0x1a2b3c4du32.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&[0xff; 8]);
+
+        // Interface 0: ethernet, nanosecond resolution
+        let mut idb0 = Vec::new();
+        idb0.extend_from_slice(&1u16.to_le_bytes());
+        idb0.extend_from_slice(&0u16.to_le_bytes());
+        idb0.extend_from_slice(&0u32.to_le_bytes());
+        idb0.extend_from_slice(&9u16.to_le_bytes());
+        idb0.extend_from_slice(&1u16.to_le_bytes());
+        idb0.extend_from_slice(&[9, 0, 0, 0]);
+        idb0.extend_from_slice(&[0, 0, 0, 0]);
+
+        // Interface 1: raw IP, default (microsecond) resolution
+        let mut idb1 = Vec::new();
+        idb1.extend_from_slice(&101u16.to_le_bytes());
+        idb1.extend_from_slice(&0u16.to_le_bytes());
+        idb1.extend_from_slice(&0u32.to_le_bytes());
+
+        let epb = |if_id: u32, ts: u64, data: &[u8]| {
+            let mut b = Vec::new();
+            b.extend_from_slice(&if_id.to_le_bytes());
+            b.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+            b.extend_from_slice(&(ts as u32).to_le_bytes());
+            b.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            b.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            b.extend_from_slice(data);
+            pcapng_block(6, &b)
+        };
+
+        let mut spb = Vec::new();
+        spb.extend_from_slice(&3u32.to_le_bytes());
+        spb.extend_from_slice(b"xyz");
+
+        let mut f = pcapng_block(0x0a0d0d0a, &shb);
+        f.extend(pcapng_block(1, &idb0));
+        f.extend(pcapng_block(1, &idb1));
+        f.extend(epb(0, 5_000_000_123, b"hello"));
+        // Statistics block, skipped
+        f.extend(pcapng_block(5, &[0u8; 12]));
+        f.extend(epb(1, 2_000_001, b"\x60"));
+        f.extend(pcapng_block(3, &spb));
+
+        let mut r = PcapReader::new(&f[..]).unwrap();
+
+        let p = r.next_packet().unwrap().unwrap();
+        assert_eq!(p.data, b"hello".to_vec());
+        assert_eq!(p.ts_ns, 5_000_000_123);
+        assert_eq!(p.linktype, LINKTYPE_ETHERNET);
+
+        let p = r.next_packet().unwrap().unwrap();
+        assert_eq!(p.data, b"\x60".to_vec());
+        assert_eq!(p.ts_ns, 2_000_001_000);
+        assert_eq!(p.linktype, LINKTYPE_RAW);
+
+        let p = r.next_packet().unwrap().unwrap();
+        assert_eq!(p.data, b"xyz".to_vec());
+
+        assert!(r.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_to_ethernet_frame()
+    {
+        let v4 = [0x45u8, 0, 0, 20];
+        let frame = to_ethernet_frame(LINKTYPE_RAW, &v4).unwrap();
+        assert_eq!(&frame[12..14], &[0x08, 0x00]);
+        assert_eq!(&frame[14..], &v4[..]);
+
+        let v6 = [0x60u8, 0, 0, 0];
+        let frame = to_ethernet_frame(LINKTYPE_IPV6, &v6).unwrap();
+        assert_eq!(&frame[12..14], &[0x86, 0xdd]);
+
+        let mut sll = vec![0u8; 16];
+        sll[14] = 0x08;
+        sll.extend_from_slice(&v4);
+        let frame = to_ethernet_frame(LINKTYPE_LINUX_SLL, &sll).unwrap();
+        assert_eq!(&frame[12..14], &[0x08, 0x00]);
+        assert_eq!(&frame[14..], &v4[..]);
+
+        assert!(to_ethernet_frame(LINKTYPE_RAW, &[0x10]).is_none());
+        assert!(to_ethernet_frame(12345, &v4).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//
+// The set of IP prefixes phantom addresses are drawn from
+// (/var/lib/dark-decoy.prefixes), one CIDR per line.
+//
+// Lookups hash the masked address once per prefix length in the list, and
+// there are only ever a handful of distinct lengths, so this is cheap enough
+// to do for every phantom packet.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::net::IpAddr;
+
+#[derive(Default)]
+pub struct PrefixList
+{
+    // (prefix length, masked networks of that length), longest first
+    v4: Vec<(u8, HashSet<u32>)>,
+    v6: Vec<(u8, HashSet<u128>)>,
+
+    // Normalized "network/len" of everything in the list, for diffing
+    entries: BTreeSet<String>,
+}
+
+pub fn mask_v4(addr: u32, len: u8) -> u32
+{
+    match len {
+        0 => 0,
+        _ => addr & (!0u32 << (32 - len as u32)),
+    }
+}
+
+pub fn mask_v6(addr: u128, len: u8) -> u128
+{
+    match len {
+        0 => 0,
+        _ => addr & (!0u128 << (128 - len as u32)),
+    }
+}
+
+fn insert<T: ::std::hash::Hash + Eq>(buckets: &mut Vec<(u8, HashSet<T>)>, len: u8, net: T)
+{
+    match buckets.iter().position(|&(l, _)| l == len) {
+        Some(i) => { buckets[i].1.insert(net); },
+        None => {
+            let mut set = HashSet::new();
+            set.insert(net);
+            buckets.push((len, set));
+            buckets.sort_by_key(|&(l, _)| Reverse(l));
+        },
+    }
+}
+
+impl PrefixList
+{
+    pub fn new() -> PrefixList
+    {
+        Default::default()
+    }
+
+    // Reads a prefix file: one CIDR (or bare address) per line, blank lines
+    // and #-comments ignored. Any bad line fails the whole file.
+    pub fn from_file(path: &str) -> Result<PrefixList, String>
+    {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let mut list = PrefixList::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            list.add_cidr(line)
+                .map_err(|e| format!("{} line {}: {}", path, i + 1, e))?;
+        }
+        Ok(list)
+    }
+
+    // Host bits are ignored (10.1.2.3/8 is 10.0.0.0/8); an address without a
+    // length is a single host.
+    pub fn add_cidr(&mut self, cidr: &str) -> Result<(), String>
+    {
+        let mut parts = cidr.splitn(2, '/');
+        let addr: IpAddr = parts.next().unwrap_or("").parse()
+            .map_err(|_| format!("bad prefix {}", cidr))?;
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let len = match parts.next() {
+            Some(l) => match l.parse::<u8>() {
+                Ok(l) if l <= max_len => l,
+                _ => return Err(format!("bad prefix length in {}", cidr)),
+            },
+            None => max_len,
+        };
+
+        let entry = match addr {
+            IpAddr::V4(a) => {
+                let net = mask_v4(u32::from(a), len);
+                insert(&mut self.v4, len, net);
+                format!("{}/{}", ::std::net::Ipv4Addr::from(net), len)
+            },
+            IpAddr::V6(a) => {
+                let net = mask_v6(u128::from(a), len);
+                insert(&mut self.v6, len, net);
+                format!("{}/{}", ::std::net::Ipv6Addr::from(net), len)
+            },
+        };
+        self.entries.insert(entry);
+        Ok(())
+    }
+
+    pub fn contains(&self, addr: &IpAddr) -> bool
+    {
+        match *addr {
+            IpAddr::V4(a) => {
+                let a = u32::from(a);
+                self.v4.iter().any(|&(len, ref nets)| nets.contains(&mask_v4(a, len)))
+            },
+            IpAddr::V6(a) => {
+                let a = u128::from(a);
+                self.v6.iter().any(|&(len, ref nets)| nets.contains(&mask_v6(a, len)))
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    // (added, removed) going from self to other
+    pub fn diff(&self, other: &PrefixList) -> (Vec<String>, Vec<String>)
+    {
+        (other.entries.difference(&self.entries).cloned().collect(),
+         self.entries.difference(&other.entries).cloned().collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use prefix_list::*;
+    use std::env;
+    use std::fs;
+    use std::net::IpAddr;
+    use std::process;
+
+    fn ip(s: &str) -> IpAddr
+    {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_contains()
+    {
+        let mut list = PrefixList::new();
+        list.add_cidr("192.122.190.0/24").unwrap();
+        list.add_cidr("10.1.2.3/8").unwrap();
+        list.add_cidr("2001:48a8:687f:1::/64").unwrap();
+        list.add_cidr("198.51.100.7").unwrap();
+        assert_eq!(list.len(), 4);
+
+        assert!(list.contains(&ip("192.122.190.200")));
+        assert!(!list.contains(&ip("192.122.191.1")));
+        assert!(list.contains(&ip("10.200.0.1")));
+        assert!(list.contains(&ip("198.51.100.7")));
+        assert!(!list.contains(&ip("198.51.100.8")));
+        assert!(list.contains(&ip("2001:48a8:687f:1:abcd::1")));
+        assert!(!list.contains(&ip("2001:48a8:687f:2::1")));
+        // v4 and v6 don't mix
+        assert!(!list.contains(&ip("::ffff:10.0.0.1")));
+
+        assert!(list.add_cidr("10.0.0.0/33").is_err());
+        assert!(list.add_cidr("10.0.0/8").is_err());
+        assert!(list.add_cidr("").is_err());
+
+        let mut all = PrefixList::new();
+        all.add_cidr("0.0.0.0/0").unwrap();
+        assert!(all.contains(&ip("1.2.3.4")));
+        assert!(!all.contains(&ip("::1")));
+    }
+
+    #[test]
+    fn test_diff()
+    {
+        let mut old = PrefixList::new();
+        old.add_cidr("10.0.0.0/8").unwrap();
+        old.add_cidr("192.0.2.0/24").unwrap();
+
+        let mut new = PrefixList::new();
+        new.add_cidr("10.9.9.9/8").unwrap();
+        new.add_cidr("2001:db8::/32").unwrap();
+
+        let (added, removed) = old.diff(&new);
+        assert_eq!(added, vec!["2001:db8::/32".to_string()]);
+        assert_eq!(removed, vec!["192.0.2.0/24".to_string()]);
+        assert_eq!(new.diff(&new), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_from_file()
+    {
+        let path = env::temp_dir().join(format!("prefix_list_test_{}", process::id()));
+        let path = path.to_str().unwrap();
+
+        fs::write(path, "# phantoms\n192.0.2.0/24\n\n2001:db8::/32  # v6\n").unwrap();
+        let list = PrefixList::from_file(path).unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(&ip("192.0.2.1")));
+
+        fs::write(path, "192.0.2.0/24\nnonsense\n").unwrap();
+        let e = PrefixList::from_file(path).err().unwrap();
+        assert!(e.contains("line 2"));
+
+        fs::remove_file(path).unwrap();
+        assert!(PrefixList::from_file(path).is_err());
+    }
+}
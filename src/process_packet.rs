@@ -5,8 +5,8 @@ use std::slice;
 use std:: str;
 
 use pnet::packet::Packet;
-use pnet::packet::ethernet::{EthernetPacket, EtherTypes};
-use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use pnet::packet::ipv4::Ipv4Packet;
 use pnet::packet::ipv6::Ipv6Packet;
 use pnet::packet::tcp::{TcpPacket,TcpFlags};
@@ -15,13 +15,20 @@ use pnet::packet::udp::UdpPacket;
 
 use std::u8;
 //use elligator;
-use flow_tracker::{Flow, FlowNoSrcPort};
+use decap::decapsulate;
+use flow_tracker::{Flow, FlowNoSrcPort, Transport, UdpFlowMark};
+use forward_sink::ip_bytes;
+use log::LogLevel;
+use logging::Event;
 // use dd_selector::DDIpSelector;
 use PerCoreGlobal;
-use util::IpPacket;
+use util::{IpPacket, UpperLayer};
 use elligator;
-use protobuf::{Message};
 use signalling::{C2SWrapper, RegistrationSource};
+use rate_limit::RateLimit;
+use sessions::ConnEvent;
+use tcp_reassembly::Reassembly;
+use clock::{now_ns, unix_secs};
 
 
 const TLS_TYPE_APPLICATION_DATA: u8 = 0x17;
@@ -33,42 +40,6 @@ const SPECIAL_UDP_PAYLOAD: &'static [u8] = b"\x38xCKe9ECO5lNwXgd5Q25w0C2qUR7whlt
 
 //const STREAM_TIMEOUT_NS: u64 = 120*1000*1000*1000; // 120 seconds
 
-fn get_ip_packet<'p>(eth_pkt: &'p EthernetPacket) -> Option<IpPacket<'p>>
-{
-    let payload = eth_pkt.payload();
-
-    fn parse_v4<'a>(p: &[u8]) -> Option<IpPacket> {
-        match Ipv4Packet::new(p) {
-            Some(pkt) => Some(IpPacket::V4(pkt)),
-            None => None
-        }
-    }
-
-    fn parse_v6(p: &[u8]) -> Option<IpPacket> {
-        match Ipv6Packet::new(p) {
-            Some(pkt) => Some(IpPacket::V6(pkt)),
-            None => None
-        }
-    }
-
-    match eth_pkt.get_ethertype() {
-        EtherTypes::Vlan => {
-            if payload[2] == 0x08 && payload[3] == 0x00 {
-                //let vlan_id: u16 = (payload[0] as u16)*256
-                //                 + (payload[1] as u16);
-                parse_v4(&payload[4..])
-            } else if payload[2] == 0x86 && payload[3] == 0xdd {
-                parse_v6(&payload[4..])
-            } else {
-                None
-            }
-        },
-        EtherTypes::Ipv4 => parse_v4(&payload[0..]),
-        EtherTypes::Ipv6 => parse_v6(&payload[0..]),
-        _ => None,
-    }
-}
-
 // The jumping off point for all of our logic. This function inspects a packet
 // that has come in the tap interface. We do not yet have any idea if we care
 // about it; it might not even be TLS. It might not even be TCP!
@@ -80,27 +51,11 @@ pub extern "C" fn rust_process_packet(ptr: *mut PerCoreGlobal,
     #[allow(unused_mut)]
     let mut global = unsafe { &mut *ptr };
 
-    let mut rust_view_len = frame_len as usize;
     let rust_view = unsafe {
         slice::from_raw_parts_mut(raw_ethframe as *mut u8, frame_len as usize)
     };
 
-    // If this is a GRE, we want to ignore the GRE overhead in our packets
-    rust_view_len -= global.gre_offset;
-
-    global.stats.packets_this_period += 1;
-    global.stats.bytes_this_period += rust_view_len as u64;
-
-    let eth_pkt = match EthernetPacket::new(&rust_view[global.gre_offset..]) {
-        Some(pkt) => pkt,
-        None => return,
-    };
-
-    match get_ip_packet(&eth_pkt) {
-        Some(IpPacket::V4(pkt)) => global.process_ipv4_packet(pkt, rust_view_len),
-        Some(IpPacket::V6(pkt)) => global.process_ipv6_packet(pkt, rust_view_len),
-        None => return,
-    }
+    global.process_packet(rust_view);
 }
 
 fn is_tls_app_pkt(tcp_pkt: &TcpPacket) -> bool
@@ -111,96 +66,137 @@ fn is_tls_app_pkt(tcp_pkt: &TcpPacket) -> bool
 
 impl PerCoreGlobal
 {
+    // Same as rust_process_packet, for callers that already hold the frame as a
+    // slice (e.g. offline replay of captured traffic).
+    pub fn process_packet(&mut self, rust_view: &[u8])
+    {
+        // If this is a GRE, we want to ignore the GRE overhead in our packets
+        if rust_view.len() < self.gre_offset {
+            return;
+        }
+        let rust_view_len = rust_view.len() - self.gre_offset;
+
+        self.stats.packets_this_period += 1;
+        self.stats.bytes_this_period += rust_view_len as u64;
+
+        // Get through any VLAN tags, MPLS labels and tunnels to the IP packet
+        let frame = &rust_view[self.gre_offset..];
+        let inner = match decapsulate(frame, &mut self.stats.decap) {
+            Some(inner) => inner,
+            None => return,
+        };
+        let eth_pkt = match EthernetPacket::new(&frame[inner.eth_offset..]) {
+            Some(pkt) => pkt,
+            None => return,
+        };
+        self.pkt_meta.update(&rust_view[..self.gre_offset + inner.eth_offset], &eth_pkt);
+
+        match inner.ip(frame) {
+            Some(IpPacket::V4(pkt)) => self.process_ipv4_packet(pkt, rust_view_len),
+            Some(IpPacket::V6(pkt)) => self.process_ipv6_packet(pkt, rust_view_len),
+            None => return,
+        }
+    }
+
     // frame_len is supposed to be the length of the whole Ethernet frame. We're
     // only passing it here for plumbing reasons, and just for stat reporting.
     fn process_ipv4_packet(&mut self, ip_pkt: Ipv4Packet, frame_len: usize)
     {
         self.stats.ipv4_packets_this_period += 1;
+        self.process_ip_packet(IpPacket::V4(ip_pkt), frame_len);
+    }
 
-        // If the packet isn't TCP, first check for a UDP special payload, then return
-        if ip_pkt.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-            let ip = IpPacket::V4(ip_pkt);
-            match ip.udp() {
-                Some(pkt) => {
-                    // Special payloads are only sent as DNS on port 53
-                    if pkt.get_destination() != 53 {
-                        return;
-                    }
+    fn process_ipv6_packet(&mut self, ip_pkt: Ipv6Packet, frame_len: usize)
+    {
+        self.stats.ipv6_packets_this_period += 1;
+        //debug!("v6 -> {} {} bytes", ip_pkt.get_destination(), ip_pkt.get_payload_length());
+        self.process_ip_packet(IpPacket::V6(ip_pkt), frame_len);
+    }
 
-                    let flow = Flow::new_udp(&ip, &pkt);
-                    self.check_udp_test_str(&flow, &pkt);
-                }
-                None => return,
-            }
-            return;
+    fn process_ip_packet(&mut self, ip: IpPacket, frame_len: usize)
+    {
+        // If the packet isn't TCP, check UDP for phantom traffic and the
+        // special payload, then return
+        match self.upper_protocol(&ip) {
+            Some(IpNextHeaderProtocols::Tcp) => {},
+            Some(IpNextHeaderProtocols::Udp) => {
+                self.process_udp_pkt(ip);
+                return;
+            },
+            _ => return,
         }
-        let ip = IpPacket::V4(ip_pkt);
 
         {
-            // Check TCP/443
             let tcp_pkt = match ip.tcp() {
                 Some(pkt) => pkt,
                 None => return,
             };
             self.stats.tcp_packets_this_period += 1;
 
-            // Ignore packets that aren't -> 443.
             // libpnet getters all return host order. Ignore the "u16be" in their
             // docs; interactions with pnet are purely host order.
-            if tcp_pkt.get_destination() != 443 {
-                return;
+            if self.is_decoy_port(tcp_pkt.get_destination()) {
+                self.stats.tls_packets_this_period += 1; // (HTTPS, really)
+                self.stats.tls_bytes_this_period += frame_len as u64;
             }
         }
-        self.stats.tls_packets_this_period += 1; // (HTTPS, really)
-        self.stats.tls_bytes_this_period += frame_len as u64;
         self.process_tls_pkt(ip);
     }
 
-    fn process_ipv6_packet(&mut self, ip_pkt: Ipv6Packet, frame_len: usize)
+    // Transport protocol of an unfragmented packet. Fragments go to the
+    // reassembler (if there is one), and any packet they complete is
+    // processed from here. IPv6 packets whose extension headers we couldn't
+    // get through are counted and otherwise ignored.
+    fn upper_protocol(&mut self, ip: &IpPacket) -> Option<IpNextHeaderProtocol>
     {
-        self.stats.ipv6_packets_this_period += 1;
-
-        // If the packet isn't TCP, first check for a UDP special payload, then return
-        if ip_pkt.get_next_header() != IpNextHeaderProtocols::Tcp {
-            let ip = IpPacket::V6(ip_pkt);
-            match ip.udp() {
-                Some(pkt) => {
-                    // Special payloads are only sent as DNS on port 53
-                    if pkt.get_destination() != 53 {
-                        return;
-                    }
-
-                    let flow = Flow::new_udp(&ip, &pkt);
-                    self.check_udp_test_str(&flow, &pkt);
+        match ip.upper_layer() {
+            UpperLayer::Payload(protocol, _) => Some(protocol),
+            UpperLayer::Fragment(frag, data) => {
+                self.stats.ip_fragments_this_period += 1;
+                let res = match self.fragments {
+                    Some(ref mut fragments) => fragments.add(ip, &frag, data, now_ns()),
+                    None => return None,
+                };
+                if let Reassembly::Complete(pkt) = res {
+                    self.process_reassembled(&pkt);
                 }
-                None => return,
-            }
-            return;
+                None
+            },
+            UpperLayer::Invalid => {
+                self.stats.bad_ip_headers_this_period += 1;
+                None
+            },
         }
-        let ip = IpPacket::V6(ip_pkt);
-
-        {
-            let tcp_pkt = match ip.tcp() {
-                Some(pkt) => pkt,
-                None => return,
-            };
-            self.stats.tcp_packets_this_period += 1;
+    }
 
-            if tcp_pkt.get_destination() != 443 {
-                return;
-            }
+    // A packet put back together from fragments goes through the same checks
+    // as one that arrived whole. (One that turns out to be a fragment itself
+    // isn't reassembled again.)
+    fn process_reassembled(&mut self, pkt: &[u8])
+    {
+        let ip = match pkt[0] >> 4 {
+            4 => Ipv4Packet::new(pkt).map(IpPacket::V4),
+            _ => Ipv6Packet::new(pkt).map(IpPacket::V6),
+        };
+        let ip = match ip {
+            Some(ip) => ip,
+            None => return,
+        };
+        if let UpperLayer::Fragment(..) = ip.upper_layer() {
+            self.stats.bad_ip_headers_this_period += 1;
+            return;
         }
-        self.stats.tls_packets_this_period += 1;
-        self.stats.tls_bytes_this_period += frame_len as u64;
+        self.process_ip_packet(ip, pkt.len());
+    }
 
-        //debug!("v6 -> {} {} bytes", ip_pkt.get_destination(), ip_pkt.get_payload_length());
-        self.process_tls_pkt(ip);
+    fn is_decoy_port(&self, port: u16) -> bool
+    {
+        self.decoy_ports.contains(&port)
     }
 
-    // Takes an IPv4 packet
-    // Assumes (for now) that TLS records are in a single TCP packet
-    // (no fragmentation).
-    // Fragments could be stored in the flow_tracker if needed.
+    // Takes any TCP packet. Packets for registered phantoms are forwarded
+    // whatever their port; everything else only matters if it's to one of
+    // the decoy ports.
     pub fn process_tls_pkt(&mut self,
                            ip_pkt: IpPacket)
     {
@@ -216,7 +212,7 @@ impl PerCoreGlobal
             return;
         }
 
-        let dd_flow = FlowNoSrcPort::from_flow(&flow);
+        let dd_flow = FlowNoSrcPort::from_flow(&flow, Transport::Tcp);
         if self.flow_tracker.is_phantom_session(&dd_flow) {
 
             // Handle packet destined for registered IP
@@ -226,21 +222,45 @@ impl PerCoreGlobal
 
                 // Non station traffic, forward to application to handle
                 Some(_) => {
-                    if  (tcp_flags & TcpFlags::SYN) != 0  && (tcp_flags & TcpFlags::ACK) == 0 {
-                        debug!("Connection for registered Phantom {}", flow);
+                    if !self.in_phantom_prefixes(&flow) {
+                        return;
                     }
-                
-                    // Update expire time if necessary
-                    self.flow_tracker.update_phantom_flow(&dd_flow);
-    
+                    let event = if (tcp_flags & TcpFlags::RST) != 0 {
+                        ConnEvent::Rst
+                    } else if (tcp_flags & TcpFlags::FIN) != 0 {
+                        ConnEvent::Fin
+                    } else if (tcp_flags & TcpFlags::SYN) != 0 && (tcp_flags & TcpFlags::ACK) == 0 {
+                        Event::new(LogLevel::Debug, "phantom_syn").flow(&flow).emit();
+                        ConnEvent::Syn
+                    } else {
+                        ConnEvent::Ack
+                    };
+
+                    // Update expire time: pushed out while the session has
+                    // connections open, a short linger once they've closed
+                    let len = ip_bytes(&ip_pkt).len();
+                    let update = self.flow_tracker.update_phantom_connection(&flow, event, len);
+
                     // Forward packet...
                     self.forward_pkt(&ip_pkt);
-                    // TODO: if it was RST or FIN, close things
+
+                    if let Some(update) = update {
+                        if update.closed_session {
+                            Event::new(LogLevel::Debug, "phantom_session_closed")
+                                .str("session", &dd_flow)
+                                .num("connections", update.counts.closed as u64)
+                                .emit();
+                        }
+                    }
                     return;
                 }
             }
         }
 
+        if !self.is_decoy_port(flow.dst_port) {
+            return;
+        }
+
         if (tcp_flags & TcpFlags::SYN) != 0 && (tcp_flags & TcpFlags::ACK) == 0
         {
             self.stats.port_443_syns_this_period += 1;
@@ -256,54 +276,153 @@ impl PerCoreGlobal
             return;
         }
 
-        if  is_tls_app_pkt(&tcp_pkt) {
-            match self.check_dark_decoy_tag(&flow, &tcp_pkt) {
-                true => {
-                    // debug!("New Conjure registration detected in {},", flow);
-                    // self.flow_tracker.mark_dark_decoy(&dd_flow);
-                    // not removing flow from stale_tracked_flows for optimization reasons:
-                    // it will be removed later
-                },
-                false => {}
-            };
-            self.flow_tracker.stop_tracking_flow(&flow);
-        }else {
+        // The tagged record may span several segments (small MSS, middleboxes
+        // resegmenting), so collect it in the flow tracker until it's whole.
+        let seq = tcp_pkt.get_sequence();
+        let reassembling = self.flow_tracker.is_reassembling(&flow);
+        let record = if reassembling {
+            self.flow_tracker.continue_reassembly(&flow, seq, tcp_pkt.payload())
+        } else if is_tls_app_pkt(&tcp_pkt) {
+            self.flow_tracker.begin_reassembly(&flow, seq, tcp_pkt.payload())
+        } else {
             self.check_connect_test_str(&flow, &tcp_pkt);
+            return;
+        };
+
+        match record {
+            Reassembly::Complete(record) => {
+                if reassembling {
+                    self.stats.tags_reassembled_this_period += 1;
+                }
+                if !self.tag_check_allowed(&flow) {
+                    self.flow_tracker.stop_tracking_flow(&flow);
+                    return;
+                }
+                match self.check_dark_decoy_tag(&flow, &record) {
+                    true => {
+                        // debug!("New Conjure registration detected in {},", flow);
+                        // self.flow_tracker.mark_dark_decoy(&dd_flow);
+                        // not removing flow from stale_tracked_flows for optimization reasons:
+                        // it will be removed later
+                    },
+                    false => {}
+                };
+                self.flow_tracker.stop_tracking_flow(&flow);
+            },
+            Reassembly::Pending => {},
+            Reassembly::Dropped => {
+                self.stats.reassembly_drops_this_period += 1;
+                Event::new(LogLevel::Debug, "reassembly_dropped").flow(&flow).emit();
+                self.flow_tracker.stop_tracking_flow(&flow);
+            },
         }
     }
 
-    fn forward_pkt(&mut self, ip_pkt: &IpPacket)
+    // UDP is only of interest if it belongs to a phantom session registered
+    // with a UDP transport (e.g. QUIC), which gets forwarded just like TCP,
+    // or if it's the DNS liveness test.
+    fn process_udp_pkt(&mut self, ip_pkt: IpPacket)
     {
-        let data = match ip_pkt {
-            IpPacket::V4(p) => p.packet(),
-            IpPacket::V6(p) => p.packet(),
+        let udp_pkt = match ip_pkt.udp() {
+            Some(pkt) => pkt,
+            None => return,
         };
+        self.stats.udp_packets_this_period += 1;
 
-        let mut tun_pkt = Vec::with_capacity(data.len()+4);
-        // These mystery bytes are a link-layer header; the kernel "receives"
-        // tun packets as if they were really physically "received". Since they
-        // weren't physically received, they do not have an Ethernet header. It
-        // looks like the tun setup has its own type of header, rather than just
-        // making up a fake Ethernet header.
-        let raw_hdr = match ip_pkt {
-            IpPacket::V4(_p) => [0x00, 0x01, 0x08, 0x00],
-            IpPacket::V6(_p) => [0x00, 0x01, 0x86, 0xdd],
-        };
-        tun_pkt.extend_from_slice(&raw_hdr);
-        tun_pkt.extend_from_slice(data);
+        let flow = Flow::new_udp(&ip_pkt, &udp_pkt);
+        let dd_flow = FlowNoSrcPort::from_flow(&flow, Transport::Udp);
+        if self.flow_tracker.is_phantom_session(&dd_flow) {
+            // Traffic from other stations (liveness testing) isn't forwarded
+            if self.filter_station_traffic(flow.src_ip.to_string()).is_some()
+                && self.in_phantom_prefixes(&flow) {
+                match self.flow_tracker.mark_udp_phantom_flow(&flow) {
+                    UdpFlowMark::New => {
+                        Event::new(LogLevel::Debug, "phantom_udp").flow(&flow).emit();
+                    },
+                    UdpFlowMark::Known => {},
+                    UdpFlowMark::OverLimit => self.stats.udp_flows_over_limit_this_period += 1,
+                }
+                self.flow_tracker.update_phantom_flow(&dd_flow, ip_bytes(&ip_pkt).len());
+                self.forward_pkt(&ip_pkt);
+            }
+            return;
+        }
+
+        // Special payloads are only sent as DNS on port 53
+        if udp_pkt.get_destination() == 53 {
+            self.check_udp_test_str(&flow, &udp_pkt);
+        }
+    }
+
+    // Registrations can name any address, but we only forward for phantoms
+    // in our prefix list. No list at all means no restriction.
+    fn in_phantom_prefixes(&mut self, flow: &Flow) -> bool
+    {
+        if self.ip_tree.is_empty() {
+            return true;
+        }
+        if self.ip_tree.contains(&flow.dst_ip) {
+            self.stats.in_tree_this_period += 1;
+            true
+        } else {
+            self.stats.not_in_tree_this_period += 1;
+            false
+        }
+    }
 
-        self.tun.send(tun_pkt).unwrap_or_else(|e|{
-            warn!("failed to send packet into tun: {}", e); 0});
+    fn forward_pkt(&mut self, ip_pkt: &IpPacket)
+    {
+        if let Err(e) = self.forwarder.forward(ip_pkt, &self.pkt_meta) {
+            self.stats.forward_failures_this_period += 1;
+            Event::new(LogLevel::Warn, "forward_failed").str("error", &e).emit();
+        }
+    }
 
+    // Whether we can afford to check flow's tag under the configured rate
+    // limits (see rate_limit.rs).
+    fn tag_check_allowed(&mut self, flow: &Flow) -> bool
+    {
+        match self.tag_limiter.check(&flow.src_ip, now_ns()) {
+            RateLimit::Allowed => true,
+            RateLimit::SourceLimited => {
+                self.stats.source_limited_this_period += 1;
+                false
+            },
+            RateLimit::GlobalLimited => {
+                self.stats.global_limited_this_period += 1;
+                false
+            },
+        }
     }
 
+    // tls_record is the client's first TLS app data record, header included.
     fn check_dark_decoy_tag(&mut self,
                             flow: &Flow,
-                            tcp_pkt: &TcpPacket) -> bool
+                            tls_record: &[u8]) -> bool
     {
         self.stats.elligator_this_period += 1;
-        match elligator::extract_payloads(&self.priv_key, &tcp_pkt.payload()) {
-            Ok(res) => {
+
+        // Try the current key first, then any older ones still accepted
+        let mut matched = None;
+        for key in self.keys.keys() {
+            if let Ok(res) = elligator::extract_payloads(&key.priv_key, tls_record) {
+                matched = Some((key.generation, res));
+                break;
+            }
+        }
+        match matched {
+            Some((generation, res)) => {
+                // A copy of a tag we've already passed on. Whoever sent it
+                // doesn't get to register (again), nor find out it was valid.
+                let now = unix_secs();
+                if let Some(ref filter) = self.replay_filter {
+                    if filter.check_and_insert(&res.0, now) {
+                        self.stats.replayed_tags_this_period += 1;
+                        Event::new(LogLevel::Debug, "replayed_tag").flow(&flow).emit();
+                        return false;
+                    }
+                }
+
                 // res.0 => shared secret
                 // res.1 => Fixed size payload
                 // res.2 => variable size payload (c2s)
@@ -320,27 +439,32 @@ impl PerCoreGlobal
                 zmq_msg.set_registration_source(RegistrationSource::Detector);
                 zmq_msg.set_decoy_address(decoy);
                 zmq_msg.set_registration_address(src);
-
-                let repr_str = hex::encode(res.0);
-                debug!("New registration {}, {}", flow, repr_str);
-
-                let zmq_payload = match zmq_msg.write_to_bytes() {
-                    Ok(b) => b,
-                    Err(e) => {
-                        warn!("Failed to generate ZMQ payload: {}", e);
-                        return false
+                zmq_msg.set_key_generation(generation);
+
+                Event::new(LogLevel::Debug, "registration")
+                    .flow(&flow)
+                    .source(RegistrationSource::Detector)
+                    .str("shared_secret", &hex::encode(res.0))
+                    .num("key_generation", generation as u64)
+                    .emit();
+
+                match self.registrar.send(&zmq_msg) {
+                    Ok(_)=> {
+                        self.stats.registrations_sent_this_period += 1;
+                        return true
                     },
-                };
-
-                match self.zmq_sock.send(&zmq_payload, 0){
-                    Ok(_)=> return true,
                     Err(e) => {
-                        warn!("Failed to send registration information over ZMQ: {}", e);
+                        self.stats.registration_send_failures_this_period += 1;
+                        Event::new(LogLevel::Warn, "registration_send_failed")
+                            .flow(flow)
+                            .source(RegistrationSource::Detector)
+                            .str("error", &e)
+                            .emit();
                         return false
                     },
                 }
             },
-            Err(_e) => {
+            None => {
                 return false;
             }
         }
@@ -350,7 +474,7 @@ impl PerCoreGlobal
         match str::from_utf8(tcp_pkt.payload()) {
             Ok(payload) => {
                 if payload == SPECIAL_PACKET_PAYLOAD {
-                    debug!("Validated traffic from {}", flow)
+                    Event::new(LogLevel::Debug, "validated").flow(flow).str("transport", &"tcp").emit();
                 }
             },
             Err(_) => {},
@@ -360,7 +484,7 @@ impl PerCoreGlobal
     fn check_udp_test_str(&mut self, flow: &Flow, udp_pkt: &UdpPacket) {
         if udp_pkt.payload().windows(SPECIAL_UDP_PAYLOAD.len())
             .any(|sub| sub == SPECIAL_UDP_PAYLOAD) {
-                debug!("Validated UDP traffic from {}", flow)
+                Event::new(LogLevel::Debug, "validated").flow(flow).str("transport", &"udp").emit();
             }
     }
 
@@ -401,10 +525,215 @@ impl PerCoreGlobal
 #[cfg(test)]
 mod tests {
     use std::env;
+    use std::error::Error;
     use std::fs;
+    use std::io;
     use toml;
-    use StationConfig;
+    use flow_tracker::{Flow, FlowTracker};
+    use forward_sink::{ForwardSink, MemorySink, PacketMeta};
+    use key_ring::KeyRing;
+    use prefix_list::PrefixList;
+    use registration_sink::RegistrationSink;
+    use sessions::SessionDetails;
+    use signalling::C2SWrapper;
+    use util::IpPacket;
+    use {PerCoreGlobal, StationConfig};
+
+    struct NullForwarder;
+    impl ForwardSink for NullForwarder
+    {
+        fn forward(&mut self, _ip_pkt: &IpPacket, _meta: &PacketMeta) -> io::Result<()> { Ok(()) }
+    }
+
+    struct NullRegistrar;
+    impl RegistrationSink for NullRegistrar
+    {
+        fn send(&mut self, _reg: &C2SWrapper) -> Result<(), Box<dyn Error>> { Ok(()) }
+    }
 
+    fn flow_to(dst: &str) -> Flow
+    {
+        Flow {
+            src_ip: "128.138.89.172".parse().unwrap(),
+            dst_ip: dst.parse().unwrap(),
+            src_port: 40000,
+            dst_port: 443,
+        }
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let conf = StationConfig {
+            detector_filter_list: vec!["192.122.200.231".to_string()],
+            ..Default::default()
+        };
+        let mut global = PerCoreGlobal::with_sinks(KeyRing::single([0u8; 32]), 0, conf,
+                                                   FlowTracker::new_without_ingest(),
+                                                   Box::new(NullForwarder),
+                                                   Box::new(NullRegistrar));
+
+        // No prefix list yet, so nothing is held back
+        assert!(global.in_phantom_prefixes(&flow_to("203.0.113.9")));
+
+        let mut prefixes = PrefixList::new();
+        prefixes.add_cidr("192.0.2.0/24").unwrap();
+        prefixes.add_cidr("2001:db8::/32").unwrap();
+        let conf = StationConfig {
+            detector_filter_list: vec!["192.122.200.232".to_string()],
+            detector_decoy_ports: vec![443, 8443],
+            ..Default::default()
+        };
+        let diff = global.apply_config(Some(prefixes), Some(conf));
+        assert_eq!(diff.prefixes, 2);
+        assert_eq!(diff.prefixes_added.len(), 2);
+        assert!(diff.prefixes_removed.is_empty());
+        assert_eq!(diff.filter_added, vec!["192.122.200.232".to_string()]);
+        assert_eq!(diff.filter_removed, vec!["192.122.200.231".to_string()]);
+        assert_eq!(diff.decoy_ports, Some(vec![443, 8443]));
+        assert!(!diff.tag_limits);
+        assert!(global.is_decoy_port(8443));
+
+        assert!(global.in_phantom_prefixes(&flow_to("192.0.2.10")));
+        assert!(global.in_phantom_prefixes(&flow_to("2001:db8::10")));
+        assert!(!global.in_phantom_prefixes(&flow_to("203.0.113.9")));
+        assert_eq!(global.stats.in_tree_this_period, 2);
+        assert_eq!(global.stats.not_in_tree_this_period, 1);
+
+        // A failed reload (None) keeps what we had
+        let diff = global.apply_config(None, None);
+        assert!(diff.is_empty());
+        assert_eq!(diff.prefixes, 2);
+        assert!(!global.in_phantom_prefixes(&flow_to("203.0.113.9")));
+
+        // The limiter is only replaced (refilling its buckets) if the limits
+        // change
+        let limited = || StationConfig {
+            detector_filter_list: vec!["192.122.200.232".to_string()],
+            detector_decoy_ports: vec![443, 8443],
+            detector_tag_check_rate: 10.0,
+            detector_tag_check_burst: 1.0,
+            ..Default::default()
+        };
+        let diff = global.apply_config(None, Some(limited()));
+        assert!(diff.tag_limits && !diff.is_empty());
+        assert!(global.apply_config(None, Some(limited())).is_empty());
+    }
+
+    #[test]
+    fn test_forward_meta() {
+        let sink = MemorySink::new(10);
+        let forwarded = sink.packets();
+        let mut flow_tracker = FlowTracker::new_without_ingest();
+        flow_tracker.phantom_flows.add_session(
+            SessionDetails::new("128.138.89.172", "192.0.2.10", 443, 60).unwrap());
+        let mut global = PerCoreGlobal::with_sinks(KeyRing::single([0u8; 32]), 0,
+                                                   StationConfig::default(), flow_tracker,
+                                                   Box::new(sink), Box::new(NullRegistrar));
+        global.gre_offset = 4;
+
+        // Outer header, Ethernet with an 802.1Q tag, then a SYN to the phantom
+        let encap = [0x10, 0x00, 0x88, 0xbe];
+        let ip = [0x45, 0x00, 0x00, 0x28, 0x00, 0x00, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00,
+                  128, 138, 89, 172, 192, 0, 2, 10,
+                  0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0,
+                  0x50, 0x02, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00];
+        let mut frame = encap.to_vec();
+        frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 0xdd, 0x02, 0, 0, 0, 0, 0x55,
+                                  0x81, 0x00, 0x00, 0x64, 0x08, 0x00]);
+        frame.extend_from_slice(&ip);
+        global.process_packet(&frame);
+
+        // Not a registered phantom
+        frame[encap.len() + 18 + 19] = 11;
+        global.process_packet(&frame);
+
+        let forwarded = forwarded.lock().unwrap();
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].data, ip.to_vec());
+        assert_eq!(forwarded[0].meta.vlan, Some(0x64));
+        assert_eq!(forwarded[0].meta.eth_src, [0x02, 0, 0, 0, 0, 0x55]);
+        assert_eq!(forwarded[0].meta.encap, encap.to_vec());
+        global.periodic_cleanup();
+        let m = global.stats.cumulative();
+        assert_eq!((m.forward_sink, m.forwarded_packets, m.forward_drops), ("memory", 1, 0));
+    }
+
+    #[test]
+    fn test_ipv6_ext_headers() {
+        let sink = MemorySink::new(10);
+        let forwarded = sink.packets();
+        let mut flow_tracker = FlowTracker::new_without_ingest();
+        flow_tracker.phantom_flows.add_session(
+            SessionDetails::new("2001:db8::1", "2001:db8::2", 443, 60).unwrap());
+        let mut global = PerCoreGlobal::with_sinks(KeyRing::single([0u8; 32]), 0,
+                                                   StationConfig::default(), flow_tracker,
+                                                   Box::new(sink), Box::new(NullRegistrar));
+
+        // Hop-by-Hop options, then a SYN to the phantom
+        let frame = |next: u8, ext: &[u8]| {
+            let mut f = vec![0x02, 0, 0, 0, 0, 0xdd, 0x02, 0, 0, 0, 0, 0x55, 0x86, 0xdd,
+                             0x60, 0, 0, 0, 0, (ext.len() + 20) as u8, next, 64];
+            f.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+            f.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+            f.extend_from_slice(ext);
+            f.extend_from_slice(&[0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0,
+                                  0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+            f
+        };
+        global.process_packet(&frame(0, &[6, 0, 1, 4, 0, 0, 0, 0]));
+        assert_eq!(forwarded.lock().unwrap().len(), 1);
+        assert_eq!(global.stats.tcp_packets_this_period, 1);
+
+        // The same SYN in two fragments, split 8 bytes into the TCP header
+        let mut first = frame(44, &[6, 0, 0, 1, 0, 0, 0, 7]);
+        let mut second = first.clone();
+        first.truncate(70);
+        first[19] = 16;
+        second.drain(62..70);
+        second[19] = 20;
+        second[57] = 8;
+        global.process_packet(&second);
+        assert_eq!(global.stats.tcp_packets_this_period, 1);
+        global.process_packet(&first);
+
+        assert_eq!(global.stats.ip_fragments_this_period, 2);
+        assert_eq!(global.stats.tcp_packets_this_period, 2);
+        let forwarded = forwarded.lock().unwrap();
+        assert_eq!(forwarded.len(), 2);
+        assert_eq!(&forwarded[1].data[..], &frame(6, &[])[14..]);
+    }
+
+    #[test]
+    fn test_fragments_dropped() {
+        let sink = MemorySink::new(10);
+        let forwarded = sink.packets();
+        let mut flow_tracker = FlowTracker::new_without_ingest();
+        flow_tracker.phantom_flows.add_session(
+            SessionDetails::new("10.0.0.1", "10.0.0.2", 443, 60).unwrap());
+        let mut conf = StationConfig::default();
+        conf.detector_fragments = Some("drop".to_string());
+        let mut global = PerCoreGlobal::with_sinks(KeyRing::single([0u8; 32]), 0,
+                                                   conf, flow_tracker,
+                                                   Box::new(sink), Box::new(NullRegistrar));
+
+        // First 8 bytes of a SYN to the phantom, then the rest
+        let frame = |flags: u8, offset: u8, tcp: &[u8]| {
+            let mut f = vec![0x02, 0, 0, 0, 0, 0xdd, 0x02, 0, 0, 0, 0, 0x55, 0x08, 0x00,
+                             0x45, 0, 0, (tcp.len() + 20) as u8, 0, 7, flags, offset, 64, 6, 0, 0,
+                             10, 0, 0, 1, 10, 0, 0, 2];
+            f.extend_from_slice(tcp);
+            f
+        };
+        let tcp = [0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0,
+                   0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0];
+        global.process_packet(&frame(0x20, 0, &tcp[..8]));
+        global.process_packet(&frame(0, 1, &tcp[8..]));
+
+        assert_eq!(global.stats.ipv4_packets_this_period, 2);
+        assert_eq!(global.stats.ip_fragments_this_period, 2);
+        assert_eq!(global.stats.tcp_packets_this_period, 0);
+        assert_eq!(forwarded.lock().unwrap().len(), 0);
+    }
 
     #[test]
     fn test_filter_station_traffic() {
@@ -0,0 +1,276 @@
+//
+// Rate limiting of registration tag checks
+//
+// Checking a tag costs an X25519 operation, and anyone who can get
+// connections through the tap can make us do one per connection. Token
+// buckets per source prefix keep any one network from monopolizing that, and
+// a per-core bucket caps the total so the packet path keeps up no matter how
+// many networks join in.
+//
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use prefix_list::{mask_v4, mask_v6};
+
+// Source buckets kept before we start ignoring new sources (they're still
+// subject to the per-core limit). Room is only made by drop_idle, from the
+// periodic cleanup: sweeping the map on the packet path whenever a new
+// source shows up is just what a flood from many prefixes would want.
+const MAX_SOURCES: usize = 100000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit
+{
+    pub per_sec: f64,
+    pub burst: f64,
+}
+
+pub struct TokenBucket
+{
+    tokens: f64,
+    last_ns: u64,
+}
+
+impl TokenBucket
+{
+    pub fn new(limit: &Limit, now_ns: u64) -> TokenBucket
+    {
+        TokenBucket { tokens: limit.burst, last_ns: now_ns }
+    }
+
+    fn refill(&mut self, limit: &Limit, now_ns: u64)
+    {
+        if now_ns > self.last_ns {
+            let secs = (now_ns - self.last_ns) as f64 / 1e9;
+            self.tokens = (self.tokens + secs * limit.per_sec).min(limit.burst);
+            self.last_ns = now_ns;
+        }
+    }
+
+    // Takes a token if there is one
+    pub fn take(&mut self, limit: &Limit, now_ns: u64) -> bool
+    {
+        self.refill(limit, now_ns);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimit
+{
+    Allowed,
+    SourceLimited,
+    GlobalLimited,
+}
+
+pub struct TagCheckLimiter
+{
+    global_limit: Option<Limit>,
+    global: TokenBucket,
+
+    source_limit: Option<Limit>,
+    // Prefix lengths sources are grouped by
+    v4_len: u8,
+    v6_len: u8,
+    sources: HashMap<(bool, u128), TokenBucket>,
+}
+
+impl TagCheckLimiter
+{
+    // None means no limit. A burst below 1 is treated as 1, since a bucket
+    // that can't hold a whole token would never allow anything.
+    pub fn new(global: Option<Limit>, per_source: Option<Limit>,
+               v4_len: u8, v6_len: u8, now_ns: u64) -> TagCheckLimiter
+    {
+        let fix = |l: Limit| Limit { per_sec: l.per_sec, burst: l.burst.max(1.0) };
+        let global_limit = global.map(&fix);
+        TagCheckLimiter {
+            global: TokenBucket::new(&global_limit.unwrap_or(Limit { per_sec: 0.0, burst: 0.0 }),
+                                     now_ns),
+            global_limit,
+            source_limit: per_source.map(&fix),
+            v4_len: v4_len.min(32),
+            v6_len: v6_len.min(128),
+            sources: HashMap::new(),
+        }
+    }
+
+    pub fn unlimited() -> TagCheckLimiter
+    {
+        TagCheckLimiter::new(None, None, 32, 128, 0)
+    }
+
+    fn source_key(&self, src: &IpAddr) -> (bool, u128)
+    {
+        match *src {
+            IpAddr::V4(a) => (false, mask_v4(u32::from(a), self.v4_len) as u128),
+            IpAddr::V6(a) => (true, mask_v6(u128::from(a), self.v6_len)),
+        }
+    }
+
+    // Takes a token for one tag check from src, if both its source bucket
+    // and the per-core bucket have one.
+    pub fn check(&mut self, src: &IpAddr, now_ns: u64) -> RateLimit
+    {
+        if let Some(ref limit) = self.global_limit {
+            self.global.refill(limit, now_ns);
+            if self.global.tokens < 1.0 {
+                return RateLimit::GlobalLimited;
+            }
+        }
+
+        if let Some(limit) = self.source_limit {
+            let key = self.source_key(src);
+            // No room for a new source: it only gets the per-core limit
+            if self.sources.len() < MAX_SOURCES || self.sources.contains_key(&key) {
+                let bucket = self.sources.entry(key)
+                    .or_insert_with(|| TokenBucket::new(&limit, now_ns));
+                bucket.refill(&limit, now_ns);
+                if bucket.tokens < 1.0 {
+                    return RateLimit::SourceLimited;
+                }
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        if self.global_limit.is_some() {
+            self.global.tokens -= 1.0;
+        }
+        RateLimit::Allowed
+    }
+
+    // Forgets sources whose buckets have refilled; they'd start out full
+    // anyway.
+    pub fn drop_idle(&mut self, now_ns: u64)
+    {
+        if let Some(limit) = self.source_limit {
+            self.sources.retain(|_, b| {
+                b.refill(&limit, now_ns);
+                b.tokens < limit.burst
+            });
+        }
+    }
+
+    pub fn count_sources(&self) -> usize
+    {
+        self.sources.len()
+    }
+
+    // Whether other limits the same way (whatever's in the buckets)
+    pub fn same_limits(&self, other: &TagCheckLimiter) -> bool
+    {
+        (self.global_limit, self.source_limit, self.v4_len, self.v6_len)
+            == (other.global_limit, other.source_limit, other.v4_len, other.v6_len)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rate_limit::*;
+    use std::net::IpAddr;
+
+    const SEC: u64 = 1000 * 1000 * 1000;
+
+    fn ip(s: &str) -> IpAddr
+    {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_unlimited()
+    {
+        let mut l = TagCheckLimiter::unlimited();
+        for i in 0..10000 {
+            assert_eq!(l.check(&ip("192.0.2.1"), i), RateLimit::Allowed);
+        }
+        assert_eq!(l.count_sources(), 0);
+    }
+
+    #[test]
+    fn test_per_source()
+    {
+        let limit = Limit { per_sec: 2.0, burst: 3.0 };
+        let mut l = TagCheckLimiter::new(None, Some(limit), 24, 48, 0);
+        let t = 10 * SEC;
+
+        for _ in 0..3 {
+            assert_eq!(l.check(&ip("192.0.2.1"), t), RateLimit::Allowed);
+        }
+        // Same /24
+        assert_eq!(l.check(&ip("192.0.2.200"), t), RateLimit::SourceLimited);
+        // Other prefixes have their own buckets
+        assert_eq!(l.check(&ip("192.0.3.1"), t), RateLimit::Allowed);
+        assert_eq!(l.check(&ip("2001:db8:1:1::1"), t), RateLimit::Allowed);
+        assert_eq!(l.count_sources(), 3);
+
+        // Half a second buys one more
+        assert_eq!(l.check(&ip("192.0.2.1"), t + SEC / 2), RateLimit::Allowed);
+        assert_eq!(l.check(&ip("192.0.2.1"), t + SEC / 2), RateLimit::SourceLimited);
+
+        // Once everyone's bucket is full again they're forgotten
+        l.drop_idle(t + 10 * SEC);
+        assert_eq!(l.count_sources(), 0);
+    }
+
+    #[test]
+    fn test_global()
+    {
+        let mut l = TagCheckLimiter::new(Some(Limit { per_sec: 10.0, burst: 5.0 }),
+                                         Some(Limit { per_sec: 1.0, burst: 2.0 }),
+                                         32, 128, 0);
+        let t = SEC;
+        for i in 0..5 {
+            let src = format!("198.51.100.{}", i);
+            assert_eq!(l.check(&ip(&src), t), RateLimit::Allowed);
+        }
+        assert_eq!(l.check(&ip("198.51.100.99"), t), RateLimit::GlobalLimited);
+
+        // 100ms later there's one more token to go around
+        assert_eq!(l.check(&ip("198.51.100.0"), t + SEC / 10), RateLimit::Allowed);
+        assert_eq!(l.check(&ip("198.51.100.1"), t + SEC / 10), RateLimit::GlobalLimited);
+
+        // A source that's over its own limit doesn't use up the global bucket
+        assert_eq!(l.check(&ip("198.51.100.0"), t + SEC / 5), RateLimit::SourceLimited);
+        assert_eq!(l.check(&ip("198.51.100.7"), t + SEC / 5), RateLimit::Allowed);
+    }
+
+    #[test]
+    fn test_same_limits()
+    {
+        let limit = Limit { per_sec: 10.0, burst: 0.5 };
+        let mut a = TagCheckLimiter::new(Some(limit), Some(limit), 24, 48, 0);
+        a.check(&ip("192.0.2.1"), 0);
+        assert!(a.same_limits(&TagCheckLimiter::new(Some(limit), Some(limit), 24, 48, SEC)));
+        assert!(!a.same_limits(&TagCheckLimiter::new(Some(limit), None, 24, 48, 0)));
+        assert!(!a.same_limits(&TagCheckLimiter::new(Some(limit), Some(limit), 16, 48, 0)));
+        assert!(!a.same_limits(&TagCheckLimiter::unlimited()));
+    }
+
+    #[test]
+    fn test_too_many_sources()
+    {
+        let limit = Limit { per_sec: 1.0, burst: 1.0 };
+        let mut l = TagCheckLimiter::new(None, Some(limit), 32, 128, 0);
+        let t = SEC;
+        for i in 0..MAX_SOURCES as u32 {
+            assert_eq!(l.check(&IpAddr::from((0x0a000000 + i).to_be_bytes()), t), RateLimit::Allowed);
+        }
+        // New sources only get the per-core limit until the cleanup makes room
+        for _ in 0..3 {
+            assert_eq!(l.check(&ip("192.0.2.1"), t), RateLimit::Allowed);
+        }
+        assert_eq!(l.count_sources(), MAX_SOURCES);
+        assert_eq!(l.check(&ip("10.0.0.0"), t), RateLimit::SourceLimited);
+
+        l.drop_idle(t + SEC);
+        assert_eq!(l.count_sources(), 0);
+        assert_eq!(l.check(&ip("192.0.2.1"), t + SEC), RateLimit::Allowed);
+        assert_eq!(l.check(&ip("192.0.2.1"), t + SEC), RateLimit::SourceLimited);
+    }
+}
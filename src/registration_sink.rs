@@ -0,0 +1,490 @@
+//
+// Where the detector sends the registrations it finds
+//
+// The default is the ZMQ PUB socket the application subscribes to (through
+// the proxy detect.c runs). PUB never reports a problem: with nobody
+// subscribed, or a subscriber that's behind, registrations are silently
+// dropped. The other sinks can tell when the application isn't taking
+// registrations, and QueuedSink holds on to them (up to a limit) and retries
+// until it is, so an application restart doesn't lose registrations.
+//
+// The same transports carry PhantomEvents, when the application asks for
+// them (see PerCoreGlobal::publish_phantom_events).
+//
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use protobuf::Message;
+use redis;
+use redis::{ConnectionAddr, IntoConnectionInfo};
+use time::precise_time_ns;
+use zmq;
+
+use signalling::C2SWrapper;
+
+// After a failed send, queued registrations aren't retried (and new ones go
+// straight to the queue) for this long.
+const RETRY_NS: u64 = 500 * 1000 * 1000;
+
+const REDIS_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Cumulative since the sink was created, except queued.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct SinkCounters
+{
+    // Failed attempts to send (including retries)
+    pub errors: u64,
+    // Registrations given up on: queue full, or no queue
+    pub dropped: u64,
+    // Registrations delivered from the queue
+    pub retried: u64,
+    // Registrations waiting in the queue right now
+    pub queued: u64,
+}
+
+// Destination for registrations found by the detector. The application
+// normally receives these over the ZMQ PUB socket that the detector connects
+// to; offline tools can collect or print them instead.
+pub trait RegistrationSink
+{
+    // Ok means the registration was sent, or queued to be.
+    fn send(&mut self, reg: &C2SWrapper) -> Result<(), Box<dyn Error>>;
+
+    // Retries anything held back. Called from the periodic cleanup.
+    fn flush(&mut self) {}
+
+    fn counters(&self) -> SinkCounters
+    {
+        SinkCounters::default()
+    }
+}
+
+// Something serialized registrations can be written to, for QueuedSink.
+// Errors are taken to mean the other end isn't ready yet and the
+// registration should be tried again later.
+pub trait RegistrationTransport
+{
+    fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+impl RegistrationTransport for Box<dyn RegistrationTransport>
+{
+    fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>
+    {
+        (**self).send_bytes(msg)
+    }
+}
+
+pub struct ZmqPubSink
+{
+    sock: zmq::Socket,
+    counters: SinkCounters,
+}
+
+impl ZmqPubSink
+{
+    pub fn new(workers_socket_addr: &str) -> ZmqPubSink
+    {
+        let zmq_ctx = zmq::Context::new();
+        let sock = zmq_ctx.socket(zmq::PUB).unwrap();
+        sock.connect(workers_socket_addr).expect("failed connecting to ZMQ");
+        ZmqPubSink { sock, counters: SinkCounters::default() }
+    }
+}
+
+impl RegistrationSink for ZmqPubSink
+{
+    fn send(&mut self, reg: &C2SWrapper) -> Result<(), Box<dyn Error>>
+    {
+        let zmq_payload = reg.write_to_bytes()?;
+        if let Err(e) = self.sock.send(&zmq_payload, 0) {
+            self.counters.errors += 1;
+            self.counters.dropped += 1;
+            return Err(Box::new(e));
+        }
+        Ok(())
+    }
+
+    fn counters(&self) -> SinkCounters
+    {
+        self.counters
+    }
+}
+
+// PUSH to an application that binds a PULL socket. Unlike PUB, a PUSH socket
+// with nobody connected (or whose peer has hwm registrations it hasn't read
+// yet) refuses new messages instead of dropping them.
+pub struct ZmqPushSink
+{
+    sock: zmq::Socket,
+}
+
+impl ZmqPushSink
+{
+    pub fn new(addr: &str, hwm: i32) -> Result<ZmqPushSink, Box<dyn Error>>
+    {
+        let zmq_ctx = zmq::Context::new();
+        let sock = zmq_ctx.socket(zmq::PUSH)?;
+        sock.set_sndhwm(hwm)?;
+        // Don't let ZMQ queue for a peer that isn't connected; we'd rather
+        // know, and queue ourselves.
+        sock.set_immediate(true)?;
+        sock.set_linger(0)?;
+        sock.connect(addr)?;
+        Ok(ZmqPushSink { sock })
+    }
+}
+
+impl RegistrationTransport for ZmqPushSink
+{
+    fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>
+    {
+        self.sock.send(msg, zmq::DONTWAIT)?;
+        Ok(())
+    }
+}
+
+// XADD to a Redis stream, trimmed to about maxlen entries. Each entry has the
+// serialized message in one field ("reg" for registrations).
+pub struct RedisStreamSink
+{
+    client: redis::Client,
+    conn: Option<redis::Connection>,
+    // Where we check Redis can be reached before connecting (see connect)
+    addr: Option<SocketAddr>,
+    stream: String,
+    field: String,
+    maxlen: usize,
+}
+
+impl RedisStreamSink
+{
+    pub fn new(url: &str, stream: &str, field: &str, maxlen: usize)
+        -> Result<RedisStreamSink, Box<dyn Error>>
+    {
+        let addr = match *url.into_connection_info()?.addr {
+            ConnectionAddr::Tcp(ref host, port) => (host.as_str(), port).to_socket_addrs()?.next(),
+            _ => None,
+        };
+        Ok(RedisStreamSink {
+            client: redis::Client::open(url)?,
+            conn: None,
+            addr,
+            stream: stream.to_string(),
+            field: field.to_string(),
+            maxlen,
+        })
+    }
+
+    fn connect(&self) -> redis::RedisResult<redis::Connection>
+    {
+        // get_connection() has no timeout of its own. With Redis unreachable
+        // (rather than refusing connections) it would hold up the packet
+        // thread for as long as the kernel keeps resending the SYN.
+        if let Some(ref addr) = self.addr {
+            TcpStream::connect_timeout(addr, REDIS_TIMEOUT)?;
+        }
+        let conn = self.client.get_connection()?;
+        conn.set_read_timeout(Some(REDIS_TIMEOUT))?;
+        conn.set_write_timeout(Some(REDIS_TIMEOUT))?;
+        Ok(conn)
+    }
+}
+
+impl RegistrationTransport for RedisStreamSink
+{
+    fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>
+    {
+        if self.conn.is_none() {
+            self.conn = Some(self.connect()?);
+        }
+        let res: redis::RedisResult<String> = match self.conn {
+            Some(ref conn) => redis::cmd("XADD").arg(&self.stream)
+                .arg("MAXLEN").arg("~").arg(self.maxlen)
+                .arg("*").arg(&self.field).arg(msg)
+                .query(conn),
+            None => unreachable!(),
+        };
+        if let Err(e) = res {
+            // Start over with a new connection next time
+            self.conn = None;
+            return Err(Box::new(e));
+        }
+        Ok(())
+    }
+}
+
+// One datagram per registration to a Unix socket the application binds.
+pub struct UnixDatagramSink
+{
+    sock: UnixDatagram,
+    path: PathBuf,
+}
+
+impl UnixDatagramSink
+{
+    pub fn new(path: &str) -> Result<UnixDatagramSink, Box<dyn Error>>
+    {
+        let sock = UnixDatagram::unbound()?;
+        sock.set_nonblocking(true)?;
+        Ok(UnixDatagramSink { sock, path: PathBuf::from(path) })
+    }
+}
+
+impl RegistrationTransport for UnixDatagramSink
+{
+    fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>
+    {
+        self.sock.send_to(msg, &self.path)?;
+        Ok(())
+    }
+}
+
+// Sends through a transport, queueing up to capacity registrations (or other
+// messages) while it fails. When the queue is full the oldest registration is dropped: its
+// client has the longest been waiting, and is the most likely to have given
+// up already.
+pub struct QueuedSink<T: RegistrationTransport>
+{
+    transport: T,
+    queue: VecDeque<Vec<u8>>,
+    capacity: usize,
+    retry_at: u64,
+    failing: bool,
+    counters: SinkCounters,
+    // What's being sent, for logging
+    what: &'static str,
+}
+
+impl<T: RegistrationTransport> QueuedSink<T>
+{
+    // A capacity of 0 means no queue: every registration is tried once.
+    pub fn new(transport: T, capacity: usize) -> QueuedSink<T>
+    {
+        QueuedSink::named(transport, capacity, "registration")
+    }
+
+    // what names the messages in logs, e.g. "registration"
+    pub fn named(transport: T, capacity: usize, what: &'static str) -> QueuedSink<T>
+    {
+        QueuedSink {
+            transport,
+            queue: VecDeque::new(),
+            capacity,
+            retry_at: 0,
+            failing: false,
+            counters: SinkCounters::default(),
+            what,
+        }
+    }
+
+    // Any protobuf message, queued like a registration
+    pub fn send_message<M: Message>(&mut self, msg: &M) -> Result<(), Box<dyn Error>>
+    {
+        let bytes = msg.write_to_bytes()?;
+        self.send_at(bytes, precise_time_ns())
+    }
+
+    fn try_send(&mut self, msg: &[u8], now: u64) -> Result<(), Box<dyn Error>>
+    {
+        match self.transport.send_bytes(msg) {
+            Ok(_) => {
+                if self.failing {
+                    self.failing = false;
+                    info!("{} sink is taking {}s again", self.what, self.what);
+                }
+                Ok(())
+            },
+            Err(e) => {
+                if !self.failing {
+                    self.failing = true;
+                    warn!("{} sink failed ({}), holding {}s for retry", self.what, e, self.what);
+                }
+                self.counters.errors += 1;
+                self.retry_at = now + RETRY_NS;
+                Err(e)
+            },
+        }
+    }
+
+    // Sends what's queued, oldest first, until something fails.
+    fn flush_at(&mut self, now: u64)
+    {
+        if now < self.retry_at {
+            return;
+        }
+        while let Some(msg) = self.queue.pop_front() {
+            if self.try_send(&msg, now).is_err() {
+                self.queue.push_front(msg);
+                break;
+            }
+            self.counters.retried += 1;
+        }
+        self.counters.queued = self.queue.len() as u64;
+    }
+
+    fn send_at(&mut self, msg: Vec<u8>, now: u64) -> Result<(), Box<dyn Error>>
+    {
+        if self.capacity == 0 {
+            let res = self.try_send(&msg, now);
+            if res.is_err() {
+                self.counters.dropped += 1;
+            }
+            return res;
+        }
+
+        // Nothing jumps ahead of what's already waiting
+        self.flush_at(now);
+        if self.queue.is_empty() && now >= self.retry_at
+            && self.try_send(&msg, now).is_ok() {
+            return Ok(());
+        }
+
+        if self.queue.len() >= self.capacity {
+            self.queue.pop_front();
+            self.counters.dropped += 1;
+        }
+        self.queue.push_back(msg);
+        self.counters.queued = self.queue.len() as u64;
+        Ok(())
+    }
+}
+
+impl<T: RegistrationTransport> RegistrationSink for QueuedSink<T>
+{
+    fn send(&mut self, reg: &C2SWrapper) -> Result<(), Box<dyn Error>>
+    {
+        self.send_message(reg)
+    }
+
+    fn flush(&mut self)
+    {
+        self.flush_at(precise_time_ns());
+    }
+
+    fn counters(&self) -> SinkCounters
+    {
+        self.counters
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use registration_sink::*;
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::rc::Rc;
+
+    const SEC: u64 = 1000 * 1000 * 1000;
+
+    // Accepts messages only while up is set
+    struct TestTransport
+    {
+        up: Rc<RefCell<bool>>,
+        sent: Sent,
+    }
+
+    impl RegistrationTransport for TestTransport
+    {
+        fn send_bytes(&mut self, msg: &[u8]) -> Result<(), Box<dyn Error>>
+        {
+            if !*self.up.borrow() {
+                return Err(From::from("down"));
+            }
+            self.sent.borrow_mut().push(msg.to_vec());
+            Ok(())
+        }
+    }
+
+    type Sent = Rc<RefCell<Vec<Vec<u8>>>>;
+
+    fn test_sink(capacity: usize) -> (QueuedSink<TestTransport>, Rc<RefCell<bool>>, Sent)
+    {
+        let up = Rc::new(RefCell::new(true));
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let t = TestTransport { up: up.clone(), sent: sent.clone() };
+        (QueuedSink::new(t, capacity), up, sent)
+    }
+
+    #[test]
+    fn test_queue_retry()
+    {
+        let (mut sink, up, sent) = test_sink(10);
+        let t = 10 * SEC;
+        sink.send_at(b"a".to_vec(), t).unwrap();
+
+        *up.borrow_mut() = false;
+        for m in [b"b", b"c", b"d"].iter() {
+            sink.send_at(m.to_vec(), t).unwrap();
+        }
+        // Only the first one was tried; the rest waited
+        assert_eq!(sink.counters(), SinkCounters { errors: 1, dropped: 0, retried: 0, queued: 3 });
+
+        *up.borrow_mut() = true;
+        sink.flush_at(t + RETRY_NS / 2);
+        assert_eq!(sent.borrow().len(), 1);
+
+        sink.flush_at(t + RETRY_NS);
+        sink.send_at(b"e".to_vec(), t + RETRY_NS).unwrap();
+        let want: Vec<Vec<u8>> = ["a", "b", "c", "d", "e"].iter()
+            .map(|s| s.as_bytes().to_vec()).collect();
+        assert_eq!(*sent.borrow(), want);
+        assert_eq!(sink.counters(), SinkCounters { errors: 1, dropped: 0, retried: 3, queued: 0 });
+    }
+
+    #[test]
+    fn test_queue_full()
+    {
+        let (mut sink, up, sent) = test_sink(2);
+        *up.borrow_mut() = false;
+        for (i, m) in [b"a", b"b", b"c", b"d"].iter().enumerate() {
+            sink.send_at(m.to_vec(), i as u64 * SEC).unwrap();
+        }
+        assert_eq!(sink.counters().dropped, 2);
+        assert_eq!(sink.counters().queued, 2);
+
+        *up.borrow_mut() = true;
+        sink.flush_at(10 * SEC);
+        assert_eq!(*sent.borrow(), vec![b"c".to_vec(), b"d".to_vec()]);
+
+        // Without a queue, failures are just dropped
+        let (mut sink, up, _) = test_sink(0);
+        *up.borrow_mut() = false;
+        assert!(sink.send_at(b"x".to_vec(), SEC).is_err());
+        assert_eq!(sink.counters(), SinkCounters { errors: 1, dropped: 1, retried: 0, queued: 0 });
+    }
+
+    #[test]
+    fn test_unix_datagram()
+    {
+        let path = env::temp_dir().join(format!("registration_sink_test_{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let transport = UnixDatagramSink::new(path.to_str().unwrap()).unwrap();
+        let mut sink = QueuedSink::new(transport, 10);
+        // Nobody listening yet
+        sink.send_at(b"first".to_vec(), SEC).unwrap();
+        assert_eq!(sink.counters().queued, 1);
+
+        let app = UnixDatagram::bind(&path).unwrap();
+        sink.flush_at(2 * SEC);
+        sink.send_at(b"second".to_vec(), 2 * SEC).unwrap();
+        assert_eq!(sink.counters().queued, 0);
+
+        let mut buf = [0u8; 64];
+        let n = app.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"first");
+        let n = app.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"second");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
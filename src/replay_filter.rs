@@ -0,0 +1,275 @@
+//
+// Replay protection for registration tags
+//
+// Anyone who sees a tagged TLS record can copy it and send it again, from
+// anywhere, and the detector would happily decrypt it and pass the same
+// registration on to the application every time. So we remember the shared
+// secret of every registration we've seen recently and drop repeats.
+//
+// "Recently" is a sliding window made of two Bloom filters that each cover
+// half of it: new secrets go in the filter for the current half-window,
+// lookups check both, and when a new half-window starts the older filter is
+// cleared and reused. A secret is remembered for between window/2 and window
+// seconds.
+//
+// A replayed record is just as likely to be hashed to another core as to the
+// one that saw the original, so the filters normally live in a file mapped
+// shared by every detector process (/dev/shm). Bits are only ever set with
+// atomic ORs, so no locking is needed; the worst a race can do is lose a few
+// bits while a half-window is being cleared.
+//
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use rand;
+
+use shm::{mix, Words};
+
+// Bits per half-window filter. 2^23 bits (1 MiB) with 7 hashes stays under a
+// 1% false positive rate up to ~800k registrations per half-window.
+const FILTER_BITS: u64 = 1 << 23;
+const FILTER_WORDS: usize = (FILTER_BITS / 64) as usize;
+const NUM_HASHES: u64 = 7;
+
+// Header words at the start of the table, then the two filters
+const STATE: usize = 0;
+const SIZE: usize = 1;
+const KEY0: usize = 2;
+const KEY1: usize = 3;
+const EPOCH0: usize = 4;     // EPOCH0 + slot
+const HEADER_WORDS: usize = 8;
+const TABLE_WORDS: usize = HEADER_WORDS + 2 * FILTER_WORDS;
+
+const STATE_UNINIT: u64 = 0;
+const STATE_INITIALIZING: u64 = 1;
+const STATE_READY: u64 = 2;
+
+pub const DEFAULT_WINDOW_SECS: u64 = 3600;
+
+pub struct ReplayFilter
+{
+    table: Words,
+    half_window: u64,
+}
+
+fn word(b: &[u8], i: usize) -> u64
+{
+    let mut w = 0u64;
+    for (j, byte) in b.iter().skip(i * 8).take(8).enumerate() {
+        w |= (*byte as u64) << (8 * j);
+    }
+    w
+}
+
+impl ReplayFilter
+{
+    // A filter private to this process.
+    pub fn new(window_secs: u64) -> ReplayFilter
+    {
+        let filter = ReplayFilter {
+            table: Words::heap(TABLE_WORDS),
+            half_window: ::std::cmp::max(window_secs / 2, 1),
+        };
+        filter.init();
+        filter
+    }
+
+    // A filter shared with every other process that opens the same path.
+    // The file is created if need be; an existing one is reused, so what it
+    // remembers survives a restart.
+    pub fn open_shared(path: &str, window_secs: u64) -> io::Result<ReplayFilter>
+    {
+        let filter = ReplayFilter {
+            table: Words::map_file(path, TABLE_WORDS)?,
+            half_window: ::std::cmp::max(window_secs / 2, 1),
+        };
+        filter.init();
+        Ok(filter)
+    }
+
+    fn words(&self) -> &[AtomicU64]
+    {
+        self.table.get()
+    }
+
+    // The hash key is random (so nobody can aim registrations at particular
+    // bits), but has to be the same in every process sharing the table: the
+    // first one there picks it.
+    fn init(&self)
+    {
+        let w = self.words();
+        if w[STATE].load(Ordering::SeqCst) == STATE_READY
+            && w[SIZE].load(Ordering::SeqCst) == FILTER_BITS {
+            return;
+        }
+        match w[STATE].compare_exchange(w[STATE].load(Ordering::SeqCst), STATE_INITIALIZING,
+                                        Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(prev) if prev != STATE_INITIALIZING => {
+                for x in w[SIZE..].iter() {
+                    x.store(0, Ordering::SeqCst);
+                }
+                w[KEY0].store(rand::random::<u64>(), Ordering::SeqCst);
+                w[KEY1].store(rand::random::<u64>(), Ordering::SeqCst);
+                w[SIZE].store(FILTER_BITS, Ordering::SeqCst);
+                w[STATE].store(STATE_READY, Ordering::SeqCst);
+            },
+            _ => {
+                // Someone else is setting it up. If they died doing it, give
+                // up waiting and start over.
+                for _ in 0..1000 {
+                    if w[STATE].load(Ordering::SeqCst) == STATE_READY {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+                w[STATE].store(STATE_UNINIT, Ordering::SeqCst);
+                self.init();
+            },
+        }
+    }
+
+    fn filter(&self, slot: u64) -> &[AtomicU64]
+    {
+        let start = HEADER_WORDS + slot as usize * FILTER_WORDS;
+        &self.words()[start..start + FILTER_WORDS]
+    }
+
+    fn bit_positions(&self, secret: &[u8]) -> [u64; NUM_HASHES as usize]
+    {
+        let w = self.words();
+        let k0 = w[KEY0].load(Ordering::Relaxed);
+        let k1 = w[KEY1].load(Ordering::Relaxed);
+        let h1 = mix(word(secret, 0) ^ k0 ^ mix(word(secret, 1) ^ k1));
+        let h2 = mix(word(secret, 2) ^ k1 ^ mix(word(secret, 3) ^ k0)) | 1;
+        let mut pos = [0u64; NUM_HASHES as usize];
+        for i in 0..NUM_HASHES {
+            pos[i as usize] = h1.wrapping_add(i.wrapping_mul(h2)) % FILTER_BITS;
+        }
+        pos
+    }
+
+    fn contains(filter: &[AtomicU64], pos: &[u64]) -> bool
+    {
+        pos.iter().all(|p| {
+            filter[(p / 64) as usize].load(Ordering::Relaxed) & (1 << (p % 64)) != 0
+        })
+    }
+
+    // Makes sure the filter for epoch is the one in its slot, clearing out
+    // whatever was there from two half-windows (or more) ago.
+    fn claim(&self, epoch: u64)
+    {
+        let slot_epoch = &self.words()[EPOCH0 + (epoch % 2) as usize];
+        let cur = slot_epoch.load(Ordering::SeqCst);
+        if cur >= epoch {
+            return;
+        }
+        if slot_epoch.compare_exchange(cur, epoch, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            for x in self.filter(epoch % 2) {
+                x.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Records secret as seen at now (unix seconds). Returns true if it had
+    // already been seen within the window (or, rarely, is a false positive).
+    pub fn check_and_insert(&self, secret: &[u8], now: u64) -> bool
+    {
+        let epoch = now / self.half_window;
+        let pos = self.bit_positions(secret);
+        let w = self.words();
+
+        let mut seen = false;
+        for slot in 0..2 {
+            let slot_epoch = w[EPOCH0 + slot].load(Ordering::SeqCst);
+            if slot_epoch + 1 >= epoch && slot_epoch <= epoch
+                && ReplayFilter::contains(self.filter(slot as u64), &pos) {
+                seen = true;
+            }
+        }
+
+        self.claim(epoch);
+        let filter = self.filter(epoch % 2);
+        for p in pos.iter() {
+            filter[(p / 64) as usize].fetch_or(1 << (p % 64), Ordering::Relaxed);
+        }
+        seen
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use replay_filter::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn secret(i: u32) -> [u8; 32]
+    {
+        let mut s = [0x5au8; 32];
+        s[0] = i as u8;
+        s[9] = (i >> 8) as u8;
+        s[17] = (i >> 16) as u8;
+        s
+    }
+
+    #[test]
+    fn test_duplicates()
+    {
+        let f = ReplayFilter::new(600);
+        let now = 1600000000;
+        for i in 0..1000 {
+            assert!(!f.check_and_insert(&secret(i), now));
+        }
+        for i in 0..1000 {
+            assert!(f.check_and_insert(&secret(i), now + 1));
+        }
+        assert!(!f.check_and_insert(&secret(5000), now + 1));
+    }
+
+    #[test]
+    fn test_window()
+    {
+        let f = ReplayFilter::new(600);
+        let now = 1600000400; // 200s into a 300s half-window
+        assert!(!f.check_and_insert(&secret(1), now));
+
+        // Still remembered in the next half-window...
+        assert!(f.check_and_insert(&secret(1), now + 300));
+        // ...which also re-inserted it, so it's around for another while
+        assert!(f.check_and_insert(&secret(1), now + 650));
+        // but not once a whole window has gone by
+        assert!(!f.check_and_insert(&secret(1), now + 1500));
+
+        assert!(!f.check_and_insert(&secret(2), now + 1500));
+        assert!(!f.check_and_insert(&secret(2), now + 2200));
+    }
+
+    #[test]
+    fn test_shared()
+    {
+        let path = env::temp_dir().join(format!("replay_filter_test_{}", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let now = 1600000000;
+        let a = ReplayFilter::open_shared(path, 600).unwrap();
+        let b = ReplayFilter::open_shared(path, 600).unwrap();
+        assert!(!a.check_and_insert(&secret(1), now));
+        assert!(b.check_and_insert(&secret(1), now));
+        assert!(!b.check_and_insert(&secret(2), now));
+        assert!(a.check_and_insert(&secret(2), now));
+        drop(a);
+        drop(b);
+
+        // Survives being reopened
+        let c = ReplayFilter::open_shared(path, 600).unwrap();
+        assert!(c.check_and_insert(&secret(1), now + 1));
+
+        fs::remove_file(path).unwrap();
+    }
+}
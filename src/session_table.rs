@@ -0,0 +1,788 @@
+//
+// Phantom session table shared by the detector processes
+//
+// Every core has to know about every session, but rather than each keeping
+// its own copy fed by its own Redis subscription, one ingest process (forked
+// by detect.c) writes the sessions into a table that every core maps from
+// /dev/shm, and the cores look packets up in it without taking any lock.
+//
+// It's an open-addressing hash table with linear probing and a fixed number
+// of slots (a power of two), each one cache line: a sequence number, the
+// SessionKey packed into three words, the session's deadline, and whether a
+// core has forwarded anything for it. Keys are the same as everywhere else:
+// the client/phantom pair for v4, the phantom alone for v6.
+//
+// Adding, moving and clearing slots (insert, remove, expire_key) takes a lock
+// in the header, which in practice only the ingest process ever wants. Each
+// slot is written between two increments of its sequence number, so a reader
+// can tell it changed under them and read it again. Removing a session
+// shifts the slots after it back instead of leaving a tombstone, and bumps a
+// count in the header before and after, so a lookup that missed while
+// entries were moving looks again. Cores push deadlines out (or bring them in
+// once a session's connections close) with a compare-and-swap on the slot.
+// The table doesn't know which sessions are due: whoever expires it keeps
+// that (a timer wheel in SessionTracker) and asks about those alone.
+//
+// A process dying with the lock held can leave a slot half written, which
+// lookups treat as a miss rather than waiting on. Whoever takes the lock over
+// (the lock word says which process has it and when that started, so a reused
+// pid isn't mistaken for it) tidies up, as does a new ingest process when it
+// opens the table; at worst a session is in the table twice until it expires.
+//
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::process;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use libc;
+use rand;
+
+use flow_tracker::Transport;
+use sessions::SessionKey;
+use shm::{mix, Words};
+
+// 16 MiB, for up to 229k sessions
+pub const DEFAULT_SLOTS: usize = 1 << 18;
+
+// Header words at the start of the table, then the slots
+const STATE: usize = 0;
+const SLOTS: usize = 1;
+const HASH_KEY: usize = 2;
+const LEN: usize = 3;
+const LOCK: usize = 4;
+const MOVES: usize = 5;
+const REJECTED: usize = 6;
+const HEADER_WORDS: usize = 8;
+
+// Words of each slot
+const SEQ: usize = 0;
+const KEY: usize = 1;      // KEY..KEY + 3
+const DEADLINE: usize = 4;
+const USED: usize = 5;
+const SLOT_WORDS: usize = 8;
+
+const STATE_UNINIT: u64 = 0;
+const STATE_INITIALIZING: u64 = 1;
+const STATE_READY: u64 = 2;
+
+// In the last key word, with the port in the low 16 bits. An empty slot's
+// last key word is 0.
+const PRESENT: u64 = 1 << 63;
+const V6: u64 = 1 << 17;
+const UDP: u64 = 1 << 16;
+
+// How long a reader waits on a slot being written before looking past it,
+// and how many times it looks again for a key it missed while a removal was
+// moving entries. A writer that died halfway leaves both looking busy until
+// the next one tidies up, so after that it's a miss.
+const SPINS: usize = 1 << 8;
+const RETRIES: usize = 4;
+
+type PackedKey = [u64; 3];
+
+fn pack(key: &SessionKey) -> PackedKey
+{
+    let flags = |port: u16, transport: Transport| {
+        PRESENT | port as u64 | if transport == Transport::Udp { UDP } else { 0 }
+    };
+    match *key {
+        SessionKey::V4 { client, phantom, port, transport } =>
+            [(u32::from(client) as u64) << 32 | u32::from(phantom) as u64, 0, flags(port, transport)],
+        SessionKey::V6 { phantom, port, transport } => {
+            let p = u128::from(phantom);
+            [(p >> 64) as u64, p as u64, V6 | flags(port, transport)]
+        },
+    }
+}
+
+fn unpack(k: &PackedKey) -> SessionKey
+{
+    let port = k[2] as u16;
+    let transport = if k[2] & UDP != 0 { Transport::Udp } else { Transport::Tcp };
+    if k[2] & V6 != 0 {
+        let phantom = Ipv6Addr::from((k[0] as u128) << 64 | k[1] as u128);
+        SessionKey::V6 { phantom, port, transport }
+    } else {
+        let (client, phantom) = (Ipv4Addr::from((k[0] >> 32) as u32), Ipv4Addr::from(k[0] as u32));
+        SessionKey::V4 { client, phantom, port, transport }
+    }
+}
+
+// Sessions and slots in use, and sessions turned away for want of room
+#[derive(Default, PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Occupancy
+{
+    pub sessions: usize,
+    pub slots: usize,
+    pub rejected: u64,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct TableFull;
+
+impl fmt::Display for TableFull
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "session table full")
+    }
+}
+
+pub struct SessionTable
+{
+    table: Words,
+    slots: usize,
+    hash_key: u64,
+}
+
+struct WriteLock<'a>
+{
+    word: &'a AtomicU64,
+}
+
+impl<'a> Drop for WriteLock<'a>
+{
+    fn drop(&mut self)
+    {
+        self.word.store(0, Ordering::SeqCst);
+    }
+}
+
+// When pid started, in clock ticks since boot (the 22nd field of its stat)
+fn start_ticks(pid: u32) -> Option<u64>
+{
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // After the command, which can have anything in it
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+// What goes in the lock word: our pid, and the low bits of when we started
+// (0 if we can't tell), so that whoever has the pid of a writer that died
+// doesn't look like it.
+fn lock_owner() -> u64
+{
+    let pid = process::id();
+    (pid as u64) << 32 | start_ticks(pid).unwrap_or(0) as u32 as u64
+}
+
+fn alive(owner: u64) -> bool
+{
+    let pid = (owner >> 32) as u32;
+    let ret = unsafe { libc::kill(pid as i32, 0) };
+    if ret != 0 && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH) {
+        return false;
+    }
+    match start_ticks(pid) {
+        Some(ticks) if owner as u32 != 0 => ticks as u32 == owner as u32,
+        _ => true,
+    }
+}
+
+impl SessionTable
+{
+    // A table private to this process, of at least slots slots.
+    pub fn new(slots: usize) -> SessionTable
+    {
+        let slots = slots.max(64).next_power_of_two();
+        // Nobody else can have set this one up with another size
+        SessionTable::init(Words::heap(HEADER_WORDS + slots * SLOT_WORDS), slots).unwrap()
+    }
+
+    // A table shared with every other process that opens the same path with
+    // the same number of slots. An existing table is reused (sessions survive
+    // a restart), but only if it has that many slots: other processes could
+    // still be using it, so it's an error rather than started over.
+    pub fn open_shared(path: &str, slots: usize) -> io::Result<SessionTable>
+    {
+        let slots = slots.max(64).next_power_of_two();
+        SessionTable::init(Words::map_file(path, HEADER_WORDS + slots * SLOT_WORDS)?, slots)
+    }
+
+    // As for the replay filter, the first process there picks the hash key.
+    fn init(table: Words, slots: usize) -> io::Result<SessionTable>
+    {
+        {
+            let w = table.get();
+            while w[STATE].load(Ordering::SeqCst) != STATE_READY {
+                let cur = w[STATE].load(Ordering::SeqCst);
+                match w[STATE].compare_exchange(cur, STATE_INITIALIZING,
+                                                Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(prev) if prev != STATE_INITIALIZING => {
+                        for x in w[SLOTS..].iter() {
+                            x.store(0, Ordering::SeqCst);
+                        }
+                        w[HASH_KEY].store(rand::random::<u64>(), Ordering::SeqCst);
+                        w[SLOTS].store(slots as u64, Ordering::SeqCst);
+                        w[STATE].store(STATE_READY, Ordering::SeqCst);
+                    },
+                    _ => {
+                        // Someone else is setting it up. If they died doing
+                        // it, give up waiting and start over.
+                        for _ in 0..1000 {
+                            if w[STATE].load(Ordering::SeqCst) == STATE_READY {
+                                break;
+                            }
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        if w[STATE].load(Ordering::SeqCst) != STATE_READY {
+                            w[STATE].store(STATE_UNINIT, Ordering::SeqCst);
+                        }
+                    },
+                }
+            }
+            let cur = w[SLOTS].load(Ordering::SeqCst);
+            if cur != slots as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("table has {} slots, not {}", cur, slots)));
+            }
+        }
+        let hash_key = table.get()[HASH_KEY].load(Ordering::SeqCst);
+        Ok(SessionTable { table, slots, hash_key })
+    }
+
+    fn words(&self) -> &[AtomicU64]
+    {
+        self.table.get()
+    }
+
+    fn slot(&self, i: usize) -> &[AtomicU64]
+    {
+        let start = HEADER_WORDS + i * SLOT_WORDS;
+        &self.words()[start..start + SLOT_WORDS]
+    }
+
+    fn home(&self, k: &PackedKey) -> usize
+    {
+        mix(k[0] ^ mix(k[1] ^ mix(k[2] ^ self.hash_key))) as usize & (self.slots - 1)
+    }
+
+    fn next(&self, i: usize) -> usize
+    {
+        (i + 1) & (self.slots - 1)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.words()[LEN].load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize
+    {
+        self.slots
+    }
+
+    pub fn occupancy(&self) -> Occupancy
+    {
+        Occupancy {
+            sessions: self.len(),
+            slots: self.slots,
+            rejected: self.words()[REJECTED].load(Ordering::Relaxed),
+        }
+    }
+
+    // A consistent read of slot i (sequence number, key, deadline), or None
+    // if it's being written and stays that way.
+    fn read_slot(&self, i: usize) -> Option<(u64, PackedKey, u64)>
+    {
+        let slot = self.slot(i);
+        for _ in 0..SPINS {
+            let seq = slot[SEQ].load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                let key = [slot[KEY].load(Ordering::Relaxed), slot[KEY + 1].load(Ordering::Relaxed),
+                           slot[KEY + 2].load(Ordering::Relaxed)];
+                let deadline = slot[DEADLINE].load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if slot[SEQ].load(Ordering::Relaxed) == seq {
+                    return Some((seq, key, deadline));
+                }
+            }
+            ::std::hint::spin_loop();
+        }
+        None
+    }
+
+    // Where k is, with the sequence number and deadline read there
+    fn find(&self, k: &PackedKey) -> Option<(usize, u64, u64)>
+    {
+        let moves = &self.words()[MOVES];
+        for _ in 0..RETRIES {
+            let before = moves.load(Ordering::SeqCst);
+            let mut i = self.home(k);
+            for _ in 0..self.slots {
+                match self.read_slot(i) {
+                    Some((seq, key, deadline)) if key == *k => return Some((i, seq, deadline)),
+                    Some((_, key, _)) if key[2] == 0 => break,
+                    _ => {},
+                }
+                i = self.next(i);
+            }
+            // Not there, unless a removal was moving entries while we looked
+            if before & 1 == 0 && moves.load(Ordering::SeqCst) == before {
+                return None;
+            }
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &SessionKey) -> bool
+    {
+        self.find(&pack(key)).is_some()
+    }
+
+    pub fn deadline(&self, key: &SessionKey) -> Option<u64>
+    {
+        self.find(&pack(key)).map(|(_, _, deadline)| deadline)
+    }
+
+    // Sets key's deadline to what f makes of the current one (leaving it if
+    // f gives None). Returns the deadline now, or None if key isn't there.
+    // Any process can do this without the lock.
+    pub fn update<F: Fn(u64) -> Option<u64>>(&self, key: &SessionKey, f: F) -> Option<u64>
+    {
+        let k = pack(key);
+        loop {
+            let (i, seq, deadline) = self.find(&k)?;
+            let new = match f(deadline) {
+                Some(new) => new,
+                None => return Some(deadline),
+            };
+            // If the slot was rewritten in the meantime our write may have
+            // been lost (or gone to whatever moved in), so go again.
+            let slot = self.slot(i);
+            if slot[DEADLINE].compare_exchange(deadline, new, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+                && slot[SEQ].load(Ordering::SeqCst) == seq {
+                return Some(new);
+            }
+        }
+    }
+
+    // Pushes key's deadline out (never in). Returns false if key isn't there.
+    pub fn extend(&self, key: &SessionKey, deadline: u64) -> bool
+    {
+        self.update(key, |cur| if cur < deadline { Some(deadline) } else { None }).is_some()
+    }
+
+    // Moves key's deadline, earlier or later. Returns false if key isn't
+    // there.
+    pub fn set_deadline(&self, key: &SessionKey, deadline: u64) -> bool
+    {
+        self.update(key, |_| Some(deadline)).is_some()
+    }
+
+    // Notes that something was forwarded for key's session
+    pub fn mark_used(&self, key: &SessionKey)
+    {
+        let k = pack(key);
+        while let Some((i, seq, _)) = self.find(&k) {
+            let slot = self.slot(i);
+            slot[USED].store(1, Ordering::SeqCst);
+            if slot[SEQ].load(Ordering::SeqCst) == seq {
+                return;
+            }
+        }
+    }
+
+    fn lock(&self) -> WriteLock<'_>
+    {
+        let word = &self.words()[LOCK];
+        let me = lock_owner();
+        let mut tries = 0;
+        loop {
+            let holder = match word.compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return WriteLock { word },
+                Err(holder) => holder,
+            };
+            tries += 1;
+            if tries < 1000 {
+                thread::yield_now();
+                continue;
+            }
+            tries = 0;
+            if !alive(holder)
+                && word.compare_exchange(holder, me, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                warn!("session table writer {} died holding the lock, taking over", holder >> 32);
+                self.recover();
+                return WriteLock { word };
+            }
+        }
+    }
+
+    // For the ingest process, when it opens the table: a writer before it may
+    // have died halfway through something (readers take whatever it left as
+    // a miss), so tidy up after it, taking the lock over if it held it.
+    pub fn recover_writer(&self)
+    {
+        let _lock = self.lock();
+        self.recover();
+    }
+
+    // After a writer died: finish whatever it left half done
+    fn recover(&self)
+    {
+        let w = self.words();
+        if w[MOVES].load(Ordering::SeqCst) & 1 != 0 {
+            w[MOVES].fetch_add(1, Ordering::SeqCst);
+        }
+        let mut len = 0;
+        for i in 0..self.slots {
+            if self.slot(i)[SEQ].load(Ordering::SeqCst) & 1 != 0 {
+                self.end(i);
+            }
+            if self.key_at(i)[2] != 0 {
+                len += 1;
+            }
+        }
+        w[LEN].store(len, Ordering::SeqCst);
+    }
+
+    // The rest need the lock
+
+    fn key_at(&self, i: usize) -> PackedKey
+    {
+        let slot = self.slot(i);
+        [slot[KEY].load(Ordering::Relaxed), slot[KEY + 1].load(Ordering::Relaxed),
+         slot[KEY + 2].load(Ordering::Relaxed)]
+    }
+
+    fn begin(&self, i: usize)
+    {
+        let seq = &self.slot(i)[SEQ];
+        seq.store(seq.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+    }
+
+    fn write(&self, i: usize, k: &PackedKey, deadline: u64, used: u64)
+    {
+        let slot = self.slot(i);
+        for j in 0..3 {
+            slot[KEY + j].store(k[j], Ordering::Relaxed);
+        }
+        slot[DEADLINE].store(deadline, Ordering::SeqCst);
+        slot[USED].store(used, Ordering::SeqCst);
+    }
+
+    fn end(&self, i: usize)
+    {
+        let seq = &self.slot(i)[SEQ];
+        seq.store(seq.load(Ordering::Relaxed) + 1, Ordering::Release);
+    }
+
+    // Adds key, or pushes its deadline out if it's already there (deadlines
+    // are never brought forward). Returns true if key wasn't there. Sessions
+    // past 7/8 of the slots are turned away, to keep probes short.
+    pub fn insert(&self, key: SessionKey, deadline: u64) -> Result<bool, TableFull>
+    {
+        let k = pack(&key);
+        let _lock = self.lock();
+        let mut i = self.home(&k);
+        loop {
+            let cur = self.key_at(i);
+            if cur == k {
+                self.slot(i)[DEADLINE].fetch_max(deadline, Ordering::SeqCst);
+                return Ok(false);
+            }
+            if cur[2] == 0 {
+                break;
+            }
+            i = self.next(i);
+        }
+        let w = self.words();
+        if w[LEN].load(Ordering::SeqCst) as usize >= self.slots - self.slots / 8 {
+            w[REJECTED].fetch_add(1, Ordering::SeqCst);
+            return Err(TableFull);
+        }
+        self.begin(i);
+        self.write(i, &k, deadline, 0);
+        self.end(i);
+        w[LEN].fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    pub fn remove(&self, key: &SessionKey) -> bool
+    {
+        let _lock = self.lock();
+        self.remove_locked(&pack(key), None).is_some()
+    }
+
+    // Removes key if its deadline is at or before now (a core may have pushed
+    // it out since whoever asked last looked). Returns whether anything was
+    // forwarded for it, or None if it wasn't removed.
+    pub fn expire_key(&self, key: &SessionKey, now: u64) -> Option<bool>
+    {
+        let _lock = self.lock();
+        self.remove_locked(&pack(key), Some(now))
+    }
+
+    // Calls f with every session and its deadline. This looks at every slot,
+    // so it's for picking up a table filled before we started, not for
+    // anything periodic.
+    pub fn for_each<F: FnMut(SessionKey, u64)>(&self, mut f: F)
+    {
+        for i in 0..self.slots {
+            if let Some((_, k, deadline)) = self.read_slot(i) {
+                if k[2] != 0 {
+                    f(unpack(&k), deadline);
+                }
+            }
+        }
+    }
+
+    // Removes k (only if its deadline is at or before due, if given), moving
+    // back the entries after it that would no longer be found. Returns
+    // whether anything was forwarded for it, or None if it wasn't removed.
+    fn remove_locked(&self, k: &PackedKey, due: Option<u64>) -> Option<bool>
+    {
+        let mut i = self.home(k);
+        loop {
+            let cur = self.key_at(i);
+            if cur == *k {
+                break;
+            }
+            if cur[2] == 0 {
+                return None;
+            }
+            i = self.next(i);
+        }
+
+        let moves = &self.words()[MOVES];
+        moves.fetch_add(1, Ordering::SeqCst);
+        self.begin(i);
+        let slot = self.slot(i);
+        // A core may have pushed it out since the caller looked
+        if due.is_some_and(|now| slot[DEADLINE].load(Ordering::SeqCst) > now) {
+            self.end(i);
+            moves.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+        let used = slot[USED].load(Ordering::SeqCst) != 0;
+
+        let mask = self.slots - 1;
+        let mut hole = i;
+        let mut j = i;
+        loop {
+            j = self.next(j);
+            let cur = self.key_at(j);
+            if cur[2] == 0 {
+                break;
+            }
+            // It can go back to the hole if the hole isn't before its home
+            let home = self.home(&cur);
+            if j.wrapping_sub(home) & mask >= j.wrapping_sub(hole) & mask {
+                self.begin(j);
+                let from = self.slot(j);
+                self.write(hole, &cur, from[DEADLINE].load(Ordering::SeqCst),
+                           from[USED].load(Ordering::SeqCst));
+                self.end(hole);
+                hole = j;
+            }
+        }
+        self.write(hole, &[0; 3], 0, 0);
+        self.end(hole);
+        moves.fetch_add(1, Ordering::SeqCst);
+        self.words()[LEN].fetch_sub(1, Ordering::SeqCst);
+        Some(used)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use session_table::*;
+    use std::env;
+    use std::fs;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    fn key(client: &str, phantom: &str, port: u16) -> SessionKey
+    {
+        let (client, phantom): (IpAddr, IpAddr) = (client.parse().unwrap(), phantom.parse().unwrap());
+        SessionKey::new(client, phantom, port, Transport::Tcp)
+    }
+
+    fn v4(i: u32) -> SessionKey
+    {
+        SessionKey::new(IpAddr::V4(Ipv4Addr::from(0xc0a80000 + i)), "10.10.0.1".parse().unwrap(),
+                        443, Transport::Tcp)
+    }
+
+    #[test]
+    fn test_keys_and_deadlines()
+    {
+        let t = SessionTable::new(100);
+        assert_eq!(t.capacity(), 128);
+        let a = key("192.168.0.1", "10.10.0.1", 443);
+        let b = key("2601::1", "2001::1234", 443);
+        let udp = SessionKey::new("192.168.0.1".parse().unwrap(), "10.10.0.1".parse().unwrap(),
+                                  443, Transport::Udp);
+        for k in &[a, b, udp, key("::1", "2001::1234", 8443)] {
+            assert_eq!(unpack(&pack(k)), *k);
+        }
+
+        assert_eq!(t.insert(a, 1000), Ok(true));
+        assert_eq!(t.insert(b, 1000), Ok(true));
+        // v6 sessions are the phantom's, whoever the client
+        assert!(t.contains_key(&key("2601::2", "2001::1234", 443)));
+        assert!(!t.contains_key(&key("192.168.0.2", "10.10.0.1", 443)));
+        assert!(!t.contains_key(&udp));
+
+        // Pushed out, but never brought forward
+        assert_eq!(t.insert(a, 2000), Ok(false));
+        assert_eq!(t.insert(a, 1500), Ok(false));
+        assert_eq!(t.deadline(&a), Some(2000));
+        assert!(t.extend(&a, 1800));
+        assert_eq!(t.deadline(&a), Some(2000));
+        assert!(t.set_deadline(&a, 1200));
+        assert_eq!(t.deadline(&a), Some(1200));
+        assert!(!t.set_deadline(&udp, 1200));
+        assert_eq!(t.update(&b, |d| if d > 5000 { Some(0) } else { None }), Some(1000));
+
+        let mut all = Vec::new();
+        t.for_each(|k, deadline| all.push((k, deadline)));
+        assert_eq!(all.len(), 2);
+        assert!(all.contains(&(a, 1200)) && all.contains(&(b, 1000)));
+
+        t.mark_used(&b);
+        assert_eq!(t.expire_key(&b, 999), None);
+        assert_eq!(t.expire_key(&a, 1000), None);
+        assert_eq!(t.expire_key(&b, 1000), Some(true));
+        assert_eq!(t.expire_key(&b, 1000), None);
+        assert!(t.remove(&a));
+        assert!(!t.remove(&a));
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn test_full_and_removals()
+    {
+        // Removals in a crowded table have to leave everything else findable
+        let t = SessionTable::new(64);
+        for i in 0..56 {
+            assert_eq!(t.insert(v4(i), 1000 + i as u64), Ok(true));
+        }
+        assert_eq!(t.insert(v4(56), 1000), Err(TableFull));
+        assert_eq!(t.occupancy(), Occupancy { sessions: 56, slots: 64, rejected: 1 });
+
+        for i in (0..56).filter(|i| i % 3 == 0) {
+            assert!(t.remove(&v4(i)));
+        }
+        assert_eq!((0..56).filter(|&i| t.expire_key(&v4(i), 1010).is_some()).count(), 7);
+        for i in 0..56 {
+            let live = i % 3 != 0 && i > 10;
+            assert_eq!(t.contains_key(&v4(i)), live, "{}", i);
+        }
+        assert_eq!(t.len(), 30);
+        assert_eq!(t.insert(v4(56), 1000), Ok(true));
+    }
+
+    #[test]
+    fn test_lookups_while_writing()
+    {
+        // Sessions that stay put are found throughout, while others come and
+        // go around them
+        let t = Arc::new(SessionTable::new(256));
+        for i in 0..100 {
+            t.insert(v4(i), u64::MAX).unwrap();
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (t, done) = (t.clone(), done.clone());
+            thread::spawn(move || {
+                let mut lookups = 0;
+                while !done.load(Ordering::SeqCst) {
+                    for i in 0..100 {
+                        assert!(t.contains_key(&v4(i)), "{}", i);
+                        assert!(t.set_deadline(&v4(i), u64::MAX));
+                    }
+                    lookups += 1;
+                }
+                lookups
+            })
+        };
+        for round in 0..200 {
+            for i in 100..200 {
+                t.insert(v4(i), round).unwrap();
+            }
+            for i in (100..200).rev() {
+                assert!(t.remove(&v4(i)));
+            }
+        }
+        done.store(true, Ordering::SeqCst);
+        assert!(reader.join().unwrap() > 0);
+        assert_eq!(t.len(), 100);
+    }
+
+    #[test]
+    fn test_writer_died()
+    {
+        let t = SessionTable::new(64);
+        for i in 0..8 {
+            t.insert(v4(i), 1000).unwrap();
+        }
+        // Halfway through removing one, and holding the lock, as someone
+        // with our pid who started at another time
+        let w = t.words();
+        w[MOVES].fetch_add(1, Ordering::SeqCst);
+        let i = t.find(&pack(&v4(3))).unwrap().0;
+        t.begin(i);
+        w[LOCK].store(lock_owner() ^ 1, Ordering::SeqCst);
+
+        // Lookups don't wait on it: it's a miss, and everything else is
+        // still found
+        assert!(!t.contains_key(&v4(3)));
+        assert!(!t.contains_key(&v4(100)));
+        for i in (0..8).filter(|&i| i != 3) {
+            assert!(t.contains_key(&v4(i)), "{}", i);
+        }
+
+        t.recover_writer();
+        assert_eq!(w[LOCK].load(Ordering::SeqCst), 0);
+        assert_eq!(w[MOVES].load(Ordering::SeqCst) & 1, 0);
+        for i in 0..8 {
+            assert!(t.contains_key(&v4(i)), "{}", i);
+        }
+        assert!(t.remove(&v4(3)));
+        assert_eq!(t.len(), 7);
+    }
+
+    #[test]
+    fn test_shared()
+    {
+        let path = env::temp_dir().join(format!("session_table_test_{}", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let a = SessionTable::open_shared(path, 1000).unwrap();
+        let b = SessionTable::open_shared(path, 1000).unwrap();
+        a.insert(v4(1), 1000).unwrap();
+        assert_eq!(b.deadline(&v4(1)), Some(1000));
+        assert!(b.extend(&v4(1), 2000));
+        assert_eq!(a.deadline(&v4(1)), Some(2000));
+        assert_eq!(b.occupancy(), Occupancy { sessions: 1, slots: 1024, rejected: 0 });
+        drop(a);
+        drop(b);
+
+        // Survives being reopened. Opening it with another size fails, and
+        // leaves it be for whoever's using it.
+        let c = SessionTable::open_shared(path, 1000).unwrap();
+        assert!(c.contains_key(&v4(1)));
+        assert!(SessionTable::open_shared(path, 4096).is_err());
+        assert!(SessionTable::open_shared(path, 64).is_err());
+        assert!(c.contains_key(&v4(1)));
+        assert_eq!(fs::metadata(path).unwrap().len(), ((8 + 1024 * 8) * 8) as u64);
+        drop(c);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -4,11 +4,13 @@
 // This file is used to implement session tacking for the detector. There are a
 // few specifics be to aware of if you are going to modify this file. 
 //
-// Current tracking is done as a Map of string to u64. The string is a
-// derived from IP addresses of flows so that lookups can be performed quickly
-// when we need to determine whether a flow is associated with a session. The
-// u64 value is the timeout for the session which are periodically cleaned up by
-// the FlowTracker that (currently) instantiates this.
+// Current tracking is done in a SessionTable (see session_table.rs) keyed by
+// SessionKey. The key is derived from IP addresses of flows so that lookups
+// can be performed quickly when we need to determine whether a flow is
+// associated with a session. The table holds the timeout for each session.
+// Normally it's shared by every core and filled by the session ingest
+// process, which also expires sessions as they time out; a tracker can also
+// have a table of its own and expire it itself (drop_stale_sessions).
 //
 // Notes:
 //  - The timeout for flows can be updated. This exists for two reasons. 
@@ -21,36 +23,64 @@
 //         registration is received that has a shorter timeout we still need to
 //         keep the longer timeout. 
 //
-// - The key strings that are matched against are currently different for ipv4
-//   and ipv6, in v4 the string is a concatenation of the source and the
-//   destination (client and phantom) addresses. In ipv6 it is only the phantom
-//   address as the chance of phantom collisions is far lower.
-//      * While not currently in use we could add the destination (phantom) port
-//        to the key strings if we need extra specificity. 
+// - The keys that are matched against (SessionKey) are different for ipv4
+//   and ipv6, in v4 the key is the source and the destination (client and
+//   phantom) addresses. In ipv6 it is only the phantom address as the chance
+//   of phantom collisions is far lower. Both include the phantom port, so a
+//   session only matches connections to the port the client was given (443
+//   unless the StationToDetector message says otherwise), and the transport,
+//   so sessions registered for UDP (e.g. QUIC) only match UDP datagrams and
+//   TCP sessions only match TCP. Keys are plain values hashed with FxHash, as
+//   they're built for every packet to a possible phantom.
+//
+// - TCP connections to a session's phantom are followed (ConnState) from the
+//   client's side, which is all the detector sees. While any of them is open
+//   every packet pushes the timeout out by TIMEOUT_PHANTOMS_NS as above; once
+//   they have all closed (RST, or the client ACKing after its FIN) the session
+//   only lingers for CLOSED_LINGER_NS, or until the registration's own
+//   timeout if that's later. UDP sessions have no connections to follow and
+//   always get the full extension.
+//
+// - With set_reporting on, the tracker also collects PhantomEvents for the
+//   application (see signalling.proto): a session's first SYN (or datagram),
+//   every connection that closes, and sessions that expire without ever
+//   seeing a packet. Whoever turned it on takes them with take_events.
 //
 // - The ingest thread is launched as a subroutine of the SessionTracker struct
-//   and pulls from redis. The messages received come in the form of
+//   (in the ingest process, or in each core without a shared table) and
+//   pulls from redis. The messages received come in the form of
 //   StationToDetector protobuf, which can be modified relatively independently.
 //   Currently there is a `from` function that parses this into SessionDetails
-//   which can be directly managed by the SessionTracker.
+//   which can be directly managed by the SessionTracker. If redis goes away
+//   the thread keeps trying to reconnect (with backoff), and each time it's
+//   back it reads in the sessions still active (see IngestConfig) to make up
+//   for any registrations it missed.
 //
 // The notes above are implemented and tested below. If you modify the code
 // please make sure the tests still pass. If you modify the way this code is
 // used please update the tests. 
 
-use std::collections::{HashMap};
+use std::collections::HashMap;
 use std::convert::From;
 use std::fmt;
-use std::net::{IpAddr};
-use std::sync::{RwLock, Arc};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
 
-use time::precise_time_ns;
+use log::LogLevel;
 use redis;
 
-use signalling::StationToDetector;
+use signalling::{IPProto, PhantomEvent, PhantomEventType, StationOperation, StationToDetector};
 use protobuf::Message;
-use flow_tracker::{FlowNoSrcPort,FLOW_CLIENT_LOG};
+use client_ip::{write_client, Client};
+use clock::{self, now_ns};
+use flow_tracker::{Flow,FlowNoSrcPort,Transport};
+use logging::Event;
+use session_table::{Occupancy, SessionTable};
+use timer_wheel::{TimerWheel, DEFAULT_TICK_NS};
+use util::FxBuildHasher;
 
 
 const S2NS: u64= 1000*1000*1000;
@@ -58,10 +88,17 @@ const S2NS: u64= 1000*1000*1000;
 // that need to be forwarded to the data plane proxying logic. (300 s = 5 mins)
 const TIMEOUT_PHANTOMS_NS: u64 = 300 * S2NS;
 
-// We _can_ filter by phantom port if we so choose, and randomize the port that
-// the clients connect to. However we are currently using exclusively port 443.
-// adding this here as a placeholder for now. 
-const DEFAULT_PHANTOM_PORT: u16 = 443;
+// How long a session outlives its last connection closing (unless it was
+// registered for longer)
+const CLOSED_LINGER_NS: u64 = 10 * S2NS;
+// Sessions whose timeout a core has pushed out are looked at again at least
+// this often, as the core can bring it back in once the connections close.
+const RECHECK_NS: u64 = S2NS;
+// Connections followed per session; more than this only keep the session open
+const MAX_CONNS_PER_SESSION: usize = 1024;
+
+// Port used for sessions whose registration doesn't specify one.
+pub const DEFAULT_PHANTOM_PORT: u16 = 443;
 
 
 // "errors" we want to catch
@@ -69,6 +106,7 @@ const DEFAULT_PHANTOM_PORT: u16 = 443;
 pub enum SessionError {
     InvalidPhantom,
     InvalidClient,
+    InvalidPort,
     MixedV4V6Error,
 }
 
@@ -84,6 +122,9 @@ impl fmt::Display for SessionError {
             SessionError::InvalidPhantom => {
                 write!(f, "Invalid phantom address")
             },
+            SessionError::InvalidPort => {
+                write!(f, "Invalid phantom port")
+            },
             SessionError::MixedV4V6Error => {
                 write!(f, "Client/Phantom v4/v6 mismatch")
             },
@@ -97,6 +138,7 @@ pub struct SessionDetails
     pub client_ip: IpAddr,
     pub phantom_ip: IpAddr,
     pub phantom_port: u16,
+    pub transport: Transport,
     timeout: u64,
 }
 
@@ -104,8 +146,9 @@ pub struct SessionDetails
 impl SessionDetails
 {
     // This function parses acceptable Session Details and returns an error if
-    // the details provided do not fit current requirements for parsing 
-    pub fn new(client_ip: &str, phantom_ip: &str, timeout: u64) -> SessionResult {
+    // the details provided do not fit current requirements for parsing. A
+    // phantom_port of 0 means DEFAULT_PHANTOM_PORT.
+    pub fn new(client_ip: &str, phantom_ip: &str, phantom_port: u16, timeout: u64) -> SessionResult {
         let phantom: IpAddr = match phantom_ip.parse() {
             Ok(ip) => ip,
             Err(_) => {return Err(SessionError::InvalidPhantom)},
@@ -129,16 +172,70 @@ impl SessionDetails
         let s = SessionDetails {
             client_ip: src,
             phantom_ip: phantom,
-            phantom_port: DEFAULT_PHANTOM_PORT,
+            phantom_port: match phantom_port {
+                0 => DEFAULT_PHANTOM_PORT,
+                p => p,
+            },
+            transport: Transport::Tcp,
             timeout: timeout,
         };
         Ok(s)
     }
 
-    pub fn get_key(&self) -> String {
-        match self.phantom_ip.is_ipv6() {
-            true => format!("{}", self.phantom_ip),
-            false => format!("{}-{}", self.client_ip, self.phantom_ip)
+    pub fn get_key(&self) -> SessionKey {
+        SessionKey::new(self.client_ip, self.phantom_ip, self.phantom_port, self.transport)
+    }
+}
+
+// What a session is looked up by; see the notes at the top.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum SessionKey {
+    V4 { client: Ipv4Addr, phantom: Ipv4Addr, port: u16, transport: Transport },
+    V6 { phantom: Ipv6Addr, port: u16, transport: Transport },
+}
+
+impl SessionKey {
+    pub fn new(client_ip: IpAddr, phantom_ip: IpAddr, phantom_port: u16,
+               transport: Transport) -> SessionKey {
+        match (client_ip, phantom_ip) {
+            (_, IpAddr::V6(phantom)) => SessionKey::V6 { phantom, port: phantom_port, transport },
+            (IpAddr::V4(client), IpAddr::V4(phantom)) =>
+                SessionKey::V4 { client, phantom, port: phantom_port, transport },
+            // SessionDetails::new() refuses these, and a flow can't be one
+            (IpAddr::V6(_), IpAddr::V4(phantom)) =>
+                SessionKey::V4 { client: Ipv4Addr::UNSPECIFIED, phantom, port: phantom_port, transport },
+        }
+    }
+
+    pub fn from_flow(flow: &FlowNoSrcPort) -> SessionKey {
+        SessionKey::new(flow.src_ip, flow.dst_ip, flow.dst_port, flow.transport)
+    }
+
+    // Client (v4 only), phantom, phantom port and transport
+    fn parts(&self) -> (Option<IpAddr>, IpAddr, u16, Transport) {
+        match *self {
+            SessionKey::V4 { client, phantom, port, transport } =>
+                (Some(IpAddr::V4(client)), IpAddr::V4(phantom), port, transport),
+            SessionKey::V6 { phantom, port, transport } =>
+                (None, IpAddr::V6(phantom), port, transport),
+        }
+    }
+}
+
+// v4 "{client}-{phantom}:{port}", v6 "[{phantom}]:{port}", plus "/udp" for
+// UDP, as the keys used to be strings.
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let transport = match *self {
+            SessionKey::V4 { transport, .. } | SessionKey::V6 { transport, .. } => transport,
+        };
+        match *self {
+            SessionKey::V4 { client, phantom, port, .. } => write!(f, "{}-{}:{}", client, phantom, port)?,
+            SessionKey::V6 { phantom, port, .. } => write!(f, "{}", SocketAddr::new(IpAddr::V6(phantom), port))?,
+        }
+        match transport {
+            Transport::Tcp => Ok(()),
+            Transport::Udp => write!(f, "/udp"),
         }
     }
 }
@@ -147,258 +244,887 @@ impl From<&StationToDetector> for SessionResult {
     fn from(s2d: &StationToDetector) -> Self {
         let source = s2d.get_client_ip();
         let phantom = s2d.get_phantom_ip();
-        return SessionDetails::new(source, phantom,  s2d.get_timeout_ns())
+        if s2d.get_dst_port() > u16::max_value() as u32 {
+            return Err(SessionError::InvalidPort)
+        }
+        let mut sd = SessionDetails::new(source, phantom, s2d.get_dst_port() as u16, s2d.get_timeout_ns())?;
+        sd.transport = match s2d.get_proto() {
+            IPProto::Udp => Transport::Udp,
+            IPProto::Tcp | IPProto::Unk => Transport::Tcp,
+        };
+        Ok(sd)
+    }
+}
+
+// What a StationToDetector message asks for
+pub enum SessionOp {
+    Add(SessionDetails),
+    Revoke(SessionDetails),
+    ExtendTo(SessionDetails),
+    RevokeClient(IpAddr),
+}
+
+impl SessionOp {
+    pub fn from_s2d(s2d: &StationToDetector) -> Result<SessionOp, SessionError> {
+        Ok(match s2d.get_operation() {
+            StationOperation::Add => SessionOp::Add(SessionResult::from(s2d)?),
+            StationOperation::Revoke => SessionOp::Revoke(SessionResult::from(s2d)?),
+            StationOperation::ExtendTo => SessionOp::ExtendTo(SessionResult::from(s2d)?),
+            StationOperation::RevokeClient => match s2d.get_client_ip().parse() {
+                Ok(ip) => SessionOp::RevokeClient(ip),
+                Err(_) => return Err(SessionError::InvalidClient),
+            },
+        })
     }
 }
 
 // TODO - make accessible
 impl fmt::Display for SessionDetails {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unsafe {
-            match FLOW_CLIENT_LOG {
-                true => write!(f, "{} -> {} ({}ns)", self.client_ip.to_string(), self.phantom_ip.to_string(), self.timeout),
-                false => write!(f, "_ -> {} ({}ns)", self.phantom_ip.to_string(), self.timeout),
-            }
+        let phantom = SocketAddr::new(self.phantom_ip, self.phantom_port);
+        let transport = match self.transport {
+            Transport::Tcp => "",
+            Transport::Udp => "/udp",
+        };
+        write_client(f, self.client_ip, None)?;
+        write!(f, " -> {}{} ({}ns)", phantom, transport, self.timeout)
+    }
+}
+
+// What a packet from the client does to its connection
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ConnEvent {
+    Syn,
+    Fin,
+    Rst,
+    // Anything else (ACKs, data)
+    Ack,
+}
+
+// Where a connection to a phantom is, as far as the client's packets tell.
+// Closed is a reset, or an ACK after the client's FIN (the only thing left to
+// ACK is the phantom's FIN).
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ConnState {
+    SynSeen,
+    Established,
+    HalfClosed,
+    Closed,
+}
+
+impl ConnState {
+    fn next(state: Option<ConnState>, event: ConnEvent) -> ConnState {
+        match (state, event) {
+            (_, ConnEvent::Rst) => ConnState::Closed,
+            // A new connection, or the client reusing the ports
+            (_, ConnEvent::Syn) => ConnState::SynSeen,
+            (Some(ConnState::Closed), _) => ConnState::Closed,
+            (_, ConnEvent::Fin) => ConnState::HalfClosed,
+            (Some(ConnState::HalfClosed), ConnEvent::Ack) => ConnState::Closed,
+            (_, ConnEvent::Ack) => ConnState::Established,
+        }
+    }
+}
+
+// Connections in each state, for a session or all of them
+#[derive(Default, PartialEq, Eq, Copy, Clone, Debug)]
+pub struct ConnCounts {
+    pub syn_seen: usize,
+    pub established: usize,
+    pub half_closed: usize,
+    pub closed: usize,
+}
+
+impl ConnCounts {
+    pub fn open(&self) -> usize {
+        self.syn_seen + self.established + self.half_closed
+    }
+
+    fn count(&mut self, state: ConnState) -> &mut usize {
+        match state {
+            ConnState::SynSeen => &mut self.syn_seen,
+            ConnState::Established => &mut self.established,
+            ConnState::HalfClosed => &mut self.half_closed,
+            ConnState::Closed => &mut self.closed,
+        }
+    }
+
+    fn remove(&mut self, other: &ConnCounts) {
+        self.syn_seen -= other.syn_seen;
+        self.established -= other.established;
+        self.half_closed -= other.half_closed;
+        self.closed -= other.closed;
+    }
+}
+
+pub struct ConnUpdate {
+    // The session's connections after this packet
+    pub counts: ConnCounts,
+    // This packet closed the session's last open connection
+    pub closed_session: bool,
+}
+
+struct Conn {
+    state: ConnState,
+    // Unix time of its first packet
+    start_ns: u64,
+    packets: u64,
+    bytes: u64,
+}
+
+impl Conn {
+    fn new() -> Conn {
+        Conn { state: ConnState::SynSeen, start_ns: unix_ns(), packets: 0, bytes: 0 }
+    }
+}
+
+// Connections of one session, keyed by client address and port (v6 sessions
+// can have several clients). UDP sessions only use the counters.
+struct SessionConns {
+    conns: HashMap<(IpAddr, u16), Conn, FxBuildHasher>,
+    counts: ConnCounts,
+    // The deadline the session had before its connections started pushing it
+    // out: the registration's, or a later re-registration's.
+    registered_until: u64,
+    // The last deadline set from here
+    extended_to: u64,
+    // Unix time of the first SYN (or datagram), and what's been forwarded
+    first_syn_ns: Option<u64>,
+    packets: u64,
+    bytes: u64,
+    // Connections followed, including closed ones since forgotten
+    opened: u32,
+}
+
+impl SessionConns {
+    fn new(deadline: u64) -> SessionConns {
+        SessionConns {
+            conns: HashMap::default(),
+            counts: ConnCounts::default(),
+            registered_until: deadline,
+            extended_to: deadline,
+            first_syn_ns: None,
+            packets: 0,
+            bytes: 0,
+            opened: 0,
+        }
+    }
+
+    fn event(&self, event: PhantomEventType, key: &SessionKey, client: IpAddr) -> PhantomEvent {
+        let mut ev = phantom_event(event, key, Some(client));
+        if let Some(t) = self.first_syn_ns {
+            ev.set_first_syn_ns(t);
         }
+        ev.set_packets(self.packets);
+        ev.set_bytes(self.bytes);
+        ev.set_connections(self.opened);
+        ev
     }
 }
 
+fn unix_ns() -> u64 {
+    match clock::system_time().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() * S2NS + d.subsec_nanos() as u64,
+        Err(_) => 0,
+    }
+}
+
+// client overrides the key's (which v6 keys don't have)
+fn phantom_event(event: PhantomEventType, key: &SessionKey, client: Option<IpAddr>) -> PhantomEvent {
+    let (key_client, phantom, port, transport) = key.parts();
+    let mut ev = PhantomEvent::new();
+    ev.set_event(event);
+    ev.set_phantom_ip(phantom.to_string());
+    if let Some(client) = client.or(key_client) {
+        ev.set_client_ip(client.to_string());
+    }
+    ev.set_dst_port(port as u32);
+    ev.set_proto(match transport {
+        Transport::Tcp => IPProto::Tcp,
+        Transport::Udp => IPProto::Udp,
+    });
+    ev.set_time_ns(unix_ns());
+    ev
+}
+
 pub struct SessionTracker
 {
     // Sessions cannot be tracked by registration because we will not be
     // receiving registration information in order to identify the sessions. As
     // such sessions are stored as a thread safe map with keys dependent on the
-    // ip version:
-    // v4 "{}-{}", client_ip, phantom_ip
-    // v6 "{}", phantom_ip
+    // ip version (SessionKey):
+    // v4 client_ip, phantom_ip, phantom_port, transport
+    // v6 phantom_ip, phantom_port, transport
     //
-    // The value stored for each of these is a timestamp to compare for timeout.
-    // Note: In the future phantom port can be optionally added to the key
-    // string to further filter incoming connections. This is left off for now
-    // to allow for testing of source-refraction. 
-    pub tracked_sessions: Arc<RwLock<HashMap<String, u64>>>,
+    // The deadline stored for each of these is the session's timeout.
+    pub tracked_sessions: Arc<SessionTable>,
+    // When each session times out, if drop_stale_sessions takes sessions
+    // that have timed out out of the table (only one tracker per table
+    // should). Shared with this tracker's SessionIngests, which keep it up to
+    // date; cores pushing timeouts out don't, so a session is looked up again
+    // when it comes due here.
+    deadlines: Option<Arc<Mutex<Deadlines>>>,
+
+    // TCP connections to each session's phantom, as seen by this core. Only
+    // the packet thread touches these, so they live outside the table.
+    connections: HashMap<SessionKey, SessionConns, FxBuildHasher>,
+    conn_totals: ConnCounts,
+
+    // PhantomEvents waiting to be taken, if reporting
+    reporting: bool,
+    events: Vec<PhantomEvent>,
 }
 
+// Slots in a table private to one tracker
+pub const PRIVATE_TABLE_SLOTS: usize = 1 << 16;
+
+type Deadlines = TimerWheel<SessionKey, FxBuildHasher>;
+
 impl<'a> SessionTracker 
 {
+    // A tracker with a table of its own, which it expires
     pub fn new() -> SessionTracker {
+        SessionTracker::with_table(Arc::new(SessionTable::new(PRIVATE_TABLE_SLOTS)), true)
+    }
+
+    pub fn with_table(table: Arc<SessionTable>, expire: bool) -> SessionTracker {
+        let deadlines = if expire {
+            let mut deadlines = TimerWheel::with_hasher(DEFAULT_TICK_NS, now_ns(), FxBuildHasher::default());
+            // Left by the ingest process we're taking over from
+            table.for_each(|key, deadline| { deadlines.insert(key, deadline); });
+            Some(Arc::new(Mutex::new(deadlines)))
+        } else {
+            None
+        };
         SessionTracker{
-            tracked_sessions: Arc::new(RwLock::new(HashMap::new())),
+            tracked_sessions: table,
+            deadlines: deadlines,
+            connections: HashMap::default(),
+            conn_totals: ConnCounts::default(),
+            reporting: false,
+            events: Vec::new(),
         }
     }
 
+    // Collect PhantomEvents (for take_events) from now on
+    pub fn set_reporting(&mut self, on: bool) {
+        self.reporting = on;
+    }
+
+    pub fn take_events(&mut self) -> Vec<PhantomEvent> {
+        mem::take(&mut self.events)
+    }
+
     pub fn add_session(&mut self, det: SessionDetails) {
         self.insert_session(det)
     }
 
-    pub fn spawn_update_thread(&self) {
-        let write_map = Arc::clone(&self.tracked_sessions);
-        thread::spawn(move || { ingest_from_pubsub(write_map) });
+    // Drops the session now. Returns false if there was no such session.
+    pub fn revoke_session(&mut self, session: &SessionDetails) -> bool {
+        let key = session.get_key();
+        if !self.tracked_sessions.remove(&key) {
+            return false
+        }
+        if let Some(ref deadlines) = self.deadlines {
+            deadlines.lock().unwrap().remove(&key);
+        }
+        if let Some(conns) = self.connections.remove(&key) {
+            self.conn_totals.remove(&conns.counts);
+        }
+        true
+    }
+
+    // A handle for changing the sessions from another thread
+    pub fn ingest(&self) -> SessionIngest {
+        SessionIngest::new(Arc::clone(&self.tracked_sessions), self.deadlines.clone())
+    }
+
+    pub fn spawn_update_thread(&self, conf: IngestConfig) -> thread::JoinHandle<()> {
+        let ingest = self.ingest();
+        thread::spawn(move || { ingest_from_pubsub(ingest, conf) })
     }
 
     pub fn is_tracked_session(&self, flow: &FlowNoSrcPort) -> bool {
-        let key = match flow.dst_ip.is_ipv6() {
-            true => format!("{}", flow.dst_ip),
-            false => format!("{}-{}", flow.src_ip, flow.dst_ip)
-        };
+        let key = SessionKey::from_flow(flow);
         self.session_exists(&key)
     }
 
     pub fn len(&self) -> usize {
-        let map = self.tracked_sessions.read().expect("RwLock Broken");
-        let res = map.len();
-        drop(map);
-        return res
+        self.tracked_sessions.len()
     }
 
-    pub fn drop_stale_sessions(&mut self) -> usize {
-        let right_now = precise_time_ns();
+    pub fn occupancy(&self) -> Occupancy {
+        self.tracked_sessions.occupancy()
+    }
 
-        let mut map = self.tracked_sessions.write().expect("RwLock Broken");
-        let num_sessions_before = map.len();
-        // Dark Decoys Map is not sorted by timeout, so need to check all
-        map.retain(|_, v| ( *v > right_now));
-        let num_sessions_after = map.len();
-        if num_sessions_before != num_sessions_after {
-            debug!("Dark Decoys drops: {} - > {}", num_sessions_before, num_sessions_after);
+    // Expires the sessions that have timed out, if this tracker does, and
+    // forgets this core's connections to sessions that have gone (timed out
+    // or revoked, whoever took them out). Returns how many sessions expired.
+    pub fn drop_stale_sessions(&mut self) -> usize {
+        let right_now = now_ns();
+        let table = &self.tracked_sessions;
+
+        let mut dropped = 0;
+        if let Some(ref deadlines) = self.deadlines {
+            let num_sessions_before = table.len();
+            let mut due = Vec::new();
+            deadlines.lock().unwrap().expire(right_now, |key| due.push(key));
+            let mut pushed_out = Vec::new();
+            for key in due {
+                match table.expire_key(&key, right_now) {
+                    Some(used) => {
+                        dropped += 1;
+                        if let Some(conns) = self.connections.remove(&key) {
+                            self.conn_totals.remove(&conns.counts);
+                        }
+                        // Nothing ever came for it, on any core
+                        if !used && self.reporting {
+                            self.events.push(phantom_event(PhantomEventType::ExpiredUnused, &key, None));
+                        }
+                    },
+                    None => if let Some(deadline) = table.deadline(&key) {
+                        pushed_out.push((key, deadline.min(right_now + RECHECK_NS)));
+                    },
+                }
+            }
+            if !pushed_out.is_empty() {
+                let mut deadlines = deadlines.lock().unwrap();
+                for (key, deadline) in pushed_out {
+                    deadlines.insert(key, deadline);
+                }
+            }
+            if dropped != 0 {
+                debug!("Dark Decoys drops: {} - > {}", num_sessions_before, table.len());
+            }
         }
-        num_sessions_before - num_sessions_after
+
+        let totals = &mut self.conn_totals;
+        self.connections.retain(|key, conns| {
+            let live = table.contains_key(key);
+            if !live {
+                totals.remove(&conns.counts);
+            }
+            live
+        });
+        dropped
     }
 
     /// Used to update (increase) the time that we  consider a session 
     /// valid for tracking purposes. Called when packets from a session are
     /// seen so that forwarding continues past the original registration timeout.
-    pub fn update_session(&mut self, flow: &FlowNoSrcPort) {
+    /// len is the size of the IP packet forwarded.
+    pub fn update_session(&mut self, flow: &FlowNoSrcPort, len: usize) {
 
-        let key = match flow.dst_ip.is_ipv6() {
-            true => format!("{}", flow.dst_ip),
-            false => format!("{}-{}", flow.src_ip, flow.dst_ip)
-        };
+        let key = SessionKey::from_flow(flow);
 
         if !self.session_exists(&key) {
             return
         }
 
+        if !self.connections.contains_key(&key) {
+            self.tracked_sessions.mark_used(&key);
+        }
+        let session = self.connections.entry(key).or_insert_with(|| SessionConns::new(0));
+        session.packets += 1;
+        session.bytes += len as u64;
+        if session.first_syn_ns.is_none() {
+            session.first_syn_ns = Some(unix_ns());
+            if self.reporting {
+                self.events.push(session.event(PhantomEventType::FirstSyn, &key, flow.src_ip));
+            }
+        }
+
         self.try_update_session_timeout(key, TIMEOUT_PHANTOMS_NS);
     }
 
-   
-    
-    fn try_update_session_timeout(&mut self, key: String, extra_time: u64) {
-        // Get writable map
-        let mut mmap = self.tracked_sessions.write().expect("RwLock broken");
+    /// Notes a TCP packet from the client on one of a session's connections,
+    /// and pushes the session's timeout out while any connection is open, or
+    /// brings it in to a short linger once they're all closed. len is the
+    /// size of the IP packet forwarded. None if there's no such session.
+    pub fn update_connection(&mut self, flow: &Flow, event: ConnEvent, len: usize) -> Option<ConnUpdate> {
+        let key = SessionKey::from_flow(&FlowNoSrcPort::from_flow(flow, Transport::Tcp));
+        let now = now_ns();
+        let deadline = self.tracked_sessions.deadline(&key)?;
+
+        if !self.connections.contains_key(&key) {
+            self.tracked_sessions.mark_used(&key);
+        }
+        let session = self.connections.entry(key).or_insert_with(|| SessionConns::new(deadline));
+        // Registered again since we last set it
+        if deadline > session.extended_to {
+            session.registered_until = deadline;
+        }
+        session.packets += 1;
+        session.bytes += len as u64;
+        let first_syn = event == ConnEvent::Syn && session.first_syn_ns.is_none();
+        if first_syn {
+            session.first_syn_ns = Some(unix_ns());
+        }
 
-        // Set timeout
-        let expire_time = precise_time_ns() + extra_time;
-
-        // compare and keep the longer
-        match mmap.get_mut(&key){
-            Some(v)=> {
-                // compare and keep the longer
-                if *v < expire_time {
-                    *v = expire_time;
-                }
-            },
-            None => {},
-        };
+        let conn_key = (flow.src_ip, flow.src_port);
+        if !session.conns.contains_key(&conn_key) && session.conns.len() >= MAX_CONNS_PER_SESSION {
+            session.conns.retain(|_, conn| conn.state != ConnState::Closed);
+            self.conn_totals.closed -= session.counts.closed;
+            session.counts.closed = 0;
+        }
+        // Past the limit connections aren't followed, but they still keep
+        // the session open.
+        let mut open = true;
+        let mut closed_session = false;
+        if session.conns.len() < MAX_CONNS_PER_SESSION || session.conns.contains_key(&conn_key) {
+            let old = session.conns.get(&conn_key).map(|conn| conn.state);
+            let new = ConnState::next(old, event);
+            if let Some(old) = old {
+                *session.counts.count(old) -= 1;
+                *self.conn_totals.count(old) -= 1;
+            }
+            *session.counts.count(new) += 1;
+            *self.conn_totals.count(new) += 1;
+            // A SYN on the ports of a closed connection starts a new one
+            let reopened = old == Some(ConnState::Closed) && new != ConnState::Closed;
+            if old.is_none() || reopened {
+                session.opened += 1;
+                session.conns.insert(conn_key, Conn::new());
+            }
+            let (start_ns, packets, bytes) = {
+                let conn = session.conns.get_mut(&conn_key).expect("just inserted");
+                conn.state = new;
+                conn.packets += 1;
+                conn.bytes += len as u64;
+                (conn.start_ns, conn.packets, conn.bytes)
+            };
+            open = session.counts.open() > 0;
+            closed_session = !open && old != Some(ConnState::Closed);
+
+            if self.reporting && new == ConnState::Closed && old != Some(ConnState::Closed) {
+                let mut ev = session.event(PhantomEventType::ConnectionClosed, &key, flow.src_ip);
+                ev.set_src_port(flow.src_port as u32);
+                ev.set_conn_start_ns(start_ns);
+                ev.set_packets(packets);
+                ev.set_bytes(bytes);
+                self.events.push(ev);
+            }
+        }
+        if self.reporting && first_syn {
+            self.events.push(session.event(PhantomEventType::FirstSyn, &key, flow.src_ip));
+        }
+
+        let extra = if open { TIMEOUT_PHANTOMS_NS } else { CLOSED_LINGER_NS };
+        let new_deadline = session.registered_until.max(now + extra);
+        // Unless it's been pushed out past both since we looked (a
+        // registration, or another core's packets)
+        let limit = session.extended_to.max(new_deadline);
+        session.extended_to = self.tracked_sessions.update(&key, |d| {
+            if d > limit { None } else { Some(new_deadline) }
+        }).unwrap_or(new_deadline);
+        Some(ConnUpdate { counts: session.counts, closed_session })
     }
 
-    fn insert_session(&mut self, session: SessionDetails) {
-        // is this already in the map? 
-        let key = session.get_key();
-        if self.session_exists(&key) {
-            self.try_update_session_timeout(key, session.timeout);
-            return
+    // Connections to the phantom of the session flow belongs to
+    pub fn connection_counts(&self, flow: &FlowNoSrcPort) -> ConnCounts {
+        match self.connections.get(&SessionKey::from_flow(flow)) {
+            Some(session) => session.counts,
+            None => ConnCounts::default(),
         }
+    }
 
-        // Get writable map
-        let mut mmap = self.tracked_sessions.write().expect("RwLock broken");
+    // Connections across all sessions
+    pub fn total_connection_counts(&self) -> ConnCounts {
+        self.conn_totals
+    }
 
+    fn try_update_session_timeout(&mut self, key: SessionKey, extra_time: u64) {
         // Set timeout
-        let expire_time = precise_time_ns() + session.timeout;
-
-        // Insert
-        *mmap.entry(key).or_insert(expire_time) = expire_time;
-
-        // Get rid of writable reference to map.
-        drop(mmap);
+        let expire_time = now_ns() + extra_time;
 
-        debug!("Added registered ip {} from redis", session);
+        // The table keeps the longer of the two
+        self.tracked_sessions.extend(&key, expire_time);
     }
 
-    // explicitly used for testing
-    fn _delete_session(&mut self, session: SessionDetails) {
-        let key = &session.get_key();
-        if ! self.session_exists(key) {
-            return
+    fn insert_session(&mut self, session: SessionDetails) {
+        // Set timeout
+        let expire_time = now_ns() + session.timeout;
+
+        // Insert, or keep the longer timeout if it's already there
+        let key = session.get_key();
+        match self.tracked_sessions.insert(key, expire_time) {
+            Ok(true) => Event::new(LogLevel::Debug, "phantom_session").str("session", &session).emit(),
+            Ok(false) => {},
+            Err(e) => {
+                warn!("{}, dropping session {}", e, session);
+                return
+            },
+        }
+        if let Some(ref deadlines) = self.deadlines {
+            deadlines.lock().unwrap().insert(key, expire_time);
         }
-        let mut mmap = self.tracked_sessions.write().expect("RwLock broken");
-        mmap.remove(key);
-        // mmap.retain(|_, v| ( v.client_ip != session.client_ip || v.phantom_ip != session.phantom_ip));
     }
 
     // lookup session by identifier
-    fn session_exists(&self, id: &String) -> bool
+    fn session_exists(&self, id: &SessionKey) -> bool
     { 
-        let rmap = self.tracked_sessions.read().expect("RwLock broken");
-        let res = rmap.contains_key(id);
-        drop(rmap);
-        return res
+        self.tracked_sessions.contains_key(id)
      }
 
 
 }
 
+// Where the ingest thread gets sessions from. New registrations are
+// published on channel as they come in; active holds the ones that are still
+// live (StationToDetector messages scored with their expiry, Unix ms), which
+// is read back every time we (re)connect so nothing published while we
+// weren't subscribed is lost. The application only ever adds to active, so
+// we keep the other operations we're sent in ops (see record_op) for the
+// backfill to replay.
+#[derive(Clone, Debug)]
+pub struct IngestConfig {
+    pub url: String,
+    pub channel: String,
+    pub active: String,
+    pub ops: String,
+}
+
+pub const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1/";
+pub const DEFAULT_SESSION_CHANNEL: &str = "dark_decoy_map";
+pub const DEFAULT_ACTIVE_SESSIONS: &str = "dark_decoy_sessions";
+pub const DEFAULT_SESSION_OPS: &str = "dark_decoy_session_ops";
+
+impl Default for IngestConfig {
+    fn default() -> IngestConfig {
+        IngestConfig {
+            url: DEFAULT_REDIS_URL.to_string(),
+            channel: DEFAULT_SESSION_CHANNEL.to_string(),
+            active: DEFAULT_ACTIVE_SESSIONS.to_string(),
+            ops: DEFAULT_SESSION_OPS.to_string(),
+        }
+    }
+}
+
+// Wait between attempts to reach redis, doubling up to the max
+const RECONNECT_MIN_MS: u64 = 100;
+const RECONNECT_MAX_MS: u64 = 10 * 1000;
+// How long the channel can be quiet before we check redis is still there,
+// and how long it gets to answer
+const IDLE_CHECK_MS: u64 = 5 * 1000;
+const PING_TIMEOUT_MS: u64 = 1000;
+
 // No returns in this function so that it runs for the lifetime of the process.
-fn ingest_from_pubsub(map: Arc<RwLock<HashMap<String, u64>>>) {
-    let mut con = get_redis_conn();
+fn ingest_from_pubsub(mut ingest: SessionIngest, conf: IngestConfig) {
+    let mut backoff = RECONNECT_MIN_MS;
+    loop {
+        let mut subscribed = false;
+        if let Err(e) = ingest_until_error(&mut ingest, &conf, &mut subscribed) {
+            if subscribed {
+                backoff = RECONNECT_MIN_MS;
+                warn!("lost redis session channel ({}), reconnecting", e);
+            } else {
+                warn!("can't get sessions from redis at {} ({}), retrying in {}ms",
+                      conf.url, e, backoff);
+            }
+        }
+        thread::sleep(Duration::from_millis(backoff));
+        backoff = (backoff * 2).min(RECONNECT_MAX_MS);
+    }
+}
+
+// Subscribes, reads in the active sessions, then adds sessions as they're
+// published until something goes wrong with the connection (or redis stops
+// answering a PING after the channel's been quiet a while). subscribed is set
+// once the first two have worked.
+fn ingest_until_error(ingest: &mut SessionIngest, conf: &IngestConfig, subscribed: &mut bool)
+    -> redis::RedisResult<()> {
+    let client = redis::Client::open(conf.url.as_str())?;
+    let mut con = client.get_connection()?;
+    // Without a timeout, a redis that went away without a RST would leave us
+    // waiting for the next message forever.
+    con.set_read_timeout(Some(Duration::from_millis(IDLE_CHECK_MS)))?;
     let mut pubsub = con.as_pubsub();
-    pubsub.subscribe("dark_decoy_map").expect("Can't subscribe to Redis");
+    // Subscribe first, so whatever is published while we backfill waits for
+    // us on this connection.
+    pubsub.subscribe(conf.channel.as_str())?;
+
+    let query = client.get_connection()?;
+    query.set_read_timeout(Some(Duration::from_millis(PING_TIMEOUT_MS)))?;
+    query.set_write_timeout(Some(Duration::from_millis(PING_TIMEOUT_MS)))?;
+    let live = |set: &str| -> redis::RedisResult<Vec<(Vec<u8>, f64)>> {
+        redis::cmd("ZRANGEBYSCORE").arg(set)
+            .arg(format!("({}", unix_ns() / 1000 / 1000)).arg("+inf").arg("WITHSCORES")
+            .query(&query)
+    };
+    let active = live(conf.active.as_str())?;
+    let ops = live(conf.ops.as_str())?;
+    let added = ingest.backfill(&active, &ops, unix_ns());
+    debug!("subscribed to {}, {} of {} active sessions added, {} operations replayed",
+           conf.channel, added, active.len(), ops.len());
+    *subscribed = true;
 
     loop {
-        let msg = match pubsub.get_message(){
-            Ok(m) => m,
-            Err(e) => {
-                debug!("Error reading message from redis: {}", e);
+        let msg = match pubsub.get_message() {
+            Ok(msg) => msg,
+            // Quiet for a while. Carry on if that's only because nothing is
+            // being published.
+            Err(ref e) if e.is_timeout() => {
+                redis::cmd("PING").query::<String>(&query)?;
                 continue
-            }
+            },
+            Err(e) => return Err(e),
         };
-        let payload : Vec<u8> = match msg.get_payload(){
+        let payload: Vec<u8> = match msg.get_payload() {
             Ok(m) => m,
             Err(e) => {
                 debug!("Error reading payload: {}", e);
                 continue
             }
         };
-        let station_to_det: StationToDetector = match Message::parse_from_bytes::<>(&payload) {
-            Ok(s2d) => s2d,
-            Err(e) => {
-                debug!("failed to parse StationToDetector message {}", e);
-                continue
-            },
+        let issued_ms = unix_ns() / 1000 / 1000;
+        let s2d = match parse_s2d(&payload) {
+            Some(s2d) => s2d,
+            None => continue,
         };
-        let sd = match SessionResult::from(&station_to_det){
-            Ok(m) => m,
-            Err(e) => {
-                debug!("Error converting S2D to SD: {}", e);
+        if ingest.apply_s2d(&s2d, None) && s2d.get_operation() != StationOperation::Add {
+            if let Err(e) = record_op(&query, conf, &s2d, &payload, issued_ms) {
+                warn!("failed to keep session operation in {} ({}), a backfill could undo it",
+                      conf.ops, e);
+            }
+        }
+    }
+}
+
+// Keeps an operation that changed something in conf.ops, scored with how
+// long it has to be kept: until every session it could have undone has
+// expired from conf.active, or for as long as a session it extended lasts.
+// The member is the time we applied it (Unix ms, big-endian) followed by the
+// message, so a backfill can tell which adds came before it.
+fn record_op(query: &redis::Connection, conf: &IngestConfig, s2d: &StationToDetector,
+             payload: &[u8], issued_ms: u64) -> redis::RedisResult<()> {
+    let latest: Vec<(Vec<u8>, f64)> = redis::cmd("ZREVRANGEBYSCORE").arg(conf.active.as_str())
+        .arg("+inf").arg("-inf").arg("WITHSCORES").arg("LIMIT").arg(0).arg(1)
+        .query(query)?;
+    let mut until_ms = latest.first().map(|&(_, expiry)| expiry as u64).unwrap_or(0);
+    if s2d.get_operation() == StationOperation::ExtendTo {
+        until_ms = until_ms.max(issued_ms + s2d.get_timeout_ns() / 1000 / 1000);
+    }
+    if until_ms <= issued_ms {
+        return Ok(())
+    }
+    redis::cmd("ZADD").arg(conf.ops.as_str()).arg(until_ms).arg(op_record(issued_ms, payload))
+        .query::<()>(query)?;
+    redis::cmd("ZREMRANGEBYSCORE").arg(conf.ops.as_str())
+        .arg("-inf").arg(format!("({}", issued_ms))
+        .query(query)
+}
+
+// Applies StationToDetector messages to a SessionTracker's sessions from
+// another thread (the redis ingest). It remembers which client each session
+// it adds was for, as IPv6 keys don't say.
+pub struct SessionIngest {
+    table: Arc<SessionTable>,
+    // The expiring tracker's (see SessionTracker)
+    deadlines: Option<Arc<Mutex<Deadlines>>>,
+    clients: HashMap<IpAddr, Vec<SessionKey>, FxBuildHasher>,
+    // Clients left after the last sweep for sessions that have gone
+    swept: usize,
+}
+
+impl SessionIngest {
+    fn new(table: Arc<SessionTable>, deadlines: Option<Arc<Mutex<Deadlines>>>) -> SessionIngest {
+        SessionIngest { table: table, deadlines: deadlines, clients: HashMap::default(), swept: 0 }
+    }
+
+    fn deadlines(&self) -> Option<MutexGuard<'_, Deadlines>> {
+        self.deadlines.as_ref().map(|deadlines| deadlines.lock().unwrap())
+    }
+
+    // Catches up on what we missed: adds the sessions in active
+    // (StationToDetector, expiry in Unix ms) that haven't expired by now_ns
+    // (Unix), and replays the operations in ops (see record_op) in between
+    // them, in the order they happened. A session revoked after it was added
+    // stays revoked, and one added again after that is kept. Returns how many
+    // sessions were new.
+    pub fn backfill(&mut self, active: &[(Vec<u8>, f64)], ops: &[(Vec<u8>, f64)],
+                    now_ns: u64) -> usize {
+        let now_ms = now_ns / 1000 / 1000;
+        // (when it happened, Unix ms, the message, and its expiry if an add)
+        let mut all = Vec::with_capacity(active.len() + ops.len());
+        for &(ref payload, expiry_ms) in active {
+            if let Some(s2d) = parse_s2d(payload) {
+                // The application adds it timeout before it expires
+                let expiry_ms = expiry_ms as u64;
+                let added_ms = expiry_ms.saturating_sub(s2d.get_timeout_ns() / 1000 / 1000);
+                all.push((added_ms, s2d, Some(expiry_ms)));
+            }
+        }
+        for (rec, _) in ops {
+            if rec.len() < 8 {
                 continue
             }
-        };
+            let mut issued_ms = [0u8; 8];
+            issued_ms.copy_from_slice(&rec[..8]);
+            if let Some(s2d) = parse_s2d(&rec[8..]) {
+                all.push((u64::from_be_bytes(issued_ms), s2d, None));
+            }
+        }
+        all.sort_by_key(|&(at_ms, _, _)| at_ms);
 
-        // is this already in the map? 
-        let key = sd.get_key();
-        // Get writable map
-        let mut mmap = map.write().expect("RwLock broken");
-        let exists = mmap.contains_key(&key);
-
-        if exists {
-            // Set timeout
-            let expire_time = precise_time_ns() + sd.timeout;
-
-            match mmap.get_mut(&key){
-                Some(v)=> {
-                    // compare and keep the longer
-                    if *v < expire_time {
-                        *v = expire_time;
-                    }
+        let mut added = 0;
+        for (at_ms, s2d, expiry_ms) in all {
+            let op = match SessionOp::from_s2d(&s2d) {
+                Ok(op) => op,
+                Err(e) => {
+                    debug!("Error converting S2D to SD: {}", e);
+                    continue
                 },
-                None => {},
             };
-
-            // Explicitly drop map write lock here (locks are automatically dropped
-            // when they fall out of scope but this is more clear.)
-            drop(mmap);
-            continue
+            match (op, expiry_ms) {
+                (SessionOp::Add(sd), Some(expiry_ms)) => {
+                    if expiry_ms > now_ms
+                        && self.apply(SessionOp::Add(sd), Some((expiry_ms - now_ms) * 1000 * 1000)) {
+                        added += 1;
+                    }
+                },
+                // Only kept if the session was there to extend, so it's put
+                // back if need be.
+                (SessionOp::ExtendTo(sd), _) => {
+                    let until_ms = at_ms + sd.timeout / 1000 / 1000;
+                    if until_ms <= now_ms {
+                        self.apply(SessionOp::Revoke(sd), None);
+                    } else {
+                        let timeout = Some((until_ms - now_ms) * 1000 * 1000);
+                        if !self.apply(SessionOp::ExtendTo(sd), timeout) {
+                            self.apply(SessionOp::Add(sd), timeout);
+                        }
+                    }
+                },
+                (op, _) => {
+                    self.apply(op, None);
+                },
+            }
         }
+        added
+    }
 
-        // Set timeout
-        let expire_time = precise_time_ns() + sd.timeout;
-
-        // Insert
-        *mmap.entry(key).or_insert(expire_time) = expire_time;
+    // Applies a serialized StationToDetector message, with timeout in place
+    // of the message's own if given. Returns true if it changed anything.
+    pub fn apply_message(&mut self, payload: &[u8], timeout: Option<u64>) -> bool {
+        match parse_s2d(payload) {
+            Some(s2d) => self.apply_s2d(&s2d, timeout),
+            None => false,
+        }
+    }
 
-        // Get rid of writable reference to map. (locks are automatically dropped
-        // when they fall out of scope but this is more clear.)
-        drop(mmap);
+    fn apply_s2d(&mut self, s2d: &StationToDetector, timeout: Option<u64>) -> bool {
+        match SessionOp::from_s2d(s2d) {
+            Ok(op) => self.apply(op, timeout),
+            Err(e) => {
+                debug!("Error converting S2D to SD: {}", e);
+                false
+            }
+        }
+    }
 
-        debug!("Added registered ip {} from redis", sd);
+    // Adding a session that's already there keeps the longer timeout;
+    // ExtendTo sets it whichever way. Returns true if anything changed.
+    pub fn apply(&mut self, op: SessionOp, timeout: Option<u64>) -> bool {
+        let now = now_ns();
+        let table = &self.table;
+        match op {
+            SessionOp::Add(sd) => {
+                let key = sd.get_key();
+                let deadline = now + timeout.unwrap_or(sd.timeout);
+                let is_new = match table.insert(key, deadline) {
+                    Ok(is_new) => is_new,
+                    Err(e) => {
+                        warn!("{}, dropping session {}", e, sd);
+                        return false
+                    },
+                };
+                if let Some(mut deadlines) = self.deadlines() {
+                    deadlines.insert(key, deadline);
+                }
+                let keys = self.clients.entry(sd.client_ip).or_insert_with(Vec::new);
+                keys.retain(|k| table.contains_key(k));
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+                if self.clients.len() > 2 * self.swept + 1024 {
+                    self.clients.retain(|_, keys| {
+                        keys.retain(|k| table.contains_key(k));
+                        !keys.is_empty()
+                    });
+                    self.swept = self.clients.len();
+                }
+                if is_new {
+                    Event::new(LogLevel::Debug, "phantom_session").str("session", &sd).emit();
+                }
+                is_new
+            },
+            SessionOp::ExtendTo(sd) => {
+                let (key, deadline) = (sd.get_key(), now + timeout.unwrap_or(sd.timeout));
+                if !table.set_deadline(&key, deadline) {
+                    return false
+                }
+                if let Some(mut deadlines) = self.deadlines() {
+                    if !deadlines.set_deadline(&key, deadline) {
+                        deadlines.insert(key, deadline);
+                    }
+                }
+                true
+            },
+            SessionOp::Revoke(sd) => {
+                let key = sd.get_key();
+                if !table.remove(&key) {
+                    return false
+                }
+                if let Some(mut deadlines) = self.deadlines() {
+                    deadlines.remove(&key);
+                }
+                Event::new(LogLevel::Debug, "phantom_session_revoked").str("session", &sd).emit();
+                true
+            },
+            SessionOp::RevokeClient(ip) => {
+                let keys = self.clients.remove(&ip).unwrap_or_default();
+                let n = keys.iter().filter(|k| table.remove(k)).count();
+                if n == 0 {
+                    return false
+                }
+                if let Some(mut deadlines) = self.deadlines() {
+                    for k in &keys {
+                        deadlines.remove(k);
+                    }
+                }
+                Event::new(LogLevel::Debug, "phantom_session_revoked")
+                    .str("client", &Client(ip, None))
+                    .num("sessions", n as u64)
+                    .emit();
+                true
+            },
+        }
     }
 }
 
-fn get_redis_conn() -> redis::Connection
-{
-    let client = redis::Client::open("redis://127.0.0.1/").expect("Can't open Redis");
-    let con = client.get_connection().expect("Can't get Redis connection");
-    con
+fn op_record(issued_ms: u64, payload: &[u8]) -> Vec<u8> {
+    let mut rec = issued_ms.to_be_bytes().to_vec();
+    rec.extend_from_slice(payload);
+    rec
 }
 
+fn parse_s2d(payload: &[u8]) -> Option<StationToDetector> {
+    match Message::parse_from_bytes::<>(payload) {
+        Ok(s2d) => Some(s2d),
+        Err(e) => {
+            debug!("failed to parse StationToDetector message {}", e);
+            None
+        },
+    }
+}
 
 #[cfg(test)]
 mod tests {
     // use std::fmt::Write;
     use sessions::*;
-    use signalling::StationToDetector;
-    use flow_tracker::FlowNoSrcPort;
+    use signalling::{IPProto, StationOperation, StationToDetector};
+    use flow_tracker::{Flow, FlowNoSrcPort, Transport};
+    use hex;
     use std::{thread, time};
 
     #[test]
@@ -427,7 +1153,7 @@ mod tests {
             ("7.0.0.2", "8.8.8.8", 5*S2NS),
         ];
     
-        st.spawn_update_thread();
+        st.spawn_update_thread(IngestConfig::default());
        
         let dur = time::Duration::new(3, 0);
         thread::sleep(dur);
@@ -440,7 +1166,7 @@ mod tests {
 
             let msg:Vec<u8> = s2d.write_to_bytes().unwrap();
 
-            let redis_conn = get_redis_conn();
+            let redis_conn = redis::Client::open(DEFAULT_REDIS_URL).unwrap().get_connection().unwrap();
             redis::cmd("PUBLISH").arg("dark_decoy_map").arg(msg).execute(&redis_conn);
         }
 
@@ -451,6 +1177,146 @@ mod tests {
         } 
     }
 
+    #[test]
+    fn test_session_backfill() {
+        let st = SessionTracker::new();
+        let s2d = |client: &str, phantom: &str| {
+            let mut s2d = StationToDetector::new();
+            s2d.set_client_ip(client.to_string());
+            s2d.set_phantom_ip(phantom.to_string());
+            s2d.write_to_bytes().unwrap()
+        };
+        let now = 1700000000 * S2NS;
+        let now_ms = (now / 1000 / 1000) as f64;
+        let active = vec![
+            (s2d("192.168.0.1", "10.10.0.1"), now_ms + 60000.0),
+            // Expired, unparseable, and already there
+            (s2d("192.168.0.2", "10.10.0.2"), now_ms),
+            (b"junk".to_vec(), now_ms + 60000.0),
+            (s2d("192.168.0.1", "10.10.0.1"), now_ms + 1000.0),
+        ];
+
+        let before = now_ns();
+        assert_eq!(st.ingest().backfill(&active, &[], now), 1);
+        assert_eq!(st.len(), 1);
+        // Whatever time it had left
+        let key = SessionDetails::new("192.168.0.1", "10.10.0.1", DEFAULT_PHANTOM_PORT, 0).unwrap().get_key();
+        let deadline = st.tracked_sessions.deadline(&key).unwrap();
+        assert!(deadline >= before + 60 * S2NS && deadline <= now_ns() + 60 * S2NS);
+    }
+
+    #[test]
+    fn test_backfill_replays_operations() {
+        let st = SessionTracker::new();
+        let s2d = |client: &str, phantom: &str, op: StationOperation, timeout_s: u64| {
+            let mut s2d = StationToDetector::new();
+            s2d.set_client_ip(client.to_string());
+            s2d.set_phantom_ip(phantom.to_string());
+            s2d.set_timeout_ns(timeout_s * S2NS);
+            s2d.set_operation(op);
+            s2d.write_to_bytes().unwrap()
+        };
+        let add = |client: &str, phantom: &str, added_s_ago: u64| {
+            let expiry_ms = 1700000000 * 1000 + (600 - added_s_ago) * 1000;
+            (s2d(client, phantom, StationOperation::Add, 600), expiry_ms as f64)
+        };
+        let op = |client: &str, phantom: &str, op: StationOperation, timeout_s: u64, s_ago: u64| {
+            let issued_ms = 1700000000 * 1000 - s_ago * 1000;
+            (op_record(issued_ms, &s2d(client, phantom, op, timeout_s)), 0.0)
+        };
+        let deadline = |c: &str, p: &str| {
+            let key = SessionDetails::new(c, p, DEFAULT_PHANTOM_PORT, 0).unwrap().get_key();
+            st.tracked_sessions.deadline(&key)
+        };
+
+        let active = vec![
+            add("192.168.0.1", "10.10.0.6", 10),
+            // Added again after it was revoked
+            add("192.168.0.1", "10.10.0.1", 2),
+            add("192.168.0.1", "10.10.0.2", 6),
+            add("192.168.0.2", "10.10.0.3", 10),
+            add("192.168.0.3", "10.10.0.4", 10),
+        ];
+        let ops = vec![
+            op("192.168.0.1", "10.10.0.6", StationOperation::Revoke, 0, 5),
+            op("192.168.0.1", "10.10.0.1", StationOperation::Revoke, 0, 5),
+            op("192.168.0.2", "10.10.0.3", StationOperation::ExtendTo, 60, 4),
+            // Already over
+            op("192.168.0.3", "10.10.0.4", StationOperation::ExtendTo, 1, 4),
+            // Gone from active, but it was there to extend
+            op("192.168.0.4", "10.10.0.5", StationOperation::ExtendTo, 600, 4),
+            op("192.168.0.1", "", StationOperation::RevokeClient, 0, 3),
+            (b"junk".to_vec(), 0.0),
+        ];
+
+        let before = now_ns();
+        assert_eq!(st.ingest().backfill(&active, &ops, 1700000000 * S2NS), 5);
+        let after = now_ns();
+        assert_eq!(st.len(), 3);
+        assert!(deadline("192.168.0.1", "10.10.0.6").is_none());
+        assert!(deadline("192.168.0.1", "10.10.0.2").is_none());
+        assert!(deadline("192.168.0.3", "10.10.0.4").is_none());
+        assert!(deadline("192.168.0.1", "10.10.0.1").unwrap() >= before + 598 * S2NS);
+        let extended = deadline("192.168.0.2", "10.10.0.3").unwrap();
+        assert!(extended >= before + 56 * S2NS && extended <= after + 56 * S2NS);
+        assert!(deadline("192.168.0.4", "10.10.0.5").unwrap() >= before + 596 * S2NS);
+    }
+
+    #[test]
+    fn test_session_operations() {
+        let mut st = SessionTracker::new();
+        let mut ingest = st.ingest();
+        let s2d = |client: &str, phantom: &str, op: StationOperation| {
+            let mut s2d = StationToDetector::new();
+            s2d.set_client_ip(client.to_string());
+            s2d.set_phantom_ip(phantom.to_string());
+            s2d.set_timeout_ns(600 * S2NS);
+            s2d.set_operation(op);
+            s2d.write_to_bytes().unwrap()
+        };
+        let deadline = |st: &SessionTracker, c: &str, p: &str| {
+            let key = SessionDetails::new(c, p, DEFAULT_PHANTOM_PORT, 0).unwrap().get_key();
+            st.tracked_sessions.deadline(&key)
+        };
+
+        assert!(ingest.apply_message(&s2d("192.168.0.1", "10.10.0.1", StationOperation::Add), None));
+        assert!(ingest.apply_message(&s2d("192.168.0.1", "10.10.0.2", StationOperation::Add), None));
+        assert!(ingest.apply_message(&s2d("192.168.0.1", "2001::1", StationOperation::Add), None));
+        assert!(ingest.apply_message(&s2d("192.168.0.2", "10.10.0.1", StationOperation::Add), None));
+        assert!(!ingest.apply_message(&s2d("192.168.0.1", "10.10.0.1", StationOperation::Add), None));
+        assert_eq!(st.len(), 4);
+
+        // ExtendTo can bring a deadline in, unlike Add
+        let before = now_ns();
+        assert!(ingest.apply_message(&s2d("192.168.0.1", "10.10.0.2", StationOperation::ExtendTo), Some(S2NS)));
+        assert!(deadline(&st, "192.168.0.1", "10.10.0.2").unwrap() < before + 2 * S2NS);
+        assert!(!ingest.apply_message(&s2d("192.168.0.3", "10.10.0.2", StationOperation::ExtendTo), None));
+
+        // The connections of revoked sessions are dropped at the next cleanup
+        let flow = Flow::from_parts("192.168.0.2".parse().unwrap(), "10.10.0.1".parse().unwrap(),
+                                    1000, DEFAULT_PHANTOM_PORT);
+        st.update_connection(&flow, ConnEvent::Syn, 60);
+        assert!(ingest.apply_message(&s2d("192.168.0.2", "10.10.0.1", StationOperation::Revoke), None));
+        assert!(!ingest.apply_message(&s2d("192.168.0.2", "10.10.0.1", StationOperation::Revoke), None));
+        assert!(deadline(&st, "192.168.0.2", "10.10.0.1").is_none());
+        assert_eq!(st.total_connection_counts().syn_seen, 1);
+        st.drop_stale_sessions();
+        assert_eq!(st.total_connection_counts(), ConnCounts::default());
+
+        // Everything for a client, including its v6 phantoms
+        assert!(ingest.apply_message(&s2d("192.168.0.1", "", StationOperation::RevokeClient), None));
+        assert!(!ingest.apply_message(&s2d("192.168.0.1", "", StationOperation::RevokeClient), None));
+        assert_eq!(st.len(), 0);
+
+        let mut bad = StationToDetector::new();
+        bad.set_client_ip("192.1".to_string());
+        bad.set_operation(StationOperation::RevokeClient);
+        match SessionOp::from_s2d(&bad) {
+            Ok(_) => panic!("Should have failed"),
+            Err(e) => assert_eq!(format!("{}", e), format!("{}", SessionError::InvalidClient)),
+        }
+    }
+
 
     #[test]
     fn test_session_details_from() {
@@ -495,7 +1361,8 @@ mod tests {
     
             // assert_eq!(entry.0, sd.client_ip.to_string());
             assert_eq!(entry.1, sd.phantom_ip.to_string());
-            assert_eq!(entry.2, sd.timeout)
+            assert_eq!(entry.2, sd.timeout);
+            assert_eq!(DEFAULT_PHANTOM_PORT, sd.phantom_port);
         }
 
         for entry in &test_tuples_bad {
@@ -533,7 +1400,7 @@ mod tests {
         ];
 
         for entry in &test_tuples {
-            let s1 = SessionDetails::new(entry.0, entry.1, entry.2).unwrap();
+            let s1 = SessionDetails::new(entry.0, entry.1, DEFAULT_PHANTOM_PORT, entry.2).unwrap();
             st.insert_session(s1);
         }
 
@@ -550,6 +1417,7 @@ mod tests {
                 src_ip: src,
                 dst_ip: entry.1.parse().unwrap(), 
                 dst_port: DEFAULT_PHANTOM_PORT,
+                transport: Transport::Tcp,
             };
             if !st.is_tracked_session(f) {
                 panic!("Session should be tracked")
@@ -557,8 +1425,9 @@ mod tests {
         }
 
         let tt = test_tuples[0];
-        let sd = SessionDetails::new(tt.0, tt.1, tt.2).unwrap();
-        st._delete_session(sd);
+        let sd = SessionDetails::new(tt.0, tt.1, DEFAULT_PHANTOM_PORT, tt.2).unwrap();
+        assert!(st.revoke_session(&sd));
+        assert!(!st.revoke_session(&sd));
 
 
         if st.len() != 4 {
@@ -589,7 +1458,7 @@ mod tests {
         ];
     
         for entry in &test_tuples {
-            let s1 = SessionDetails::new(entry.0, entry.1, entry.2).unwrap();
+            let s1 = SessionDetails::new(entry.0, entry.1, DEFAULT_PHANTOM_PORT, entry.2).unwrap();
             st.insert_session(s1);
         }
 
@@ -603,6 +1472,7 @@ mod tests {
                 src_ip: entry.0.parse().unwrap(),
                 dst_ip: entry.1.parse().unwrap(), 
                 dst_port: DEFAULT_PHANTOM_PORT,
+                transport: Transport::Tcp,
             };
             assert_eq!(st.is_tracked_session(f), entry.3)
         }
@@ -611,4 +1481,237 @@ mod tests {
         
         assert_eq!(st.drop_stale_sessions(), 5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_session_tracker_ports() {
+        let mut st = SessionTracker::new();
+
+        let mut s2d = StationToDetector::new();
+        s2d.set_client_ip("192.168.0.1".to_string());
+        s2d.set_phantom_ip("10.10.0.1".to_string());
+        s2d.set_timeout_ns(100000);
+        s2d.set_dst_port(8443);
+        st.insert_session(SessionResult::from(&s2d).unwrap());
+
+        s2d.set_client_ip("".to_string());
+        s2d.set_phantom_ip("2001::1234".to_string());
+        s2d.set_dst_port(0);
+        st.insert_session(SessionResult::from(&s2d).unwrap());
+
+        s2d.set_dst_port(65536);
+        match SessionResult::from(&s2d) {
+            Ok(_) => panic!("Should have failed"),
+            Err(e) => assert_eq!(format!("{}", e), format!("{}", SessionError::InvalidPort)),
+        }
+
+        let tests = [
+            // (client_ip, phantom_ip, port, tracked)
+            ("192.168.0.1", "10.10.0.1", 8443, true),
+            ("192.168.0.1", "10.10.0.1", 443, false),
+            ("192.168.0.2", "10.10.0.1", 8443, false),
+            ("2601::1", "2001::1234", 443, true),
+            ("2601::1", "2001::1234", 8443, false),
+        ];
+        for entry in &tests {
+            let f = &FlowNoSrcPort{
+                src_ip: entry.0.parse().unwrap(),
+                dst_ip: entry.1.parse().unwrap(),
+                dst_port: entry.2,
+                transport: Transport::Tcp,
+            };
+            assert_eq!(st.is_tracked_session(f), entry.3, "{:?}", entry);
+        }
+    }
+
+    // What the application sends for a registration to 10.10.0.1:8443
+    // (TestDetectorMessage in application/lib/registration_test.go)
+    #[test]
+    fn test_application_registration() {
+        let msg = hex::decode(include_str!("../application/lib/test/station_to_detector.hex").trim()).unwrap();
+        let s2d = parse_s2d(&msg).unwrap();
+        assert_eq!(s2d.get_dst_port(), 8443);
+
+        let mut st = SessionTracker::new();
+        st.insert_session(SessionResult::from(&s2d).unwrap());
+        for &(port, tracked) in &[(8443, true), (DEFAULT_PHANTOM_PORT, false)] {
+            let f = FlowNoSrcPort{
+                src_ip: "192.168.0.1".parse().unwrap(),
+                dst_ip: "10.10.0.1".parse().unwrap(),
+                dst_port: port,
+                transport: Transport::Tcp,
+            };
+            assert_eq!(st.is_tracked_session(&f), tracked, "{}", port);
+        }
+    }
+
+    #[test]
+    fn test_session_tracker_udp() {
+        let mut st = SessionTracker::new();
+
+        let mut s2d = StationToDetector::new();
+        s2d.set_client_ip("192.168.0.1".to_string());
+        s2d.set_phantom_ip("10.10.0.1".to_string());
+        s2d.set_timeout_ns(100000);
+        s2d.set_proto(IPProto::Udp);
+        st.insert_session(SessionResult::from(&s2d).unwrap());
+
+        s2d.set_phantom_ip("10.10.0.2".to_string());
+        s2d.set_proto(IPProto::Tcp);
+        st.insert_session(SessionResult::from(&s2d).unwrap());
+
+        let tests = [
+            // (phantom_ip, transport, tracked)
+            ("10.10.0.1", Transport::Udp, true),
+            ("10.10.0.1", Transport::Tcp, false),
+            ("10.10.0.2", Transport::Tcp, true),
+            ("10.10.0.2", Transport::Udp, false),
+        ];
+        for entry in &tests {
+            let f = &FlowNoSrcPort{
+                src_ip: "192.168.0.1".parse().unwrap(),
+                dst_ip: entry.0.parse().unwrap(),
+                dst_port: DEFAULT_PHANTOM_PORT,
+                transport: entry.1,
+            };
+            assert_eq!(st.is_tracked_session(f), entry.2, "{:?}", entry);
+        }
+    }
+
+    #[test]
+    fn test_session_keys() {
+        let key = |c: &str, p: &str, port: u16, t: Transport| {
+            SessionKey::new(c.parse().unwrap(), p.parse().unwrap(), port, t)
+        };
+        // v4 keys are the client/phantom pair, v6 the phantom alone
+        assert!(key("192.168.0.1", "10.10.0.1", 443, Transport::Tcp)
+                != key("192.168.0.2", "10.10.0.1", 443, Transport::Tcp));
+        assert_eq!(key("2601::123:abcd", "2001::1234", 443, Transport::Tcp),
+                   key("192.168.0.1", "2001::1234", 443, Transport::Tcp));
+        assert!(key("::1", "2001::1234", 443, Transport::Tcp)
+                != key("::1", "2001::1234", 8443, Transport::Tcp));
+        assert!(key("::1", "2001::1234", 443, Transport::Tcp)
+                != key("::1", "2001::1234", 443, Transport::Udp));
+
+        // Same text as the string keys these replaced
+        assert_eq!(key("192.168.0.1", "10.10.0.1", 443, Transport::Tcp).to_string(),
+                   "192.168.0.1-10.10.0.1:443");
+        assert_eq!(key("::1", "2001::1234", 443, Transport::Udp).to_string(),
+                   "[2001::1234]:443/udp");
+
+        let sd = SessionDetails::new("", "2001::1234", 0, 1).unwrap();
+        let flow = FlowNoSrcPort::from_parts("2601::1".parse().unwrap(), "2001::1234".parse().unwrap(),
+                                             DEFAULT_PHANTOM_PORT, Transport::Tcp);
+        assert_eq!(sd.get_key(), SessionKey::from_flow(&flow));
+    }
+
+    #[test]
+    fn test_session_connections() {
+        let mut st = SessionTracker::new();
+        st.set_reporting(true);
+        let conn = |client: &str, phantom: &str, port| Flow::from_parts(
+            client.parse().unwrap(), phantom.parse().unwrap(), port, DEFAULT_PHANTOM_PORT);
+        let deadline = |st: &SessionTracker, flow: &Flow| {
+            let key = SessionKey::from_flow(&FlowNoSrcPort::from_flow(flow, Transport::Tcp));
+            st.tracked_sessions.deadline(&key).unwrap()
+        };
+
+        // Registered with a timeout that's already up, so it's only the
+        // connections keeping it
+        st.insert_session(SessionDetails::new("192.168.0.1", "10.10.0.1", DEFAULT_PHANTOM_PORT, 1).unwrap());
+        let (a, b) = (conn("192.168.0.1", "10.10.0.1", 1000), conn("192.168.0.1", "10.10.0.1", 1001));
+        let now = now_ns();
+
+        let u = st.update_connection(&a, ConnEvent::Syn, 60).unwrap();
+        assert_eq!(u.counts, ConnCounts { syn_seen: 1, ..Default::default() });
+        assert!(deadline(&st, &a) >= now + TIMEOUT_PHANTOMS_NS);
+        st.update_connection(&a, ConnEvent::Ack, 52);
+        st.update_connection(&b, ConnEvent::Syn, 60);
+        st.update_connection(&a, ConnEvent::Fin, 52);
+        assert_eq!(st.update_connection(&a, ConnEvent::Fin, 52).unwrap().counts.half_closed, 1);
+        let u = st.update_connection(&a, ConnEvent::Ack, 52).unwrap();
+        assert_eq!(u.counts, ConnCounts { syn_seen: 1, closed: 1, ..Default::default() });
+        assert!(!u.closed_session);
+        assert!(deadline(&st, &a) >= now + TIMEOUT_PHANTOMS_NS);
+
+        // Once the last one is reset the session only lingers
+        let u = st.update_connection(&b, ConnEvent::Rst, 40).unwrap();
+        assert!(u.closed_session);
+        assert_eq!(u.counts.closed, 2);
+        assert!(deadline(&st, &a) < now_ns() + CLOSED_LINGER_NS + S2NS);
+        let u = st.update_connection(&b, ConnEvent::Ack, 52).unwrap();
+        assert!(!u.closed_session && u.counts.open() == 0);
+
+        // But not past a longer registration
+        st.insert_session(SessionDetails::new("", "2001::1234", DEFAULT_PHANTOM_PORT, 600 * S2NS).unwrap());
+        let c = conn("2601::1", "2001::1234", 1000);
+        st.update_connection(&c, ConnEvent::Syn, 60);
+        assert!(st.update_connection(&c, ConnEvent::Rst, 40).unwrap().closed_session);
+        assert!(deadline(&st, &c) >= now + 600 * S2NS);
+
+        let totals = st.total_connection_counts();
+        assert_eq!(totals, ConnCounts { closed: 3, ..Default::default() });
+        assert_eq!(st.connection_counts(&FlowNoSrcPort::from_flow(&c, Transport::Tcp)).closed, 1);
+        assert!(st.update_connection(&conn("192.168.0.2", "10.10.0.1", 1000), ConnEvent::Syn, 60).is_none());
+
+        // Both sessions' first SYN, and every connection closing (b twice)
+        let events = st.take_events();
+        let kinds: Vec<_> = events.iter().map(|e| e.get_event()).collect();
+        assert_eq!(kinds, vec![PhantomEventType::FirstSyn, PhantomEventType::ConnectionClosed,
+                               PhantomEventType::ConnectionClosed, PhantomEventType::FirstSyn,
+                               PhantomEventType::ConnectionClosed]);
+        let (first, closed) = (&events[0], &events[1]);
+        assert_eq!((first.get_phantom_ip(), first.get_client_ip()), ("10.10.0.1", "192.168.0.1"));
+        assert_eq!((first.get_proto(), first.get_dst_port()), (IPProto::Tcp, DEFAULT_PHANTOM_PORT as u32));
+        assert_eq!((first.get_packets(), first.get_bytes(), first.get_connections()), (1, 60, 1));
+        // a's SYN, ACK, two FINs and the last ACK
+        assert_eq!((closed.get_src_port(), closed.get_packets(), closed.get_bytes()), (1000, 5, 268));
+        assert_eq!(closed.get_first_syn_ns(), first.get_first_syn_ns());
+        assert!(closed.get_conn_start_ns() >= first.get_first_syn_ns());
+        assert_eq!(events[2].get_connections(), 2);
+        assert_eq!(events[4].get_client_ip(), "2601::1");
+        assert!(st.take_events().is_empty());
+
+        // Sessions that time out without a packet are reported too
+        st.insert_session(SessionDetails::new("192.168.0.3", "10.10.0.3", DEFAULT_PHANTOM_PORT, 1).unwrap());
+        thread::sleep(::std::time::Duration::from_millis(20));
+        st.drop_stale_sessions();
+        let events = st.take_events();
+        assert!(events.iter().any(|e| e.get_event() == PhantomEventType::ExpiredUnused
+                                  && e.get_client_ip() == "192.168.0.3"));
+        assert!(events.iter().all(|e| e.get_phantom_ip() != "10.10.0.1"));
+    }
+
+    #[test]
+    fn test_shared_table_expiry() {
+        // On the capture's clock, so nothing here depends on how long it takes
+        thread::spawn(|| {
+            let start = 1_600_000_000 * S2NS;
+            clock::follow_capture(start);
+            let table = Arc::new(SessionTable::new(64));
+            // Left by an ingest process that's gone
+            let left = SessionDetails::new("192.168.0.9", "10.10.0.9", DEFAULT_PHANTOM_PORT, 0).unwrap();
+            table.insert(left.get_key(), now_ns() + S2NS).unwrap();
+
+            let mut ingest = SessionTracker::with_table(Arc::clone(&table), true);
+            let mut core = SessionTracker::with_table(Arc::clone(&table), false);
+            ingest.insert_session(SessionDetails::new("192.168.0.1", "10.10.0.1", DEFAULT_PHANTOM_PORT, 2 * S2NS).unwrap());
+            let flow = Flow::from_parts("192.168.0.1".parse().unwrap(), "10.10.0.1".parse().unwrap(),
+                                        1000, DEFAULT_PHANTOM_PORT);
+            core.update_connection(&flow, ConnEvent::Syn, 60).unwrap();
+
+            // The core's connection keeps it past its registration
+            clock::follow_capture(start + 3 * S2NS);
+            assert_eq!(ingest.drop_stale_sessions(), 1);
+            assert!(!table.contains_key(&left.get_key()));
+            assert_eq!(table.len(), 1);
+
+            // Then brings it in to linger once the connection closes
+            assert!(core.update_connection(&flow, ConnEvent::Rst, 40).unwrap().closed_session);
+            clock::follow_capture(start + 4 * S2NS + S2NS / 10);
+            assert_eq!(ingest.drop_stale_sessions(), 0);
+            clock::follow_capture(start + 3 * S2NS + CLOSED_LINGER_NS + S2NS / 2);
+            assert_eq!(ingest.drop_stale_sessions(), 1);
+            assert!(table.is_empty());
+        }).join().unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//
+// Tables of atomic words that every detector process can map
+//
+// The replay filter and the phantom session table are shared between the
+// per-core processes through a file in /dev/shm. Either can also be kept on
+// the heap, private to one process (tests, offline tools, or when the file
+// can't be opened); the code using them can't tell the difference.
+//
+
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::AtomicU64;
+
+use libc;
+
+pub enum Words
+{
+    Heap(Vec<AtomicU64>),
+    Mapped(*mut libc::c_void, usize),
+}
+
+// The mapping is only ever read and written through the atomics.
+unsafe impl Send for Words {}
+unsafe impl Sync for Words {}
+
+impl Drop for Words
+{
+    fn drop(&mut self)
+    {
+        if let Words::Mapped(addr, len) = *self {
+            unsafe { libc::munmap(addr, len); }
+        }
+    }
+}
+
+impl Words
+{
+    // n words, all zero
+    pub fn heap(n: usize) -> Words
+    {
+        let mut words = Vec::with_capacity(n);
+        for _ in 0..n {
+            words.push(AtomicU64::new(0));
+        }
+        Words::Heap(words)
+    }
+
+    // n words of the file at path, mapped shared. The file is created if
+    // need be; whatever an existing one holds is kept. An existing one has to
+    // be that size already: other processes may have it mapped, and
+    // shrinking it would SIGBUS them, growing it wipe what they share.
+    pub fn map_file(path: &str, n: usize) -> io::Result<Words>
+    {
+        let len = n * 8;
+        let f = OpenOptions::new().read(true).write(true).create(true)
+            .truncate(false).open(path)?;
+        match f.metadata()?.len() {
+            0 => f.set_len(len as u64)?,
+            l if l == len as u64 => {},
+            l => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                           format!("{} is {} bytes, not {}", path, l, len))),
+        }
+        let addr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_SHARED, f.as_raw_fd(), 0)
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Words::Mapped(addr, len))
+    }
+
+    pub fn get(&self) -> &[AtomicU64]
+    {
+        match *self {
+            Words::Heap(ref v) => v,
+            Words::Mapped(addr, len) => unsafe {
+                slice::from_raw_parts(addr as *const AtomicU64, len / 8)
+            },
+        }
+    }
+}
+
+// splitmix64 finalizer
+pub fn mix(mut x: u64) -> u64
+{
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
@@ -2974,6 +2974,7 @@ pub struct C2SWrapper {
     registration_source: ::std::option::Option<RegistrationSource>,
     registration_address: ::protobuf::SingularField<::std::vec::Vec<u8>>,
     decoy_address: ::protobuf::SingularField<::std::vec::Vec<u8>>,
+    key_generation: ::std::option::Option<u32>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -3149,6 +3150,25 @@ impl C2SWrapper {
     pub fn take_decoy_address(&mut self) -> ::std::vec::Vec<u8> {
         self.decoy_address.take().unwrap_or_else(|| ::std::vec::Vec::new())
     }
+
+    // optional uint32 key_generation = 8;
+
+
+    pub fn get_key_generation(&self) -> u32 {
+        self.key_generation.unwrap_or(0)
+    }
+    pub fn clear_key_generation(&mut self) {
+        self.key_generation = ::std::option::Option::None;
+    }
+
+    pub fn has_key_generation(&self) -> bool {
+        self.key_generation.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_key_generation(&mut self, v: u32) {
+        self.key_generation = ::std::option::Option::Some(v);
+    }
 }
 
 impl ::protobuf::Message for C2SWrapper {
@@ -3180,6 +3200,13 @@ impl ::protobuf::Message for C2SWrapper {
                 7 => {
                     ::protobuf::rt::read_singular_bytes_into(wire_type, is, &mut self.decoy_address)?;
                 },
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.key_generation = ::std::option::Option::Some(tmp);
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -3208,6 +3235,9 @@ impl ::protobuf::Message for C2SWrapper {
         if let Some(ref v) = self.decoy_address.as_ref() {
             my_size += ::protobuf::rt::bytes_size(7, &v);
         }
+        if let Some(v) = self.key_generation {
+            my_size += ::protobuf::rt::value_size(8, v, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -3231,6 +3261,9 @@ impl ::protobuf::Message for C2SWrapper {
         if let Some(ref v) = self.decoy_address.as_ref() {
             os.write_bytes(7, &v)?;
         }
+        if let Some(v) = self.key_generation {
+            os.write_uint32(8, v)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -3294,6 +3327,11 @@ impl ::protobuf::Message for C2SWrapper {
                 |m: &C2SWrapper| { &m.decoy_address },
                 |m: &mut C2SWrapper| { &mut m.decoy_address },
             ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                "key_generation",
+                |m: &C2SWrapper| { &m.key_generation },
+                |m: &mut C2SWrapper| { &mut m.key_generation },
+            ));
             ::protobuf::reflect::MessageDescriptor::new_pb_name::<C2SWrapper>(
                 "C2SWrapper",
                 fields,
@@ -3315,6 +3353,7 @@ impl ::protobuf::Clear for C2SWrapper {
         self.registration_source = ::std::option::Option::None;
         self.registration_address.clear();
         self.decoy_address.clear();
+        self.key_generation = ::std::option::Option::None;
         self.unknown_fields.clear();
     }
 }
@@ -3649,6 +3688,9 @@ pub struct StationToDetector {
     phantom_ip: ::protobuf::SingularField<::std::string::String>,
     client_ip: ::protobuf::SingularField<::std::string::String>,
     timeout_ns: ::std::option::Option<u64>,
+    dst_port: ::std::option::Option<u32>,
+    proto: ::std::option::Option<IPProto>,
+    operation: ::std::option::Option<StationOperation>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -3755,6 +3797,63 @@ impl StationToDetector {
     pub fn set_timeout_ns(&mut self, v: u64) {
         self.timeout_ns = ::std::option::Option::Some(v);
     }
+
+    // optional uint32 dst_port = 4;
+
+
+    pub fn get_dst_port(&self) -> u32 {
+        self.dst_port.unwrap_or(0)
+    }
+    pub fn clear_dst_port(&mut self) {
+        self.dst_port = ::std::option::Option::None;
+    }
+
+    pub fn has_dst_port(&self) -> bool {
+        self.dst_port.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_dst_port(&mut self, v: u32) {
+        self.dst_port = ::std::option::Option::Some(v);
+    }
+
+    // optional .tapdance.IPProto proto = 5;
+
+
+    pub fn get_proto(&self) -> IPProto {
+        self.proto.unwrap_or(IPProto::Unk)
+    }
+    pub fn clear_proto(&mut self) {
+        self.proto = ::std::option::Option::None;
+    }
+
+    pub fn has_proto(&self) -> bool {
+        self.proto.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_proto(&mut self, v: IPProto) {
+        self.proto = ::std::option::Option::Some(v);
+    }
+
+    // optional .tapdance.StationOperation operation = 6;
+
+
+    pub fn get_operation(&self) -> StationOperation {
+        self.operation.unwrap_or(StationOperation::Add)
+    }
+    pub fn clear_operation(&mut self) {
+        self.operation = ::std::option::Option::None;
+    }
+
+    pub fn has_operation(&self) -> bool {
+        self.operation.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_operation(&mut self, v: StationOperation) {
+        self.operation = ::std::option::Option::Some(v);
+    }
 }
 
 impl ::protobuf::Message for StationToDetector {
@@ -3779,6 +3878,19 @@ impl ::protobuf::Message for StationToDetector {
                     let tmp = is.read_uint64()?;
                     self.timeout_ns = ::std::option::Option::Some(tmp);
                 },
+                4 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.dst_port = ::std::option::Option::Some(tmp);
+                },
+                5 => {
+                    ::protobuf::rt::read_proto2_enum_with_unknown_fields_into(wire_type, is, &mut self.proto, 5, &mut self.unknown_fields)?
+                },
+                6 => {
+                    ::protobuf::rt::read_proto2_enum_with_unknown_fields_into(wire_type, is, &mut self.operation, 6, &mut self.unknown_fields)?
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -3800,6 +3912,15 @@ impl ::protobuf::Message for StationToDetector {
         if let Some(v) = self.timeout_ns {
             my_size += ::protobuf::rt::value_size(3, v, ::protobuf::wire_format::WireTypeVarint);
         }
+        if let Some(v) = self.dst_port {
+            my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.proto {
+            my_size += ::protobuf::rt::enum_size(5, v);
+        }
+        if let Some(v) = self.operation {
+            my_size += ::protobuf::rt::enum_size(6, v);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -3815,6 +3936,15 @@ impl ::protobuf::Message for StationToDetector {
         if let Some(v) = self.timeout_ns {
             os.write_uint64(3, v)?;
         }
+        if let Some(v) = self.dst_port {
+            os.write_uint32(4, v)?;
+        }
+        if let Some(v) = self.proto {
+            os.write_enum(5, ::protobuf::ProtobufEnum::value(&v))?;
+        }
+        if let Some(v) = self.operation {
+            os.write_enum(6, ::protobuf::ProtobufEnum::value(&v))?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -3868,6 +3998,21 @@ impl ::protobuf::Message for StationToDetector {
                 |m: &StationToDetector| { &m.timeout_ns },
                 |m: &mut StationToDetector| { &mut m.timeout_ns },
             ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                "dst_port",
+                |m: &StationToDetector| { &m.dst_port },
+                |m: &mut StationToDetector| { &mut m.dst_port },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeEnum<IPProto>>(
+                "proto",
+                |m: &StationToDetector| { &m.proto },
+                |m: &mut StationToDetector| { &mut m.proto },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeEnum<StationOperation>>(
+                "operation",
+                |m: &StationToDetector| { &m.operation },
+                |m: &mut StationToDetector| { &mut m.operation },
+            ));
             ::protobuf::reflect::MessageDescriptor::new_pb_name::<StationToDetector>(
                 "StationToDetector",
                 fields,
@@ -3887,6 +4032,9 @@ impl ::protobuf::Clear for StationToDetector {
         self.phantom_ip.clear();
         self.client_ip.clear();
         self.timeout_ns = ::std::option::Option::None;
+        self.dst_port = ::std::option::Option::None;
+        self.proto = ::std::option::Option::None;
+        self.operation = ::std::option::Option::None;
         self.unknown_fields.clear();
     }
 }
@@ -3903,180 +4051,783 @@ impl ::protobuf::reflect::ProtobufValue for StationToDetector {
     }
 }
 
-#[derive(Clone,PartialEq,Eq,Debug,Hash)]
-pub enum KeyType {
-    AES_GCM_128 = 90,
-    AES_GCM_256 = 91,
+#[derive(PartialEq,Clone,Default)]
+pub struct PhantomEvent {
+    // message fields
+    event: ::std::option::Option<PhantomEventType>,
+    phantom_ip: ::protobuf::SingularField<::std::string::String>,
+    client_ip: ::protobuf::SingularField<::std::string::String>,
+    dst_port: ::std::option::Option<u32>,
+    proto: ::std::option::Option<IPProto>,
+    src_port: ::std::option::Option<u32>,
+    time_ns: ::std::option::Option<u64>,
+    first_syn_ns: ::std::option::Option<u64>,
+    conn_start_ns: ::std::option::Option<u64>,
+    packets: ::std::option::Option<u64>,
+    bytes: ::std::option::Option<u64>,
+    connections: ::std::option::Option<u32>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
 }
 
-impl ::protobuf::ProtobufEnum for KeyType {
-    fn value(&self) -> i32 {
-        *self as i32
+impl<'a> ::std::default::Default for &'a PhantomEvent {
+    fn default() -> &'a PhantomEvent {
+        <PhantomEvent as ::protobuf::Message>::default_instance()
     }
+}
 
-    fn from_i32(value: i32) -> ::std::option::Option<KeyType> {
-        match value {
-            90 => ::std::option::Option::Some(KeyType::AES_GCM_128),
-            91 => ::std::option::Option::Some(KeyType::AES_GCM_256),
-            _ => ::std::option::Option::None
-        }
+impl PhantomEvent {
+    pub fn new() -> PhantomEvent {
+        ::std::default::Default::default()
     }
 
-    fn values() -> &'static [Self] {
-        static values: &'static [KeyType] = &[
-            KeyType::AES_GCM_128,
-            KeyType::AES_GCM_256,
-        ];
-        values
-    }
+    // optional .tapdance.PhantomEventType event = 1;
 
-    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
-        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
-        descriptor.get(|| {
-            ::protobuf::reflect::EnumDescriptor::new_pb_name::<KeyType>("KeyType", file_descriptor_proto())
-        })
-    }
-}
 
-impl ::std::marker::Copy for KeyType {
-}
+    pub fn get_event(&self) -> PhantomEventType {
+        self.event.unwrap_or(PhantomEventType::FirstSyn)
+    }
+    pub fn clear_event(&mut self) {
+        self.event = ::std::option::Option::None;
+    }
 
-// Note, `Default` is implemented although default value is not 0
-impl ::std::default::Default for KeyType {
-    fn default() -> Self {
-        KeyType::AES_GCM_128
+    pub fn has_event(&self) -> bool {
+        self.event.is_some()
     }
-}
 
-impl ::protobuf::reflect::ProtobufValue for KeyType {
-    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
-        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    // Param is passed by value, moved
+    pub fn set_event(&mut self, v: PhantomEventType) {
+        self.event = ::std::option::Option::Some(v);
     }
-}
 
-#[derive(Clone,PartialEq,Eq,Debug,Hash)]
-pub enum C2S_Transition {
-    C2S_NO_CHANGE = 0,
-    C2S_SESSION_INIT = 1,
-    C2S_SESSION_COVERT_INIT = 11,
-    C2S_EXPECT_RECONNECT = 2,
-    C2S_SESSION_CLOSE = 3,
-    C2S_YIELD_UPLOAD = 4,
-    C2S_ACQUIRE_UPLOAD = 5,
-    C2S_EXPECT_UPLOADONLY_RECONN = 6,
-    C2S_ERROR = 255,
-}
+    // optional string phantom_ip = 2;
 
-impl ::protobuf::ProtobufEnum for C2S_Transition {
-    fn value(&self) -> i32 {
-        *self as i32
-    }
 
-    fn from_i32(value: i32) -> ::std::option::Option<C2S_Transition> {
-        match value {
-            0 => ::std::option::Option::Some(C2S_Transition::C2S_NO_CHANGE),
-            1 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_INIT),
-            11 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_COVERT_INIT),
-            2 => ::std::option::Option::Some(C2S_Transition::C2S_EXPECT_RECONNECT),
-            3 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_CLOSE),
-            4 => ::std::option::Option::Some(C2S_Transition::C2S_YIELD_UPLOAD),
-            5 => ::std::option::Option::Some(C2S_Transition::C2S_ACQUIRE_UPLOAD),
-            6 => ::std::option::Option::Some(C2S_Transition::C2S_EXPECT_UPLOADONLY_RECONN),
-            255 => ::std::option::Option::Some(C2S_Transition::C2S_ERROR),
-            _ => ::std::option::Option::None
+    pub fn get_phantom_ip(&self) -> &str {
+        match self.phantom_ip.as_ref() {
+            Some(v) => &v,
+            None => "",
         }
     }
-
-    fn values() -> &'static [Self] {
-        static values: &'static [C2S_Transition] = &[
-            C2S_Transition::C2S_NO_CHANGE,
-            C2S_Transition::C2S_SESSION_INIT,
-            C2S_Transition::C2S_SESSION_COVERT_INIT,
-            C2S_Transition::C2S_EXPECT_RECONNECT,
-            C2S_Transition::C2S_SESSION_CLOSE,
-            C2S_Transition::C2S_YIELD_UPLOAD,
-            C2S_Transition::C2S_ACQUIRE_UPLOAD,
-            C2S_Transition::C2S_EXPECT_UPLOADONLY_RECONN,
-            C2S_Transition::C2S_ERROR,
-        ];
-        values
+    pub fn clear_phantom_ip(&mut self) {
+        self.phantom_ip.clear();
     }
 
-    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
-        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
-        descriptor.get(|| {
-            ::protobuf::reflect::EnumDescriptor::new_pb_name::<C2S_Transition>("C2S_Transition", file_descriptor_proto())
-        })
+    pub fn has_phantom_ip(&self) -> bool {
+        self.phantom_ip.is_some()
     }
-}
 
-impl ::std::marker::Copy for C2S_Transition {
-}
+    // Param is passed by value, moved
+    pub fn set_phantom_ip(&mut self, v: ::std::string::String) {
+        self.phantom_ip = ::protobuf::SingularField::some(v);
+    }
 
-impl ::std::default::Default for C2S_Transition {
-    fn default() -> Self {
-        C2S_Transition::C2S_NO_CHANGE
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_phantom_ip(&mut self) -> &mut ::std::string::String {
+        if self.phantom_ip.is_none() {
+            self.phantom_ip.set_default();
+        }
+        self.phantom_ip.as_mut().unwrap()
     }
-}
 
-impl ::protobuf::reflect::ProtobufValue for C2S_Transition {
-    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
-        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    // Take field
+    pub fn take_phantom_ip(&mut self) -> ::std::string::String {
+        self.phantom_ip.take().unwrap_or_else(|| ::std::string::String::new())
     }
-}
 
-#[derive(Clone,PartialEq,Eq,Debug,Hash)]
-pub enum S2C_Transition {
-    S2C_NO_CHANGE = 0,
-    S2C_SESSION_INIT = 1,
-    S2C_SESSION_COVERT_INIT = 11,
-    S2C_CONFIRM_RECONNECT = 2,
-    S2C_SESSION_CLOSE = 3,
-    S2C_ERROR = 255,
-}
+    // optional string client_ip = 3;
 
-impl ::protobuf::ProtobufEnum for S2C_Transition {
-    fn value(&self) -> i32 {
-        *self as i32
-    }
 
-    fn from_i32(value: i32) -> ::std::option::Option<S2C_Transition> {
-        match value {
-            0 => ::std::option::Option::Some(S2C_Transition::S2C_NO_CHANGE),
-            1 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_INIT),
-            11 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_COVERT_INIT),
-            2 => ::std::option::Option::Some(S2C_Transition::S2C_CONFIRM_RECONNECT),
-            3 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_CLOSE),
-            255 => ::std::option::Option::Some(S2C_Transition::S2C_ERROR),
-            _ => ::std::option::Option::None
+    pub fn get_client_ip(&self) -> &str {
+        match self.client_ip.as_ref() {
+            Some(v) => &v,
+            None => "",
         }
     }
-
-    fn values() -> &'static [Self] {
-        static values: &'static [S2C_Transition] = &[
-            S2C_Transition::S2C_NO_CHANGE,
-            S2C_Transition::S2C_SESSION_INIT,
-            S2C_Transition::S2C_SESSION_COVERT_INIT,
-            S2C_Transition::S2C_CONFIRM_RECONNECT,
-            S2C_Transition::S2C_SESSION_CLOSE,
-            S2C_Transition::S2C_ERROR,
-        ];
-        values
+    pub fn clear_client_ip(&mut self) {
+        self.client_ip.clear();
     }
 
-    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
-        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
-        descriptor.get(|| {
-            ::protobuf::reflect::EnumDescriptor::new_pb_name::<S2C_Transition>("S2C_Transition", file_descriptor_proto())
-        })
+    pub fn has_client_ip(&self) -> bool {
+        self.client_ip.is_some()
     }
-}
 
-impl ::std::marker::Copy for S2C_Transition {
-}
+    // Param is passed by value, moved
+    pub fn set_client_ip(&mut self, v: ::std::string::String) {
+        self.client_ip = ::protobuf::SingularField::some(v);
+    }
 
-impl ::std::default::Default for S2C_Transition {
-    fn default() -> Self {
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_client_ip(&mut self) -> &mut ::std::string::String {
+        if self.client_ip.is_none() {
+            self.client_ip.set_default();
+        }
+        self.client_ip.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_client_ip(&mut self) -> ::std::string::String {
+        self.client_ip.take().unwrap_or_else(|| ::std::string::String::new())
+    }
+
+    // optional uint32 dst_port = 4;
+
+
+    pub fn get_dst_port(&self) -> u32 {
+        self.dst_port.unwrap_or(0)
+    }
+    pub fn clear_dst_port(&mut self) {
+        self.dst_port = ::std::option::Option::None;
+    }
+
+    pub fn has_dst_port(&self) -> bool {
+        self.dst_port.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_dst_port(&mut self, v: u32) {
+        self.dst_port = ::std::option::Option::Some(v);
+    }
+
+    // optional .tapdance.IPProto proto = 5;
+
+
+    pub fn get_proto(&self) -> IPProto {
+        self.proto.unwrap_or(IPProto::Unk)
+    }
+    pub fn clear_proto(&mut self) {
+        self.proto = ::std::option::Option::None;
+    }
+
+    pub fn has_proto(&self) -> bool {
+        self.proto.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_proto(&mut self, v: IPProto) {
+        self.proto = ::std::option::Option::Some(v);
+    }
+
+    // optional uint32 src_port = 6;
+
+
+    pub fn get_src_port(&self) -> u32 {
+        self.src_port.unwrap_or(0)
+    }
+    pub fn clear_src_port(&mut self) {
+        self.src_port = ::std::option::Option::None;
+    }
+
+    pub fn has_src_port(&self) -> bool {
+        self.src_port.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_src_port(&mut self, v: u32) {
+        self.src_port = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 time_ns = 7;
+
+
+    pub fn get_time_ns(&self) -> u64 {
+        self.time_ns.unwrap_or(0)
+    }
+    pub fn clear_time_ns(&mut self) {
+        self.time_ns = ::std::option::Option::None;
+    }
+
+    pub fn has_time_ns(&self) -> bool {
+        self.time_ns.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_time_ns(&mut self, v: u64) {
+        self.time_ns = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 first_syn_ns = 8;
+
+
+    pub fn get_first_syn_ns(&self) -> u64 {
+        self.first_syn_ns.unwrap_or(0)
+    }
+    pub fn clear_first_syn_ns(&mut self) {
+        self.first_syn_ns = ::std::option::Option::None;
+    }
+
+    pub fn has_first_syn_ns(&self) -> bool {
+        self.first_syn_ns.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_first_syn_ns(&mut self, v: u64) {
+        self.first_syn_ns = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 conn_start_ns = 9;
+
+
+    pub fn get_conn_start_ns(&self) -> u64 {
+        self.conn_start_ns.unwrap_or(0)
+    }
+    pub fn clear_conn_start_ns(&mut self) {
+        self.conn_start_ns = ::std::option::Option::None;
+    }
+
+    pub fn has_conn_start_ns(&self) -> bool {
+        self.conn_start_ns.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_conn_start_ns(&mut self, v: u64) {
+        self.conn_start_ns = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 packets = 10;
+
+
+    pub fn get_packets(&self) -> u64 {
+        self.packets.unwrap_or(0)
+    }
+    pub fn clear_packets(&mut self) {
+        self.packets = ::std::option::Option::None;
+    }
+
+    pub fn has_packets(&self) -> bool {
+        self.packets.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_packets(&mut self, v: u64) {
+        self.packets = ::std::option::Option::Some(v);
+    }
+
+    // optional uint64 bytes = 11;
+
+
+    pub fn get_bytes(&self) -> u64 {
+        self.bytes.unwrap_or(0)
+    }
+    pub fn clear_bytes(&mut self) {
+        self.bytes = ::std::option::Option::None;
+    }
+
+    pub fn has_bytes(&self) -> bool {
+        self.bytes.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_bytes(&mut self, v: u64) {
+        self.bytes = ::std::option::Option::Some(v);
+    }
+
+    // optional uint32 connections = 12;
+
+
+    pub fn get_connections(&self) -> u32 {
+        self.connections.unwrap_or(0)
+    }
+    pub fn clear_connections(&mut self) {
+        self.connections = ::std::option::Option::None;
+    }
+
+    pub fn has_connections(&self) -> bool {
+        self.connections.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_connections(&mut self, v: u32) {
+        self.connections = ::std::option::Option::Some(v);
+    }
+}
+
+impl ::protobuf::Message for PhantomEvent {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_proto2_enum_with_unknown_fields_into(wire_type, is, &mut self.event, 1, &mut self.unknown_fields)?
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.phantom_ip)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_string_into(wire_type, is, &mut self.client_ip)?;
+                },
+                4 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.dst_port = ::std::option::Option::Some(tmp);
+                },
+                5 => {
+                    ::protobuf::rt::read_proto2_enum_with_unknown_fields_into(wire_type, is, &mut self.proto, 5, &mut self.unknown_fields)?
+                },
+                6 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.src_port = ::std::option::Option::Some(tmp);
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.time_ns = ::std::option::Option::Some(tmp);
+                },
+                8 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.first_syn_ns = ::std::option::Option::Some(tmp);
+                },
+                9 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.conn_start_ns = ::std::option::Option::Some(tmp);
+                },
+                10 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.packets = ::std::option::Option::Some(tmp);
+                },
+                11 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.bytes = ::std::option::Option::Some(tmp);
+                },
+                12 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint32()?;
+                    self.connections = ::std::option::Option::Some(tmp);
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if let Some(v) = self.event {
+            my_size += ::protobuf::rt::enum_size(1, v);
+        }
+        if let Some(ref v) = self.phantom_ip.as_ref() {
+            my_size += ::protobuf::rt::string_size(2, &v);
+        }
+        if let Some(ref v) = self.client_ip.as_ref() {
+            my_size += ::protobuf::rt::string_size(3, &v);
+        }
+        if let Some(v) = self.dst_port {
+            my_size += ::protobuf::rt::value_size(4, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.proto {
+            my_size += ::protobuf::rt::enum_size(5, v);
+        }
+        if let Some(v) = self.src_port {
+            my_size += ::protobuf::rt::value_size(6, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.time_ns {
+            my_size += ::protobuf::rt::value_size(7, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.first_syn_ns {
+            my_size += ::protobuf::rt::value_size(8, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.conn_start_ns {
+            my_size += ::protobuf::rt::value_size(9, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.packets {
+            my_size += ::protobuf::rt::value_size(10, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.bytes {
+            my_size += ::protobuf::rt::value_size(11, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if let Some(v) = self.connections {
+            my_size += ::protobuf::rt::value_size(12, v, ::protobuf::wire_format::WireTypeVarint);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if let Some(v) = self.event {
+            os.write_enum(1, ::protobuf::ProtobufEnum::value(&v))?;
+        }
+        if let Some(ref v) = self.phantom_ip.as_ref() {
+            os.write_string(2, &v)?;
+        }
+        if let Some(ref v) = self.client_ip.as_ref() {
+            os.write_string(3, &v)?;
+        }
+        if let Some(v) = self.dst_port {
+            os.write_uint32(4, v)?;
+        }
+        if let Some(v) = self.proto {
+            os.write_enum(5, ::protobuf::ProtobufEnum::value(&v))?;
+        }
+        if let Some(v) = self.src_port {
+            os.write_uint32(6, v)?;
+        }
+        if let Some(v) = self.time_ns {
+            os.write_uint64(7, v)?;
+        }
+        if let Some(v) = self.first_syn_ns {
+            os.write_uint64(8, v)?;
+        }
+        if let Some(v) = self.conn_start_ns {
+            os.write_uint64(9, v)?;
+        }
+        if let Some(v) = self.packets {
+            os.write_uint64(10, v)?;
+        }
+        if let Some(v) = self.bytes {
+            os.write_uint64(11, v)?;
+        }
+        if let Some(v) = self.connections {
+            os.write_uint32(12, v)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> PhantomEvent {
+        PhantomEvent::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeEnum<PhantomEventType>>(
+                "event",
+                |m: &PhantomEvent| { &m.event },
+                |m: &mut PhantomEvent| { &mut m.event },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "phantom_ip",
+                |m: &PhantomEvent| { &m.phantom_ip },
+                |m: &mut PhantomEvent| { &mut m.phantom_ip },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_singular_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "client_ip",
+                |m: &PhantomEvent| { &m.client_ip },
+                |m: &mut PhantomEvent| { &mut m.client_ip },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                "dst_port",
+                |m: &PhantomEvent| { &m.dst_port },
+                |m: &mut PhantomEvent| { &mut m.dst_port },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeEnum<IPProto>>(
+                "proto",
+                |m: &PhantomEvent| { &m.proto },
+                |m: &mut PhantomEvent| { &mut m.proto },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                "src_port",
+                |m: &PhantomEvent| { &m.src_port },
+                |m: &mut PhantomEvent| { &mut m.src_port },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "time_ns",
+                |m: &PhantomEvent| { &m.time_ns },
+                |m: &mut PhantomEvent| { &mut m.time_ns },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "first_syn_ns",
+                |m: &PhantomEvent| { &m.first_syn_ns },
+                |m: &mut PhantomEvent| { &mut m.first_syn_ns },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "conn_start_ns",
+                |m: &PhantomEvent| { &m.conn_start_ns },
+                |m: &mut PhantomEvent| { &mut m.conn_start_ns },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "packets",
+                |m: &PhantomEvent| { &m.packets },
+                |m: &mut PhantomEvent| { &mut m.packets },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "bytes",
+                |m: &PhantomEvent| { &m.bytes },
+                |m: &mut PhantomEvent| { &mut m.bytes },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_option_accessor::<_, ::protobuf::types::ProtobufTypeUint32>(
+                "connections",
+                |m: &PhantomEvent| { &m.connections },
+                |m: &mut PhantomEvent| { &mut m.connections },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<PhantomEvent>(
+                "PhantomEvent",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static PhantomEvent {
+        static instance: ::protobuf::rt::LazyV2<PhantomEvent> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(PhantomEvent::new)
+    }
+}
+
+impl ::protobuf::Clear for PhantomEvent {
+    fn clear(&mut self) {
+        self.event = ::std::option::Option::None;
+        self.phantom_ip.clear();
+        self.client_ip.clear();
+        self.dst_port = ::std::option::Option::None;
+        self.proto = ::std::option::Option::None;
+        self.src_port = ::std::option::Option::None;
+        self.time_ns = ::std::option::Option::None;
+        self.first_syn_ns = ::std::option::Option::None;
+        self.conn_start_ns = ::std::option::Option::None;
+        self.packets = ::std::option::Option::None;
+        self.bytes = ::std::option::Option::None;
+        self.connections = ::std::option::Option::None;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for PhantomEvent {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for PhantomEvent {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum KeyType {
+    AES_GCM_128 = 90,
+    AES_GCM_256 = 91,
+}
+
+impl ::protobuf::ProtobufEnum for KeyType {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<KeyType> {
+        match value {
+            90 => ::std::option::Option::Some(KeyType::AES_GCM_128),
+            91 => ::std::option::Option::Some(KeyType::AES_GCM_256),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [KeyType] = &[
+            KeyType::AES_GCM_128,
+            KeyType::AES_GCM_256,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<KeyType>("KeyType", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for KeyType {
+}
+
+// Note, `Default` is implemented although default value is not 0
+impl ::std::default::Default for KeyType {
+    fn default() -> Self {
+        KeyType::AES_GCM_128
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for KeyType {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum C2S_Transition {
+    C2S_NO_CHANGE = 0,
+    C2S_SESSION_INIT = 1,
+    C2S_SESSION_COVERT_INIT = 11,
+    C2S_EXPECT_RECONNECT = 2,
+    C2S_SESSION_CLOSE = 3,
+    C2S_YIELD_UPLOAD = 4,
+    C2S_ACQUIRE_UPLOAD = 5,
+    C2S_EXPECT_UPLOADONLY_RECONN = 6,
+    C2S_ERROR = 255,
+}
+
+impl ::protobuf::ProtobufEnum for C2S_Transition {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<C2S_Transition> {
+        match value {
+            0 => ::std::option::Option::Some(C2S_Transition::C2S_NO_CHANGE),
+            1 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_INIT),
+            11 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_COVERT_INIT),
+            2 => ::std::option::Option::Some(C2S_Transition::C2S_EXPECT_RECONNECT),
+            3 => ::std::option::Option::Some(C2S_Transition::C2S_SESSION_CLOSE),
+            4 => ::std::option::Option::Some(C2S_Transition::C2S_YIELD_UPLOAD),
+            5 => ::std::option::Option::Some(C2S_Transition::C2S_ACQUIRE_UPLOAD),
+            6 => ::std::option::Option::Some(C2S_Transition::C2S_EXPECT_UPLOADONLY_RECONN),
+            255 => ::std::option::Option::Some(C2S_Transition::C2S_ERROR),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [C2S_Transition] = &[
+            C2S_Transition::C2S_NO_CHANGE,
+            C2S_Transition::C2S_SESSION_INIT,
+            C2S_Transition::C2S_SESSION_COVERT_INIT,
+            C2S_Transition::C2S_EXPECT_RECONNECT,
+            C2S_Transition::C2S_SESSION_CLOSE,
+            C2S_Transition::C2S_YIELD_UPLOAD,
+            C2S_Transition::C2S_ACQUIRE_UPLOAD,
+            C2S_Transition::C2S_EXPECT_UPLOADONLY_RECONN,
+            C2S_Transition::C2S_ERROR,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<C2S_Transition>("C2S_Transition", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for C2S_Transition {
+}
+
+impl ::std::default::Default for C2S_Transition {
+    fn default() -> Self {
+        C2S_Transition::C2S_NO_CHANGE
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for C2S_Transition {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum S2C_Transition {
+    S2C_NO_CHANGE = 0,
+    S2C_SESSION_INIT = 1,
+    S2C_SESSION_COVERT_INIT = 11,
+    S2C_CONFIRM_RECONNECT = 2,
+    S2C_SESSION_CLOSE = 3,
+    S2C_ERROR = 255,
+}
+
+impl ::protobuf::ProtobufEnum for S2C_Transition {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<S2C_Transition> {
+        match value {
+            0 => ::std::option::Option::Some(S2C_Transition::S2C_NO_CHANGE),
+            1 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_INIT),
+            11 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_COVERT_INIT),
+            2 => ::std::option::Option::Some(S2C_Transition::S2C_CONFIRM_RECONNECT),
+            3 => ::std::option::Option::Some(S2C_Transition::S2C_SESSION_CLOSE),
+            255 => ::std::option::Option::Some(S2C_Transition::S2C_ERROR),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [S2C_Transition] = &[
+            S2C_Transition::S2C_NO_CHANGE,
+            S2C_Transition::S2C_SESSION_INIT,
+            S2C_Transition::S2C_SESSION_COVERT_INIT,
+            S2C_Transition::S2C_CONFIRM_RECONNECT,
+            S2C_Transition::S2C_SESSION_CLOSE,
+            S2C_Transition::S2C_ERROR,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<S2C_Transition>("S2C_Transition", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for S2C_Transition {
+}
+
+impl ::std::default::Default for S2C_Transition {
+    fn default() -> Self {
         S2C_Transition::S2C_NO_CHANGE
     }
 }
@@ -4264,6 +5015,170 @@ impl ::protobuf::reflect::ProtobufValue for RegistrationSource {
     }
 }
 
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum IPProto {
+    Unk = 0,
+    Tcp = 1,
+    Udp = 2,
+}
+
+impl ::protobuf::ProtobufEnum for IPProto {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<IPProto> {
+        match value {
+            0 => ::std::option::Option::Some(IPProto::Unk),
+            1 => ::std::option::Option::Some(IPProto::Tcp),
+            2 => ::std::option::Option::Some(IPProto::Udp),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [IPProto] = &[
+            IPProto::Unk,
+            IPProto::Tcp,
+            IPProto::Udp,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<IPProto>("IPProto", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for IPProto {
+}
+
+impl ::std::default::Default for IPProto {
+    fn default() -> Self {
+        IPProto::Unk
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for IPProto {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum StationOperation {
+    Add = 1,
+    Revoke = 2,
+    ExtendTo = 3,
+    RevokeClient = 4,
+}
+
+impl ::protobuf::ProtobufEnum for StationOperation {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<StationOperation> {
+        match value {
+            1 => ::std::option::Option::Some(StationOperation::Add),
+            2 => ::std::option::Option::Some(StationOperation::Revoke),
+            3 => ::std::option::Option::Some(StationOperation::ExtendTo),
+            4 => ::std::option::Option::Some(StationOperation::RevokeClient),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [StationOperation] = &[
+            StationOperation::Add,
+            StationOperation::Revoke,
+            StationOperation::ExtendTo,
+            StationOperation::RevokeClient,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<StationOperation>("StationOperation", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for StationOperation {
+}
+
+// Note, `Default` is implemented although default value is not 0
+impl ::std::default::Default for StationOperation {
+    fn default() -> Self {
+        StationOperation::Add
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for StationOperation {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug,Hash)]
+pub enum PhantomEventType {
+    FirstSyn = 1,
+    ConnectionClosed = 2,
+    ExpiredUnused = 3,
+}
+
+impl ::protobuf::ProtobufEnum for PhantomEventType {
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<PhantomEventType> {
+        match value {
+            1 => ::std::option::Option::Some(PhantomEventType::FirstSyn),
+            2 => ::std::option::Option::Some(PhantomEventType::ConnectionClosed),
+            3 => ::std::option::Option::Some(PhantomEventType::ExpiredUnused),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn values() -> &'static [Self] {
+        static values: &'static [PhantomEventType] = &[
+            PhantomEventType::FirstSyn,
+            PhantomEventType::ConnectionClosed,
+            PhantomEventType::ExpiredUnused,
+        ];
+        values
+    }
+
+    fn enum_descriptor_static() -> &'static ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            ::protobuf::reflect::EnumDescriptor::new_pb_name::<PhantomEventType>("PhantomEventType", file_descriptor_proto())
+        })
+    }
+}
+
+impl ::std::marker::Copy for PhantomEventType {
+}
+
+// Note, `Default` is implemented although default value is not 0
+impl ::std::default::Default for PhantomEventType {
+    fn default() -> Self {
+        PhantomEventType::FirstSyn
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for PhantomEventType {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Enum(::protobuf::ProtobufEnum::descriptor(self))
+    }
+}
+
 static file_descriptor_proto_data: &'static [u8] = b"\
     \n\x10signalling.proto\x12\x08tapdance\"A\n\x06PubKey\x12\x10\n\x03key\
     \x18\x01\x20\x01(\x0cR\x03key\x12%\n\x04type\x18\x02\x20\x01(\x0e2\x11.t\
@@ -4307,252 +5222,271 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \x15maskedDecoyServerName\x12\x1d\n\nv6_support\x18\x16\x20\x01(\x08R\tv\
     6Support\x12\x1d\n\nv4_support\x18\x17\x20\x01(\x08R\tv4Support\x121\n\
     \x05flags\x18\x18\x20\x01(\x0b2\x1b.tapdance.RegistrationFlagsR\x05flags\
-    \x12\x18\n\x07padding\x18d\x20\x01(\x0cR\x07padding\"\xa6\x02\n\nC2SWrap\
+    \x12\x18\n\x07padding\x18d\x20\x01(\x0cR\x07padding\"\xcd\x02\n\nC2SWrap\
     per\x12#\n\rshared_secret\x18\x01\x20\x01(\x0cR\x0csharedSecret\x12L\n\
     \x14registration_payload\x18\x03\x20\x01(\x0b2\x19.tapdance.ClientToStat\
     ionR\x13registrationPayload\x12M\n\x13registration_source\x18\x04\x20\
     \x01(\x0e2\x1c.tapdance.RegistrationSourceR\x12registrationSource\x121\n\
     \x14registration_address\x18\x06\x20\x01(\x0cR\x13registrationAddress\
-    \x12#\n\rdecoy_address\x18\x07\x20\x01(\x0cR\x0cdecoyAddress\"\xdd\x01\n\
-    \x0cSessionStats\x120\n\x14failed_decoys_amount\x18\x14\x20\x01(\rR\x12f\
-    ailedDecoysAmount\x121\n\x15total_time_to_connect\x18\x1f\x20\x01(\rR\
-    \x12totalTimeToConnect\x12$\n\x0ertt_to_station\x18!\x20\x01(\rR\x0crttT\
-    oStation\x12\x20\n\x0ctls_to_decoy\x18&\x20\x01(\rR\ntlsToDecoy\x12\x20\
-    \n\x0ctcp_to_decoy\x18'\x20\x01(\rR\ntcpToDecoy\"n\n\x11StationToDetecto\
-    r\x12\x1d\n\nphantom_ip\x18\x01\x20\x01(\tR\tphantomIp\x12\x1b\n\tclient\
-    _ip\x18\x02\x20\x01(\tR\x08clientIp\x12\x1d\n\ntimeout_ns\x18\x03\x20\
-    \x01(\x04R\ttimeoutNs*+\n\x07KeyType\x12\x0f\n\x0bAES_GCM_128\x10Z\x12\
-    \x0f\n\x0bAES_GCM_256\x10[*\xe7\x01\n\x0eC2S_Transition\x12\x11\n\rC2S_N\
-    O_CHANGE\x10\0\x12\x14\n\x10C2S_SESSION_INIT\x10\x01\x12\x1b\n\x17C2S_SE\
-    SSION_COVERT_INIT\x10\x0b\x12\x18\n\x14C2S_EXPECT_RECONNECT\x10\x02\x12\
-    \x15\n\x11C2S_SESSION_CLOSE\x10\x03\x12\x14\n\x10C2S_YIELD_UPLOAD\x10\
-    \x04\x12\x16\n\x12C2S_ACQUIRE_UPLOAD\x10\x05\x12\x20\n\x1cC2S_EXPECT_UPL\
-    OADONLY_RECONN\x10\x06\x12\x0e\n\tC2S_ERROR\x10\xff\x01*\x98\x01\n\x0eS2\
-    C_Transition\x12\x11\n\rS2C_NO_CHANGE\x10\0\x12\x14\n\x10S2C_SESSION_INI\
-    T\x10\x01\x12\x1b\n\x17S2C_SESSION_COVERT_INIT\x10\x0b\x12\x19\n\x15S2C_\
-    CONFIRM_RECONNECT\x10\x02\x12\x15\n\x11S2C_SESSION_CLOSE\x10\x03\x12\x0e\
-    \n\tS2C_ERROR\x10\xff\x01*\xac\x01\n\x0eErrorReasonS2C\x12\x0c\n\x08NO_E\
-    RROR\x10\0\x12\x11\n\rCOVERT_STREAM\x10\x01\x12\x13\n\x0fCLIENT_REPORTED\
-    \x10\x02\x12\x13\n\x0fCLIENT_PROTOCOL\x10\x03\x12\x14\n\x10STATION_INTER\
-    NAL\x10\x04\x12\x12\n\x0eDECOY_OVERLOAD\x10\x05\x12\x11\n\rCLIENT_STREAM\
+    \x12#\n\rdecoy_address\x18\x07\x20\x01(\x0cR\x0cdecoyAddress\x12%\n\x0ek\
+    ey_generation\x18\x08\x20\x01(\rR\rkeyGeneration\"\xdd\x01\n\x0cSessionS\
+    tats\x120\n\x14failed_decoys_amount\x18\x14\x20\x01(\rR\x12failedDecoysA\
+    mount\x121\n\x15total_time_to_connect\x18\x1f\x20\x01(\rR\x12totalTimeTo\
+    Connect\x12$\n\x0ertt_to_station\x18!\x20\x01(\rR\x0crttToStation\x12\
+    \x20\n\x0ctls_to_decoy\x18&\x20\x01(\rR\ntlsToDecoy\x12\x20\n\x0ctcp_to_\
+    decoy\x18'\x20\x01(\rR\ntcpToDecoy\"\xec\x01\n\x11StationToDetector\x12\
+    \x1d\n\nphantom_ip\x18\x01\x20\x01(\tR\tphantomIp\x12\x1b\n\tclient_ip\
+    \x18\x02\x20\x01(\tR\x08clientIp\x12\x1d\n\ntimeout_ns\x18\x03\x20\x01(\
+    \x04R\ttimeoutNs\x12\x19\n\x08dst_port\x18\x04\x20\x01(\rR\x07dstPort\
+    \x12'\n\x05proto\x18\x05\x20\x01(\x0e2\x11.tapdance.IPProtoR\x05proto\
+    \x128\n\toperation\x18\x06\x20\x01(\x0e2\x1a.tapdance.StationOperationR\
+    \toperation\"\x8c\x03\n\x0cPhantomEvent\x120\n\x05event\x18\x01\x20\x01(\
+    \x0e2\x1a.tapdance.PhantomEventTypeR\x05event\x12\x1d\n\nphantom_ip\x18\
+    \x02\x20\x01(\tR\tphantomIp\x12\x1b\n\tclient_ip\x18\x03\x20\x01(\tR\x08\
+    clientIp\x12\x19\n\x08dst_port\x18\x04\x20\x01(\rR\x07dstPort\x12'\n\x05\
+    proto\x18\x05\x20\x01(\x0e2\x11.tapdance.IPProtoR\x05proto\x12\x19\n\x08\
+    src_port\x18\x06\x20\x01(\rR\x07srcPort\x12\x17\n\x07time_ns\x18\x07\x20\
+    \x01(\x04R\x06timeNs\x12\x20\n\x0cfirst_syn_ns\x18\x08\x20\x01(\x04R\nfi\
+    rstSynNs\x12\"\n\rconn_start_ns\x18\t\x20\x01(\x04R\x0bconnStartNs\x12\
+    \x18\n\x07packets\x18\n\x20\x01(\x04R\x07packets\x12\x14\n\x05bytes\x18\
+    \x0b\x20\x01(\x04R\x05bytes\x12\x20\n\x0bconnections\x18\x0c\x20\x01(\rR\
+    \x0bconnections*+\n\x07KeyType\x12\x0f\n\x0bAES_GCM_128\x10Z\x12\x0f\n\
+    \x0bAES_GCM_256\x10[*\xe7\x01\n\x0eC2S_Transition\x12\x11\n\rC2S_NO_CHAN\
+    GE\x10\0\x12\x14\n\x10C2S_SESSION_INIT\x10\x01\x12\x1b\n\x17C2S_SESSION_\
+    COVERT_INIT\x10\x0b\x12\x18\n\x14C2S_EXPECT_RECONNECT\x10\x02\x12\x15\n\
+    \x11C2S_SESSION_CLOSE\x10\x03\x12\x14\n\x10C2S_YIELD_UPLOAD\x10\x04\x12\
+    \x16\n\x12C2S_ACQUIRE_UPLOAD\x10\x05\x12\x20\n\x1cC2S_EXPECT_UPLOADONLY_\
+    RECONN\x10\x06\x12\x0e\n\tC2S_ERROR\x10\xff\x01*\x98\x01\n\x0eS2C_Transi\
+    tion\x12\x11\n\rS2C_NO_CHANGE\x10\0\x12\x14\n\x10S2C_SESSION_INIT\x10\
+    \x01\x12\x1b\n\x17S2C_SESSION_COVERT_INIT\x10\x0b\x12\x19\n\x15S2C_CONFI\
+    RM_RECONNECT\x10\x02\x12\x15\n\x11S2C_SESSION_CLOSE\x10\x03\x12\x0e\n\tS\
+    2C_ERROR\x10\xff\x01*\xac\x01\n\x0eErrorReasonS2C\x12\x0c\n\x08NO_ERROR\
+    \x10\0\x12\x11\n\rCOVERT_STREAM\x10\x01\x12\x13\n\x0fCLIENT_REPORTED\x10\
+    \x02\x12\x13\n\x0fCLIENT_PROTOCOL\x10\x03\x12\x14\n\x10STATION_INTERNAL\
+    \x10\x04\x12\x12\n\x0eDECOY_OVERLOAD\x10\x05\x12\x11\n\rCLIENT_STREAM\
     \x10d\x12\x12\n\x0eCLIENT_TIMEOUT\x10e*-\n\rTransportType\x12\x08\n\x04N\
     ull\x10\0\x12\x07\n\x03Min\x10\x01\x12\t\n\x05Obfs4\x10\x02*Q\n\x12Regis\
     trationSource\x12\x0f\n\x0bUnspecified\x10\0\x12\x0c\n\x08Detector\x10\
-    \x01\x12\x07\n\x03API\x10\x02\x12\x13\n\x0fDetectorPrescan\x10\x03J\x81V\
-    \n\x07\x12\x05\0\0\x87\x02\x01\n\x08\n\x01\x0c\x12\x03\0\0\x12\n\xb0\x01\
-    \n\x01\x02\x12\x03\x06\x08\x102\xa5\x01\x20TODO:\x20We're\x20using\x20pr\
-    oto2\x20because\x20it's\x20the\x20default\x20on\x20Ubuntu\x2016.04.\n\
-    \x20At\x20some\x20point\x20we\x20will\x20want\x20to\x20migrate\x20to\x20\
-    proto3,\x20but\x20we\x20are\x20not\n\x20using\x20any\x20proto3\x20featur\
-    es\x20yet.\n\n\n\n\x02\x05\0\x12\x04\x08\0\x0b\x01\n\n\n\x03\x05\0\x01\
-    \x12\x03\x08\x05\x0c\n\x0b\n\x04\x05\0\x02\0\x12\x03\t\x04\x15\n\x0c\n\
-    \x05\x05\0\x02\0\x01\x12\x03\t\x04\x0f\n\x0c\n\x05\x05\0\x02\0\x02\x12\
-    \x03\t\x12\x14\n\x20\n\x04\x05\0\x02\x01\x12\x03\n\x04\x15\"\x13\x20not\
-    \x20supported\x20atm\n\n\x0c\n\x05\x05\0\x02\x01\x01\x12\x03\n\x04\x0f\n\
-    \x0c\n\x05\x05\0\x02\x01\x02\x12\x03\n\x12\x14\n\n\n\x02\x04\0\x12\x04\r\
-    \0\x12\x01\n\n\n\x03\x04\0\x01\x12\x03\r\x08\x0e\n4\n\x04\x04\0\x02\0\
-    \x12\x03\x0f\x04\x1b\x1a'\x20A\x20public\x20key,\x20as\x20used\x20by\x20\
-    the\x20station.\n\n\x0c\n\x05\x04\0\x02\0\x04\x12\x03\x0f\x04\x0c\n\x0c\
-    \n\x05\x04\0\x02\0\x05\x12\x03\x0f\r\x12\n\x0c\n\x05\x04\0\x02\0\x01\x12\
-    \x03\x0f\x13\x16\n\x0c\n\x05\x04\0\x02\0\x03\x12\x03\x0f\x19\x1a\n\x0b\n\
-    \x04\x04\0\x02\x01\x12\x03\x11\x04\x1e\n\x0c\n\x05\x04\0\x02\x01\x04\x12\
-    \x03\x11\x04\x0c\n\x0c\n\x05\x04\0\x02\x01\x06\x12\x03\x11\r\x14\n\x0c\n\
-    \x05\x04\0\x02\x01\x01\x12\x03\x11\x15\x19\n\x0c\n\x05\x04\0\x02\x01\x03\
-    \x12\x03\x11\x1c\x1d\n\n\n\x02\x04\x01\x12\x04\x14\0:\x01\n\n\n\x03\x04\
-    \x01\x01\x12\x03\x14\x08\x14\n\xa1\x01\n\x04\x04\x01\x02\0\x12\x03\x19\
-    \x04!\x1a\x93\x01\x20The\x20hostname/SNI\x20to\x20use\x20for\x20this\x20\
-    host\n\n\x20The\x20hostname\x20is\x20the\x20only\x20required\x20field,\
-    \x20although\x20other\n\x20fields\x20are\x20expected\x20to\x20be\x20pres\
-    ent\x20in\x20most\x20cases.\n\n\x0c\n\x05\x04\x01\x02\0\x04\x12\x03\x19\
-    \x04\x0c\n\x0c\n\x05\x04\x01\x02\0\x05\x12\x03\x19\r\x13\n\x0c\n\x05\x04\
-    \x01\x02\0\x01\x12\x03\x19\x14\x1c\n\x0c\n\x05\x04\x01\x02\0\x03\x12\x03\
-    \x19\x1f\x20\n\xf7\x01\n\x04\x04\x01\x02\x01\x12\x03\x20\x04\"\x1a\xe9\
-    \x01\x20The\x2032-bit\x20ipv4\x20address,\x20in\x20network\x20byte\x20or\
-    der\n\n\x20If\x20the\x20IPv4\x20address\x20is\x20absent,\x20then\x20it\
-    \x20may\x20be\x20resolved\x20via\n\x20DNS\x20by\x20the\x20client,\x20or\
-    \x20the\x20client\x20may\x20discard\x20this\x20decoy\x20spec\n\x20if\x20\
-    local\x20DNS\x20is\x20untrusted,\x20or\x20the\x20service\x20may\x20be\
-    \x20multihomed.\n\n\x0c\n\x05\x04\x01\x02\x01\x04\x12\x03\x20\x04\x0c\n\
-    \x0c\n\x05\x04\x01\x02\x01\x05\x12\x03\x20\r\x14\n\x0c\n\x05\x04\x01\x02\
-    \x01\x01\x12\x03\x20\x15\x1d\n\x0c\n\x05\x04\x01\x02\x01\x03\x12\x03\x20\
-    \x20!\n>\n\x04\x04\x01\x02\x02\x12\x03#\x04\x20\x1a1\x20The\x20128-bit\
-    \x20ipv6\x20address,\x20in\x20network\x20byte\x20order\n\n\x0c\n\x05\x04\
-    \x01\x02\x02\x04\x12\x03#\x04\x0c\n\x0c\n\x05\x04\x01\x02\x02\x05\x12\
-    \x03#\r\x12\n\x0c\n\x05\x04\x01\x02\x02\x01\x12\x03#\x13\x1b\n\x0c\n\x05\
-    \x04\x01\x02\x02\x03\x12\x03#\x1e\x1f\n\x91\x01\n\x04\x04\x01\x02\x03\
-    \x12\x03)\x04\x1f\x1a\x83\x01\x20The\x20Tapdance\x20station\x20public\
-    \x20key\x20to\x20use\x20when\x20contacting\x20this\n\x20decoy\n\n\x20If\
-    \x20omitted,\x20the\x20default\x20station\x20public\x20key\x20(if\x20any\
-    )\x20is\x20used.\n\n\x0c\n\x05\x04\x01\x02\x03\x04\x12\x03)\x04\x0c\n\
-    \x0c\n\x05\x04\x01\x02\x03\x06\x12\x03)\r\x13\n\x0c\n\x05\x04\x01\x02\
-    \x03\x01\x12\x03)\x14\x1a\n\x0c\n\x05\x04\x01\x02\x03\x03\x12\x03)\x1d\
-    \x1e\n\xee\x01\n\x04\x04\x01\x02\x04\x12\x030\x04\x20\x1a\xe0\x01\x20The\
-    \x20maximum\x20duration,\x20in\x20milliseconds,\x20to\x20maintain\x20an\
-    \x20open\n\x20connection\x20to\x20this\x20decoy\x20(because\x20the\x20de\
-    coy\x20may\x20close\x20the\n\x20connection\x20itself\x20after\x20this\
-    \x20length\x20of\x20time)\n\n\x20If\x20omitted,\x20a\x20default\x20of\
-    \x2030,000\x20milliseconds\x20is\x20assumed.\n\n\x0c\n\x05\x04\x01\x02\
-    \x04\x04\x12\x030\x04\x0c\n\x0c\n\x05\x04\x01\x02\x04\x05\x12\x030\r\x13\
-    \n\x0c\n\x05\x04\x01\x02\x04\x01\x12\x030\x14\x1b\n\x0c\n\x05\x04\x01\
-    \x02\x04\x03\x12\x030\x1e\x1f\n\xb0\x02\n\x04\x04\x01\x02\x05\x12\x039\
-    \x04\x1f\x1a\xa2\x02\x20The\x20maximum\x20TCP\x20window\x20size\x20to\
-    \x20attempt\x20to\x20use\x20for\x20this\x20decoy.\n\n\x20If\x20omitted,\
-    \x20a\x20default\x20of\x2015360\x20is\x20assumed.\n\n\x20TODO:\x20the\
-    \x20default\x20is\x20based\x20on\x20the\x20current\x20heuristic\x20of\
-    \x20only\n\x20using\x20decoys\x20that\x20permit\x20windows\x20of\x2015KB\
-    \x20or\x20larger.\x20\x20If\x20this\n\x20heuristic\x20changes,\x20then\
-    \x20this\x20default\x20doesn't\x20make\x20sense.\n\n\x0c\n\x05\x04\x01\
-    \x02\x05\x04\x12\x039\x04\x0c\n\x0c\n\x05\x04\x01\x02\x05\x05\x12\x039\r\
-    \x13\n\x0c\n\x05\x04\x01\x02\x05\x01\x12\x039\x14\x1a\n\x0c\n\x05\x04\
-    \x01\x02\x05\x03\x12\x039\x1d\x1e\n\x83\x08\n\x02\x04\x02\x12\x04Q\0W\
-    \x012\xf6\x07\x20In\x20version\x201,\x20the\x20request\x20is\x20very\x20\
-    simple:\x20when\n\x20the\x20client\x20sends\x20a\x20MSG_PROTO\x20to\x20t\
-    he\x20station,\x20if\x20the\n\x20generation\x20number\x20is\x20present,\
-    \x20then\x20this\x20request\x20includes\n\x20(in\x20addition\x20to\x20wh\
-    atever\x20other\x20operations\x20are\x20part\x20of\x20the\n\x20request)\
-    \x20a\x20request\x20for\x20the\x20station\x20to\x20send\x20a\x20copy\x20\
-    of\n\x20the\x20current\x20decoy\x20set\x20that\x20has\x20a\x20generation\
-    \x20number\x20greater\n\x20than\x20the\x20generation\x20number\x20in\x20\
-    its\x20request.\n\n\x20If\x20the\x20response\x20contains\x20a\x20DecoyLi\
-    stUpdate\x20with\x20a\x20generation\x20number\x20equal\n\x20to\x20that\
-    \x20which\x20the\x20client\x20sent,\x20then\x20the\x20client\x20is\x20\"\
-    caught\x20up\"\x20with\n\x20the\x20station\x20and\x20the\x20response\x20\
-    contains\x20no\x20new\x20information\n\x20(and\x20all\x20other\x20fields\
-    \x20may\x20be\x20omitted\x20or\x20empty).\x20\x20Otherwise,\n\x20the\x20\
-    station\x20will\x20send\x20the\x20latest\x20configuration\x20information\
-    ,\n\x20along\x20with\x20its\x20generation\x20number.\n\n\x20The\x20stati\
-    on\x20can\x20also\x20send\x20ClientConf\x20messages\n\x20(as\x20part\x20\
-    of\x20Station2Client\x20messages)\x20whenever\x20it\x20wants.\n\x20The\
-    \x20client\x20is\x20expected\x20to\x20react\x20as\x20if\x20it\x20had\x20\
-    requested\n\x20such\x20messages\x20--\x20possibly\x20by\x20ignoring\x20t\
-    hem,\x20if\x20the\x20client\n\x20is\x20already\x20up-to-date\x20accordin\
-    g\x20to\x20the\x20generation\x20number.\n\n\n\n\x03\x04\x02\x01\x12\x03Q\
-    \x08\x12\n\x0b\n\x04\x04\x02\x02\0\x12\x03R\x04&\n\x0c\n\x05\x04\x02\x02\
-    \0\x04\x12\x03R\x04\x0c\n\x0c\n\x05\x04\x02\x02\0\x06\x12\x03R\r\x16\n\
-    \x0c\n\x05\x04\x02\x02\0\x01\x12\x03R\x17!\n\x0c\n\x05\x04\x02\x02\0\x03\
-    \x12\x03R$%\n\x0b\n\x04\x04\x02\x02\x01\x12\x03S\x04#\n\x0c\n\x05\x04\
-    \x02\x02\x01\x04\x12\x03S\x04\x0c\n\x0c\n\x05\x04\x02\x02\x01\x05\x12\
-    \x03S\r\x13\n\x0c\n\x05\x04\x02\x02\x01\x01\x12\x03S\x14\x1e\n\x0c\n\x05\
-    \x04\x02\x02\x01\x03\x12\x03S!\"\n\x0b\n\x04\x04\x02\x02\x02\x12\x03T\
-    \x04'\n\x0c\n\x05\x04\x02\x02\x02\x04\x12\x03T\x04\x0c\n\x0c\n\x05\x04\
-    \x02\x02\x02\x06\x12\x03T\r\x13\n\x0c\n\x05\x04\x02\x02\x02\x01\x12\x03T\
-    \x14\"\n\x0c\n\x05\x04\x02\x02\x02\x03\x12\x03T%&\n\x0b\n\x04\x04\x02\
-    \x02\x03\x12\x03U\x049\n\x0c\n\x05\x04\x02\x02\x03\x04\x12\x03U\x04\x0c\
-    \n\x0c\n\x05\x04\x02\x02\x03\x06\x12\x03U\r\x1f\n\x0c\n\x05\x04\x02\x02\
-    \x03\x01\x12\x03U\x204\n\x0c\n\x05\x04\x02\x02\x03\x03\x12\x03U78\n\x0b\
-    \n\x04\x04\x02\x02\x04\x12\x03V\x04'\n\x0c\n\x05\x04\x02\x02\x04\x04\x12\
-    \x03V\x04\x0c\n\x0c\n\x05\x04\x02\x02\x04\x06\x12\x03V\r\x13\n\x0c\n\x05\
-    \x04\x02\x02\x04\x01\x12\x03V\x14\"\n\x0c\n\x05\x04\x02\x02\x04\x03\x12\
-    \x03V%&\n\n\n\x02\x04\x03\x12\x04Y\0[\x01\n\n\n\x03\x04\x03\x01\x12\x03Y\
-    \x08\x11\n\x0b\n\x04\x04\x03\x02\0\x12\x03Z\x04)\n\x0c\n\x05\x04\x03\x02\
-    \0\x04\x12\x03Z\x04\x0c\n\x0c\n\x05\x04\x03\x02\0\x06\x12\x03Z\r\x19\n\
-    \x0c\n\x05\x04\x03\x02\0\x01\x12\x03Z\x1a$\n\x0c\n\x05\x04\x03\x02\0\x03\
-    \x12\x03Z'(\n\n\n\x02\x04\x04\x12\x04]\0_\x01\n\n\n\x03\x04\x04\x01\x12\
-    \x03]\x08\x1a\n\x0b\n\x04\x04\x04\x02\0\x12\x03^\x041\n\x0c\n\x05\x04\
-    \x04\x02\0\x04\x12\x03^\x04\x0c\n\x0c\n\x05\x04\x04\x02\0\x06\x12\x03^\r\
-    \x1b\n\x0c\n\x05\x04\x04\x02\0\x01\x12\x03^\x1c,\n\x0c\n\x05\x04\x04\x02\
-    \0\x03\x12\x03^/0\n\n\n\x02\x04\x05\x12\x04a\0d\x01\n\n\n\x03\x04\x05\
-    \x01\x12\x03a\x08\x16\n\x0b\n\x04\x04\x05\x02\0\x12\x03b\x04\x1f\n\x0c\n\
-    \x05\x04\x05\x02\0\x04\x12\x03b\x04\x0c\n\x0c\n\x05\x04\x05\x02\0\x05\
-    \x12\x03b\r\x13\n\x0c\n\x05\x04\x05\x02\0\x01\x12\x03b\x14\x1a\n\x0c\n\
-    \x05\x04\x05\x02\0\x03\x12\x03b\x1d\x1e\n\x0b\n\x04\x04\x05\x02\x01\x12\
-    \x03c\x04\x20\n\x0c\n\x05\x04\x05\x02\x01\x04\x12\x03c\x04\x0c\n\x0c\n\
-    \x05\x04\x05\x02\x01\x05\x12\x03c\r\x13\n\x0c\n\x05\x04\x05\x02\x01\x01\
-    \x12\x03c\x14\x1b\n\x0c\n\x05\x04\x05\x02\x01\x03\x12\x03c\x1e\x1f\n-\n\
-    \x02\x05\x01\x12\x04g\0q\x01\x1a!\x20State\x20transitions\x20of\x20the\
-    \x20client\n\n\n\n\x03\x05\x01\x01\x12\x03g\x05\x13\n\x0b\n\x04\x05\x01\
-    \x02\0\x12\x03h\x04\x16\n\x0c\n\x05\x05\x01\x02\0\x01\x12\x03h\x04\x11\n\
-    \x0c\n\x05\x05\x01\x02\0\x02\x12\x03h\x14\x15\n\"\n\x04\x05\x01\x02\x01\
-    \x12\x03i\x04\x19\"\x15\x20connect\x20me\x20to\x20squid\n\n\x0c\n\x05\
-    \x05\x01\x02\x01\x01\x12\x03i\x04\x14\n\x0c\n\x05\x05\x01\x02\x01\x02\
-    \x12\x03i\x17\x18\n,\n\x04\x05\x01\x02\x02\x12\x03j\x04!\"\x1f\x20connec\
-    t\x20me\x20to\x20provided\x20covert\n\n\x0c\n\x05\x05\x01\x02\x02\x01\
-    \x12\x03j\x04\x1b\n\x0c\n\x05\x05\x01\x02\x02\x02\x12\x03j\x1e\x20\n\x0b\
-    \n\x04\x05\x01\x02\x03\x12\x03k\x04\x1d\n\x0c\n\x05\x05\x01\x02\x03\x01\
-    \x12\x03k\x04\x18\n\x0c\n\x05\x05\x01\x02\x03\x02\x12\x03k\x1b\x1c\n\x0b\
-    \n\x04\x05\x01\x02\x04\x12\x03l\x04\x1a\n\x0c\n\x05\x05\x01\x02\x04\x01\
-    \x12\x03l\x04\x15\n\x0c\n\x05\x05\x01\x02\x04\x02\x12\x03l\x18\x19\n\x0b\
-    \n\x04\x05\x01\x02\x05\x12\x03m\x04\x19\n\x0c\n\x05\x05\x01\x02\x05\x01\
-    \x12\x03m\x04\x14\n\x0c\n\x05\x05\x01\x02\x05\x02\x12\x03m\x17\x18\n\x0b\
-    \n\x04\x05\x01\x02\x06\x12\x03n\x04\x1b\n\x0c\n\x05\x05\x01\x02\x06\x01\
-    \x12\x03n\x04\x16\n\x0c\n\x05\x05\x01\x02\x06\x02\x12\x03n\x19\x1a\n\x0b\
-    \n\x04\x05\x01\x02\x07\x12\x03o\x04%\n\x0c\n\x05\x05\x01\x02\x07\x01\x12\
-    \x03o\x04\x20\n\x0c\n\x05\x05\x01\x02\x07\x02\x12\x03o#$\n\x0b\n\x04\x05\
-    \x01\x02\x08\x12\x03p\x04\x14\n\x0c\n\x05\x05\x01\x02\x08\x01\x12\x03p\
-    \x04\r\n\x0c\n\x05\x05\x01\x02\x08\x02\x12\x03p\x10\x13\n-\n\x02\x05\x02\
-    \x12\x04t\0|\x01\x1a!\x20State\x20transitions\x20of\x20the\x20server\n\n\
-    \n\n\x03\x05\x02\x01\x12\x03t\x05\x13\n\x0b\n\x04\x05\x02\x02\0\x12\x03u\
-    \x04\x16\n\x0c\n\x05\x05\x02\x02\0\x01\x12\x03u\x04\x11\n\x0c\n\x05\x05\
-    \x02\x02\0\x02\x12\x03u\x14\x15\n!\n\x04\x05\x02\x02\x01\x12\x03v\x04\
-    \x19\"\x14\x20connected\x20to\x20squid\n\n\x0c\n\x05\x05\x02\x02\x01\x01\
-    \x12\x03v\x04\x14\n\x0c\n\x05\x05\x02\x02\x01\x02\x12\x03v\x17\x18\n'\n\
-    \x04\x05\x02\x02\x02\x12\x03w\x04!\"\x1a\x20connected\x20to\x20covert\
-    \x20host\n\n\x0c\n\x05\x05\x02\x02\x02\x01\x12\x03w\x04\x1b\n\x0c\n\x05\
-    \x05\x02\x02\x02\x02\x12\x03w\x1e\x20\n\x0b\n\x04\x05\x02\x02\x03\x12\
-    \x03x\x04\x1e\n\x0c\n\x05\x05\x02\x02\x03\x01\x12\x03x\x04\x19\n\x0c\n\
-    \x05\x05\x02\x02\x03\x02\x12\x03x\x1c\x1d\n\x0b\n\x04\x05\x02\x02\x04\
-    \x12\x03y\x04\x1a\n\x0c\n\x05\x05\x02\x02\x04\x01\x12\x03y\x04\x15\n\x0c\
-    \n\x05\x05\x02\x02\x04\x02\x12\x03y\x18\x19\nR\n\x04\x05\x02\x02\x05\x12\
-    \x03{\x04\x14\x1aE\x20TODO\x20should\x20probably\x20also\x20allow\x20EXP\
-    ECT_RECONNECT\x20here,\x20for\x20DittoTap\n\n\x0c\n\x05\x05\x02\x02\x05\
-    \x01\x12\x03{\x04\r\n\x0c\n\x05\x05\x02\x02\x05\x02\x12\x03{\x10\x13\n7\
-    \n\x02\x05\x03\x12\x05\x7f\0\x89\x01\x01\x1a*\x20Should\x20accompany\x20\
-    all\x20S2C_ERROR\x20messages.\n\n\n\n\x03\x05\x03\x01\x12\x03\x7f\x05\
-    \x13\n\x0c\n\x04\x05\x03\x02\0\x12\x04\x80\x01\x04\x11\n\r\n\x05\x05\x03\
-    \x02\0\x01\x12\x04\x80\x01\x04\x0c\n\r\n\x05\x05\x03\x02\0\x02\x12\x04\
-    \x80\x01\x0f\x10\n*\n\x04\x05\x03\x02\x01\x12\x04\x81\x01\x04\x16\"\x1c\
-    \x20Squid\x20TCP\x20connection\x20broke\n\n\r\n\x05\x05\x03\x02\x01\x01\
-    \x12\x04\x81\x01\x04\x11\n\r\n\x05\x05\x03\x02\x01\x02\x12\x04\x81\x01\
-    \x14\x15\n7\n\x04\x05\x03\x02\x02\x12\x04\x82\x01\x04\x18\")\x20You\x20t\
-    old\x20me\x20something\x20was\x20wrong,\x20client\n\n\r\n\x05\x05\x03\
-    \x02\x02\x01\x12\x04\x82\x01\x04\x13\n\r\n\x05\x05\x03\x02\x02\x02\x12\
-    \x04\x82\x01\x16\x17\n@\n\x04\x05\x03\x02\x03\x12\x04\x83\x01\x04\x18\"2\
-    \x20You\x20messed\x20up,\x20client\x20(e.g.\x20sent\x20a\x20bad\x20proto\
-    buf)\n\n\r\n\x05\x05\x03\x02\x03\x01\x12\x04\x83\x01\x04\x13\n\r\n\x05\
-    \x05\x03\x02\x03\x02\x12\x04\x83\x01\x16\x17\n\x17\n\x04\x05\x03\x02\x04\
-    \x12\x04\x84\x01\x04\x19\"\t\x20I\x20broke\n\n\r\n\x05\x05\x03\x02\x04\
-    \x01\x12\x04\x84\x01\x04\x14\n\r\n\x05\x05\x03\x02\x04\x02\x12\x04\x84\
-    \x01\x17\x18\nE\n\x04\x05\x03\x02\x05\x12\x04\x85\x01\x04\x17\"7\x20Ever\
-    ything's\x20fine,\x20but\x20don't\x20use\x20this\x20decoy\x20right\x20no\
-    w\n\n\r\n\x05\x05\x03\x02\x05\x01\x12\x04\x85\x01\x04\x12\n\r\n\x05\x05\
-    \x03\x02\x05\x02\x12\x04\x85\x01\x15\x16\nD\n\x04\x05\x03\x02\x06\x12\
-    \x04\x87\x01\x04\x18\"6\x20My\x20stream\x20to\x20you\x20broke.\x20(This\
-    \x20is\x20impossible\x20to\x20send)\n\n\r\n\x05\x05\x03\x02\x06\x01\x12\
-    \x04\x87\x01\x04\x11\n\r\n\x05\x05\x03\x02\x06\x02\x12\x04\x87\x01\x14\
-    \x17\nA\n\x04\x05\x03\x02\x07\x12\x04\x88\x01\x04\x19\"3\x20You\x20never\
-    \x20came\x20back.\x20(This\x20is\x20impossible\x20to\x20send)\n\n\r\n\
-    \x05\x05\x03\x02\x07\x01\x12\x04\x88\x01\x04\x12\n\r\n\x05\x05\x03\x02\
-    \x07\x02\x12\x04\x88\x01\x15\x18\n\x0c\n\x02\x05\x04\x12\x06\x8b\x01\0\
-    \x8f\x01\x01\n\x0b\n\x03\x05\x04\x01\x12\x04\x8b\x01\x05\x12\n\x0c\n\x04\
-    \x05\x04\x02\0\x12\x04\x8c\x01\x04\r\n\r\n\x05\x05\x04\x02\0\x01\x12\x04\
-    \x8c\x01\x04\x08\n\r\n\x05\x05\x04\x02\0\x02\x12\x04\x8c\x01\x0b\x0c\n`\
-    \n\x04\x05\x04\x02\x01\x12\x04\x8d\x01\x04\x0c\"R\x20Send\x20a\x2032-byt\
-    e\x20HMAC\x20id\x20to\x20let\x20the\x20station\x20distinguish\x20registr\
-    ations\x20to\x20same\x20host\n\n\r\n\x05\x05\x04\x02\x01\x01\x12\x04\x8d\
-    \x01\x04\x07\n\r\n\x05\x05\x04\x02\x01\x02\x12\x04\x8d\x01\n\x0b\n$\n\
-    \x04\x05\x04\x02\x02\x12\x04\x8e\x01\x04\x0e\"\x16\x20Not\x20implemented\
-    \x20yet?\n\n\r\n\x05\x05\x04\x02\x02\x01\x12\x04\x8e\x01\x04\t\n\r\n\x05\
-    \x05\x04\x02\x02\x02\x12\x04\x8e\x01\x0c\r\n\x0c\n\x02\x04\x06\x12\x06\
-    \x91\x01\0\xa8\x01\x01\n\x0b\n\x03\x04\x06\x01\x12\x04\x91\x01\x08\x17\n\
-    O\n\x04\x04\x06\x02\0\x12\x04\x93\x01\x04)\x1aA\x20Should\x20accompany\
-    \x20(at\x20least)\x20SESSION_INIT\x20and\x20CONFIRM_RECONNECT.\n\n\r\n\
-    \x05\x04\x06\x02\0\x04\x12\x04\x93\x01\x04\x0c\n\r\n\x05\x04\x06\x02\0\
-    \x05\x12\x04\x93\x01\r\x13\n\r\n\x05\x04\x06\x02\0\x01\x12\x04\x93\x01\
-    \x14$\n\r\n\x05\x04\x06\x02\0\x03\x12\x04\x93\x01'(\nv\n\x04\x04\x06\x02\
-    \x01\x12\x04\x97\x01\x041\x1ah\x20There\x20might\x20be\x20a\x20state\x20\
-    transition.\x20May\x20be\x20absent;\x20absence\x20should\x20be\n\x20trea\
-    ted\x20identically\x20to\x20NO_CHANGE.\n\n\r\n\x05\x04\x06\x02\x01\x04\
-    \x12\x04\x97\x01\x04\x0c\n\r\n\x05\x04\x06\x02\x01\x06\x12\x04\x97\x01\r\
-    \x1b\n\r\n\x05\x04\x06\x02\x01\x01\x12\x04\x97\x01\x1c,\n\r\n\x05\x04\
-    \x06\x02\x01\x03\x12\x04\x97\x01/0\nc\n\x04\x04\x06\x02\x02\x12\x04\x9b\
-    \x01\x04(\x1aU\x20The\x20station\x20can\x20send\x20client\x20config\x20i\
-    nfo\x20piggybacked\n\x20on\x20any\x20message,\x20as\x20it\x20sees\x20fit\
-    \n\n\r\n\x05\x04\x06\x02\x02\x04\x12\x04\x9b\x01\x04\x0c\n\r\n\x05\x04\
-    \x06\x02\x02\x06\x12\x04\x9b\x01\r\x17\n\r\n\x05\x04\x06\x02\x02\x01\x12\
-    \x04\x9b\x01\x18#\n\r\n\x05\x04\x06\x02\x02\x03\x12\x04\x9b\x01&'\nP\n\
-    \x04\x04\x06\x02\x03\x12\x04\x9e\x01\x04+\x1aB\x20If\x20state_transition\
-    \x20==\x20S2C_ERROR,\x20this\x20field\x20is\x20the\x20explanation.\n\n\r\
-    \n\x05\x04\x06\x02\x03\x04\x12\x04\x9e\x01\x04\x0c\n\r\n\x05\x04\x06\x02\
+    \x01\x12\x07\n\x03API\x10\x02\x12\x13\n\x0fDetectorPrescan\x10\x03*$\n\
+    \x07IPProto\x12\x07\n\x03Unk\x10\0\x12\x07\n\x03Tcp\x10\x01\x12\x07\n\
+    \x03Udp\x10\x02*G\n\x10StationOperation\x12\x07\n\x03Add\x10\x01\x12\n\n\
+    \x06Revoke\x10\x02\x12\x0c\n\x08ExtendTo\x10\x03\x12\x10\n\x0cRevokeClie\
+    nt\x10\x04*I\n\x10PhantomEventType\x12\x0c\n\x08FirstSyn\x10\x01\x12\x14\
+    \n\x10ConnectionClosed\x10\x02\x12\x11\n\rExpiredUnused\x10\x03J\xc8p\n\
+    \x07\x12\x05\0\0\xd2\x02\x01\n\x08\n\x01\x0c\x12\x03\0\0\x12\n\xb0\x01\n\
+    \x01\x02\x12\x03\x06\x08\x102\xa5\x01\x20TODO:\x20We're\x20using\x20prot\
+    o2\x20because\x20it's\x20the\x20default\x20on\x20Ubuntu\x2016.04.\n\x20A\
+    t\x20some\x20point\x20we\x20will\x20want\x20to\x20migrate\x20to\x20proto\
+    3,\x20but\x20we\x20are\x20not\n\x20using\x20any\x20proto3\x20features\
+    \x20yet.\n\n\n\n\x02\x05\0\x12\x04\x08\0\x0b\x01\n\n\n\x03\x05\0\x01\x12\
+    \x03\x08\x05\x0c\n\x0b\n\x04\x05\0\x02\0\x12\x03\t\x04\x15\n\x0c\n\x05\
+    \x05\0\x02\0\x01\x12\x03\t\x04\x0f\n\x0c\n\x05\x05\0\x02\0\x02\x12\x03\t\
+    \x12\x14\n\x20\n\x04\x05\0\x02\x01\x12\x03\n\x04\x15\"\x13\x20not\x20sup\
+    ported\x20atm\n\n\x0c\n\x05\x05\0\x02\x01\x01\x12\x03\n\x04\x0f\n\x0c\n\
+    \x05\x05\0\x02\x01\x02\x12\x03\n\x12\x14\n\n\n\x02\x04\0\x12\x04\r\0\x12\
+    \x01\n\n\n\x03\x04\0\x01\x12\x03\r\x08\x0e\n4\n\x04\x04\0\x02\0\x12\x03\
+    \x0f\x04\x1b\x1a'\x20A\x20public\x20key,\x20as\x20used\x20by\x20the\x20s\
+    tation.\n\n\x0c\n\x05\x04\0\x02\0\x04\x12\x03\x0f\x04\x0c\n\x0c\n\x05\
+    \x04\0\x02\0\x05\x12\x03\x0f\r\x12\n\x0c\n\x05\x04\0\x02\0\x01\x12\x03\
+    \x0f\x13\x16\n\x0c\n\x05\x04\0\x02\0\x03\x12\x03\x0f\x19\x1a\n\x0b\n\x04\
+    \x04\0\x02\x01\x12\x03\x11\x04\x1e\n\x0c\n\x05\x04\0\x02\x01\x04\x12\x03\
+    \x11\x04\x0c\n\x0c\n\x05\x04\0\x02\x01\x06\x12\x03\x11\r\x14\n\x0c\n\x05\
+    \x04\0\x02\x01\x01\x12\x03\x11\x15\x19\n\x0c\n\x05\x04\0\x02\x01\x03\x12\
+    \x03\x11\x1c\x1d\n\n\n\x02\x04\x01\x12\x04\x14\0:\x01\n\n\n\x03\x04\x01\
+    \x01\x12\x03\x14\x08\x14\n\xa1\x01\n\x04\x04\x01\x02\0\x12\x03\x19\x04!\
+    \x1a\x93\x01\x20The\x20hostname/SNI\x20to\x20use\x20for\x20this\x20host\
+    \n\n\x20The\x20hostname\x20is\x20the\x20only\x20required\x20field,\x20al\
+    though\x20other\n\x20fields\x20are\x20expected\x20to\x20be\x20present\
+    \x20in\x20most\x20cases.\n\n\x0c\n\x05\x04\x01\x02\0\x04\x12\x03\x19\x04\
+    \x0c\n\x0c\n\x05\x04\x01\x02\0\x05\x12\x03\x19\r\x13\n\x0c\n\x05\x04\x01\
+    \x02\0\x01\x12\x03\x19\x14\x1c\n\x0c\n\x05\x04\x01\x02\0\x03\x12\x03\x19\
+    \x1f\x20\n\xf7\x01\n\x04\x04\x01\x02\x01\x12\x03\x20\x04\"\x1a\xe9\x01\
+    \x20The\x2032-bit\x20ipv4\x20address,\x20in\x20network\x20byte\x20order\
+    \n\n\x20If\x20the\x20IPv4\x20address\x20is\x20absent,\x20then\x20it\x20m\
+    ay\x20be\x20resolved\x20via\n\x20DNS\x20by\x20the\x20client,\x20or\x20th\
+    e\x20client\x20may\x20discard\x20this\x20decoy\x20spec\n\x20if\x20local\
+    \x20DNS\x20is\x20untrusted,\x20or\x20the\x20service\x20may\x20be\x20mult\
+    ihomed.\n\n\x0c\n\x05\x04\x01\x02\x01\x04\x12\x03\x20\x04\x0c\n\x0c\n\
+    \x05\x04\x01\x02\x01\x05\x12\x03\x20\r\x14\n\x0c\n\x05\x04\x01\x02\x01\
+    \x01\x12\x03\x20\x15\x1d\n\x0c\n\x05\x04\x01\x02\x01\x03\x12\x03\x20\x20\
+    !\n>\n\x04\x04\x01\x02\x02\x12\x03#\x04\x20\x1a1\x20The\x20128-bit\x20ip\
+    v6\x20address,\x20in\x20network\x20byte\x20order\n\n\x0c\n\x05\x04\x01\
+    \x02\x02\x04\x12\x03#\x04\x0c\n\x0c\n\x05\x04\x01\x02\x02\x05\x12\x03#\r\
+    \x12\n\x0c\n\x05\x04\x01\x02\x02\x01\x12\x03#\x13\x1b\n\x0c\n\x05\x04\
+    \x01\x02\x02\x03\x12\x03#\x1e\x1f\n\x91\x01\n\x04\x04\x01\x02\x03\x12\
+    \x03)\x04\x1f\x1a\x83\x01\x20The\x20Tapdance\x20station\x20public\x20key\
+    \x20to\x20use\x20when\x20contacting\x20this\n\x20decoy\n\n\x20If\x20omit\
+    ted,\x20the\x20default\x20station\x20public\x20key\x20(if\x20any)\x20is\
+    \x20used.\n\n\x0c\n\x05\x04\x01\x02\x03\x04\x12\x03)\x04\x0c\n\x0c\n\x05\
+    \x04\x01\x02\x03\x06\x12\x03)\r\x13\n\x0c\n\x05\x04\x01\x02\x03\x01\x12\
+    \x03)\x14\x1a\n\x0c\n\x05\x04\x01\x02\x03\x03\x12\x03)\x1d\x1e\n\xee\x01\
+    \n\x04\x04\x01\x02\x04\x12\x030\x04\x20\x1a\xe0\x01\x20The\x20maximum\
+    \x20duration,\x20in\x20milliseconds,\x20to\x20maintain\x20an\x20open\n\
+    \x20connection\x20to\x20this\x20decoy\x20(because\x20the\x20decoy\x20may\
+    \x20close\x20the\n\x20connection\x20itself\x20after\x20this\x20length\
+    \x20of\x20time)\n\n\x20If\x20omitted,\x20a\x20default\x20of\x2030,000\
+    \x20milliseconds\x20is\x20assumed.\n\n\x0c\n\x05\x04\x01\x02\x04\x04\x12\
+    \x030\x04\x0c\n\x0c\n\x05\x04\x01\x02\x04\x05\x12\x030\r\x13\n\x0c\n\x05\
+    \x04\x01\x02\x04\x01\x12\x030\x14\x1b\n\x0c\n\x05\x04\x01\x02\x04\x03\
+    \x12\x030\x1e\x1f\n\xb0\x02\n\x04\x04\x01\x02\x05\x12\x039\x04\x1f\x1a\
+    \xa2\x02\x20The\x20maximum\x20TCP\x20window\x20size\x20to\x20attempt\x20\
+    to\x20use\x20for\x20this\x20decoy.\n\n\x20If\x20omitted,\x20a\x20default\
+    \x20of\x2015360\x20is\x20assumed.\n\n\x20TODO:\x20the\x20default\x20is\
+    \x20based\x20on\x20the\x20current\x20heuristic\x20of\x20only\n\x20using\
+    \x20decoys\x20that\x20permit\x20windows\x20of\x2015KB\x20or\x20larger.\
+    \x20\x20If\x20this\n\x20heuristic\x20changes,\x20then\x20this\x20default\
+    \x20doesn't\x20make\x20sense.\n\n\x0c\n\x05\x04\x01\x02\x05\x04\x12\x039\
+    \x04\x0c\n\x0c\n\x05\x04\x01\x02\x05\x05\x12\x039\r\x13\n\x0c\n\x05\x04\
+    \x01\x02\x05\x01\x12\x039\x14\x1a\n\x0c\n\x05\x04\x01\x02\x05\x03\x12\
+    \x039\x1d\x1e\n\x83\x08\n\x02\x04\x02\x12\x04Q\0W\x012\xf6\x07\x20In\x20\
+    version\x201,\x20the\x20request\x20is\x20very\x20simple:\x20when\n\x20th\
+    e\x20client\x20sends\x20a\x20MSG_PROTO\x20to\x20the\x20station,\x20if\
+    \x20the\n\x20generation\x20number\x20is\x20present,\x20then\x20this\x20r\
+    equest\x20includes\n\x20(in\x20addition\x20to\x20whatever\x20other\x20op\
+    erations\x20are\x20part\x20of\x20the\n\x20request)\x20a\x20request\x20fo\
+    r\x20the\x20station\x20to\x20send\x20a\x20copy\x20of\n\x20the\x20current\
+    \x20decoy\x20set\x20that\x20has\x20a\x20generation\x20number\x20greater\
+    \n\x20than\x20the\x20generation\x20number\x20in\x20its\x20request.\n\n\
+    \x20If\x20the\x20response\x20contains\x20a\x20DecoyListUpdate\x20with\
+    \x20a\x20generation\x20number\x20equal\n\x20to\x20that\x20which\x20the\
+    \x20client\x20sent,\x20then\x20the\x20client\x20is\x20\"caught\x20up\"\
+    \x20with\n\x20the\x20station\x20and\x20the\x20response\x20contains\x20no\
+    \x20new\x20information\n\x20(and\x20all\x20other\x20fields\x20may\x20be\
+    \x20omitted\x20or\x20empty).\x20\x20Otherwise,\n\x20the\x20station\x20wi\
+    ll\x20send\x20the\x20latest\x20configuration\x20information,\n\x20along\
+    \x20with\x20its\x20generation\x20number.\n\n\x20The\x20station\x20can\
+    \x20also\x20send\x20ClientConf\x20messages\n\x20(as\x20part\x20of\x20Sta\
+    tion2Client\x20messages)\x20whenever\x20it\x20wants.\n\x20The\x20client\
+    \x20is\x20expected\x20to\x20react\x20as\x20if\x20it\x20had\x20requested\
+    \n\x20such\x20messages\x20--\x20possibly\x20by\x20ignoring\x20them,\x20i\
+    f\x20the\x20client\n\x20is\x20already\x20up-to-date\x20according\x20to\
+    \x20the\x20generation\x20number.\n\n\n\n\x03\x04\x02\x01\x12\x03Q\x08\
+    \x12\n\x0b\n\x04\x04\x02\x02\0\x12\x03R\x04&\n\x0c\n\x05\x04\x02\x02\0\
+    \x04\x12\x03R\x04\x0c\n\x0c\n\x05\x04\x02\x02\0\x06\x12\x03R\r\x16\n\x0c\
+    \n\x05\x04\x02\x02\0\x01\x12\x03R\x17!\n\x0c\n\x05\x04\x02\x02\0\x03\x12\
+    \x03R$%\n\x0b\n\x04\x04\x02\x02\x01\x12\x03S\x04#\n\x0c\n\x05\x04\x02\
+    \x02\x01\x04\x12\x03S\x04\x0c\n\x0c\n\x05\x04\x02\x02\x01\x05\x12\x03S\r\
+    \x13\n\x0c\n\x05\x04\x02\x02\x01\x01\x12\x03S\x14\x1e\n\x0c\n\x05\x04\
+    \x02\x02\x01\x03\x12\x03S!\"\n\x0b\n\x04\x04\x02\x02\x02\x12\x03T\x04'\n\
+    \x0c\n\x05\x04\x02\x02\x02\x04\x12\x03T\x04\x0c\n\x0c\n\x05\x04\x02\x02\
+    \x02\x06\x12\x03T\r\x13\n\x0c\n\x05\x04\x02\x02\x02\x01\x12\x03T\x14\"\n\
+    \x0c\n\x05\x04\x02\x02\x02\x03\x12\x03T%&\n\x0b\n\x04\x04\x02\x02\x03\
+    \x12\x03U\x049\n\x0c\n\x05\x04\x02\x02\x03\x04\x12\x03U\x04\x0c\n\x0c\n\
+    \x05\x04\x02\x02\x03\x06\x12\x03U\r\x1f\n\x0c\n\x05\x04\x02\x02\x03\x01\
+    \x12\x03U\x204\n\x0c\n\x05\x04\x02\x02\x03\x03\x12\x03U78\n\x0b\n\x04\
+    \x04\x02\x02\x04\x12\x03V\x04'\n\x0c\n\x05\x04\x02\x02\x04\x04\x12\x03V\
+    \x04\x0c\n\x0c\n\x05\x04\x02\x02\x04\x06\x12\x03V\r\x13\n\x0c\n\x05\x04\
+    \x02\x02\x04\x01\x12\x03V\x14\"\n\x0c\n\x05\x04\x02\x02\x04\x03\x12\x03V\
+    %&\n\n\n\x02\x04\x03\x12\x04Y\0[\x01\n\n\n\x03\x04\x03\x01\x12\x03Y\x08\
+    \x11\n\x0b\n\x04\x04\x03\x02\0\x12\x03Z\x04)\n\x0c\n\x05\x04\x03\x02\0\
+    \x04\x12\x03Z\x04\x0c\n\x0c\n\x05\x04\x03\x02\0\x06\x12\x03Z\r\x19\n\x0c\
+    \n\x05\x04\x03\x02\0\x01\x12\x03Z\x1a$\n\x0c\n\x05\x04\x03\x02\0\x03\x12\
+    \x03Z'(\n\n\n\x02\x04\x04\x12\x04]\0_\x01\n\n\n\x03\x04\x04\x01\x12\x03]\
+    \x08\x1a\n\x0b\n\x04\x04\x04\x02\0\x12\x03^\x041\n\x0c\n\x05\x04\x04\x02\
+    \0\x04\x12\x03^\x04\x0c\n\x0c\n\x05\x04\x04\x02\0\x06\x12\x03^\r\x1b\n\
+    \x0c\n\x05\x04\x04\x02\0\x01\x12\x03^\x1c,\n\x0c\n\x05\x04\x04\x02\0\x03\
+    \x12\x03^/0\n\n\n\x02\x04\x05\x12\x04a\0d\x01\n\n\n\x03\x04\x05\x01\x12\
+    \x03a\x08\x16\n\x0b\n\x04\x04\x05\x02\0\x12\x03b\x04\x1f\n\x0c\n\x05\x04\
+    \x05\x02\0\x04\x12\x03b\x04\x0c\n\x0c\n\x05\x04\x05\x02\0\x05\x12\x03b\r\
+    \x13\n\x0c\n\x05\x04\x05\x02\0\x01\x12\x03b\x14\x1a\n\x0c\n\x05\x04\x05\
+    \x02\0\x03\x12\x03b\x1d\x1e\n\x0b\n\x04\x04\x05\x02\x01\x12\x03c\x04\x20\
+    \n\x0c\n\x05\x04\x05\x02\x01\x04\x12\x03c\x04\x0c\n\x0c\n\x05\x04\x05\
+    \x02\x01\x05\x12\x03c\r\x13\n\x0c\n\x05\x04\x05\x02\x01\x01\x12\x03c\x14\
+    \x1b\n\x0c\n\x05\x04\x05\x02\x01\x03\x12\x03c\x1e\x1f\n-\n\x02\x05\x01\
+    \x12\x04g\0q\x01\x1a!\x20State\x20transitions\x20of\x20the\x20client\n\n\
+    \n\n\x03\x05\x01\x01\x12\x03g\x05\x13\n\x0b\n\x04\x05\x01\x02\0\x12\x03h\
+    \x04\x16\n\x0c\n\x05\x05\x01\x02\0\x01\x12\x03h\x04\x11\n\x0c\n\x05\x05\
+    \x01\x02\0\x02\x12\x03h\x14\x15\n\"\n\x04\x05\x01\x02\x01\x12\x03i\x04\
+    \x19\"\x15\x20connect\x20me\x20to\x20squid\n\n\x0c\n\x05\x05\x01\x02\x01\
+    \x01\x12\x03i\x04\x14\n\x0c\n\x05\x05\x01\x02\x01\x02\x12\x03i\x17\x18\n\
+    ,\n\x04\x05\x01\x02\x02\x12\x03j\x04!\"\x1f\x20connect\x20me\x20to\x20pr\
+    ovided\x20covert\n\n\x0c\n\x05\x05\x01\x02\x02\x01\x12\x03j\x04\x1b\n\
+    \x0c\n\x05\x05\x01\x02\x02\x02\x12\x03j\x1e\x20\n\x0b\n\x04\x05\x01\x02\
+    \x03\x12\x03k\x04\x1d\n\x0c\n\x05\x05\x01\x02\x03\x01\x12\x03k\x04\x18\n\
+    \x0c\n\x05\x05\x01\x02\x03\x02\x12\x03k\x1b\x1c\n\x0b\n\x04\x05\x01\x02\
+    \x04\x12\x03l\x04\x1a\n\x0c\n\x05\x05\x01\x02\x04\x01\x12\x03l\x04\x15\n\
+    \x0c\n\x05\x05\x01\x02\x04\x02\x12\x03l\x18\x19\n\x0b\n\x04\x05\x01\x02\
+    \x05\x12\x03m\x04\x19\n\x0c\n\x05\x05\x01\x02\x05\x01\x12\x03m\x04\x14\n\
+    \x0c\n\x05\x05\x01\x02\x05\x02\x12\x03m\x17\x18\n\x0b\n\x04\x05\x01\x02\
+    \x06\x12\x03n\x04\x1b\n\x0c\n\x05\x05\x01\x02\x06\x01\x12\x03n\x04\x16\n\
+    \x0c\n\x05\x05\x01\x02\x06\x02\x12\x03n\x19\x1a\n\x0b\n\x04\x05\x01\x02\
+    \x07\x12\x03o\x04%\n\x0c\n\x05\x05\x01\x02\x07\x01\x12\x03o\x04\x20\n\
+    \x0c\n\x05\x05\x01\x02\x07\x02\x12\x03o#$\n\x0b\n\x04\x05\x01\x02\x08\
+    \x12\x03p\x04\x14\n\x0c\n\x05\x05\x01\x02\x08\x01\x12\x03p\x04\r\n\x0c\n\
+    \x05\x05\x01\x02\x08\x02\x12\x03p\x10\x13\n-\n\x02\x05\x02\x12\x04t\0|\
+    \x01\x1a!\x20State\x20transitions\x20of\x20the\x20server\n\n\n\n\x03\x05\
+    \x02\x01\x12\x03t\x05\x13\n\x0b\n\x04\x05\x02\x02\0\x12\x03u\x04\x16\n\
+    \x0c\n\x05\x05\x02\x02\0\x01\x12\x03u\x04\x11\n\x0c\n\x05\x05\x02\x02\0\
+    \x02\x12\x03u\x14\x15\n!\n\x04\x05\x02\x02\x01\x12\x03v\x04\x19\"\x14\
+    \x20connected\x20to\x20squid\n\n\x0c\n\x05\x05\x02\x02\x01\x01\x12\x03v\
+    \x04\x14\n\x0c\n\x05\x05\x02\x02\x01\x02\x12\x03v\x17\x18\n'\n\x04\x05\
+    \x02\x02\x02\x12\x03w\x04!\"\x1a\x20connected\x20to\x20covert\x20host\n\
+    \n\x0c\n\x05\x05\x02\x02\x02\x01\x12\x03w\x04\x1b\n\x0c\n\x05\x05\x02\
+    \x02\x02\x02\x12\x03w\x1e\x20\n\x0b\n\x04\x05\x02\x02\x03\x12\x03x\x04\
+    \x1e\n\x0c\n\x05\x05\x02\x02\x03\x01\x12\x03x\x04\x19\n\x0c\n\x05\x05\
+    \x02\x02\x03\x02\x12\x03x\x1c\x1d\n\x0b\n\x04\x05\x02\x02\x04\x12\x03y\
+    \x04\x1a\n\x0c\n\x05\x05\x02\x02\x04\x01\x12\x03y\x04\x15\n\x0c\n\x05\
+    \x05\x02\x02\x04\x02\x12\x03y\x18\x19\nR\n\x04\x05\x02\x02\x05\x12\x03{\
+    \x04\x14\x1aE\x20TODO\x20should\x20probably\x20also\x20allow\x20EXPECT_R\
+    ECONNECT\x20here,\x20for\x20DittoTap\n\n\x0c\n\x05\x05\x02\x02\x05\x01\
+    \x12\x03{\x04\r\n\x0c\n\x05\x05\x02\x02\x05\x02\x12\x03{\x10\x13\n7\n\
+    \x02\x05\x03\x12\x05\x7f\0\x89\x01\x01\x1a*\x20Should\x20accompany\x20al\
+    l\x20S2C_ERROR\x20messages.\n\n\n\n\x03\x05\x03\x01\x12\x03\x7f\x05\x13\
+    \n\x0c\n\x04\x05\x03\x02\0\x12\x04\x80\x01\x04\x11\n\r\n\x05\x05\x03\x02\
+    \0\x01\x12\x04\x80\x01\x04\x0c\n\r\n\x05\x05\x03\x02\0\x02\x12\x04\x80\
+    \x01\x0f\x10\n*\n\x04\x05\x03\x02\x01\x12\x04\x81\x01\x04\x16\"\x1c\x20S\
+    quid\x20TCP\x20connection\x20broke\n\n\r\n\x05\x05\x03\x02\x01\x01\x12\
+    \x04\x81\x01\x04\x11\n\r\n\x05\x05\x03\x02\x01\x02\x12\x04\x81\x01\x14\
+    \x15\n7\n\x04\x05\x03\x02\x02\x12\x04\x82\x01\x04\x18\")\x20You\x20told\
+    \x20me\x20something\x20was\x20wrong,\x20client\n\n\r\n\x05\x05\x03\x02\
+    \x02\x01\x12\x04\x82\x01\x04\x13\n\r\n\x05\x05\x03\x02\x02\x02\x12\x04\
+    \x82\x01\x16\x17\n@\n\x04\x05\x03\x02\x03\x12\x04\x83\x01\x04\x18\"2\x20\
+    You\x20messed\x20up,\x20client\x20(e.g.\x20sent\x20a\x20bad\x20protobuf)\
+    \n\n\r\n\x05\x05\x03\x02\x03\x01\x12\x04\x83\x01\x04\x13\n\r\n\x05\x05\
+    \x03\x02\x03\x02\x12\x04\x83\x01\x16\x17\n\x17\n\x04\x05\x03\x02\x04\x12\
+    \x04\x84\x01\x04\x19\"\t\x20I\x20broke\n\n\r\n\x05\x05\x03\x02\x04\x01\
+    \x12\x04\x84\x01\x04\x14\n\r\n\x05\x05\x03\x02\x04\x02\x12\x04\x84\x01\
+    \x17\x18\nE\n\x04\x05\x03\x02\x05\x12\x04\x85\x01\x04\x17\"7\x20Everythi\
+    ng's\x20fine,\x20but\x20don't\x20use\x20this\x20decoy\x20right\x20now\n\
+    \n\r\n\x05\x05\x03\x02\x05\x01\x12\x04\x85\x01\x04\x12\n\r\n\x05\x05\x03\
+    \x02\x05\x02\x12\x04\x85\x01\x15\x16\nD\n\x04\x05\x03\x02\x06\x12\x04\
+    \x87\x01\x04\x18\"6\x20My\x20stream\x20to\x20you\x20broke.\x20(This\x20i\
+    s\x20impossible\x20to\x20send)\n\n\r\n\x05\x05\x03\x02\x06\x01\x12\x04\
+    \x87\x01\x04\x11\n\r\n\x05\x05\x03\x02\x06\x02\x12\x04\x87\x01\x14\x17\n\
+    A\n\x04\x05\x03\x02\x07\x12\x04\x88\x01\x04\x19\"3\x20You\x20never\x20ca\
+    me\x20back.\x20(This\x20is\x20impossible\x20to\x20send)\n\n\r\n\x05\x05\
+    \x03\x02\x07\x01\x12\x04\x88\x01\x04\x12\n\r\n\x05\x05\x03\x02\x07\x02\
+    \x12\x04\x88\x01\x15\x18\n\x0c\n\x02\x05\x04\x12\x06\x8b\x01\0\x8f\x01\
+    \x01\n\x0b\n\x03\x05\x04\x01\x12\x04\x8b\x01\x05\x12\n\x0c\n\x04\x05\x04\
+    \x02\0\x12\x04\x8c\x01\x04\r\n\r\n\x05\x05\x04\x02\0\x01\x12\x04\x8c\x01\
+    \x04\x08\n\r\n\x05\x05\x04\x02\0\x02\x12\x04\x8c\x01\x0b\x0c\n`\n\x04\
+    \x05\x04\x02\x01\x12\x04\x8d\x01\x04\x0c\"R\x20Send\x20a\x2032-byte\x20H\
+    MAC\x20id\x20to\x20let\x20the\x20station\x20distinguish\x20registrations\
+    \x20to\x20same\x20host\n\n\r\n\x05\x05\x04\x02\x01\x01\x12\x04\x8d\x01\
+    \x04\x07\n\r\n\x05\x05\x04\x02\x01\x02\x12\x04\x8d\x01\n\x0b\n$\n\x04\
+    \x05\x04\x02\x02\x12\x04\x8e\x01\x04\x0e\"\x16\x20Not\x20implemented\x20\
+    yet?\n\n\r\n\x05\x05\x04\x02\x02\x01\x12\x04\x8e\x01\x04\t\n\r\n\x05\x05\
+    \x04\x02\x02\x02\x12\x04\x8e\x01\x0c\r\n\x0c\n\x02\x04\x06\x12\x06\x91\
+    \x01\0\xa8\x01\x01\n\x0b\n\x03\x04\x06\x01\x12\x04\x91\x01\x08\x17\nO\n\
+    \x04\x04\x06\x02\0\x12\x04\x93\x01\x04)\x1aA\x20Should\x20accompany\x20(\
+    at\x20least)\x20SESSION_INIT\x20and\x20CONFIRM_RECONNECT.\n\n\r\n\x05\
+    \x04\x06\x02\0\x04\x12\x04\x93\x01\x04\x0c\n\r\n\x05\x04\x06\x02\0\x05\
+    \x12\x04\x93\x01\r\x13\n\r\n\x05\x04\x06\x02\0\x01\x12\x04\x93\x01\x14$\
+    \n\r\n\x05\x04\x06\x02\0\x03\x12\x04\x93\x01'(\nv\n\x04\x04\x06\x02\x01\
+    \x12\x04\x97\x01\x041\x1ah\x20There\x20might\x20be\x20a\x20state\x20tran\
+    sition.\x20May\x20be\x20absent;\x20absence\x20should\x20be\n\x20treated\
+    \x20identically\x20to\x20NO_CHANGE.\n\n\r\n\x05\x04\x06\x02\x01\x04\x12\
+    \x04\x97\x01\x04\x0c\n\r\n\x05\x04\x06\x02\x01\x06\x12\x04\x97\x01\r\x1b\
+    \n\r\n\x05\x04\x06\x02\x01\x01\x12\x04\x97\x01\x1c,\n\r\n\x05\x04\x06\
+    \x02\x01\x03\x12\x04\x97\x01/0\nc\n\x04\x04\x06\x02\x02\x12\x04\x9b\x01\
+    \x04(\x1aU\x20The\x20station\x20can\x20send\x20client\x20config\x20info\
+    \x20piggybacked\n\x20on\x20any\x20message,\x20as\x20it\x20sees\x20fit\n\
+    \n\r\n\x05\x04\x06\x02\x02\x04\x12\x04\x9b\x01\x04\x0c\n\r\n\x05\x04\x06\
+    \x02\x02\x06\x12\x04\x9b\x01\r\x17\n\r\n\x05\x04\x06\x02\x02\x01\x12\x04\
+    \x9b\x01\x18#\n\r\n\x05\x04\x06\x02\x02\x03\x12\x04\x9b\x01&'\nP\n\x04\
+    \x04\x06\x02\x03\x12\x04\x9e\x01\x04+\x1aB\x20If\x20state_transition\x20\
+    ==\x20S2C_ERROR,\x20this\x20field\x20is\x20the\x20explanation.\n\n\r\n\
+    \x05\x04\x06\x02\x03\x04\x12\x04\x9e\x01\x04\x0c\n\r\n\x05\x04\x06\x02\
     \x03\x06\x12\x04\x9e\x01\r\x1b\n\r\n\x05\x04\x06\x02\x03\x01\x12\x04\x9e\
     \x01\x1c&\n\r\n\x05\x04\x06\x02\x03\x03\x12\x04\x9e\x01)*\nQ\n\x04\x04\
     \x06\x02\x04\x12\x04\xa1\x01\x04$\x1aC\x20Signals\x20client\x20to\x20sto\
@@ -4673,7 +5607,7 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \x08\x0b\n\r\n\x05\x05\x05\x02\x02\x02\x12\x04\xe4\x01\x0e\x0f\n\x0c\n\
     \x04\x05\x05\x02\x03\x12\x04\xe5\x01\x04\x18\n\r\n\x05\x05\x05\x02\x03\
     \x01\x12\x04\xe5\x01\x04\x13\n\r\n\x05\x05\x05\x02\x03\x02\x12\x04\xe5\
-    \x01\x16\x17\n\x0c\n\x02\x04\t\x12\x06\xe8\x01\0\xf2\x01\x01\n\x0b\n\x03\
+    \x01\x16\x17\n\x0c\n\x02\x04\t\x12\x06\xe8\x01\0\xf5\x01\x01\n\x0b\n\x03\
     \x04\t\x01\x12\x04\xe8\x01\x08\x12\n\x0c\n\x04\x04\t\x02\0\x12\x04\xe9\
     \x01\x08)\n\r\n\x05\x04\t\x02\0\x04\x12\x04\xe9\x01\x08\x10\n\r\n\x05\
     \x04\t\x02\0\x05\x12\x04\xe9\x01\x11\x16\n\r\n\x05\x04\t\x02\0\x01\x12\
@@ -4694,43 +5628,165 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     registrar\n\n\r\n\x05\x04\t\x02\x04\x04\x12\x04\xf1\x01\x04\x0c\n\r\n\
     \x05\x04\t\x02\x04\x05\x12\x04\xf1\x01\r\x12\n\r\n\x05\x04\t\x02\x04\x01\
     \x12\x04\xf1\x01\x13\x20\n\r\n\x05\x04\t\x02\x04\x03\x12\x04\xf1\x01#$\n\
-    \x0c\n\x02\x04\n\x12\x06\xf4\x01\0\x80\x02\x01\n\x0b\n\x03\x04\n\x01\x12\
-    \x04\xf4\x01\x08\x14\n9\n\x04\x04\n\x02\0\x12\x04\xf5\x01\x04.\"+\x20how\
-    \x20many\x20decoys\x20were\x20tried\x20before\x20success\n\n\r\n\x05\x04\
-    \n\x02\0\x04\x12\x04\xf5\x01\x04\x0c\n\r\n\x05\x04\n\x02\0\x05\x12\x04\
-    \xf5\x01\r\x13\n\r\n\x05\x04\n\x02\0\x01\x12\x04\xf5\x01\x14(\n\r\n\x05\
-    \x04\n\x02\0\x03\x12\x04\xf5\x01+-\nm\n\x04\x04\n\x02\x01\x12\x04\xfa\
-    \x01\x04/\x1a\x1e\x20Applicable\x20to\x20whole\x20session:\n\"\x1a\x20in\
-    cludes\x20failed\x20attempts\n2#\x20Timings\x20below\x20are\x20in\x20mil\
-    liseconds\n\n\r\n\x05\x04\n\x02\x01\x04\x12\x04\xfa\x01\x04\x0c\n\r\n\
-    \x05\x04\n\x02\x01\x05\x12\x04\xfa\x01\r\x13\n\r\n\x05\x04\n\x02\x01\x01\
-    \x12\x04\xfa\x01\x14)\n\r\n\x05\x04\n\x02\x01\x03\x12\x04\xfa\x01,.\nR\n\
-    \x04\x04\n\x02\x02\x12\x04\xfd\x01\x04(\x1a\x1f\x20Last\x20(i.e.\x20succ\
-    essful)\x20decoy:\n\"#\x20measured\x20during\x20initial\x20handshake\n\n\
-    \r\n\x05\x04\n\x02\x02\x04\x12\x04\xfd\x01\x04\x0c\n\r\n\x05\x04\n\x02\
-    \x02\x05\x12\x04\xfd\x01\r\x13\n\r\n\x05\x04\n\x02\x02\x01\x12\x04\xfd\
-    \x01\x14\"\n\r\n\x05\x04\n\x02\x02\x03\x12\x04\xfd\x01%'\n%\n\x04\x04\n\
-    \x02\x03\x12\x04\xfe\x01\x04&\"\x17\x20includes\x20tcp\x20to\x20decoy\n\
-    \n\r\n\x05\x04\n\x02\x03\x04\x12\x04\xfe\x01\x04\x0c\n\r\n\x05\x04\n\x02\
-    \x03\x05\x12\x04\xfe\x01\r\x13\n\r\n\x05\x04\n\x02\x03\x01\x12\x04\xfe\
-    \x01\x14\x20\n\r\n\x05\x04\n\x02\x03\x03\x12\x04\xfe\x01#%\nB\n\x04\x04\
-    \n\x02\x04\x12\x04\xff\x01\x04&\"4\x20measured\x20when\x20establishing\
-    \x20tcp\x20connection\x20to\x20decot\n\n\r\n\x05\x04\n\x02\x04\x04\x12\
-    \x04\xff\x01\x04\x0c\n\r\n\x05\x04\n\x02\x04\x05\x12\x04\xff\x01\r\x13\n\
-    \r\n\x05\x04\n\x02\x04\x01\x12\x04\xff\x01\x14\x20\n\r\n\x05\x04\n\x02\
-    \x04\x03\x12\x04\xff\x01#%\n\x0c\n\x02\x04\x0b\x12\x06\x82\x02\0\x87\x02\
-    \x01\n\x0b\n\x03\x04\x0b\x01\x12\x04\x82\x02\x08\x19\n\x0c\n\x04\x04\x0b\
-    \x02\0\x12\x04\x83\x02\x04#\n\r\n\x05\x04\x0b\x02\0\x04\x12\x04\x83\x02\
-    \x04\x0c\n\r\n\x05\x04\x0b\x02\0\x05\x12\x04\x83\x02\r\x13\n\r\n\x05\x04\
-    \x0b\x02\0\x01\x12\x04\x83\x02\x14\x1e\n\r\n\x05\x04\x0b\x02\0\x03\x12\
-    \x04\x83\x02!\"\n\x0c\n\x04\x04\x0b\x02\x01\x12\x04\x84\x02\x04\"\n\r\n\
-    \x05\x04\x0b\x02\x01\x04\x12\x04\x84\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\
-    \x01\x05\x12\x04\x84\x02\r\x13\n\r\n\x05\x04\x0b\x02\x01\x01\x12\x04\x84\
-    \x02\x14\x1d\n\r\n\x05\x04\x0b\x02\x01\x03\x12\x04\x84\x02\x20!\n\x0c\n\
-    \x04\x04\x0b\x02\x02\x12\x04\x86\x02\x04#\n\r\n\x05\x04\x0b\x02\x02\x04\
-    \x12\x04\x86\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\x02\x05\x12\x04\x86\x02\r\
-    \x13\n\r\n\x05\x04\x0b\x02\x02\x01\x12\x04\x86\x02\x14\x1e\n\r\n\x05\x04\
-    \x0b\x02\x02\x03\x12\x04\x86\x02!\"\
+    Q\n\x04\x04\t\x02\x05\x12\x04\xf4\x01\x04'\x1aC\x20Generation\x20of\x20t\
+    he\x20station\x20key\x20that\x20decrypted\x20the\x20registration\x20tag\
+    \n\n\r\n\x05\x04\t\x02\x05\x04\x12\x04\xf4\x01\x04\x0c\n\r\n\x05\x04\t\
+    \x02\x05\x05\x12\x04\xf4\x01\r\x13\n\r\n\x05\x04\t\x02\x05\x01\x12\x04\
+    \xf4\x01\x14\"\n\r\n\x05\x04\t\x02\x05\x03\x12\x04\xf4\x01%&\n\x0c\n\x02\
+    \x04\n\x12\x06\xf7\x01\0\x83\x02\x01\n\x0b\n\x03\x04\n\x01\x12\x04\xf7\
+    \x01\x08\x14\n9\n\x04\x04\n\x02\0\x12\x04\xf8\x01\x04.\"+\x20how\x20many\
+    \x20decoys\x20were\x20tried\x20before\x20success\n\n\r\n\x05\x04\n\x02\0\
+    \x04\x12\x04\xf8\x01\x04\x0c\n\r\n\x05\x04\n\x02\0\x05\x12\x04\xf8\x01\r\
+    \x13\n\r\n\x05\x04\n\x02\0\x01\x12\x04\xf8\x01\x14(\n\r\n\x05\x04\n\x02\
+    \0\x03\x12\x04\xf8\x01+-\nm\n\x04\x04\n\x02\x01\x12\x04\xfd\x01\x04/\x1a\
+    \x1e\x20Applicable\x20to\x20whole\x20session:\n\"\x1a\x20includes\x20fai\
+    led\x20attempts\n2#\x20Timings\x20below\x20are\x20in\x20milliseconds\n\n\
+    \r\n\x05\x04\n\x02\x01\x04\x12\x04\xfd\x01\x04\x0c\n\r\n\x05\x04\n\x02\
+    \x01\x05\x12\x04\xfd\x01\r\x13\n\r\n\x05\x04\n\x02\x01\x01\x12\x04\xfd\
+    \x01\x14)\n\r\n\x05\x04\n\x02\x01\x03\x12\x04\xfd\x01,.\nR\n\x04\x04\n\
+    \x02\x02\x12\x04\x80\x02\x04(\x1a\x1f\x20Last\x20(i.e.\x20successful)\
+    \x20decoy:\n\"#\x20measured\x20during\x20initial\x20handshake\n\n\r\n\
+    \x05\x04\n\x02\x02\x04\x12\x04\x80\x02\x04\x0c\n\r\n\x05\x04\n\x02\x02\
+    \x05\x12\x04\x80\x02\r\x13\n\r\n\x05\x04\n\x02\x02\x01\x12\x04\x80\x02\
+    \x14\"\n\r\n\x05\x04\n\x02\x02\x03\x12\x04\x80\x02%'\n%\n\x04\x04\n\x02\
+    \x03\x12\x04\x81\x02\x04&\"\x17\x20includes\x20tcp\x20to\x20decoy\n\n\r\
+    \n\x05\x04\n\x02\x03\x04\x12\x04\x81\x02\x04\x0c\n\r\n\x05\x04\n\x02\x03\
+    \x05\x12\x04\x81\x02\r\x13\n\r\n\x05\x04\n\x02\x03\x01\x12\x04\x81\x02\
+    \x14\x20\n\r\n\x05\x04\n\x02\x03\x03\x12\x04\x81\x02#%\nB\n\x04\x04\n\
+    \x02\x04\x12\x04\x82\x02\x04&\"4\x20measured\x20when\x20establishing\x20\
+    tcp\x20connection\x20to\x20decot\n\n\r\n\x05\x04\n\x02\x04\x04\x12\x04\
+    \x82\x02\x04\x0c\n\r\n\x05\x04\n\x02\x04\x05\x12\x04\x82\x02\r\x13\n\r\n\
+    \x05\x04\n\x02\x04\x01\x12\x04\x82\x02\x14\x20\n\r\n\x05\x04\n\x02\x04\
+    \x03\x12\x04\x82\x02#%\nL\n\x02\x05\x06\x12\x06\x86\x02\0\x8a\x02\x01\
+    \x1a>\x20IP\x20protocol\x20of\x20the\x20connection\x20a\x20client\x20mak\
+    es\x20to\x20its\x20phantom.\n\n\x0b\n\x03\x05\x06\x01\x12\x04\x86\x02\
+    \x05\x0c\n\x0c\n\x04\x05\x06\x02\0\x12\x04\x87\x02\x04\x0c\n\r\n\x05\x05\
+    \x06\x02\0\x01\x12\x04\x87\x02\x04\x07\n\r\n\x05\x05\x06\x02\0\x02\x12\
+    \x04\x87\x02\n\x0b\n\x0c\n\x04\x05\x06\x02\x01\x12\x04\x88\x02\x04\x0c\n\
+    \r\n\x05\x05\x06\x02\x01\x01\x12\x04\x88\x02\x04\x07\n\r\n\x05\x05\x06\
+    \x02\x01\x02\x12\x04\x88\x02\n\x0b\n\x0c\n\x04\x05\x06\x02\x02\x12\x04\
+    \x89\x02\x04\x0c\n\r\n\x05\x05\x06\x02\x02\x01\x12\x04\x89\x02\x04\x07\n\
+    \r\n\x05\x05\x06\x02\x02\x02\x12\x04\x89\x02\n\x0b\nH\n\x02\x05\x07\x12\
+    \x06\x8d\x02\0\x99\x02\x01\x1a:\x20What\x20a\x20StationToDetector\x20mes\
+    sage\x20asks\x20the\x20detector\x20to\x20do\n\n\x0b\n\x03\x05\x07\x01\
+    \x12\x04\x8d\x02\x05\x15\nN\n\x04\x05\x07\x02\0\x12\x04\x8f\x02\x04\x0c\
+    \x1a@\x20Add\x20the\x20session,\x20or\x20keep\x20it\x20until\x20at\x20le\
+    ast\x20timeout_ns\x20from\x20now\n\n\r\n\x05\x05\x07\x02\0\x01\x12\x04\
+    \x8f\x02\x04\x07\n\r\n\x05\x05\x07\x02\0\x02\x12\x04\x8f\x02\n\x0b\n$\n\
+    \x04\x05\x07\x02\x01\x12\x04\x91\x02\x04\x0f\x1a\x16\x20Drop\x20the\x20s\
+    ession\x20now\n\n\r\n\x05\x05\x07\x02\x01\x01\x12\x04\x91\x02\x04\n\n\r\
+    \n\x05\x05\x07\x02\x01\x02\x12\x04\x91\x02\r\x0e\n\x8b\x01\n\x04\x05\x07\
+    \x02\x02\x12\x04\x94\x02\x04\x11\x1a}\x20Have\x20the\x20session\x20expir\
+    e\x20timeout_ns\x20from\x20now,\x20sooner\x20or\x20later\x20than\x20it\n\
+    \x20would\x20have.\x20Does\x20nothing\x20if\x20there\x20is\x20no\x20such\
+    \x20session.\n\n\r\n\x05\x05\x07\x02\x02\x01\x12\x04\x94\x02\x04\x0c\n\r\
+    \n\x05\x05\x07\x02\x02\x02\x12\x04\x94\x02\x0f\x10\n\xbe\x01\n\x04\x05\
+    \x07\x02\x03\x12\x04\x98\x02\x04\x15\x1a\xaf\x01\x20Drop\x20every\x20ses\
+    sion\x20registered\x20for\x20client_ip;\x20the\x20other\x20fields\x20are\
+    \n\x20ignored.\x20IPv6\x20sessions\x20are\x20per\x20phantom,\x20so\x20th\
+    is\x20also\x20drops\x20them\x20for\n\x20any\x20other\x20client\x20of\x20\
+    the\x20same\x20phantom.\n\n\r\n\x05\x05\x07\x02\x03\x01\x12\x04\x98\x02\
+    \x04\x10\n\r\n\x05\x05\x07\x02\x03\x02\x12\x04\x98\x02\x13\x14\n\x0c\n\
+    \x02\x04\x0b\x12\x06\x9b\x02\0\xa9\x02\x01\n\x0b\n\x03\x04\x0b\x01\x12\
+    \x04\x9b\x02\x08\x19\n\x0c\n\x04\x04\x0b\x02\0\x12\x04\x9c\x02\x04#\n\r\
+    \n\x05\x04\x0b\x02\0\x04\x12\x04\x9c\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\0\
+    \x05\x12\x04\x9c\x02\r\x13\n\r\n\x05\x04\x0b\x02\0\x01\x12\x04\x9c\x02\
+    \x14\x1e\n\r\n\x05\x04\x0b\x02\0\x03\x12\x04\x9c\x02!\"\n\x0c\n\x04\x04\
+    \x0b\x02\x01\x12\x04\x9d\x02\x04\"\n\r\n\x05\x04\x0b\x02\x01\x04\x12\x04\
+    \x9d\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\x01\x05\x12\x04\x9d\x02\r\x13\n\r\
+    \n\x05\x04\x0b\x02\x01\x01\x12\x04\x9d\x02\x14\x1d\n\r\n\x05\x04\x0b\x02\
+    \x01\x03\x12\x04\x9d\x02\x20!\n\x0c\n\x04\x04\x0b\x02\x02\x12\x04\x9f\
+    \x02\x04#\n\r\n\x05\x04\x0b\x02\x02\x04\x12\x04\x9f\x02\x04\x0c\n\r\n\
+    \x05\x04\x0b\x02\x02\x05\x12\x04\x9f\x02\r\x13\n\r\n\x05\x04\x0b\x02\x02\
+    \x01\x12\x04\x9f\x02\x14\x1e\n\r\n\x05\x04\x0b\x02\x02\x03\x12\x04\x9f\
+    \x02!\"\nP\n\x04\x04\x0b\x02\x03\x12\x04\xa2\x02\x04!\x1aB\x20Phantom\
+    \x20port\x20the\x20client\x20will\x20connect\x20to.\x20Unset\x20(or\x200\
+    )\x20means\x20443.\n\n\r\n\x05\x04\x0b\x02\x03\x04\x12\x04\xa2\x02\x04\
+    \x0c\n\r\n\x05\x04\x0b\x02\x03\x05\x12\x04\xa2\x02\r\x13\n\r\n\x05\x04\
+    \x0b\x02\x03\x01\x12\x04\xa2\x02\x14\x1c\n\r\n\x05\x04\x0b\x02\x03\x03\
+    \x12\x04\xa2\x02\x1f\x20\n]\n\x04\x04\x0b\x02\x04\x12\x04\xa5\x02\x04\
+    \x1f\x1aO\x20Transport\x20the\x20client\x20will\x20use\x20to\x20reach\
+    \x20the\x20phantom.\x20Unset\x20(or\x20Unk)\x20means\x20Tcp.\n\n\r\n\x05\
+    \x04\x0b\x02\x04\x04\x12\x04\xa5\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\x04\
+    \x06\x12\x04\xa5\x02\r\x14\n\r\n\x05\x04\x0b\x02\x04\x01\x12\x04\xa5\x02\
+    \x15\x1a\n\r\n\x05\x04\x0b\x02\x04\x03\x12\x04\xa5\x02\x1d\x1e\n=\n\x04\
+    \x04\x0b\x02\x05\x12\x04\xa8\x02\x04,\x1a/\x20What\x20to\x20do\x20with\
+    \x20the\x20session.\x20Unset\x20means\x20Add.\n\n\r\n\x05\x04\x0b\x02\
+    \x05\x04\x12\x04\xa8\x02\x04\x0c\n\r\n\x05\x04\x0b\x02\x05\x06\x12\x04\
+    \xa8\x02\r\x1d\n\r\n\x05\x04\x0b\x02\x05\x01\x12\x04\xa8\x02\x1e'\n\r\n\
+    \x05\x04\x0b\x02\x05\x03\x12\x04\xa8\x02*+\n+\n\x02\x05\x08\x12\x06\xac\
+    \x02\0\xb3\x02\x01\x1a\x1d\x20What\x20a\x20PhantomEvent\x20reports\n\n\
+    \x0b\n\x03\x05\x08\x01\x12\x04\xac\x02\x05\x15\nN\n\x04\x05\x08\x02\0\
+    \x12\x04\xae\x02\x04\x11\x1a@\x20The\x20client's\x20first\x20SYN\x20to\
+    \x20the\x20phantom\x20(first\x20datagram\x20for\x20UDP)\n\n\r\n\x05\x05\
+    \x08\x02\0\x01\x12\x04\xae\x02\x04\x0c\n\r\n\x05\x05\x08\x02\0\x02\x12\
+    \x04\xae\x02\x0f\x10\nJ\n\x04\x05\x08\x02\x01\x12\x04\xb0\x02\x04\x19\
+    \x1a<\x20A\x20connection\x20to\x20the\x20phantom\x20was\x20reset,\x20or\
+    \x20closed\x20with\x20FINs\n\n\r\n\x05\x05\x08\x02\x01\x01\x12\x04\xb0\
+    \x02\x04\x14\n\r\n\x05\x05\x08\x02\x01\x02\x12\x04\xb0\x02\x17\x18\nR\n\
+    \x04\x05\x08\x02\x02\x12\x04\xb2\x02\x04\x16\x1aD\x20The\x20session\x20t\
+    imed\x20out\x20without\x20the\x20client\x20ever\x20reaching\x20the\x20ph\
+    antom\n\n\r\n\x05\x05\x08\x02\x02\x01\x12\x04\xb2\x02\x04\x11\n\r\n\x05\
+    \x05\x08\x02\x02\x02\x12\x04\xb2\x02\x14\x15\n\xab\x01\n\x02\x04\x0c\x12\
+    \x06\xb8\x02\0\xd2\x02\x01\x1a\x9c\x01\x20Published\x20by\x20the\x20dete\
+    ctor\x20about\x20sessions\x20registered\x20with\n\x20StationToDetector,\
+    \x20so\x20the\x20application\x20can\x20tell\x20whether\x20and\x20when\
+    \x20the\x20client\n\x20reached\x20its\x20phantom.\n\n\x0b\n\x03\x04\x0c\
+    \x01\x12\x04\xb8\x02\x08\x14\n\x0c\n\x04\x04\x0c\x02\0\x12\x04\xb9\x02\
+    \x04(\n\r\n\x05\x04\x0c\x02\0\x04\x12\x04\xb9\x02\x04\x0c\n\r\n\x05\x04\
+    \x0c\x02\0\x06\x12\x04\xb9\x02\r\x1d\n\r\n\x05\x04\x0c\x02\0\x01\x12\x04\
+    \xb9\x02\x1e#\n\r\n\x05\x04\x0c\x02\0\x03\x12\x04\xb9\x02&'\n\x90\x01\n\
+    \x04\x04\x0c\x02\x01\x12\x04\xbd\x02\x04#\x1a\x81\x01\x20The\x20session,\
+    \x20as\x20registered.\x20client_ip\x20is\x20unset\x20for\x20an\x20IPv6\
+    \x20session\x20that\n\x20expired\x20unused,\x20as\x20those\x20aren't\x20\
+    registered\x20per\x20client.\n\n\r\n\x05\x04\x0c\x02\x01\x04\x12\x04\xbd\
+    \x02\x04\x0c\n\r\n\x05\x04\x0c\x02\x01\x05\x12\x04\xbd\x02\r\x13\n\r\n\
+    \x05\x04\x0c\x02\x01\x01\x12\x04\xbd\x02\x14\x1e\n\r\n\x05\x04\x0c\x02\
+    \x01\x03\x12\x04\xbd\x02!\"\n\x0c\n\x04\x04\x0c\x02\x02\x12\x04\xbe\x02\
+    \x04\"\n\r\n\x05\x04\x0c\x02\x02\x04\x12\x04\xbe\x02\x04\x0c\n\r\n\x05\
+    \x04\x0c\x02\x02\x05\x12\x04\xbe\x02\r\x13\n\r\n\x05\x04\x0c\x02\x02\x01\
+    \x12\x04\xbe\x02\x14\x1d\n\r\n\x05\x04\x0c\x02\x02\x03\x12\x04\xbe\x02\
+    \x20!\n\x0c\n\x04\x04\x0c\x02\x03\x12\x04\xbf\x02\x04!\n\r\n\x05\x04\x0c\
+    \x02\x03\x04\x12\x04\xbf\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\x03\x05\x12\
+    \x04\xbf\x02\r\x13\n\r\n\x05\x04\x0c\x02\x03\x01\x12\x04\xbf\x02\x14\x1c\
+    \n\r\n\x05\x04\x0c\x02\x03\x03\x12\x04\xbf\x02\x1f\x20\n\x0c\n\x04\x04\
+    \x0c\x02\x04\x12\x04\xc0\x02\x04\x1f\n\r\n\x05\x04\x0c\x02\x04\x04\x12\
+    \x04\xc0\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\x04\x06\x12\x04\xc0\x02\r\x14\
+    \n\r\n\x05\x04\x0c\x02\x04\x01\x12\x04\xc0\x02\x15\x1a\n\r\n\x05\x04\x0c\
+    \x02\x04\x03\x12\x04\xc0\x02\x1d\x1e\n4\n\x04\x04\x0c\x02\x05\x12\x04\
+    \xc3\x02\x04!\x1a&\x20Client\x20port\x20of\x20the\x20closed\x20connectio\
+    n\n\n\r\n\x05\x04\x0c\x02\x05\x04\x12\x04\xc3\x02\x04\x0c\n\r\n\x05\x04\
+    \x0c\x02\x05\x05\x12\x04\xc3\x02\r\x13\n\r\n\x05\x04\x0c\x02\x05\x01\x12\
+    \x04\xc3\x02\x14\x1c\n\r\n\x05\x04\x0c\x02\x05\x03\x12\x04\xc3\x02\x1f\
+    \x20\n\x8b\x01\n\x04\x04\x0c\x02\x06\x12\x04\xc7\x02\x04\x20\x1a}\x20Uni\
+    x\x20times\x20in\x20nanoseconds:\x20of\x20the\x20event,\x20of\x20the\x20\
+    session's\x20first\x20SYN,\n\x20and\x20of\x20the\x20first\x20packet\x20s\
+    een\x20on\x20the\x20closed\x20connection\n\n\r\n\x05\x04\x0c\x02\x06\x04\
+    \x12\x04\xc7\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\x06\x05\x12\x04\xc7\x02\r\
+    \x13\n\r\n\x05\x04\x0c\x02\x06\x01\x12\x04\xc7\x02\x14\x1b\n\r\n\x05\x04\
+    \x0c\x02\x06\x03\x12\x04\xc7\x02\x1e\x1f\n\x0c\n\x04\x04\x0c\x02\x07\x12\
+    \x04\xc8\x02\x04%\n\r\n\x05\x04\x0c\x02\x07\x04\x12\x04\xc8\x02\x04\x0c\
+    \n\r\n\x05\x04\x0c\x02\x07\x05\x12\x04\xc8\x02\r\x13\n\r\n\x05\x04\x0c\
+    \x02\x07\x01\x12\x04\xc8\x02\x14\x20\n\r\n\x05\x04\x0c\x02\x07\x03\x12\
+    \x04\xc8\x02#$\n\x0c\n\x04\x04\x0c\x02\x08\x12\x04\xc9\x02\x04&\n\r\n\
+    \x05\x04\x0c\x02\x08\x04\x12\x04\xc9\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\
+    \x08\x05\x12\x04\xc9\x02\r\x13\n\r\n\x05\x04\x0c\x02\x08\x01\x12\x04\xc9\
+    \x02\x14!\n\r\n\x05\x04\x0c\x02\x08\x03\x12\x04\xc9\x02$%\n\x9e\x01\n\
+    \x04\x04\x0c\x02\t\x12\x04\xcd\x02\x04!\x1a\x8f\x01\x20IP\x20packets\x20\
+    and\x20bytes\x20forwarded\x20from\x20the\x20client\x20to\x20the\x20phant\
+    om:\x20on\x20the\n\x20closed\x20connection\x20for\x20ConnectionClosed,\
+    \x20on\x20the\x20whole\x20session\x20otherwise\n\n\r\n\x05\x04\x0c\x02\t\
+    \x04\x12\x04\xcd\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\t\x05\x12\x04\xcd\x02\
+    \r\x13\n\r\n\x05\x04\x0c\x02\t\x01\x12\x04\xcd\x02\x14\x1b\n\r\n\x05\x04\
+    \x0c\x02\t\x03\x12\x04\xcd\x02\x1e\x20\n\x0c\n\x04\x04\x0c\x02\n\x12\x04\
+    \xce\x02\x04\x1f\n\r\n\x05\x04\x0c\x02\n\x04\x12\x04\xce\x02\x04\x0c\n\r\
+    \n\x05\x04\x0c\x02\n\x05\x12\x04\xce\x02\r\x13\n\r\n\x05\x04\x0c\x02\n\
+    \x01\x12\x04\xce\x02\x14\x19\n\r\n\x05\x04\x0c\x02\n\x03\x12\x04\xce\x02\
+    \x1c\x1e\n6\n\x04\x04\x0c\x02\x0b\x12\x04\xd1\x02\x04%\x1a(\x20Connectio\
+    ns\x20the\x20session\x20has\x20had\x20so\x20far\n\n\r\n\x05\x04\x0c\x02\
+    \x0b\x04\x12\x04\xd1\x02\x04\x0c\n\r\n\x05\x04\x0c\x02\x0b\x05\x12\x04\
+    \xd1\x02\r\x13\n\r\n\x05\x04\x0c\x02\x0b\x01\x12\x04\xd1\x02\x14\x1f\n\r\
+    \n\x05\x04\x0c\x02\x0b\x03\x12\x04\xd1\x02\"$\
 ";
 
 static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::rt::LazyV2::INIT;
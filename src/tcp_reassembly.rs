@@ -0,0 +1,414 @@
+//
+// TLS record reassembly for tag checking
+//
+// Registration tags live at the end of the first TLS application data record
+// a client sends. On small-MSS paths, or when a middlebox resegments, that
+// record spans several TCP segments. The FlowTracker keeps one of these per
+// core to collect the record for tracked flows whose first app data segment
+// came up short.
+//
+// Everything here is bounded: a record may not claim more than
+// MAX_RECORD_LEN bytes, at most MAX_FLOWS flows and MAX_BUFFERED_BYTES bytes
+// (including out-of-order segments) are held at a time, and a flow that has
+// not completed its record within TIMEOUT_NS is forgotten. When a limit is
+// hit the flow is given up on rather than evicting someone else's state.
+//
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use flow_tracker::Flow;
+
+const TLS_HEADER_LEN: usize = 5;
+// 2^14 plaintext + 2048 bytes of expansion is the largest record TLS allows.
+pub const MAX_RECORD_LEN: usize = TLS_HEADER_LEN + 16384 + 2048;
+pub const MAX_FLOWS: usize = 4096;
+pub const MAX_BUFFERED_BYTES: usize = 16 * 1024 * 1024;
+// Out-of-order segments held per flow before we give up on it.
+const MAX_OOO_SEGMENTS: usize = 16;
+pub const TIMEOUT_NS: u64 = 10 * 1000 * 1000 * 1000;
+
+pub enum Reassembly
+{
+    // The whole record (header included) is available.
+    Complete(Vec<u8>),
+    // Still waiting for more segments.
+    Pending,
+    // Gave up on this flow (limits exceeded or not a usable record).
+    Dropped,
+}
+
+struct PartialRecord
+{
+    // Sequence number of the first byte of the record.
+    base_seq: u32,
+    // Total record length, header included.
+    needed: usize,
+    // In-order bytes collected so far, starting at base_seq.
+    buf: Vec<u8>,
+    // Segments beyond the in-order edge, keyed by offset from base_seq.
+    ooo: BTreeMap<usize, Vec<u8>>,
+    expire_time: u64,
+}
+
+impl PartialRecord
+{
+    fn buffered(&self) -> usize
+    {
+        self.buf.len() + self.ooo.values().map(|s| s.len()).sum::<usize>()
+    }
+
+    // Moves any out-of-order segments that now touch the in-order edge into
+    // buf.
+    fn drain_ooo(&mut self)
+    {
+        loop {
+            let off = match self.ooo.keys().next() {
+                Some(off) if *off <= self.buf.len() => *off,
+                _ => return,
+            };
+            let seg = self.ooo.remove(&off).unwrap();
+            let skip = self.buf.len() - off;
+            if skip < seg.len() {
+                self.buf.extend_from_slice(&seg[skip..]);
+            }
+        }
+    }
+}
+
+// Length of the TLS record starting at payload[0] (header included), if the
+// header is there.
+pub fn tls_record_len(payload: &[u8]) -> Option<usize>
+{
+    if payload.len() < TLS_HEADER_LEN {
+        return None;
+    }
+    Some(TLS_HEADER_LEN + (((payload[3] as usize) << 8) | payload[4] as usize))
+}
+
+#[derive(Default)]
+pub struct TlsRecordReassembler
+{
+    partial: HashMap<Flow, PartialRecord>,
+    // Expiry events, sorted by time. Entries for flows that have since
+    // completed or been dropped are skipped when they come due.
+    expiries: VecDeque<(u64, Flow)>,
+    bytes_buffered: usize,
+}
+
+impl TlsRecordReassembler
+{
+    pub fn new() -> TlsRecordReassembler
+    {
+        TlsRecordReassembler {
+            partial: HashMap::new(),
+            expiries: VecDeque::new(),
+            bytes_buffered: 0,
+        }
+    }
+
+    pub fn is_reassembling(&self, flow: &Flow) -> bool
+    {
+        self.partial.contains_key(flow)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.partial.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.partial.is_empty()
+    }
+
+    pub fn bytes_buffered(&self) -> usize
+    {
+        self.bytes_buffered
+    }
+
+    // Called with the first app data segment of a tracked flow, which starts
+    // with a TLS record header. Returns Complete straight away when the whole
+    // record is already in this segment.
+    pub fn start(&mut self, flow: &Flow, seq: u32, payload: &[u8], now: u64) -> Reassembly
+    {
+        let needed = match tls_record_len(payload) {
+            Some(n) => n,
+            None => return Reassembly::Dropped,
+        };
+        if payload.len() >= needed {
+            return Reassembly::Complete(payload[..needed].to_vec());
+        }
+        if needed > MAX_RECORD_LEN
+            || self.partial.len() >= MAX_FLOWS
+            || self.bytes_buffered + payload.len() > MAX_BUFFERED_BYTES {
+            return Reassembly::Dropped;
+        }
+
+        self.remove(flow);
+        let expire_time = now + TIMEOUT_NS;
+        self.partial.insert(*flow, PartialRecord {
+            base_seq: seq,
+            needed,
+            buf: payload.to_vec(),
+            ooo: BTreeMap::new(),
+            expire_time,
+        });
+        self.bytes_buffered += payload.len();
+        self.expiries.push_back((expire_time, *flow));
+        Reassembly::Pending
+    }
+
+    // Adds a later segment of a flow that is being reassembled. Duplicate and
+    // overlapping data is trimmed; data past the end of the record is ignored.
+    pub fn add_segment(&mut self, flow: &Flow, seq: u32, payload: &[u8]) -> Reassembly
+    {
+        let (result, before, after) = {
+            let rec = match self.partial.get_mut(flow) {
+                Some(rec) => rec,
+                None => return Reassembly::Dropped,
+            };
+            let before = rec.buffered();
+
+            // Offset of this segment relative to the start of the record.
+            // Records are far smaller than 2^31, so a negative offset means
+            // the segment starts before the record (retransmission).
+            let rel = seq.wrapping_sub(rec.base_seq) as i32 as i64;
+            let (off, data) = if rel < 0 {
+                let skip = (-rel) as usize;
+                if skip >= payload.len() {
+                    (0, &payload[0..0])
+                } else {
+                    (0, &payload[skip..])
+                }
+            } else {
+                (rel as usize, payload)
+            };
+
+            let mut result = Reassembly::Pending;
+            if off < rec.needed && !data.is_empty() {
+                let end = if off + data.len() > rec.needed { rec.needed } else { off + data.len() };
+                let data = &data[..end - off];
+                let have = rec.buf.len();
+                if off <= have {
+                    if off + data.len() > have {
+                        rec.buf.extend_from_slice(&data[have - off..]);
+                        rec.drain_ooo();
+                    }
+                } else if rec.ooo.len() >= MAX_OOO_SEGMENTS {
+                    result = Reassembly::Dropped;
+                } else {
+                    let keep = match rec.ooo.get(&off) {
+                        Some(existing) => existing.len() < data.len(),
+                        None => true,
+                    };
+                    if keep {
+                        rec.ooo.insert(off, data.to_vec());
+                    }
+                }
+            }
+
+            if rec.buf.len() >= rec.needed {
+                rec.buf.truncate(rec.needed);
+                result = Reassembly::Complete(rec.buf.clone());
+            }
+            (result, before, rec.buffered())
+        };
+
+        self.bytes_buffered = self.bytes_buffered - before + after;
+        if self.bytes_buffered > MAX_BUFFERED_BYTES {
+            self.remove(flow);
+            return Reassembly::Dropped;
+        }
+        match result {
+            Reassembly::Pending => Reassembly::Pending,
+            other => {
+                self.remove(flow);
+                other
+            },
+        }
+    }
+
+    pub fn remove(&mut self, flow: &Flow)
+    {
+        if let Some(rec) = self.partial.remove(flow) {
+            self.bytes_buffered -= rec.buffered();
+        }
+    }
+
+    // Forgets flows whose record did not complete in time. Returns how many
+    // were dropped.
+    pub fn drop_stale(&mut self, now: u64) -> usize
+    {
+        let mut dropped = 0;
+        loop {
+            let (expire_time, flow) = match self.expiries.front() {
+                Some(&(t, f)) if t <= now => (t, f),
+                _ => return dropped,
+            };
+            self.expiries.pop_front();
+            // The flow may have completed and started over since this event
+            // was queued; only drop it if this event is still the live one.
+            let live = match self.partial.get(&flow) {
+                Some(rec) => rec.expire_time == expire_time,
+                None => false,
+            };
+            if live {
+                self.remove(&flow);
+                dropped += 1;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use flow_tracker::Flow;
+    use tcp_reassembly::*;
+
+    fn flow() -> Flow
+    {
+        Flow {
+            src_ip: "10.22.0.1".parse().unwrap(),
+            dst_ip: "128.138.97.6".parse().unwrap(),
+            src_port: 5672,
+            dst_port: 443,
+        }
+    }
+
+    // TLS app data record with a body of `len` bytes counting up from 0.
+    fn record(len: usize) -> Vec<u8>
+    {
+        let mut r = vec![0x17, 0x03, 0x03, (len >> 8) as u8, len as u8];
+        for i in 0..len {
+            r.push(i as u8);
+        }
+        r
+    }
+
+    fn complete(r: Reassembly) -> Vec<u8>
+    {
+        match r {
+            Reassembly::Complete(v) => v,
+            Reassembly::Pending => panic!("expected Complete, got Pending"),
+            Reassembly::Dropped => panic!("expected Complete, got Dropped"),
+        }
+    }
+
+    fn is_pending(r: &Reassembly) -> bool
+    {
+        matches!(*r, Reassembly::Pending)
+    }
+
+    fn is_dropped(r: &Reassembly) -> bool
+    {
+        matches!(*r, Reassembly::Dropped)
+    }
+
+    #[test]
+    fn test_single_segment_record()
+    {
+        let mut r = TlsRecordReassembler::new();
+        let mut rec = record(300);
+        let want = rec.clone();
+        // Trailing bytes of a following record are not part of the result.
+        rec.extend_from_slice(&[0x17, 0x03]);
+        assert_eq!(complete(r.start(&flow(), 1000, &rec, 0)), want);
+        assert_eq!(r.len(), 0);
+    }
+
+    #[test]
+    fn test_in_order_and_retransmitted_segments()
+    {
+        let mut r = TlsRecordReassembler::new();
+        let rec = record(1000);
+        // seq wraps in the middle of the record
+        let base = 0xffff_ff00u32;
+
+        assert!(is_pending(&r.start(&flow(), base, &rec[..400], 0)));
+        assert!(r.is_reassembling(&flow()));
+        assert_eq!(r.bytes_buffered(), 400);
+
+        // Exact retransmission, then one that overlaps the edge
+        assert!(is_pending(&r.add_segment(&flow(), base, &rec[..400])));
+        assert!(is_pending(&r.add_segment(&flow(), base.wrapping_add(300), &rec[300..700])));
+        assert_eq!(r.bytes_buffered(), 700);
+
+        let out = complete(r.add_segment(&flow(), base.wrapping_add(700), &rec[700..]));
+        assert_eq!(out, rec);
+        assert!(!r.is_reassembling(&flow()));
+        assert_eq!(r.bytes_buffered(), 0);
+    }
+
+    #[test]
+    fn test_out_of_order_segments()
+    {
+        let mut r = TlsRecordReassembler::new();
+        let rec = record(900);
+
+        assert!(is_pending(&r.start(&flow(), 1, &rec[..300], 0)));
+        assert!(is_pending(&r.add_segment(&flow(), 601, &rec[600..])));
+        assert!(is_pending(&r.add_segment(&flow(), 451, &rec[450..600])));
+        let out = complete(r.add_segment(&flow(), 301, &rec[300..450]));
+        assert_eq!(out, rec);
+        assert_eq!(r.bytes_buffered(), 0);
+    }
+
+    #[test]
+    fn test_limits()
+    {
+        let mut r = TlsRecordReassembler::new();
+
+        // Record claiming more than TLS allows
+        let mut huge = vec![0x17, 0x03, 0x03, 0xff, 0xff];
+        huge.extend_from_slice(&[0u8; 100]);
+        assert!(is_dropped(&r.start(&flow(), 1, &huge, 0)));
+
+        // Too many out of order segments
+        let rec = record(10000);
+        assert!(is_pending(&r.start(&flow(), 1, &rec[..10], 0)));
+        let mut last = Reassembly::Pending;
+        for i in 0..(MAX_OOO_SEGMENTS + 1) {
+            let off = 100 + i * 20;
+            last = r.add_segment(&flow(), 1 + off as u32, &rec[off..off + 10]);
+        }
+        assert!(is_dropped(&last));
+        assert!(!r.is_reassembling(&flow()));
+        assert_eq!(r.bytes_buffered(), 0);
+
+        // Too many flows
+        for i in 0..MAX_FLOWS {
+            let mut f = flow();
+            f.src_port = i as u16;
+            assert!(is_pending(&r.start(&f, 1, &rec[..10], 0)));
+        }
+        let mut f = flow();
+        f.src_port = 65535;
+        assert!(is_dropped(&r.start(&f, 1, &rec[..10], 0)));
+    }
+
+    #[test]
+    fn test_timeouts()
+    {
+        let mut r = TlsRecordReassembler::new();
+        let rec = record(1000);
+
+        let mut f2 = flow();
+        f2.src_port = 2;
+        assert!(is_pending(&r.start(&flow(), 1, &rec[..10], 0)));
+        assert!(is_pending(&r.start(&f2, 1, &rec[..10], TIMEOUT_NS / 2)));
+
+        assert_eq!(r.drop_stale(TIMEOUT_NS - 1), 0);
+        assert_eq!(r.drop_stale(TIMEOUT_NS), 1);
+        assert!(!r.is_reassembling(&flow()));
+        assert!(r.is_reassembling(&f2));
+
+        // A flow that restarted keeps its newer deadline
+        assert!(is_pending(&r.start(&flow(), 1, &rec[..10], TIMEOUT_NS)));
+        assert_eq!(r.drop_stale(TIMEOUT_NS + TIMEOUT_NS / 2), 1);
+        assert!(r.is_reassembling(&flow()));
+        assert_eq!(r.drop_stale(2 * TIMEOUT_NS), 1);
+        assert_eq!(r.len(), 0);
+        assert_eq!(r.bytes_buffered(), 0);
+    }
+}
@@ -0,0 +1,345 @@
+//
+// Hierarchical timer wheel
+//
+// Keeps a deadline for each key and hands back the keys whose deadline has
+// passed. Adding a key, pushing its deadline out, removing it and expiring it
+// are all O(1), and a key is only ever in the wheel once, however often it's
+// refreshed, so memory stays at one node per key.
+//
+// Time is counted in ticks of tick_ns. There are LEVELS wheels of SLOTS
+// slots: level 0 has a slot per tick, level 1 a slot per SLOTS ticks, and so
+// on. A key sits at the level of the highest digit (base SLOTS) where its
+// deadline differs from the current tick, and moves down (cascades) once the
+// current tick gets to its slot. Deadlines past the top level wait in an
+// overflow list that is looked at again each time the top level comes round.
+//
+// Keys expire at most a tick after their deadline, never before it.
+//
+
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+// Fine enough for the 100ms housekeeping, and 4 levels of 64 slots cover
+// 46 hours before anything goes to the overflow list.
+pub const DEFAULT_TICK_NS: u64 = 10 * 1000 * 1000;
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 4;
+const OVERFLOW: usize = LEVELS * SLOTS;
+const NIL: usize = usize::MAX;
+
+struct Node<K>
+{
+    // None while the node is on the free list
+    key: Option<K>,
+    deadline_ns: u64,
+    slot: usize,
+    prev: usize,
+    next: usize,
+}
+
+pub struct TimerWheel<K, S = RandomState>
+{
+    tick_ns: u64,
+    // Next tick to process; everything due before it has been expired.
+    current: u64,
+    // First node in each slot, plus the overflow list at OVERFLOW
+    heads: Vec<usize>,
+    // Nodes at each level (and in the overflow list)
+    counts: [usize; LEVELS + 1],
+    nodes: Vec<Node<K>>,
+    // Unused nodes, linked through next
+    free: usize,
+    index: HashMap<K, usize, S>,
+}
+
+fn digit(tick: u64, level: usize) -> usize
+{
+    (tick >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1)
+}
+
+impl<K: Hash + Eq + Clone> TimerWheel<K>
+{
+    pub fn new(tick_ns: u64, now_ns: u64) -> TimerWheel<K>
+    {
+        TimerWheel::with_hasher(tick_ns, now_ns, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: BuildHasher> TimerWheel<K, S>
+{
+    // Keys are looked up with hasher, e.g. something faster than SipHash for
+    // keys that aren't attacker controlled.
+    pub fn with_hasher(tick_ns: u64, now_ns: u64, hasher: S) -> TimerWheel<K, S>
+    {
+        let tick_ns = tick_ns.max(1);
+        TimerWheel {
+            tick_ns,
+            current: now_ns / tick_ns,
+            heads: vec![NIL; OVERFLOW + 1],
+            counts: [0; LEVELS + 1],
+            nodes: Vec::new(),
+            free: NIL,
+            index: HashMap::with_hasher(hasher),
+        }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool
+    {
+        self.index.contains_key(key)
+    }
+
+    pub fn deadline(&self, key: &K) -> Option<u64>
+    {
+        self.index.get(key).map(|&i| self.nodes[i].deadline_ns)
+    }
+
+    // Adds key, or pushes its deadline out if it's already there (deadlines
+    // are never brought forward). Returns true if key wasn't there.
+    pub fn insert(&mut self, key: K, deadline_ns: u64) -> bool
+    {
+        if let Some(&i) = self.index.get(&key) {
+            if self.nodes[i].deadline_ns < deadline_ns {
+                self.unlink(i);
+                self.nodes[i].deadline_ns = deadline_ns;
+                self.place(i);
+            }
+            return false;
+        }
+        let node = Node { key: Some(key.clone()), deadline_ns, slot: NIL, prev: NIL, next: NIL };
+        let i = match self.free {
+            NIL => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+            i => {
+                self.free = self.nodes[i].next;
+                self.nodes[i] = node;
+                i
+            },
+        };
+        self.index.insert(key, i);
+        self.place(i);
+        true
+    }
+
+    // Moves key's deadline, earlier or later. Returns false if key isn't
+    // there.
+    pub fn set_deadline(&mut self, key: &K, deadline_ns: u64) -> bool
+    {
+        match self.index.get(key) {
+            Some(&i) => {
+                self.unlink(i);
+                self.nodes[i].deadline_ns = deadline_ns;
+                self.place(i);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool
+    {
+        match self.index.remove(key) {
+            Some(i) => {
+                self.unlink(i);
+                self.release(i);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Removes every key whose deadline is at or before now_ns, handing each
+    // to expired. Returns how many there were.
+    pub fn expire<F: FnMut(K)>(&mut self, now_ns: u64, mut expired: F) -> usize
+    {
+        let now = now_ns / self.tick_ns;
+        if self.index.is_empty() {
+            self.current = self.current.max(now + 1);
+            return 0;
+        }
+        let mut count = 0;
+        while self.current <= now {
+            let t = self.current;
+            // Bring down whatever is due from here until the next slot at
+            // each level comes round, top level first.
+            for level in (1..LEVELS + 1).rev() {
+                if t & ((1 << (SLOT_BITS * level as u32)) - 1) == 0 {
+                    match level {
+                        LEVELS => self.cascade(OVERFLOW),
+                        _ => self.cascade(level * SLOTS + digit(t, level)),
+                    }
+                }
+            }
+            let mut i = mem::replace(&mut self.heads[digit(t, 0)], NIL);
+            while i != NIL {
+                let next = self.nodes[i].next;
+                if let Some(key) = self.nodes[i].key.take() {
+                    self.index.remove(&key);
+                    expired(key);
+                    count += 1;
+                }
+                self.counts[0] -= 1;
+                self.release(i);
+                i = next;
+            }
+            // Skip ahead over empty levels to where the next cascade is due
+            let mut next = t + 1;
+            for level in 0..LEVELS {
+                if self.counts[level] != 0 {
+                    break;
+                }
+                next = (t | ((1 << (SLOT_BITS * (level as u32 + 1))) - 1)) + 1;
+            }
+            self.current = next.min(now + 1);
+        }
+        count
+    }
+
+    fn place(&mut self, i: usize)
+    {
+        // The first tick at or after the deadline that hasn't been processed
+        let tick = self.nodes[i].deadline_ns.div_ceil(self.tick_ns).max(self.current);
+        let diff = tick ^ self.current;
+        let slot = match (0..LEVELS).find(|&l| diff >> (SLOT_BITS * (l as u32 + 1)) == 0) {
+            Some(level) => level * SLOTS + digit(tick, level),
+            None => OVERFLOW,
+        };
+        self.counts[slot / SLOTS] += 1;
+        let head = self.heads[slot];
+        if head != NIL {
+            self.nodes[head].prev = i;
+        }
+        let node = &mut self.nodes[i];
+        node.slot = slot;
+        node.prev = NIL;
+        node.next = head;
+        self.heads[slot] = i;
+    }
+
+    fn unlink(&mut self, i: usize)
+    {
+        let (slot, prev, next) = (self.nodes[i].slot, self.nodes[i].prev, self.nodes[i].next);
+        self.counts[slot / SLOTS] -= 1;
+        match prev {
+            NIL => self.heads[slot] = next,
+            p => self.nodes[p].next = next,
+        }
+        if next != NIL {
+            self.nodes[next].prev = prev;
+        }
+    }
+
+    fn release(&mut self, i: usize)
+    {
+        let node = &mut self.nodes[i];
+        node.key = None;
+        node.slot = NIL;
+        node.prev = NIL;
+        node.next = self.free;
+        self.free = i;
+    }
+
+    fn cascade(&mut self, slot: usize)
+    {
+        let mut i = mem::replace(&mut self.heads[slot], NIL);
+        while i != NIL {
+            let next = self.nodes[i].next;
+            self.counts[slot / SLOTS] -= 1;
+            self.place(i);
+            i = next;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use timer_wheel::*;
+
+    #[test]
+    fn test_expire_and_refresh()
+    {
+        let mut w = TimerWheel::new(10, 1000);
+        assert!(w.insert("a", 1050));
+        assert!(w.insert("b", 1100));
+        assert!(w.insert("c", 1100));
+        // Pushed out, but never brought forward
+        assert!(!w.insert("b", 1200));
+        assert!(!w.insert("c", 1010));
+        assert_eq!(w.deadline(&"c"), Some(1100));
+        assert!(w.set_deadline(&"c", 1090));
+        assert!(!w.set_deadline(&"x", 1090));
+        assert_eq!(w.deadline(&"c"), Some(1090));
+        assert_eq!(w.len(), 3);
+
+        let mut gone = Vec::new();
+        assert_eq!(w.expire(1049, |k| gone.push(k)), 0);
+        assert_eq!(w.expire(1050, |k| gone.push(k)), 1);
+        assert!(w.remove(&"c"));
+        assert!(!w.remove(&"c"));
+        assert_eq!(w.expire(1199, |k| gone.push(k)), 0);
+        assert_eq!(w.expire(5000, |k| gone.push(k)), 1);
+        assert_eq!(gone, vec!["a", "b"]);
+        assert!(w.is_empty());
+
+        // Deadlines already past go at the next tick
+        w.insert("d", 10);
+        assert_eq!(w.expire(5000, |_| {}), 0);
+        assert_eq!(w.expire(5010, |_| {}), 1);
+
+        // Nodes get reused
+        let nodes = w.nodes.len();
+        for i in 0..nodes {
+            w.insert(["e", "f", "g"][i], 6000);
+        }
+        assert_eq!(w.nodes.len(), nodes);
+    }
+
+    #[test]
+    fn test_levels()
+    {
+        // Deadlines at every level and in the overflow list, expired in
+        // uneven steps, against a plain list.
+        let mut w = TimerWheel::new(1, 7);
+        let mut expected = Vec::new();
+        let mut x: u64 = 12345;
+        for k in 0..2000u64 {
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let deadline = 7 + (x >> 33) % (1 << (6 * (k % 5) + 6));
+            w.insert(k, deadline);
+            expected.push((deadline, k));
+        }
+        w.insert(5000, 1 << 40);
+        expected.push((1 << 40, 5000));
+
+        let mut now = 7;
+        let mut step = 1;
+        while !w.is_empty() {
+            now += step;
+            step = step * 3 + 1;
+            let mut gone = Vec::new();
+            w.expire(now, |k| gone.push(k));
+            gone.sort();
+            let mut want: Vec<u64> = expected.iter().filter(|e| e.0 <= now).map(|e| e.1).collect();
+            want.sort();
+            expected.retain(|e| e.0 > now);
+            assert_eq!(gone, want);
+            assert_eq!(w.len(), expected.len());
+        }
+    }
+}
@@ -8,8 +8,10 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::error::Error;
+use std::hash::{BuildHasherDefault, Hasher};
 
 use pnet::packet::Packet;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use pnet::packet::tcp::{TcpOptionNumbers, TcpPacket};
 use pnet::packet::udp::UdpPacket;
 use pnet::packet::ipv4::Ipv4Packet;
@@ -21,21 +23,116 @@ pub enum IpPacket<'p> {
     V6(Ipv6Packet<'p>),
 }
 
+// Where a fragment's data goes in the packet it's part of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FragmentInfo
+{
+    pub id: u32,
+    // In bytes, from the start of what follows the (unfragmentable) headers
+    pub offset: usize,
+    pub more: bool,
+    // IPv4 protocol, or the IPv6 Next Header after the fragment header (which
+    // can be another extension header; see ipv6_upper_layer)
+    pub protocol: IpNextHeaderProtocol,
+}
+
+// What an IP packet carries once the IPv6 extension headers are skipped
+#[derive(Debug, PartialEq)]
+pub enum UpperLayer<'p>
+{
+    Payload(IpNextHeaderProtocol, &'p [u8]),
+    Fragment(FragmentInfo, &'p [u8]),
+    // More than MAX_IPV6_EXT_HEADERS extension headers, or one running past
+    // the end of the packet
+    Invalid,
+}
+
+// Longest IPv6 extension header chain we walk before giving up on a packet
+pub const MAX_IPV6_EXT_HEADERS: usize = 8;
+
+const IPV4_MORE_FRAGMENTS: u8 = 0x1;
+
+// Length of the IPv6 extension header of type next at the start of data (at
+// least 8, even if data is too short to say), or None if next isn't an
+// extension header we know how to skip.
+pub fn ipv6_ext_header_len(next: IpNextHeaderProtocol, data: &[u8]) -> Option<usize>
+{
+    let len = data.get(1).map(|l| *l as usize).unwrap_or(0);
+    match next {
+        IpNextHeaderProtocols::Hopopt
+        | IpNextHeaderProtocols::Ipv6Route
+        | IpNextHeaderProtocols::Ipv6Opts => Some((len + 1) * 8),
+        IpNextHeaderProtocols::Ah => Some((len + 2) * 4),
+        IpNextHeaderProtocols::Ipv6Frag => Some(8),
+        _ => None,
+    }
+}
+
+// Walks the extension headers starting with next (as found in an IPv6
+// header or a fragment header) at the start of data. Atomic fragments (offset
+// 0, no more fragments) are walked through like any other header.
+pub fn ipv6_upper_layer<'p>(mut next: IpNextHeaderProtocol, mut data: &'p [u8]) -> UpperLayer<'p>
+{
+    for _ in 0..MAX_IPV6_EXT_HEADERS + 1 {
+        let hdr_len = match ipv6_ext_header_len(next, data) {
+            Some(len) => len,
+            None => return UpperLayer::Payload(next, data),
+        };
+        if data.len() < hdr_len {
+            return UpperLayer::Invalid;
+        }
+
+        if next == IpNextHeaderProtocols::Ipv6Frag {
+            let off_flags = (data[2] as u16) << 8 | data[3] as u16;
+            let frag = FragmentInfo {
+                id: deser_be_u32_slice(&data[4..8]),
+                offset: (off_flags & !0x7) as usize,
+                more: off_flags & 0x1 != 0,
+                protocol: IpNextHeaderProtocol(data[0]),
+            };
+            if frag.offset != 0 || frag.more {
+                return UpperLayer::Fragment(frag, &data[8..]);
+            }
+        }
+        next = IpNextHeaderProtocol(data[0]);
+        data = &data[hdr_len..];
+    }
+    UpperLayer::Invalid
+}
+
 impl<'p> IpPacket<'p> {
+    pub fn upper_layer(&'p self) -> UpperLayer<'p> {
+        match self {
+            IpPacket::V4(v4) => {
+                let protocol = v4.get_next_level_protocol();
+                let offset = v4.get_fragment_offset() as usize * 8;
+                let more = v4.get_flags() & IPV4_MORE_FRAGMENTS != 0;
+                match offset != 0 || more {
+                    true => UpperLayer::Fragment(FragmentInfo {
+                        id: v4.get_identification() as u32,
+                        offset,
+                        more,
+                        protocol,
+                    }, v4.payload()),
+                    false => UpperLayer::Payload(protocol, v4.payload()),
+                }
+            },
+            IpPacket::V6(v6) => ipv6_upper_layer(v6.get_next_header(), v6.payload()),
+        }
+    }
+
     pub fn tcp(&'p self) -> Option<TcpPacket<'p>> {
-        let payload = match self {
-            IpPacket::V4(v4) => v4.payload(),
-            IpPacket::V6(v6) => v6.payload(),
-        };
-        TcpPacket::new(payload)
+        match self.upper_layer() {
+            UpperLayer::Payload(IpNextHeaderProtocols::Tcp, payload) => TcpPacket::new(payload),
+            _ => None,
+        }
     }
 
     pub fn udp(&'p self) -> Option<UdpPacket<'p>> {
-        let payload = match self {
-            IpPacket::V4(v4) => v4.payload(),
-            IpPacket::V6(v6) => v6.payload(),
-        };
-        UdpPacket::new(payload)
+        match self.upper_layer() {
+            UpperLayer::Payload(IpNextHeaderProtocols::Udp, payload) => UdpPacket::new(payload),
+            _ => None,
+        }
     }
 }
 
@@ -243,14 +340,195 @@ impl FSP {
     }
 }
 
+// (added, removed) going from old to new, in new's/old's order
+pub fn list_diff<T: PartialEq + Clone>(old: &[T], new: &[T]) -> (Vec<T>, Vec<T>)
+{
+    (new.iter().filter(|x| !old.contains(x)).cloned().collect(),
+     old.iter().filter(|x| !new.contains(x)).cloned().collect())
+}
+
+// The hash rustc uses for its own tables (FxHash): a multiply and rotate per
+// word. Much cheaper than the default SipHash for small keys, but with no
+// protection against crafted collisions, so only for keys an attacker can't
+// pick freely.
+#[derive(Default, Clone, Copy)]
+pub struct FxHasher
+{
+    hash: u64,
+}
+
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher
+{
+    fn add(&mut self, word: u64)
+    {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+impl Hasher for FxHasher
+{
+    fn write(&mut self, mut bytes: &[u8])
+    {
+        while bytes.len() >= 8 {
+            self.add(u64::from_le_bytes(*array_ref![bytes, 0, 8]));
+            bytes = &bytes[8..];
+        }
+        if bytes.len() >= 4 {
+            self.add(u32::from_le_bytes(*array_ref![bytes, 0, 4]) as u64);
+            bytes = &bytes[4..];
+        }
+        for &b in bytes {
+            self.add(b as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) { self.add(i as u64); }
+    fn write_u16(&mut self, i: u16) { self.add(i as u64); }
+    fn write_u32(&mut self, i: u32) { self.add(i as u64); }
+    fn write_u64(&mut self, i: u64) { self.add(i); }
+    fn write_usize(&mut self, i: usize) { self.add(i as u64); }
+
+    // The multiply leaves the high bits best mixed, but tables index by the
+    // low ones.
+    fn finish(&self) -> u64
+    {
+        self.hash.rotate_left(26)
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const TCP: u8 = 6;
+    const DEST_OPTS: u8 = 60;
+
+    // IPv6 header (next header next), then payload and a TCP header to 443
+    fn ipv6_packet(next: u8, payload: &[u8]) -> Vec<u8>
+    {
+        let len = payload.len() + 20;
+        let mut p = vec![0x60, 0, 0, 0, (len >> 8) as u8, len as u8, next, 64];
+        p.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        p.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        p.extend_from_slice(payload);
+        p.extend_from_slice(&[0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0,
+                              0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        p
+    }
+
+    fn upper_layer_of<'p>(pkt: &'p [u8]) -> UpperLayer<'p>
+    {
+        ipv6_upper_layer(IpNextHeaderProtocol(pkt[6]), &pkt[40..])
+    }
+
+    #[test]
+    fn ipv6_extension_headers()
+    {
+        // Hop-by-Hop (8 bytes), Destination Options (16 bytes), TCP
+        let mut ext = vec![DEST_OPTS, 0, 1, 4, 0, 0, 0, 0];
+        ext.extend_from_slice(&[TCP, 1, 1, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let pkt = ipv6_packet(0, &ext);
+        let ip = IpPacket::V6(Ipv6Packet::new(&pkt).unwrap());
+        assert_eq!(ip.upper_layer(), UpperLayer::Payload(IpNextHeaderProtocols::Tcp, &pkt[64..]));
+        assert_eq!(ip.tcp().unwrap().get_destination(), 443);
+        assert!(ip.udp().is_none());
+
+        // A fragment header (offset 1480, more to come)
+        let pkt = ipv6_packet(44, &[TCP, 0, 0x05, 0xc9, 0xde, 0xad, 0xbe, 0xef]);
+        let ip = IpPacket::V6(Ipv6Packet::new(&pkt).unwrap());
+        let frag = FragmentInfo {
+            id: 0xdeadbeef,
+            offset: 1480,
+            more: true,
+            protocol: IpNextHeaderProtocols::Tcp,
+        };
+        assert_eq!(ip.upper_layer(), UpperLayer::Fragment(frag, &pkt[48..]));
+        assert!(ip.tcp().is_none());
+
+        // ...unless it's an atomic fragment
+        let pkt = ipv6_packet(44, &[TCP, 0, 0, 0, 0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(upper_layer_of(&pkt), UpperLayer::Payload(IpNextHeaderProtocols::Tcp, &pkt[48..]));
+
+        // As many headers as we're willing to walk, and one more
+        let chain = |n: usize| {
+            let mut ext = Vec::new();
+            for i in 0..n {
+                ext.extend_from_slice(&[if i + 1 == n { TCP } else { DEST_OPTS }, 0, 1, 4, 0, 0, 0, 0]);
+            }
+            ipv6_packet(DEST_OPTS, &ext)
+        };
+        let pkt = chain(MAX_IPV6_EXT_HEADERS);
+        match upper_layer_of(&pkt) {
+            UpperLayer::Payload(IpNextHeaderProtocols::Tcp, tcp) => assert_eq!(tcp.len(), 20),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(upper_layer_of(&chain(MAX_IPV6_EXT_HEADERS + 1)), UpperLayer::Invalid);
+
+        // A header longer than the packet
+        let mut pkt = ipv6_packet(DEST_OPTS, &[TCP, 3, 1, 4, 0, 0, 0, 0]);
+        assert_eq!(upper_layer_of(&pkt), UpperLayer::Invalid);
+        pkt.truncate(41);
+        assert_eq!(upper_layer_of(&pkt), UpperLayer::Invalid);
+    }
+
+    #[test]
+    fn ipv4_fragments()
+    {
+        let mut pkt = vec![0x45, 0, 0, 28, 0x12, 0x34, 0x20, 0x00, 64, 17, 0, 0,
+                           192, 0, 2, 1, 198, 51, 100, 1,
+                           0x9c, 0x40, 0, 53, 0, 8, 0, 0];
+        {
+            let ip = IpPacket::V4(Ipv4Packet::new(&pkt).unwrap());
+            let frag = FragmentInfo {
+                id: 0x1234,
+                offset: 0,
+                more: true,
+                protocol: IpNextHeaderProtocols::Udp,
+            };
+            assert_eq!(ip.upper_layer(), UpperLayer::Fragment(frag, &pkt[20..]));
+            assert!(ip.udp().is_none());
+        }
+        pkt[6] = 0x40; // DF, no offset
+        let ip = IpPacket::V4(Ipv4Packet::new(&pkt).unwrap());
+        assert_eq!(ip.upper_layer(), UpperLayer::Payload(IpNextHeaderProtocols::Udp, &pkt[20..]));
+        assert_eq!(ip.udp().unwrap().get_destination(), 53);
+    }
+
     #[test]
     fn mem_used_kb_parses_something()
     {
         assert!(mem_used_kb() > 0);
     }
+
+    #[test]
+    fn fx_hasher_spreads_keys()
+    {
+        use std::hash::BuildHasher;
+        let h = |bytes: &[u8]| {
+            let mut hasher = FxBuildHasher::default().build_hasher();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(h(b"same key"), h(b"same key"));
+        assert!(h(&[10, 0, 0, 1]) != h(&[10, 0, 0, 2]));
+        assert!(h(&[1, 2, 3, 4, 5, 6, 7, 8, 9]) != h(&[1, 2, 3, 4, 5, 6, 7, 8, 10]));
+        // Addresses that only differ in the last octet still spread out
+        let buckets: ::std::collections::HashSet<u64> =
+            (0..1024u32).map(|i| h(&(0x0a00_0000 | i).to_be_bytes()) & 1023).collect();
+        assert!(buckets.len() > 500);
+    }
+
+    #[test]
+    fn list_diff_finds_changes()
+    {
+        let old = vec!["a", "b", "c"];
+        let new = vec!["c", "d", "a"];
+        assert_eq!(list_diff(&old, &new), (vec!["d"], vec!["b"]));
+        assert_eq!(list_diff(&old, &old), (vec![], vec![]));
+    }
 }